@@ -0,0 +1,194 @@
+use std::{net::IpAddr, sync::Arc};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::NaiveDateTime;
+use db_schema::schema;
+use diesel::{
+    ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, Queryable, RunQueryDsl,
+    Selectable, SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+/// Rows a single list request may return.
+const MAX_LIMIT: i64 = 100;
+
+pub struct AppState {
+    pub db: Mutex<PgConnection>,
+    /// Serve as a public mirror (see `public`)
+    pub public: bool,
+}
+
+type ApiResult<T> = Result<Json<T>, StatusCode>;
+
+#[derive(Queryable, Selectable, Serialize)]
+#[diesel(table_name = schema::servers)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Server {
+    pub id: i32,
+    pub ip: String,
+    pub online: i32,
+    pub max: i32,
+    pub version_name: String,
+    pub protocol: i32,
+    pub license: Option<bool>,
+    pub white_list: Option<bool>,
+    pub last_seen: NaiveDateTime,
+    pub description: Value,
+    /// `java` or `bedrock`
+    pub edition: String,
+}
+
+#[derive(Queryable, Selectable, Serialize)]
+#[diesel(table_name = schema::players)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Player {
+    pub uuid: String,
+    pub name: String,
+    pub last_seen: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+pub struct ServerDetail {
+    #[serde(flatten)]
+    pub server: Server,
+    pub players: Vec<Player>,
+}
+
+#[derive(Serialize)]
+pub struct Sighting {
+    /// Left out of a public mirror, where it would lead back to the server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<i32>,
+    pub ip: String,
+    pub last_seen: NaiveDateTime,
+}
+
+#[derive(Deserialize)]
+pub struct Page {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+impl Page {
+    /// Rows to return, 50 unless asked for, at most `MAX_LIMIT`.
+    pub fn limit(&self) -> i64 {
+        self.limit.unwrap_or(50).clamp(1, MAX_LIMIT)
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.offset.unwrap_or(0).max(0)
+    }
+}
+
+fn db_error(_: diesel::result::Error) -> StatusCode {
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Most recently seen servers.
+pub async fn servers(
+    State(state): State<Arc<AppState>>,
+    Query(page): Query<Page>,
+) -> ApiResult<Vec<Server>> {
+    let mut servers: Vec<Server> = schema::servers::dsl::servers
+        .order(schema::servers::dsl::last_seen.desc())
+        .limit(page.limit())
+        .offset(page.offset())
+        .select(Server::as_select())
+        .load(&mut *state.db.lock().await)
+        .map_err(db_error)?;
+
+    if state.public {
+        for server in &mut servers {
+            server.ip = truncate_ip(&server.ip);
+        }
+    }
+    Ok(Json(servers))
+}
+
+/// One server with the players seen on it.
+pub async fn server(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i32>,
+) -> ApiResult<ServerDetail> {
+    let mut conn = state.db.lock().await;
+
+    let mut server = schema::servers::dsl::servers
+        .find(id)
+        .select(Server::as_select())
+        .first(&mut *conn)
+        .optional()
+        .map_err(db_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let players = schema::players::dsl::players
+        .filter(schema::players::dsl::server_id.eq(id))
+        .order(schema::players::dsl::last_seen.desc())
+        .select(Player::as_select())
+        .load(&mut *conn)
+        .map_err(db_error)?;
+
+    if state.public {
+        server.ip = truncate_ip(&server.ip);
+    }
+
+    Ok(Json(ServerDetail { server, players }))
+}
+
+/// Servers a player name was seen on.
+pub async fn player(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> ApiResult<Vec<Sighting>> {
+    let rows: Vec<(i32, String, NaiveDateTime)> = schema::players::dsl::players
+        .inner_join(schema::servers::dsl::servers)
+        .filter(schema::players::dsl::name.eq(&name))
+        .order(schema::players::dsl::last_seen.desc())
+        .limit(MAX_LIMIT)
+        .select((
+            schema::servers::dsl::id,
+            schema::servers::dsl::ip,
+            schema::players::dsl::last_seen,
+        ))
+        .load(&mut *state.db.lock().await)
+        .map_err(db_error)?;
+
+    Ok(Json(
+        rows.into_iter()
+            .map(|(server_id, ip, last_seen)| match state.public {
+                true => Sighting {
+                    server_id: None,
+                    ip: truncate_ip(&ip),
+                    last_seen,
+                },
+                false => Sighting {
+                    server_id: Some(server_id),
+                    ip,
+                    last_seen,
+                },
+            })
+            .collect(),
+    ))
+}
+
+/// Hides the host part of an address (IPv4 /24, IPv6 /48) so a public mirror
+/// doesn't pin a player to an exact machine, which may be someone's home.
+/// Every server a mirror lists gets it, as ids tie listings to sightings.
+fn truncate_ip(ip: &str) -> String {
+    match ip.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            let [a, b, c, _] = ip.octets();
+            format!("{}.{}.{}.0/24", a, b, c)
+        }
+        Ok(IpAddr::V6(ip)) => {
+            let segments = ip.segments();
+            format!("{:x}:{:x}:{:x}::/48", segments[0], segments[1], segments[2])
+        }
+        Err(_) => "hidden".to_string(),
+    }
+}
@@ -1,7 +1,17 @@
-use axum::{routing::get, Router};
+use std::{env, net::SocketAddr, sync::Arc};
+
+use api::AppState;
+use axum::{middleware, routing::get, Router};
+use db_schema::version::{self, Compatibility, SCHEMA_VERSION};
+use diesel::{Connection, PgConnection};
+use public::PublicGuard;
+use tokio::sync::Mutex;
 use tower_http::trace::{self, TraceLayer};
 use tracing::Level;
 
+mod api;
+mod public;
+
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt()
@@ -9,17 +19,58 @@ async fn main() {
         .compact()
         .init();
 
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let mut conn = PgConnection::establish(&database_url)
+        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url));
+    match version::check(&mut conn) {
+        Ok(Compatibility::Current) => {}
+        Ok(Compatibility::Newer(live)) => eprintln!(
+            "[!] The database schema is at migration {}, newer than the {} of this build",
+            live, SCHEMA_VERSION
+        ),
+        Err(e) => {
+            eprintln!("[!] Refusing to start: {}", e);
+            std::process::exit(1);
+        }
+    }
+    let public = env::var("PUBLIC_MODE")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false);
+
+    let state = Arc::new(AppState {
+        db: Mutex::new(conn),
+        public,
+    });
+
     let layer = TraceLayer::new_for_http()
         .make_span_with(trace::DefaultMakeSpan::new().level(Level::INFO))
         .on_response(trace::DefaultOnResponse::new().level(Level::INFO));
 
-    let app = Router::new().route("/", get(root)).layer(layer);
+    // Every route is read-only; new write endpoints must stay out of public mode
+    let mut app = Router::new()
+        .route("/", get(root))
+        .route("/api/servers", get(api::servers))
+        .route("/api/servers/{id}", get(api::server))
+        .route("/api/players/{name}", get(api::player))
+        .with_state(state);
+
+    if public {
+        let guard = Arc::new(PublicGuard::from_env());
+        app = app.layer(middleware::from_fn_with_state(guard, public::guard));
+        println!("[+] Public mirror mode");
+    }
+    let app = app.layer(layer);
 
     // run our app with hyper, listening globally on port 3000
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
 
     println!("🚀 Server started successfully");
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
 
 // basic handler that responds with a static string
@@ -0,0 +1,197 @@
+use std::{
+    collections::HashMap,
+    env,
+    hash::Hash,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::{to_bytes, Body, Bytes, HttpBody},
+    extract::{ConnectInfo, Query, Request, State},
+    http::{header, HeaderValue, Method, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::api::Page;
+
+/// Largest response body kept in the cache; larger ones are served uncached.
+const MAX_CACHED_BODY: usize = 1 << 20;
+/// Clients and cached pages kept before stale entries are swept out.
+const SWEEP_AT: usize = 10_000;
+/// Clients and cached pages kept at most; past it the least recently used
+/// is dropped.
+const MAX_ENTRIES: usize = 2 * SWEEP_AT;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Guards for a publicly exposed instance: only reads are served, every
+/// client address gets a small request budget and responses are cached so
+/// bursts don't reach the database.
+pub struct PublicGuard {
+    per_minute: f64,
+    cache_ttl: Duration,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    cache: Mutex<HashMap<String, (Instant, Bytes)>>,
+}
+
+impl PublicGuard {
+    pub fn from_env() -> Self {
+        Self {
+            per_minute: env::var("PUBLIC_RATE_LIMIT")
+                .unwrap_or("30".to_string())
+                .parse()
+                .unwrap(),
+            cache_ttl: Duration::from_secs(
+                env::var("PUBLIC_CACHE_TTL")
+                    .unwrap_or("60".to_string())
+                    .parse()
+                    .unwrap(),
+            ),
+            buckets: Mutex::new(HashMap::new()),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Token bucket per address that refills `per_minute` tokens a minute.
+    fn allow(&self, ip: IpAddr) -> bool {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= SWEEP_AT && !buckets.contains_key(&ip) {
+            // Untouched for a minute, a bucket is full again anyway
+            buckets.retain(|_, bucket| bucket.updated.elapsed() < Duration::from_secs(60));
+            if buckets.len() >= MAX_ENTRIES {
+                evict(&mut buckets, |bucket| bucket.updated);
+            }
+        }
+
+        let now = Instant::now();
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.per_minute,
+            updated: now,
+        });
+        let refill = now.duration_since(bucket.updated).as_secs_f64() * self.per_minute / 60.0;
+        bucket.tokens = (bucket.tokens + refill).min(self.per_minute);
+        bucket.updated = now;
+
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+
+    fn cached(&self, key: &str) -> Option<Bytes> {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(key)
+            .filter(|(at, _)| at.elapsed() < self.cache_ttl)
+            .map(|(_, body)| body.clone())
+    }
+
+    fn store(&self, key: String, body: Bytes) {
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= SWEEP_AT && !cache.contains_key(&key) {
+            cache.retain(|_, (at, _)| at.elapsed() < self.cache_ttl);
+            if cache.len() >= MAX_ENTRIES {
+                evict(&mut cache, |(at, _)| *at);
+            }
+        }
+        cache.insert(key, (Instant::now(), body));
+    }
+}
+
+/// Drops the entry of `map` that `used` says was used longest ago.
+fn evict<K: Clone + Eq + Hash, V>(map: &mut HashMap<K, V>, used: impl Fn(&V) -> Instant) {
+    let oldest = map
+        .iter()
+        .min_by_key(|(_, value)| used(value))
+        .map(|(key, _)| key.clone());
+    if let Some(key) = oldest {
+        map.remove(&key);
+    }
+}
+
+/// Cache key of an API request: its path and the page it asks for, as the
+/// handlers read it, so other query parameters can't fill the cache. `None`
+/// for queries the handlers would reject.
+fn cache_key(uri: &Uri) -> Option<String> {
+    let page: Query<Page> = Query::try_from_uri(uri).ok()?;
+    match uri.path() {
+        "/api/servers" => Some(format!(
+            "/api/servers?limit={}&offset={}",
+            page.limit(),
+            page.offset()
+        )),
+        path => Some(path.to_string()),
+    }
+}
+
+/// IPv6 clients usually get a whole /64, so they share one budget.
+fn client_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => ip,
+        IpAddr::V6(ip) => {
+            let s = ip.segments();
+            IpAddr::V6([s[0], s[1], s[2], s[3], 0, 0, 0, 0].into())
+        }
+    }
+}
+
+fn json(body: Bytes) -> Response {
+    let mut response = Body::from(body).into_response();
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+pub async fn guard(
+    State(guard): State<Arc<PublicGuard>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    }
+    if !guard.allow(client_key(addr.ip())) {
+        return StatusCode::TOO_MANY_REQUESTS.into_response();
+    }
+
+    if !request.uri().path().starts_with("/api/") || request.method() == Method::HEAD {
+        return next.run(request).await;
+    }
+    let Some(key) = cache_key(request.uri()) else {
+        return next.run(request).await;
+    };
+    if let Some(body) = guard.cached(&key) {
+        return json(body);
+    }
+
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let small = body
+        .size_hint()
+        .upper()
+        .is_some_and(|size| size <= MAX_CACHED_BODY as u64);
+    if !small {
+        return Response::from_parts(parts, body);
+    }
+    match to_bytes(body, MAX_CACHED_BODY).await {
+        Ok(body) => {
+            guard.store(key, body.clone());
+            Response::from_parts(parts, Body::from(body))
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
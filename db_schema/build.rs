@@ -0,0 +1,23 @@
+//! Records the newest migration as the schema version this crate describes.
+
+use std::{env, fs, path::Path};
+
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+
+    // Diesel keeps a migration's directory name up to the first `_`, without
+    // dashes, as its version
+    let version = fs::read_dir("migrations")
+        .expect("migrations directory")
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter_map(|name| Some(name.split_once('_')?.0.replace('-', "")))
+        .max()
+        .expect("no migrations");
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("version.rs");
+    fs::write(
+        out,
+        format!("pub const SCHEMA_VERSION: &str = {:?};\n", version),
+    )
+    .unwrap();
+}
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE servers DROP COLUMN run_id;
+
+ALTER TABLE servers DROP COLUMN discovery_source;
+
+DROP TABLE runs;
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+
+CREATE TABLE runs (
+    id SERIAL PRIMARY KEY,
+    started_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+ALTER TABLE servers ADD COLUMN discovery_source TEXT DEFAULT 'random' NOT NULL;
+
+ALTER TABLE servers ADD COLUMN run_id INTEGER REFERENCES runs(id);
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+
+CREATE TABLE motd_history (
+    id SERIAL PRIMARY KEY,
+    server_id INTEGER NOT NULL,
+    old_value JSONB NOT NULL,
+    new_value JSONB NOT NULL,
+    observed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (server_id) REFERENCES servers(id)
+);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX servers_protocol_idx;
+
+DROP TABLE version_history;
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+CREATE TABLE version_history (
+    id SERIAL PRIMARY KEY,
+    server_id INTEGER NOT NULL,
+    old_version_name TEXT NOT NULL,
+    new_version_name TEXT NOT NULL,
+    old_protocol INTEGER NOT NULL,
+    new_protocol INTEGER NOT NULL,
+    observed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (server_id) REFERENCES servers(id)
+);
+
+CREATE INDEX servers_protocol_idx ON servers (protocol);
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+CREATE TABLE white_list_history (
+    id SERIAL PRIMARY KEY,
+    server_id INTEGER NOT NULL,
+    old_license BOOLEAN NOT NULL,
+    new_license BOOLEAN NOT NULL,
+    old_white_list BOOLEAN,
+    new_white_list BOOLEAN,
+    observed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (server_id) REFERENCES servers(id)
+);
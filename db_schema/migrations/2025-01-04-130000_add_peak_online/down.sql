@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE servers DROP COLUMN peak_online_at;
+
+ALTER TABLE servers DROP COLUMN peak_online;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+ALTER TABLE servers ADD COLUMN peak_online INTEGER DEFAULT 0 NOT NULL;
+
+ALTER TABLE servers ADD COLUMN peak_online_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL;
+
+UPDATE servers SET peak_online = online, peak_online_at = last_seen;
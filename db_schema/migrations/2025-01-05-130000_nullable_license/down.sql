@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+
+UPDATE white_list_history SET old_license = FALSE WHERE old_license IS NULL;
+
+ALTER TABLE white_list_history ALTER COLUMN old_license SET NOT NULL;
+
+UPDATE servers SET license = FALSE WHERE license IS NULL;
+
+ALTER TABLE servers ALTER COLUMN license SET NOT NULL;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+ALTER TABLE servers ALTER COLUMN license DROP NOT NULL;
+
+ALTER TABLE white_list_history ALTER COLUMN old_license DROP NOT NULL;
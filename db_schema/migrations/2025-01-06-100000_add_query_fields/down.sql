@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE servers DROP COLUMN query_host_port;
+
+ALTER TABLE servers DROP COLUMN query_game_id;
+
+ALTER TABLE servers DROP COLUMN query_game_type;
+
+ALTER TABLE servers DROP COLUMN query_map;
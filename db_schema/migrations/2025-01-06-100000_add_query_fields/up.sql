@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+ALTER TABLE servers ADD COLUMN query_map TEXT;
+
+ALTER TABLE servers ADD COLUMN query_game_type TEXT;
+
+ALTER TABLE servers ADD COLUMN query_game_id TEXT;
+
+ALTER TABLE servers ADD COLUMN query_host_port INTEGER;
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+CREATE TABLE virtual_hosts (
+    id SERIAL PRIMARY KEY,
+    server_id INTEGER NOT NULL,
+    hostname TEXT NOT NULL,
+    version_name TEXT NOT NULL,
+    description JSONB NOT NULL,
+    differs_from_ip BOOLEAN NOT NULL,
+    observed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (server_id) REFERENCES servers(id),
+    UNIQUE (server_id, hostname)
+);
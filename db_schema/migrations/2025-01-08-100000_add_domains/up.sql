@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+CREATE TABLE domains (
+    id SERIAL PRIMARY KEY,
+    domain TEXT NOT NULL UNIQUE,
+    ip TEXT,
+    port INTEGER,
+    srv BOOLEAN DEFAULT FALSE NOT NULL,
+    reachable BOOLEAN DEFAULT FALSE NOT NULL,
+    version_name TEXT,
+    server_id INTEGER,
+    last_checked TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (server_id) REFERENCES servers(id)
+);
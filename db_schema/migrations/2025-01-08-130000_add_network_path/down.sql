@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE servers DROP COLUMN hop_count;
+ALTER TABLE servers DROP COLUMN last_hop_network;
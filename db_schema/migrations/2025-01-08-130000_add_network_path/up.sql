@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE servers ADD COLUMN hop_count INTEGER;
+ALTER TABLE servers ADD COLUMN last_hop_network TEXT;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE runs DROP COLUMN bytes_sent;
+ALTER TABLE runs DROP COLUMN bytes_received;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE runs ADD COLUMN bytes_sent BIGINT DEFAULT 0 NOT NULL;
+ALTER TABLE runs ADD COLUMN bytes_received BIGINT DEFAULT 0 NOT NULL;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE runs DROP COLUMN finished_at;
+ALTER TABLE runs DROP COLUMN summary;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE runs ADD COLUMN finished_at TIMESTAMP;
+ALTER TABLE runs ADD COLUMN summary JSONB;
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+
+CREATE TABLE service_fingerprints (
+    id SERIAL PRIMARY KEY,
+    ip TEXT NOT NULL,
+    port INTEGER NOT NULL,
+    protocol TEXT NOT NULL,
+    banner BYTEA NOT NULL,
+    observed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    UNIQUE (ip, port)
+);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE servers ADD COLUMN raw_status TEXT;
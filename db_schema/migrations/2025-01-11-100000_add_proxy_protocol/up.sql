@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE servers ADD COLUMN proxy_protocol BOOLEAN DEFAULT FALSE NOT NULL;
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+CREATE TABLE saved_servers (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    address TEXT NOT NULL,
+    ip TEXT,
+    port INTEGER,
+    reachable BOOLEAN DEFAULT FALSE NOT NULL,
+    server_id INTEGER,
+    last_checked TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    UNIQUE (name, address),
+    FOREIGN KEY (server_id) REFERENCES servers(id)
+);
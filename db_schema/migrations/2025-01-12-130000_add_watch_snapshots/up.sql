@@ -0,0 +1,17 @@
+-- Your SQL goes here
+
+CREATE TABLE watch_snapshots (
+    id SERIAL PRIMARY KEY,
+    address TEXT NOT NULL,
+    server_id INTEGER,
+    reachable BOOLEAN NOT NULL,
+    online INTEGER,
+    max INTEGER,
+    version_name TEXT,
+    players TEXT[] DEFAULT '{}' NOT NULL,
+    latency_ms INTEGER,
+    taken_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (server_id) REFERENCES servers(id)
+);
+
+CREATE INDEX watch_snapshots_address_taken_at ON watch_snapshots (address, taken_at);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE servers ADD COLUMN discovered_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+CREATE TABLE server_activity (
+    server_id INTEGER PRIMARY KEY,
+    online_sum FLOAT8[] NOT NULL,
+    samples INTEGER[] NOT NULL,
+    average_online FLOAT8[] NOT NULL,
+    peak_hour INTEGER NOT NULL,
+    peak_online FLOAT8 NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (server_id) REFERENCES servers(id)
+);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE server_activity DROP COLUMN region;
+ALTER TABLE server_activity DROP COLUMN utc_offset;
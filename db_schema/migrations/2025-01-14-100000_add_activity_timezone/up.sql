@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE server_activity ADD COLUMN utc_offset INTEGER;
+ALTER TABLE server_activity ADD COLUMN region TEXT;
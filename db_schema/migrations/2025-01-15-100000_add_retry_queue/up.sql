@@ -0,0 +1,15 @@
+-- Your SQL goes here
+
+CREATE TABLE retry_queue (
+    id SERIAL PRIMARY KEY,
+    ip TEXT NOT NULL,
+    port INTEGER NOT NULL,
+    discovery_source TEXT NOT NULL,
+    attempts INTEGER DEFAULT 0 NOT NULL,
+    last_error TEXT NOT NULL,
+    next_attempt_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    UNIQUE (ip, port)
+);
+
+CREATE INDEX retry_queue_next_attempt_at ON retry_queue (next_attempt_at);
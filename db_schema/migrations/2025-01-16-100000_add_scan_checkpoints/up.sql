@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+CREATE TABLE scan_checkpoints (
+    name TEXT PRIMARY KEY,
+    multiplier BIGINT NOT NULL,
+    increment BIGINT NOT NULL,
+    position BIGINT NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
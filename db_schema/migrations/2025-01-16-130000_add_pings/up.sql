@@ -0,0 +1,15 @@
+-- Your SQL goes here
+
+CREATE TABLE pings (
+    id SERIAL PRIMARY KEY,
+    server_id INTEGER NOT NULL,
+    online INTEGER NOT NULL,
+    max INTEGER NOT NULL,
+    version_name TEXT NOT NULL,
+    protocol INTEGER NOT NULL,
+    latency_ms INTEGER,
+    checked_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (server_id) REFERENCES servers(id)
+);
+
+CREATE INDEX pings_server_id_checked_at ON pings (server_id, checked_at);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE servers DROP CONSTRAINT servers_ip_edition_key;
+ALTER TABLE servers ADD CONSTRAINT servers_ip_key UNIQUE (ip);
+ALTER TABLE servers DROP COLUMN edition;
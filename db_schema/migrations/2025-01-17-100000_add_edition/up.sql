@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+ALTER TABLE servers ADD COLUMN edition TEXT NOT NULL DEFAULT 'java';
+
+-- A Java and a Bedrock server can share an address
+ALTER TABLE servers DROP CONSTRAINT servers_ip_key;
+ALTER TABLE servers ADD CONSTRAINT servers_ip_edition_key UNIQUE (ip, edition);
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE mods;
+ALTER TABLE servers DROP COLUMN software;
+ALTER TABLE servers DROP COLUMN motd_plain;
+ALTER TABLE servers DROP COLUMN motd;
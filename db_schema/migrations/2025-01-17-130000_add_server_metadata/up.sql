@@ -0,0 +1,19 @@
+-- Your SQL goes here
+
+-- MOTD with `§` codes and as plain text, filled for known servers by the next update pass
+ALTER TABLE servers ADD COLUMN motd TEXT;
+ALTER TABLE servers ADD COLUMN motd_plain TEXT;
+-- vanilla, paper, forge, fabric, velocity, ...
+ALTER TABLE servers ADD COLUMN software TEXT;
+
+CREATE TABLE mods (
+    id SERIAL PRIMARY KEY,
+    server_id INTEGER NOT NULL,
+    mod_id TEXT NOT NULL,
+    version TEXT,
+    -- status, handshake, registry or query
+    source TEXT NOT NULL,
+    last_seen TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (server_id) REFERENCES servers(id),
+    UNIQUE (server_id, mod_id)
+);
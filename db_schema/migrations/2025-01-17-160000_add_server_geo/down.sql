@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX servers_geo_pending;
+ALTER TABLE servers DROP COLUMN geo_checked_at;
+ALTER TABLE servers DROP COLUMN isp;
+ALTER TABLE servers DROP COLUMN asn;
+ALTER TABLE servers DROP COLUMN city;
+ALTER TABLE servers DROP COLUMN country;
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+
+-- Filled from the GeoIP databases by the enrichment task, which also backfills older rows
+ALTER TABLE servers ADD COLUMN country TEXT;
+ALTER TABLE servers ADD COLUMN city TEXT;
+ALTER TABLE servers ADD COLUMN asn BIGINT;
+ALTER TABLE servers ADD COLUMN isp TEXT;
+-- NULL until looked up, whether or not the address was found
+ALTER TABLE servers ADD COLUMN geo_checked_at TIMESTAMP;
+
+CREATE INDEX servers_geo_pending ON servers (id) WHERE geo_checked_at IS NULL;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+CREATE TABLE liveness (
+    id SERIAL PRIMARY KEY,
+    server_id INTEGER NOT NULL,
+    reachable BOOLEAN NOT NULL,
+    latency_ms INTEGER,
+    checked_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (server_id) REFERENCES servers(id)
+);
+
+CREATE INDEX liveness_server_id_checked_at ON liveness (server_id, checked_at);
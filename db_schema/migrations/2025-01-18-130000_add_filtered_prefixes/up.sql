@@ -0,0 +1,8 @@
+-- Your SQL goes here
+
+CREATE TABLE filtered_prefixes (
+    prefix TEXT PRIMARY KEY,
+    probes INTEGER NOT NULL DEFAULT 0,
+    filtered INTEGER NOT NULL DEFAULT 0,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
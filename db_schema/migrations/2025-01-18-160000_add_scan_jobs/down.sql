@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE scan_job_servers;
+DROP TABLE scan_jobs;
@@ -0,0 +1,22 @@
+-- Your SQL goes here
+
+CREATE TABLE scan_jobs (
+    id SERIAL PRIMARY KEY,
+    spec JSONB NOT NULL,
+    status TEXT NOT NULL DEFAULT 'queued',
+    total INTEGER NOT NULL,
+    probed INTEGER NOT NULL DEFAULT 0,
+    found INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    started_at TIMESTAMP,
+    finished_at TIMESTAMP
+);
+
+CREATE TABLE scan_job_servers (
+    job_id INTEGER NOT NULL,
+    server_id INTEGER NOT NULL,
+    PRIMARY KEY (job_id, server_id),
+    FOREIGN KEY (job_id) REFERENCES scan_jobs(id),
+    FOREIGN KEY (server_id) REFERENCES servers(id)
+);
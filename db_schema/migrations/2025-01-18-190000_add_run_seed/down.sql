@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE runs DROP COLUMN position;
+ALTER TABLE runs DROP COLUMN seed;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE runs ADD COLUMN seed BIGINT;
+ALTER TABLE runs ADD COLUMN position BIGINT;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE servers ADD COLUMN anomalous_response BOOLEAN NOT NULL DEFAULT FALSE;
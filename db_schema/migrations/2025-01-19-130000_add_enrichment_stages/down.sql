@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE mojang_profiles;
+ALTER TABLE servers DROP COLUMN rdns_checked_at;
+ALTER TABLE servers DROP COLUMN rdns;
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+
+ALTER TABLE servers ADD COLUMN rdns TEXT;
+ALTER TABLE servers ADD COLUMN rdns_checked_at TIMESTAMP;
+
+CREATE TABLE mojang_profiles (
+    uuid TEXT PRIMARY KEY,
+    premium BOOLEAN NOT NULL,
+    checked_at TIMESTAMP NOT NULL
+);
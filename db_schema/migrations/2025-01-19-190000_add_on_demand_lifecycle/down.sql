@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE servers DROP COLUMN lifecycle_checked_at;
+ALTER TABLE servers DROP COLUMN lifecycle_changed_at;
+ALTER TABLE servers DROP COLUMN lifecycle;
+ALTER TABLE servers DROP COLUMN on_demand_host;
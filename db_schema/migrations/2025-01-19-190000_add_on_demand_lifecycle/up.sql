@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+ALTER TABLE servers ADD COLUMN on_demand_host TEXT;
+ALTER TABLE servers ADD COLUMN lifecycle TEXT;
+ALTER TABLE servers ADD COLUMN lifecycle_changed_at TIMESTAMP;
+ALTER TABLE servers ADD COLUMN lifecycle_checked_at TIMESTAMP;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE servers DROP COLUMN modpack_version;
+ALTER TABLE servers DROP COLUMN modpack;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- Identified from the stored mods by modpacks.json of the worker
+ALTER TABLE servers ADD COLUMN modpack TEXT;
+ALTER TABLE servers ADD COLUMN modpack_version TEXT;
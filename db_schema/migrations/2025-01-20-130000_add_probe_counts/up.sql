@@ -0,0 +1,10 @@
+-- Your SQL goes here
+
+-- Probes sent to each address per day, shared by every probing path
+CREATE TABLE probe_counts (
+    target TEXT NOT NULL,
+    day DATE NOT NULL,
+    probes INTEGER DEFAULT 0 NOT NULL,
+    refused INTEGER DEFAULT 0 NOT NULL,
+    PRIMARY KEY (target, day)
+);
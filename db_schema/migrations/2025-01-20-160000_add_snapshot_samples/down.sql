@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE liveness DROP COLUMN samples;
+ALTER TABLE pings DROP COLUMN samples;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- Raw snapshots a row stands for once compaction merged it
+ALTER TABLE pings ADD COLUMN samples INTEGER DEFAULT 1 NOT NULL;
+ALTER TABLE liveness ADD COLUMN samples INTEGER DEFAULT 1 NOT NULL;
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE servers DROP COLUMN block_kind;
+ALTER TABLE servers DROP COLUMN blocked_at;
+ALTER TABLE servers DROP COLUMN rejections;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+-- Servers that stopped answering this scanner while answering before
+ALTER TABLE servers ADD COLUMN rejections INTEGER DEFAULT 0 NOT NULL;
+ALTER TABLE servers ADD COLUMN blocked_at TIMESTAMP;
+ALTER TABLE servers ADD COLUMN block_kind TEXT;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+CREATE VIEW schema_version AS
+SELECT max(version) AS version FROM __diesel_schema_migrations;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+-- Pre-1.7 protocol era of servers that only answered an old handshake
+ALTER TABLE servers ADD COLUMN era TEXT;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE pipeline_targets;
+DROP TABLE pipeline_runs;
@@ -0,0 +1,24 @@
+-- Your SQL goes here
+
+CREATE TABLE pipeline_runs (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    -- Last stage finished: import, probe, export or notify
+    stage TEXT,
+    started_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    finished_at TIMESTAMP
+);
+
+CREATE INDEX pipeline_runs_name ON pipeline_runs (name, id);
+
+-- Addresses of the import stage and what probing them found
+CREATE TABLE pipeline_targets (
+    pipeline_run_id INTEGER NOT NULL,
+    ip TEXT NOT NULL,
+    port INTEGER NOT NULL,
+    probed BOOLEAN DEFAULT FALSE NOT NULL,
+    server_id INTEGER,
+    PRIMARY KEY (pipeline_run_id, ip, port),
+    FOREIGN KEY (pipeline_run_id) REFERENCES pipeline_runs(id) ON DELETE CASCADE,
+    FOREIGN KEY (server_id) REFERENCES servers(id) ON DELETE SET NULL
+);
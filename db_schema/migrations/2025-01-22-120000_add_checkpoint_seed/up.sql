@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+-- Seed the sweep order was derived from, so resumed runs record it
+ALTER TABLE scan_checkpoints ADD COLUMN seed BIGINT;
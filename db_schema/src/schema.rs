@@ -1,5 +1,102 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    domains (id) {
+        id -> Int4,
+        domain -> Text,
+        ip -> Nullable<Text>,
+        port -> Nullable<Int4>,
+        srv -> Bool,
+        reachable -> Bool,
+        version_name -> Nullable<Text>,
+        server_id -> Nullable<Int4>,
+        last_checked -> Timestamp,
+    }
+}
+
+diesel::table! {
+    filtered_prefixes (prefix) {
+        prefix -> Text,
+        probes -> Int4,
+        filtered -> Int4,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    liveness (id) {
+        id -> Int4,
+        server_id -> Int4,
+        reachable -> Bool,
+        latency_ms -> Nullable<Int4>,
+        checked_at -> Timestamp,
+        samples -> Int4,
+    }
+}
+
+diesel::table! {
+    mods (id) {
+        id -> Int4,
+        server_id -> Int4,
+        mod_id -> Text,
+        version -> Nullable<Text>,
+        source -> Text,
+        last_seen -> Timestamp,
+    }
+}
+
+diesel::table! {
+    mojang_profiles (uuid) {
+        uuid -> Text,
+        premium -> Bool,
+        checked_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    motd_history (id) {
+        id -> Int4,
+        server_id -> Int4,
+        old_value -> Jsonb,
+        new_value -> Jsonb,
+        observed_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    pings (id) {
+        id -> Int4,
+        server_id -> Int4,
+        online -> Int4,
+        max -> Int4,
+        version_name -> Text,
+        protocol -> Int4,
+        latency_ms -> Nullable<Int4>,
+        checked_at -> Timestamp,
+        samples -> Int4,
+    }
+}
+
+diesel::table! {
+    pipeline_runs (id) {
+        id -> Int4,
+        name -> Text,
+        stage -> Nullable<Text>,
+        started_at -> Timestamp,
+        finished_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    pipeline_targets (pipeline_run_id, ip, port) {
+        pipeline_run_id -> Int4,
+        ip -> Text,
+        port -> Int4,
+        probed -> Bool,
+        server_id -> Nullable<Int4>,
+    }
+}
+
 diesel::table! {
     players (id) {
         id -> Int4,
@@ -10,6 +107,101 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    probe_counts (target, day) {
+        target -> Text,
+        day -> Date,
+        probes -> Int4,
+        refused -> Int4,
+    }
+}
+
+diesel::table! {
+    retry_queue (id) {
+        id -> Int4,
+        ip -> Text,
+        port -> Int4,
+        discovery_source -> Text,
+        attempts -> Int4,
+        last_error -> Text,
+        next_attempt_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    runs (id) {
+        id -> Int4,
+        started_at -> Timestamp,
+        bytes_sent -> Int8,
+        bytes_received -> Int8,
+        finished_at -> Nullable<Timestamp>,
+        summary -> Nullable<Jsonb>,
+        seed -> Nullable<Int8>,
+        position -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    saved_servers (id) {
+        id -> Int4,
+        name -> Text,
+        address -> Text,
+        ip -> Nullable<Text>,
+        port -> Nullable<Int4>,
+        reachable -> Bool,
+        server_id -> Nullable<Int4>,
+        last_checked -> Timestamp,
+    }
+}
+
+diesel::table! {
+    scan_checkpoints (name) {
+        name -> Text,
+        multiplier -> Int8,
+        increment -> Int8,
+        position -> Int8,
+        updated_at -> Timestamp,
+        seed -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    scan_job_servers (job_id, server_id) {
+        job_id -> Int4,
+        server_id -> Int4,
+    }
+}
+
+diesel::table! {
+    scan_jobs (id) {
+        id -> Int4,
+        spec -> Jsonb,
+        status -> Text,
+        total -> Int4,
+        probed -> Int4,
+        found -> Int4,
+        error -> Nullable<Text>,
+        created_at -> Timestamp,
+        started_at -> Nullable<Timestamp>,
+        finished_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    server_activity (server_id) {
+        server_id -> Int4,
+        online_sum -> Array<Float8>,
+        samples -> Array<Int4>,
+        average_online -> Array<Float8>,
+        peak_hour -> Int4,
+        peak_online -> Float8,
+        updated_at -> Timestamp,
+        utc_offset -> Nullable<Int4>,
+        region -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     servers (id) {
         id -> Int4,
@@ -18,16 +210,158 @@ diesel::table! {
         max -> Int4,
         version_name -> Text,
         protocol -> Int4,
-        license -> Bool,
+        license -> Nullable<Bool>,
         white_list -> Nullable<Bool>,
         last_seen -> Timestamp,
         description -> Jsonb,
+        discovery_source -> Text,
+        run_id -> Nullable<Int4>,
+        peak_online -> Int4,
+        peak_online_at -> Timestamp,
+        tags -> Array<Text>,
+        query_map -> Nullable<Text>,
+        query_game_type -> Nullable<Text>,
+        query_game_id -> Nullable<Text>,
+        query_host_port -> Nullable<Int4>,
+        tps -> Nullable<Float4>,
+        queue_server -> Bool,
+        hop_count -> Nullable<Int4>,
+        last_hop_network -> Nullable<Text>,
+        raw_status -> Nullable<Text>,
+        proxy_protocol -> Bool,
+        favicon -> Nullable<Text>,
+        discovered_at -> Timestamp,
+        canonical_id -> Nullable<Int4>,
+        appeal_contact -> Nullable<Text>,
+        edition -> Text,
+        motd -> Nullable<Text>,
+        motd_plain -> Nullable<Text>,
+        software -> Nullable<Text>,
+        country -> Nullable<Text>,
+        city -> Nullable<Text>,
+        asn -> Nullable<Int8>,
+        isp -> Nullable<Text>,
+        geo_checked_at -> Nullable<Timestamp>,
+        anomalous_response -> Bool,
+        rdns -> Nullable<Text>,
+        rdns_checked_at -> Nullable<Timestamp>,
+        status_class -> Nullable<Text>,
+        on_demand_host -> Nullable<Text>,
+        lifecycle -> Nullable<Text>,
+        lifecycle_changed_at -> Nullable<Timestamp>,
+        lifecycle_checked_at -> Nullable<Timestamp>,
+        modpack -> Nullable<Text>,
+        modpack_version -> Nullable<Text>,
+        rejections -> Int4,
+        blocked_at -> Nullable<Timestamp>,
+        block_kind -> Nullable<Text>,
+        era -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    service_fingerprints (id) {
+        id -> Int4,
+        ip -> Text,
+        port -> Int4,
+        protocol -> Text,
+        banner -> Bytea,
+        observed_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    version_history (id) {
+        id -> Int4,
+        server_id -> Int4,
+        old_version_name -> Text,
+        new_version_name -> Text,
+        old_protocol -> Int4,
+        new_protocol -> Int4,
+        observed_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    virtual_hosts (id) {
+        id -> Int4,
+        server_id -> Int4,
+        hostname -> Text,
+        version_name -> Text,
+        description -> Jsonb,
+        differs_from_ip -> Bool,
+        observed_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    watch_snapshots (id) {
+        id -> Int4,
+        address -> Text,
+        server_id -> Nullable<Int4>,
+        reachable -> Bool,
+        online -> Nullable<Int4>,
+        max -> Nullable<Int4>,
+        version_name -> Nullable<Text>,
+        players -> Array<Text>,
+        latency_ms -> Nullable<Int4>,
+        taken_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    white_list_history (id) {
+        id -> Int4,
+        server_id -> Int4,
+        old_license -> Nullable<Bool>,
+        new_license -> Bool,
+        old_white_list -> Nullable<Bool>,
+        new_white_list -> Nullable<Bool>,
+        observed_at -> Timestamp,
     }
 }
 
+diesel::joinable!(domains -> servers (server_id));
+diesel::joinable!(liveness -> servers (server_id));
+diesel::joinable!(mods -> servers (server_id));
+diesel::joinable!(motd_history -> servers (server_id));
+diesel::joinable!(pings -> servers (server_id));
+diesel::joinable!(pipeline_targets -> pipeline_runs (pipeline_run_id));
+diesel::joinable!(pipeline_targets -> servers (server_id));
 diesel::joinable!(players -> servers (server_id));
+diesel::joinable!(saved_servers -> servers (server_id));
+diesel::joinable!(scan_job_servers -> scan_jobs (job_id));
+diesel::joinable!(scan_job_servers -> servers (server_id));
+diesel::joinable!(server_activity -> servers (server_id));
+diesel::joinable!(servers -> runs (run_id));
+diesel::joinable!(version_history -> servers (server_id));
+diesel::joinable!(virtual_hosts -> servers (server_id));
+diesel::joinable!(watch_snapshots -> servers (server_id));
+diesel::joinable!(white_list_history -> servers (server_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    domains,
+    filtered_prefixes,
+    liveness,
+    mods,
+    mojang_profiles,
+    motd_history,
+    pings,
+    pipeline_runs,
+    pipeline_targets,
     players,
+    probe_counts,
+    retry_queue,
+    runs,
+    saved_servers,
+    scan_checkpoints,
+    scan_job_servers,
+    scan_jobs,
+    server_activity,
     servers,
+    service_fingerprints,
+    version_history,
+    virtual_hosts,
+    watch_snapshots,
+    white_list_history,
 );
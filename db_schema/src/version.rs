@@ -0,0 +1,43 @@
+use std::cmp::Ordering;
+
+use diesel::{sql_query, sql_types::Text, PgConnection, QueryableByName, RunQueryDsl};
+
+include!(concat!(env!("OUT_DIR"), "/version.rs"));
+
+#[derive(QueryableByName)]
+struct Version {
+    #[diesel(sql_type = Text)]
+    version: String,
+}
+
+/// How the live schema compares to the migrations this build was made with.
+pub enum Compatibility {
+    Current,
+    /// The database has migrations this binary doesn't know about yet
+    Newer(String),
+}
+
+/// Compares `schema_version` with [`SCHEMA_VERSION`], refusing a database
+/// that misses migrations with a hint on how to apply them.
+pub fn check(conn: &mut PgConnection) -> Result<Compatibility, String> {
+    let live = sql_query("SELECT version FROM schema_version")
+        .get_result::<Version>(conn)
+        .map(|row| row.version)
+        .map_err(|_| {
+            format!(
+                "the database has no schema_version, its schema predates migration {}; \
+                 run `diesel migration run` in db_schema",
+                SCHEMA_VERSION
+            )
+        })?;
+
+    match live.as_str().cmp(SCHEMA_VERSION) {
+        Ordering::Less => Err(format!(
+            "the database schema is at migration {} but this build expects {}; \
+             run `diesel migration run` in db_schema",
+            live, SCHEMA_VERSION
+        )),
+        Ordering::Equal => Ok(Compatibility::Current),
+        Ordering::Greater => Ok(Compatibility::Newer(live)),
+    }
+}
@@ -0,0 +1,219 @@
+//! Generates the packet id tables of the `protocol` module from the data in
+//! `protocol/`, or straight from a minecraft-data checkout when
+//! `MINECRAFT_DATA_DIR` points at one.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+/// A packet the scanner needs the id of, and that id per protocol number.
+struct Packet {
+    function: String,
+    state: String,
+    direction: String,
+    name: String,
+    ids: BTreeMap<i32, i32>,
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=protocol");
+    println!("cargo:rerun-if-env-changed=MINECRAFT_DATA_DIR");
+
+    let mut packets = read_packets(Path::new("protocol/packets.json"));
+    let versions = match env::var("MINECRAFT_DATA_DIR") {
+        Ok(dir) => {
+            println!("cargo:rerun-if-changed={}", dir);
+            from_minecraft_data(Path::new(&dir), &mut packets)
+        }
+        Err(_) => read_versions(Path::new("protocol/protocolVersions.json")),
+    };
+    let latest = *versions.last().expect("no protocol versions");
+
+    let mut out = String::from("// Generated by build.rs, edit protocol/*.json instead\n\n");
+    writeln!(out, "/// Newest protocol number with known packet ids.").unwrap();
+    writeln!(out, "pub const LATEST: i32 = {};", latest).unwrap();
+
+    for packet in &packets {
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "/// Id of the `{}` {} `{}` packet.",
+            packet.state, packet.direction, packet.name
+        )
+        .unwrap();
+        let ranges = ranges(&versions, &packet.ids);
+        if ranges.is_empty() {
+            // Not in any known version, like configuration packets in old dumps
+            writeln!(
+                out,
+                "pub fn {}(_protocol: i32) -> Option<i32> {{",
+                packet.function
+            )
+            .unwrap();
+            writeln!(out, "    None").unwrap();
+            writeln!(out, "}}").unwrap();
+            continue;
+        }
+
+        writeln!(
+            out,
+            "pub fn {}(protocol: i32) -> Option<i32> {{",
+            packet.function
+        )
+        .unwrap();
+        writeln!(out, "    Some(match protocol {{").unwrap();
+        for (first, last, id) in ranges {
+            if first == last {
+                writeln!(out, "        {} => 0x{:02X},", first, id).unwrap();
+            } else {
+                writeln!(out, "        {}..={} => 0x{:02X},", first, last, id).unwrap();
+            }
+        }
+        writeln!(out, "        _ => return None,").unwrap();
+        writeln!(out, "    }})").unwrap();
+        writeln!(out, "}}").unwrap();
+    }
+
+    let path = PathBuf::from(env::var("OUT_DIR").unwrap()).join("protocol.rs");
+    fs::write(path, out).unwrap();
+}
+
+fn read_json(path: &Path) -> Value {
+    let text = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Could not read {}: {}", path.display(), e));
+    serde_json::from_str(&text)
+        .unwrap_or_else(|e| panic!("Could not parse {}: {}", path.display(), e))
+}
+
+fn parse_id(value: &str) -> i32 {
+    i32::from_str_radix(value.trim_start_matches("0x"), 16)
+        .unwrap_or_else(|_| panic!("Bad packet id {}", value))
+}
+
+/// Sorted protocol numbers of a minecraft-data `protocolVersions.json`.
+fn read_versions(path: &Path) -> Vec<i32> {
+    let mut versions: Vec<i32> = read_json(path)
+        .as_array()
+        .expect("protocolVersions.json is not an array")
+        .iter()
+        .filter_map(|v| v["version"].as_i64())
+        .map(|v| v as i32)
+        .collect();
+    versions.sort_unstable();
+    versions.dedup();
+    versions
+}
+
+fn read_packets(path: &Path) -> Vec<Packet> {
+    let field = |packet: &Value, key: &str| {
+        packet[key]
+            .as_str()
+            .unwrap_or_else(|| panic!("Packet without {}", key))
+            .to_string()
+    };
+
+    read_json(path)
+        .as_array()
+        .expect("packets.json is not an array")
+        .iter()
+        .map(|packet| Packet {
+            function: field(packet, "function"),
+            state: field(packet, "state"),
+            direction: field(packet, "direction"),
+            name: field(packet, "name"),
+            ids: packet["ids"]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(protocol, id)| {
+                    let protocol = protocol.parse().expect("protocol number");
+                    (protocol, parse_id(id.as_str().expect("packet id")))
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Replaces the bundled ids with the ones of the minecraft-data protocol dumps
+/// of every release with netty networking, returning those protocol numbers.
+fn from_minecraft_data(dir: &Path, packets: &mut [Packet]) -> Vec<i32> {
+    let data = dir.join("data");
+    let paths = read_json(&data.join("dataPaths.json"));
+    let numbers = read_json(&data.join("pc/common/protocolVersions.json"));
+
+    let mut dumps: BTreeMap<String, Value> = BTreeMap::new();
+    let mut versions = Vec::new();
+    for packet in packets.iter_mut() {
+        packet.ids.clear();
+    }
+
+    for (name, entry) in paths["pc"].as_object().expect("dataPaths.json has no pc") {
+        let Some(dump) = entry["protocol"].as_str() else {
+            continue;
+        };
+        let Some(protocol) = numbers
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|v| v["minecraftVersion"].as_str() == Some(name))
+            .and_then(|v| v["version"].as_i64())
+            .map(|v| v as i32)
+        else {
+            continue;
+        };
+        // Versions before 1.8 share no packet layout with the scanner
+        if protocol < 47 || versions.contains(&protocol) {
+            continue;
+        }
+        versions.push(protocol);
+
+        let dump = dumps
+            .entry(dump.to_string())
+            .or_insert_with(|| read_json(&data.join(dump).join("protocol.json")));
+        for packet in packets.iter_mut() {
+            if let Some(id) = packet_id(dump, &packet.state, &packet.direction, &packet.name) {
+                packet.ids.insert(protocol, id);
+            }
+        }
+    }
+
+    versions.sort_unstable();
+    versions
+}
+
+/// Id of a named packet in a minecraft-data `protocol.json`, read from the
+/// mapper of the packet container's `name` field.
+fn packet_id(dump: &Value, state: &str, direction: &str, name: &str) -> Option<i32> {
+    let fields = dump[state][direction]["types"]["packet"][1].as_array()?;
+    let mapper = fields.iter().find(|f| f["name"] == "name")?;
+    mapper["type"][1]["mappings"]
+        .as_object()?
+        .iter()
+        .find(|(_, packet)| packet.as_str() == Some(name))
+        .map(|(id, _)| parse_id(id))
+}
+
+/// Runs of consecutive known protocol numbers sharing a packet id.
+fn ranges(versions: &[i32], ids: &BTreeMap<i32, i32>) -> Vec<(i32, i32, i32)> {
+    let mut ranges: Vec<(i32, i32, i32)> = Vec::new();
+    let mut previous: Option<i32> = None;
+
+    for &protocol in versions {
+        let id = ids.get(&protocol).copied();
+        match (ranges.last_mut(), id) {
+            (Some(last), Some(id)) if previous == Some(last.1) && last.2 == id => {
+                last.1 = protocol;
+            }
+            (_, Some(id)) => ranges.push((protocol, protocol, id)),
+            (_, None) => {}
+        }
+        previous = Some(protocol);
+    }
+    ranges
+}
@@ -0,0 +1,141 @@
+use chrono::{Local, Timelike, Utc};
+use diesel::{
+    dsl::insert_into, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl, SelectableHelper,
+};
+
+use crate::{database::ServerActivityModel, schema, Context};
+
+/// Typical share of the daily peak online per local hour on game servers:
+/// quiet in the early morning, busiest in the evening.
+const LOCAL_PROFILE: [f64; 24] = [
+    0.55, 0.40, 0.30, 0.22, 0.18, 0.17, 0.20, 0.25, 0.30, 0.35, 0.40, 0.45, 0.50, 0.55, 0.60, 0.65,
+    0.72, 0.80, 0.90, 0.97, 1.00, 0.95, 0.85, 0.70,
+];
+/// Hours of the day that need samples before the curve is trusted.
+const MIN_HOURS: usize = 16;
+/// Correlation with the shifted profile below which no timezone is guessed.
+const MIN_CORRELATION: f64 = 0.5;
+
+/// Adds the online count seen now to the server's hourly population curve.
+pub async fn record(server_id: i32, online: i32, ctx: &Context) {
+    let hour = Utc::now().hour() as usize;
+    let mut conn = ctx.pool().get().await;
+
+    let mut activity = schema::server_activity::dsl::server_activity
+        .filter(schema::server_activity::dsl::server_id.eq(server_id))
+        .select(ServerActivityModel::as_select())
+        .first(&mut conn.conn)
+        .optional()
+        .unwrap()
+        .unwrap_or_else(|| ServerActivityModel {
+            server_id,
+            online_sum: vec![0.0; 24],
+            samples: vec![0; 24],
+            average_online: vec![0.0; 24],
+            peak_hour: 0,
+            peak_online: 0.0,
+            updated_at: Local::now().naive_local(),
+            utc_offset: None,
+            region: None,
+        });
+
+    add_sample(&mut activity, hour, online);
+    activity.updated_at = Local::now().naive_local().with_nanosecond(0).unwrap();
+
+    insert_into(schema::server_activity::dsl::server_activity)
+        .values(&activity)
+        .on_conflict(schema::server_activity::dsl::server_id)
+        .do_update()
+        .set(&activity)
+        .execute(&mut conn.conn)
+        .unwrap();
+}
+
+fn add_sample(activity: &mut ServerActivityModel, hour: usize, online: i32) {
+    // Rows written by other versions may hold fewer hours
+    activity.online_sum.resize(24, 0.0);
+    activity.samples.resize(24, 0);
+
+    activity.online_sum[hour] += online as f64;
+    activity.samples[hour] += 1;
+
+    activity.average_online = activity
+        .online_sum
+        .iter()
+        .zip(&activity.samples)
+        .map(|(sum, samples)| {
+            if *samples > 0 {
+                sum / *samples as f64
+            } else {
+                0.0
+            }
+        })
+        .collect();
+
+    let mut peak_hour = 0;
+    for (hour, average) in activity.average_online.iter().enumerate() {
+        if *average > activity.average_online[peak_hour] {
+            peak_hour = hour;
+        }
+    }
+    let peak_online = activity.average_online[peak_hour];
+    activity.peak_hour = peak_hour as i32;
+    activity.peak_online = peak_online;
+
+    activity.utc_offset = infer_utc_offset(&activity.average_online, &activity.samples);
+    activity.region = activity.utc_offset.map(|offset| region(offset).to_string());
+}
+
+/// Finds the UTC offset under which the curve best matches `LOCAL_PROFILE`.
+/// Hosting location says little about where players are, so this complements
+/// GeoIP for networks hosted abroad.
+fn infer_utc_offset(average: &[f64], samples: &[i32]) -> Option<i32> {
+    let hours: Vec<usize> = (0..24).filter(|h| samples[*h] > 0).collect();
+    if hours.len() < MIN_HOURS {
+        return None;
+    }
+
+    let (offset, correlation) = (-11..=14)
+        .map(|offset| {
+            let expected: Vec<f64> = hours
+                .iter()
+                .map(|h| LOCAL_PROFILE[(*h as i32 + offset).rem_euclid(24) as usize])
+                .collect();
+            let observed: Vec<f64> = hours.iter().map(|h| average[*h]).collect();
+            (offset, correlation(&observed, &expected))
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+    (correlation >= MIN_CORRELATION).then_some(offset)
+}
+
+fn correlation(x: &[f64], y: &[f64]) -> f64 {
+    let n = x.len() as f64;
+    let (mean_x, mean_y) = (x.iter().sum::<f64>() / n, y.iter().sum::<f64>() / n);
+
+    let mut covariance = 0.0;
+    let (mut var_x, mut var_y) = (0.0, 0.0);
+    for (a, b) in x.iter().zip(y) {
+        covariance += (a - mean_x) * (b - mean_y);
+        var_x += (a - mean_x).powi(2);
+        var_y += (b - mean_y).powi(2);
+    }
+
+    // A flat curve (e.g. an idle server) carries no timezone information
+    if var_x == 0.0 || var_y == 0.0 {
+        return 0.0;
+    }
+    covariance / (var_x * var_y).sqrt()
+}
+
+/// Coarse region for a UTC offset; neighbouring regions blur at the edges.
+fn region(offset: i32) -> &'static str {
+    match offset {
+        -11..=-9 => "Pacific",
+        -8..=-3 => "Americas",
+        -2..=2 => "Europe/Africa",
+        3..=5 => "Middle East/Central Asia",
+        6..=9 => "Asia",
+        _ => "Oceania",
+    }
+}
@@ -0,0 +1,98 @@
+use std::{collections::HashSet, env, sync::Mutex, time::Duration};
+
+use mine_search::filter::{FieldValue, Filter, ParseError, Record};
+
+use crate::{database::ServerModel, server_actions::without_connection::Status};
+
+/// `name: filter` rules checked against every server during updater passes.
+/// A rule fires once when a server starts matching it and again only after the
+/// server stopped matching in between.
+pub struct AlertRules {
+    rules: Vec<(String, Filter)>,
+    firing: Mutex<HashSet<(usize, i32)>>,
+}
+
+impl AlertRules {
+    pub fn from_env() -> Self {
+        match env::var("ALERT_RULES") {
+            Ok(value) => Self::parse(&value).unwrap_or_else(|e| panic!("ALERT_RULES: {}", e)),
+            Err(_) => Self {
+                rules: vec![],
+                firing: Mutex::new(HashSet::new()),
+            },
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, ParseError> {
+        let mut rules = vec![];
+
+        for rule in value.split(';').filter(|r| !r.trim().is_empty()) {
+            let (name, filter) = rule
+                .split_once(':')
+                .filter(|(name, _)| !name.trim().is_empty())
+                .ok_or_else(|| ParseError::new(format!("expected 'name: filter' in {}", rule)))?;
+
+            rules.push((name.trim().to_string(), Filter::parse(filter)?));
+        }
+
+        Ok(Self {
+            rules,
+            firing: Mutex::new(HashSet::new()),
+        })
+    }
+
+    pub fn describe(&self) -> Vec<String> {
+        self.rules
+            .iter()
+            .map(|(name, filter)| format!("{}: {}", name, filter))
+            .collect()
+    }
+
+    /// Names of the rules that started matching with this update.
+    pub fn evaluate(&self, record: &AlertRecord) -> Vec<String> {
+        if self.rules.is_empty() {
+            return vec![];
+        }
+
+        let mut firing = self.firing.lock().unwrap();
+        let mut started = vec![];
+
+        for (i, (name, filter)) in self.rules.iter().enumerate() {
+            let key = (i, record.server.id);
+            if filter.matches(record) {
+                if firing.insert(key) {
+                    started.push(name.clone());
+                }
+            } else {
+                firing.remove(&key);
+            }
+        }
+
+        started
+    }
+}
+
+/// A stored server together with the status just fetched for it. Plain fields
+/// are the new values; `previous_online`, `previous_max` and `max_changed`
+/// compare against what was stored before.
+pub struct AlertRecord<'a> {
+    pub server: &'a ServerModel,
+    pub status: &'a Status,
+    pub latency: Duration,
+}
+
+impl Record for AlertRecord<'_> {
+    fn field(&self, name: &str) -> Option<FieldValue> {
+        Some(match name {
+            "online" => self.status.players.online.into(),
+            "max" => self.status.players.max.into(),
+            "version" => self.status.version.name.as_str().into(),
+            "protocol" => self.status.version.protocol.into(),
+            "latency" => (self.latency.as_millis() as i64).into(),
+            "previous_online" => self.server.online.into(),
+            "previous_max" => self.server.max.into(),
+            "max_changed" => (self.server.max as i64 != self.status.players.max).into(),
+            _ => return self.server.field(name),
+        })
+    }
+}
@@ -0,0 +1,111 @@
+use std::{io::ErrorKind, time::Duration};
+
+use chrono::{Local, NaiveDateTime, TimeDelta, Timelike};
+use diesel::{
+    dsl::count, AggregateExpressionMethods, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
+};
+
+use crate::{database::ServerModel, schema, Context};
+
+/// Failures faster than this are a refusal, not a server too busy to answer.
+const INSTANT: Duration = Duration::from_millis(500);
+/// Instant rejections in a row before a server counts as blocking us; a
+/// restarting server refuses for a pass or two as well.
+const BLOCK_AFTER: i32 = 3;
+/// How recently a server must have answered to be worth watching.
+const RESPONSIVE_WITHIN: TimeDelta = TimeDelta::days(1);
+
+const LOGIN_BAN: &str = "login_ban";
+
+fn now() -> NaiveDateTime {
+    Local::now().naive_local().with_nanosecond(0).unwrap()
+}
+
+/// The `block_kind` of a failed status probe, when it looks like a firewall
+/// or plugin turning us away rather than the server being down or slow.
+pub fn rejection(kind: ErrorKind, elapsed: Duration) -> Option<&'static str> {
+    if elapsed > INSTANT {
+        return None;
+    }
+    match kind {
+        ErrorKind::ConnectionRefused => Some("refused"),
+        ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::UnexpectedEof => {
+            Some("reset")
+        }
+        _ => None,
+    }
+}
+
+/// Counts an instant rejection of a server that answered recently, marking
+/// it blocked once they keep coming.
+pub async fn rejected(server: &ServerModel, kind: &str, ctx: &Context) {
+    let now = now();
+    if server.rejections == 0 && server.last_seen < now - RESPONSIVE_WITHIN {
+        return;
+    }
+
+    let rejections = server.rejections + 1;
+    let blocked_at = match server.blocked_at {
+        Some(at) => Some(at),
+        None => (rejections >= BLOCK_AFTER).then_some(now),
+    };
+    diesel::update(schema::servers::dsl::servers.find(server.id))
+        .set((
+            schema::servers::dsl::rejections.eq(rejections),
+            schema::servers::dsl::blocked_at.eq(blocked_at),
+            schema::servers::dsl::block_kind.eq(blocked_at.map(|_| kind)),
+        ))
+        .execute(&mut ctx.pool().get().await.conn)
+        .unwrap();
+}
+
+/// A status came through, so only a login ban can still be in place.
+pub async fn answered(server: &ServerModel, ctx: &Context) {
+    let login_ban = server.block_kind.as_deref() == Some(LOGIN_BAN);
+    if server.rejections == 0 && (server.blocked_at.is_none() || login_ban) {
+        return;
+    }
+
+    diesel::update(schema::servers::dsl::servers.find(server.id))
+        .set((
+            schema::servers::dsl::rejections.eq(0),
+            schema::servers::dsl::blocked_at.eq(server.blocked_at.filter(|_| login_ban)),
+            schema::servers::dsl::block_kind.eq(server.block_kind.as_deref().filter(|_| login_ban)),
+        ))
+        .execute(&mut ctx.pool().get().await.conn)
+        .unwrap();
+}
+
+/// Records a login probe kicked as banned, or lifts an earlier login ban.
+pub async fn login(server: &ServerModel, banned: bool, ctx: &Context) {
+    let login_ban = server.block_kind.as_deref() == Some(LOGIN_BAN);
+    let update = diesel::update(schema::servers::dsl::servers.find(server.id));
+    let changed = match (banned, server.blocked_at, login_ban) {
+        (true, None, _) => update
+            .set((
+                schema::servers::dsl::blocked_at.eq(Some(now())),
+                schema::servers::dsl::block_kind.eq(Some(LOGIN_BAN)),
+            ))
+            .execute(&mut ctx.pool().get().await.conn),
+        (false, Some(_), true) => update
+            .set((
+                schema::servers::dsl::blocked_at.eq(None::<NaiveDateTime>),
+                schema::servers::dsl::block_kind.eq(None::<String>),
+            ))
+            .execute(&mut ctx.pool().get().await.conn),
+        _ => return,
+    };
+    changed.unwrap();
+}
+
+/// Servers blocking us and the distinct ASNs they are in.
+pub fn census(conn: &mut PgConnection) -> (i64, i64) {
+    schema::servers::dsl::servers
+        .filter(schema::servers::dsl::blocked_at.is_not_null())
+        .select((
+            count(schema::servers::dsl::id),
+            count(schema::servers::dsl::asn).aggregate_distinct(),
+        ))
+        .first(conn)
+        .unwrap()
+}
@@ -0,0 +1,45 @@
+use diesel::{QueryDsl, RunQueryDsl, SelectableHelper};
+
+use crate::{
+    database::{DatabaseWrapper, ServerActivityModel},
+    schema,
+};
+
+/// Prints the servers with the highest average population at `hour` (UTC),
+/// along with each one's own peak hour.
+pub fn run(hour: u32, limit: usize) {
+    let hour = hour as usize % 24;
+    let mut db = DatabaseWrapper::establish_read();
+
+    let mut rows: Vec<(ServerActivityModel, String)> =
+        schema::server_activity::dsl::server_activity
+            .inner_join(schema::servers::dsl::servers)
+            .select((ServerActivityModel::as_select(), schema::servers::dsl::ip))
+            .load(&mut db.conn)
+            .unwrap();
+
+    let at_hour =
+        |activity: &ServerActivityModel| activity.average_online.get(hour).copied().unwrap_or(0.0);
+    rows.sort_by(|a, b| at_hour(&b.0).total_cmp(&at_hour(&a.0)));
+
+    println!(
+        "{:<40} {:>12} {:>10} {:>12}  player timezone",
+        "ip",
+        format!("avg {:02}:00", hour),
+        "peak hour",
+        "peak avg"
+    );
+    for (activity, ip) in rows.iter().take(limit) {
+        println!(
+            "{:<40} {:>12.1} {:>7}:00 {:>12.1}  {}",
+            ip,
+            at_hour(activity),
+            format!("{:02}", activity.peak_hour),
+            activity.peak_online,
+            match (activity.utc_offset, &activity.region) {
+                (Some(offset), Some(region)) => format!("UTC{:+} ({})", offset, region),
+                _ => "?".to_string(),
+            }
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use chrono::{Local, Timelike};
+use diesel::{Connection, ExpressionMethods, QueryDsl, RunQueryDsl};
+use tokio::sync::Semaphore;
+
+use crate::{
+    enrich::{self, Stage},
+    reporter::Pass,
+    schema, Context,
+};
+
+/// Rows between progress lines.
+const PROGRESS_EVERY: usize = 500;
+/// GeoIP rows stored per transaction.
+const BATCH: usize = 500;
+
+pub fn parse_stage(value: &str) -> Result<Stage, String> {
+    match Stage::parse(value) {
+        Some(stage @ (Stage::GeoIp | Stage::Rdns | Stage::Mojang)) => Ok(stage),
+        Some(stage) => Err(format!(
+            "{} needs a fresh status and only runs on discovery",
+            stage.as_str()
+        )),
+        None => Err(format!("{} is not geoip, rdns or mojang", value)),
+    }
+}
+
+struct Progress {
+    stage: Stage,
+    total: usize,
+    done: AtomicUsize,
+}
+
+impl Progress {
+    fn new(stage: Stage, total: usize) -> Self {
+        println!("[+] Backfilling {}: {} rows", stage.as_str(), total);
+        Self {
+            stage,
+            total,
+            done: AtomicUsize::new(0),
+        }
+    }
+
+    fn add(&self, n: usize) {
+        let before = self.done.fetch_add(n, Ordering::Relaxed);
+        let done = before + n;
+        if before / PROGRESS_EVERY != done / PROGRESS_EVERY || done == self.total {
+            println!(
+                "    {}: {}/{} ({:.1}%)",
+                self.stage.as_str(),
+                done,
+                self.total,
+                done as f64 * 100.0 / self.total.max(1) as f64
+            );
+        }
+    }
+}
+
+/// Runs one enrichment stage over servers stored before it was enabled, or
+/// over every stored server with `all`, instead of only new discoveries.
+pub async fn run(stage: Stage, all: bool, concurrency: Option<usize>) {
+    let ctx = Arc::new(Context::establish());
+    let concurrency = concurrency.unwrap_or(stage.default_concurrency()).max(1);
+    ctx.reporter.pass_started(Pass::Backfill);
+
+    match stage {
+        Stage::GeoIp => geoip(&ctx, all).await,
+        Stage::Rdns => rdns(&ctx, all, concurrency).await,
+        Stage::Mojang => mojang(&ctx, all, concurrency).await,
+        Stage::DeepProbe | Stage::Notify => unreachable!("refused by parse_stage"),
+    }
+
+    ctx.reporter.pass_finished(Pass::Backfill);
+    ctx.finish_run().await;
+}
+
+async fn geoip(ctx: &Context, all: bool) {
+    if !ctx.geoip.is_enabled() {
+        eprintln!("[!] No GeoIP database configured, see GEOIP_DATABASE");
+        return;
+    }
+
+    let mut query = schema::servers::dsl::servers
+        .select((schema::servers::dsl::id, schema::servers::dsl::ip))
+        .order(schema::servers::dsl::id)
+        .into_boxed();
+    if !all {
+        query = query.filter(schema::servers::dsl::geo_checked_at.is_null());
+    }
+    let mut db = ctx.pool().get().await;
+    let rows: Vec<(i32, String)> = query.load(&mut db.conn).unwrap();
+
+    let progress = Progress::new(Stage::GeoIp, rows.len());
+    let now = Local::now().naive_local().with_nanosecond(0).unwrap();
+    for batch in rows.chunks(BATCH) {
+        db.conn
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                for (id, ip) in batch {
+                    enrich::store_location(conn, &ctx.geoip, *id, ip, now);
+                }
+                Ok(())
+            })
+            .unwrap();
+        progress.add(batch.len());
+    }
+}
+
+async fn rdns(ctx: &Arc<Context>, all: bool, concurrency: usize) {
+    let mut query = schema::servers::dsl::servers
+        .select((schema::servers::dsl::id, schema::servers::dsl::ip))
+        .order(schema::servers::dsl::id)
+        .into_boxed();
+    if !all {
+        query = query.filter(schema::servers::dsl::rdns_checked_at.is_null());
+    }
+    let rows: Vec<(i32, String)> = query.load(&mut ctx.pool().get().await.conn).unwrap();
+
+    let progress = Arc::new(Progress::new(Stage::Rdns, rows.len()));
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+
+    let mut handles = vec![];
+    for (id, ip) in rows {
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let ctx = ctx.clone();
+        let progress = progress.clone();
+
+        handles.push(tokio::spawn(async move {
+            let _permit = permit;
+            if let Some(ip) = enrich::row_ip(&ip) {
+                enrich::store_rdns(&ctx, id, ip).await;
+            }
+            progress.add(1);
+        }));
+    }
+
+    for handle in handles {
+        let _ = handle.await;
+    }
+}
+
+/// Player UUIDs come from `players`, which keeps every sample player seen.
+async fn mojang(ctx: &Arc<Context>, all: bool, concurrency: usize) {
+    let mut conn = ctx.pool().get().await;
+    let known: HashSet<String> = if all {
+        HashSet::new()
+    } else {
+        schema::mojang_profiles::dsl::mojang_profiles
+            .select(schema::mojang_profiles::dsl::uuid)
+            .load::<String>(&mut conn.conn)
+            .unwrap()
+            .into_iter()
+            .collect()
+    };
+    let uuids: HashSet<String> = schema::players::dsl::players
+        .select(schema::players::dsl::uuid)
+        .distinct()
+        .load::<String>(&mut conn.conn)
+        .unwrap()
+        .iter()
+        .filter_map(|id| enrich::profile_uuid(id))
+        .filter(|uuid| !known.contains(uuid))
+        .collect();
+    drop(conn);
+
+    let progress = Arc::new(Progress::new(Stage::Mojang, uuids.len()));
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+    let limited = Arc::new(AtomicBool::new(false));
+
+    let mut handles = vec![];
+    for uuid in uuids {
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        if limited.load(Ordering::Relaxed) {
+            break;
+        }
+        let ctx = ctx.clone();
+        let progress = progress.clone();
+        let limited = limited.clone();
+
+        handles.push(tokio::spawn(async move {
+            let _permit = permit;
+            if enrich::check_profile(&uuid, &ctx).await {
+                progress.add(1);
+            } else {
+                limited.store(true, Ordering::Relaxed);
+            }
+        }));
+    }
+
+    for handle in handles {
+        let _ = handle.await;
+    }
+    if limited.load(Ordering::Relaxed) {
+        eprintln!(
+            "[!] Session server stopped answering after {} profiles, run again later to continue",
+            progress.done.load(Ordering::Relaxed)
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use serde_json::json;
+use tokio::{net::TcpListener, time::timeout};
+
+use crate::{
+    conn_wrapper::{Connection, ConnectionWrapper, Metered},
+    packets::{Handshake, StatusRequest, StatusResponse},
+    server_actions::without_connection::get_status_on,
+};
+
+#[derive(Default)]
+struct Counters {
+    connects: AtomicU64,
+    statuses: AtomicU64,
+    failures: AtomicU64,
+}
+
+/// Runs the status probe against a local mock server for every combination of
+/// worker count and timeout, printing the achieved rates.
+pub async fn run(workers: &[usize], timeouts: &[u64], duration: u64, latency: u64) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(mock_server(listener, Duration::from_millis(latency)));
+
+    println!(
+        "Mock server on {} | latency {}ms | {}s per run",
+        addr, latency, duration
+    );
+    println!(
+        "{:>8} {:>10} {:>14} {:>14} {:>10}",
+        "workers", "timeout", "connects/s", "statuses/s", "failures"
+    );
+
+    for &worker_count in workers {
+        for &timeout_ms in timeouts {
+            let counters = Arc::new(Counters::default());
+            let deadline = Instant::now() + Duration::from_secs(duration);
+
+            let handles: Vec<_> = (0..worker_count)
+                .map(|_| {
+                    let th_counters = counters.clone();
+                    tokio::spawn(probe_loop(
+                        addr,
+                        Duration::from_millis(timeout_ms),
+                        deadline,
+                        th_counters,
+                    ))
+                })
+                .collect();
+
+            for handle in handles {
+                let _ = handle.await;
+            }
+
+            let seconds = duration.max(1) as f64;
+            println!(
+                "{:>8} {:>8}ms {:>14.1} {:>14.1} {:>10}",
+                worker_count,
+                timeout_ms,
+                counters.connects.load(Ordering::Relaxed) as f64 / seconds,
+                counters.statuses.load(Ordering::Relaxed) as f64 / seconds,
+                counters.failures.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+
+async fn probe_loop(
+    addr: SocketAddr,
+    probe_timeout: Duration,
+    deadline: Instant,
+    counters: Arc<Counters>,
+) {
+    while Instant::now() < deadline {
+        let mut conn = match timeout(probe_timeout, Connection::connect(addr)).await {
+            Ok(Ok(conn)) => conn,
+            _ => {
+                counters.failures.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+        };
+        counters.connects.fetch_add(1, Ordering::Relaxed);
+
+        match timeout(
+            probe_timeout,
+            get_status_on(&mut conn, &addr.ip().to_string(), addr.port()),
+        )
+        .await
+        {
+            Ok(Ok(_)) => counters.statuses.fetch_add(1, Ordering::Relaxed),
+            _ => counters.failures.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+}
+
+async fn mock_server(listener: TcpListener, latency: Duration) {
+    let response = json!({
+        "version": { "name": "1.20.4", "protocol": 765 },
+        "players": { "online": 3, "max": 20, "sample": [] },
+        "description": { "text": "mine_search bench" },
+    })
+    .to_string();
+
+    while let Ok((stream, _)) = listener.accept().await {
+        let response = response.clone();
+
+        tokio::spawn(async move {
+            let mut conn = Metered::new(stream);
+            let _: Handshake = conn.read_packet().await?;
+            let _: StatusRequest = conn.read_packet().await?;
+            tokio::time::sleep(latency).await;
+            conn.write_packet(StatusResponse { response }).await
+        });
+    }
+}
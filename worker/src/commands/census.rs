@@ -0,0 +1,220 @@
+use std::{collections::HashMap, fs};
+
+use chrono::{Local, TimeDelta};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    database::DatabaseWrapper,
+    schema,
+    stats::{CensusCounts, SampleEstimate},
+};
+
+/// Versions and countries listed by name; the rest are summed up as "other".
+const LISTED: usize = 20;
+/// z of a two-sided 95% interval.
+const Z: f64 = 1.96;
+
+#[derive(Serialize)]
+struct Share {
+    name: String,
+    found: u64,
+    estimated: u64,
+    share: f64,
+    /// 95% interval of the share, for sampled runs
+    low: Option<f64>,
+    high: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct Census {
+    source: String,
+    /// Share of the range probed, for sampled runs
+    sample: Option<f64>,
+    servers_found: u64,
+    estimated_servers: u64,
+    estimated_low: Option<u64>,
+    estimated_high: Option<u64>,
+    versions: Vec<Share>,
+    license: Vec<Share>,
+    countries: Vec<Share>,
+}
+
+/// Aggregate report of the servers answering during run `run`, extrapolated
+/// to the whole range with 95% intervals when the run probed a `--sample`,
+/// or of every canonical server seen within `since_days` otherwise. Printed,
+/// and written as JSON to `out` if given.
+pub fn run(run: Option<i32>, since_days: i64, out: Option<&str>) {
+    let mut db = DatabaseWrapper::establish_read();
+
+    let census = match run {
+        Some(id) => {
+            let summary: Option<Value> = schema::runs::dsl::runs
+                .find(id)
+                .select(schema::runs::dsl::summary)
+                .first(&mut db.conn)
+                .optional()
+                .unwrap()
+                .unwrap_or_else(|| panic!("No run {}", id));
+            let summary = summary.unwrap_or_else(|| panic!("Run {} has not finished", id));
+            let counts: CensusCounts = serde_json::from_value(summary["census"].clone())
+                .unwrap_or_else(|_| panic!("Run {} predates census counts", id));
+            let sample: Option<SampleEstimate> =
+                serde_json::from_value(summary["sample"].clone()).unwrap_or(None);
+            build(format!("run {}", id), counts, sample.as_ref())
+        }
+        None => {
+            let since = Local::now().naive_local() - TimeDelta::days(since_days);
+            let rows: Vec<(String, Option<String>, Option<bool>)> = schema::servers::dsl::servers
+                .filter(schema::servers::dsl::last_seen.ge(since))
+                .filter(schema::servers::dsl::canonical_id.is_null())
+                .select((
+                    schema::servers::dsl::version_name,
+                    schema::servers::dsl::country,
+                    schema::servers::dsl::license,
+                ))
+                .load(&mut db.conn)
+                .unwrap();
+            build(
+                format!("servers seen in the last {} days", since_days),
+                stored_counts(rows),
+                None,
+            )
+        }
+    };
+
+    print(&census);
+    if let Some(out) = out {
+        fs::write(out, serde_json::to_string_pretty(&census).unwrap())
+            .unwrap_or_else(|e| panic!("Error writing {}: {}", out, e));
+        println!("Census written to {}", out);
+    }
+}
+
+fn stored_counts(rows: Vec<(String, Option<String>, Option<bool>)>) -> CensusCounts {
+    let mut counts = CensusCounts::default();
+    for (version, country, license) in rows {
+        counts.servers += 1;
+        *counts.versions.entry(version).or_default() += 1;
+        if let Some(country) = country {
+            *counts.countries.entry(country).or_default() += 1;
+        }
+        match license {
+            Some(true) => counts.premium += 1,
+            Some(false) => counts.cracked += 1,
+            None => counts.license_unknown += 1,
+        }
+    }
+    counts
+}
+
+fn build(source: String, counts: CensusCounts, sample: Option<&SampleEstimate>) -> Census {
+    let n = counts.servers;
+    let scale = sample.map_or(1.0, |sample| {
+        sample.addresses as f64 / sample.sampled.max(1) as f64
+    });
+    let sampled = sample.is_some();
+
+    let share = |name: String, found: u64| {
+        let (low, high) = match sampled {
+            true => {
+                let (low, high) = wilson(found, n);
+                (Some(low), Some(high))
+            }
+            false => (None, None),
+        };
+        Share {
+            name,
+            found,
+            estimated: (found as f64 * scale).round() as u64,
+            share: found as f64 / n.max(1) as f64,
+            low,
+            high,
+        }
+    };
+    let ranked = |map: HashMap<String, u64>| -> Vec<Share> {
+        let mut entries: Vec<(String, u64)> = map.into_iter().collect();
+        entries.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let other: u64 = entries.iter().skip(LISTED).map(|(_, count)| count).sum();
+        entries.truncate(LISTED);
+        if other > 0 {
+            entries.push(("other".to_string(), other));
+        }
+        entries
+            .into_iter()
+            .map(|(name, count)| share(name, count))
+            .collect()
+    };
+
+    let margin = Z * (n as f64).sqrt();
+    Census {
+        source,
+        sample: sample.map(|sample| sample.fraction),
+        servers_found: n,
+        estimated_servers: (n as f64 * scale).round() as u64,
+        estimated_low: sampled.then(|| ((n as f64 - margin) * scale).round().max(n as f64) as u64),
+        estimated_high: sampled.then(|| ((n as f64 + margin) * scale).round() as u64),
+        license: vec![
+            share("premium".to_string(), counts.premium),
+            share("cracked".to_string(), counts.cracked),
+            share("unknown".to_string(), counts.license_unknown),
+        ],
+        versions: ranked(counts.versions),
+        countries: ranked(counts.countries),
+    }
+}
+
+/// Wilson score interval of `found` out of `n`.
+fn wilson(found: u64, n: u64) -> (f64, f64) {
+    if n == 0 {
+        return (0.0, 1.0);
+    }
+    let n = n as f64;
+    let p = found as f64 / n;
+    let denominator = 1.0 + Z * Z / n;
+    let centre = p + Z * Z / (2.0 * n);
+    let spread = Z * (p * (1.0 - p) / n + Z * Z / (4.0 * n * n)).sqrt();
+    (
+        ((centre - spread) / denominator).max(0.0),
+        ((centre + spread) / denominator).min(1.0),
+    )
+}
+
+fn print(census: &Census) {
+    println!("Census of {}", census.source);
+    match (census.sample, census.estimated_low, census.estimated_high) {
+        (Some(fraction), Some(low), Some(high)) => println!(
+            "  {} servers found in a {}% sample, estimated {} in the range (95%: {}-{})",
+            census.servers_found,
+            fraction * 100.0,
+            census.estimated_servers,
+            low,
+            high
+        ),
+        _ => println!("  {} servers", census.servers_found),
+    }
+
+    for (title, shares) in [
+        ("License", &census.license),
+        ("Versions", &census.versions),
+        ("Countries", &census.countries),
+    ] {
+        println!("{}", title);
+        for share in shares {
+            let interval = match (share.low, share.high) {
+                (Some(low), Some(high)) => {
+                    format!("  (95%: {:.1}-{:.1}%)", low * 100.0, high * 100.0)
+                }
+                _ => String::new(),
+            };
+            println!(
+                "  {:<24} {:>8} {:>6.1}%{}",
+                share.name,
+                share.estimated,
+                share.share * 100.0,
+                interval
+            );
+        }
+    }
+}
@@ -0,0 +1,12 @@
+use crate::{compaction, database::DatabaseWrapper};
+
+/// Downsamples old status snapshots once, for running from cron instead of
+/// `COMPACT_INTERVAL`.
+pub fn run() {
+    let mut db = DatabaseWrapper::establish();
+    let (pings, liveness) = compaction::compact(&mut db.conn);
+    println!(
+        "Compacted {} pings into {} and {} liveness checks into {}",
+        pings.removed, pings.written, liveness.removed, liveness.written
+    );
+}
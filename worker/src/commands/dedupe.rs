@@ -0,0 +1,232 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+};
+
+use diesel::{
+    dsl::{delete, update},
+    sql_query,
+    sql_types::Integer,
+    Connection, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
+};
+
+use crate::{
+    database::{DatabaseWrapper, Edition},
+    schema,
+};
+
+/// `servers.ip` in its canonical form: the bare address for the edition's
+/// default port, `addr:port` otherwise, with IPv4-mapped IPv6 addresses
+/// unwrapped.
+fn canonical(ip: &str, edition: Edition) -> Option<String> {
+    let ip = ip.trim();
+    let addr = match ip.parse::<SocketAddr>() {
+        Ok(addr) => addr,
+        Err(_) => SocketAddr::new(ip.parse().ok()?, edition.default_port()),
+    };
+    let host = match addr.ip() {
+        IpAddr::V6(v6) => v6
+            .to_ipv4_mapped()
+            .map(IpAddr::V4)
+            .unwrap_or(IpAddr::V6(v6)),
+        v4 => v4,
+    };
+
+    Some(edition.key(&host, addr.port()))
+}
+
+/// Finds server rows that are the same address and port written in different
+/// forms, left behind by inserts from before the upsert keyed on the
+/// canonical address. With `apply`, every group is merged into one row inside
+/// a transaction: history, players, snapshots and other references move to
+/// the survivor, which keeps the earliest discovery and the latest sighting.
+pub fn run(apply: bool) {
+    let mut db = DatabaseWrapper::establish();
+
+    let rows: Vec<(i32, String, String)> = schema::servers::dsl::servers
+        .select((
+            schema::servers::dsl::id,
+            schema::servers::dsl::ip,
+            schema::servers::dsl::edition,
+        ))
+        .order(schema::servers::dsl::id)
+        .load(&mut db.conn)
+        .unwrap();
+
+    // Java and Bedrock servers on one address are different servers
+    let mut groups: HashMap<(String, String), Vec<(i32, String)>> = HashMap::new();
+    for (id, ip, edition) in rows {
+        let parsed = Edition::parse(&edition).unwrap_or(Edition::Java);
+        if let Some(key) = canonical(&ip, parsed) {
+            groups.entry((key, edition)).or_default().push((id, ip));
+        }
+    }
+
+    let mut groups: Vec<(String, Vec<(i32, String)>)> = groups
+        .into_iter()
+        .filter(|(_, rows)| rows.len() > 1)
+        .map(|((key, _), rows)| (key, rows))
+        .collect();
+    groups.sort_by_key(|(_, rows)| rows[0].0);
+
+    if groups.is_empty() {
+        println!("No duplicate servers found");
+        return;
+    }
+
+    for (key, rows) in &groups {
+        let survivor = survivor(key, rows);
+        let others: Vec<String> = rows
+            .iter()
+            .filter(|(id, _)| *id != survivor)
+            .map(|(id, ip)| format!("#{} {:?}", id, ip))
+            .collect();
+        println!("{}: keep #{}, merge {}", key, survivor, others.join(", "));
+    }
+
+    let duplicates: usize = groups.iter().map(|(_, rows)| rows.len() - 1).sum();
+    if !apply {
+        println!(
+            "{} duplicate rows in {} groups, run with --apply to merge them",
+            duplicates,
+            groups.len()
+        );
+        return;
+    }
+
+    db.conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            for (key, rows) in &groups {
+                let survivor = survivor(key, rows);
+                for (id, _) in rows.iter().filter(|(id, _)| *id != survivor) {
+                    merge(conn, *id, survivor)?;
+                }
+
+                update(schema::servers::dsl::servers.find(survivor))
+                    .set(schema::servers::dsl::ip.eq(key))
+                    .execute(conn)?;
+            }
+            Ok(())
+        })
+        .unwrap();
+
+    println!(
+        "Merged {} duplicate rows into {} servers",
+        duplicates,
+        groups.len()
+    );
+}
+
+/// The row already stored under the canonical form, else the oldest one.
+fn survivor(key: &str, rows: &[(i32, String)]) -> i32 {
+    rows.iter().find(|(_, ip)| ip == key).unwrap_or(&rows[0]).0
+}
+
+/// Moves everything referencing server `from` to `to` and deletes `from`.
+fn merge(conn: &mut PgConnection, from: i32, to: i32) -> Result<(), diesel::result::Error> {
+    // Rows unique per server: keep the survivor's and drop the duplicate's
+    sql_query(
+        "UPDATE players s SET last_seen = GREATEST(s.last_seen, d.last_seen) \
+         FROM players d WHERE s.server_id = $2 AND d.server_id = $1 AND s.name = d.name",
+    )
+    .bind::<Integer, _>(from)
+    .bind::<Integer, _>(to)
+    .execute(conn)?;
+    sql_query(
+        "DELETE FROM players d USING players s \
+         WHERE d.server_id = $1 AND s.server_id = $2 AND s.name = d.name",
+    )
+    .bind::<Integer, _>(from)
+    .bind::<Integer, _>(to)
+    .execute(conn)?;
+    sql_query(
+        "DELETE FROM virtual_hosts d USING virtual_hosts s \
+         WHERE d.server_id = $1 AND s.server_id = $2 AND s.hostname = d.hostname",
+    )
+    .bind::<Integer, _>(from)
+    .bind::<Integer, _>(to)
+    .execute(conn)?;
+    sql_query(
+        "DELETE FROM server_activity \
+         WHERE server_id = $1 AND EXISTS (SELECT 1 FROM server_activity WHERE server_id = $2)",
+    )
+    .bind::<Integer, _>(from)
+    .bind::<Integer, _>(to)
+    .execute(conn)?;
+
+    update(schema::players::dsl::players.filter(schema::players::dsl::server_id.eq(from)))
+        .set(schema::players::dsl::server_id.eq(to))
+        .execute(conn)?;
+    update(
+        schema::virtual_hosts::dsl::virtual_hosts
+            .filter(schema::virtual_hosts::dsl::server_id.eq(from)),
+    )
+    .set(schema::virtual_hosts::dsl::server_id.eq(to))
+    .execute(conn)?;
+    update(schema::server_activity::dsl::server_activity.find(from))
+        .set(schema::server_activity::dsl::server_id.eq(to))
+        .execute(conn)?;
+    update(schema::pings::dsl::pings.filter(schema::pings::dsl::server_id.eq(from)))
+        .set(schema::pings::dsl::server_id.eq(to))
+        .execute(conn)?;
+    update(
+        schema::watch_snapshots::dsl::watch_snapshots
+            .filter(schema::watch_snapshots::dsl::server_id.eq(from)),
+    )
+    .set(schema::watch_snapshots::dsl::server_id.eq(to))
+    .execute(conn)?;
+    update(
+        schema::motd_history::dsl::motd_history
+            .filter(schema::motd_history::dsl::server_id.eq(from)),
+    )
+    .set(schema::motd_history::dsl::server_id.eq(to))
+    .execute(conn)?;
+    update(
+        schema::version_history::dsl::version_history
+            .filter(schema::version_history::dsl::server_id.eq(from)),
+    )
+    .set(schema::version_history::dsl::server_id.eq(to))
+    .execute(conn)?;
+    update(
+        schema::white_list_history::dsl::white_list_history
+            .filter(schema::white_list_history::dsl::server_id.eq(from)),
+    )
+    .set(schema::white_list_history::dsl::server_id.eq(to))
+    .execute(conn)?;
+    update(schema::domains::dsl::domains.filter(schema::domains::dsl::server_id.eq(from)))
+        .set(schema::domains::dsl::server_id.eq(to))
+        .execute(conn)?;
+    update(
+        schema::saved_servers::dsl::saved_servers
+            .filter(schema::saved_servers::dsl::server_id.eq(from)),
+    )
+    .set(schema::saved_servers::dsl::server_id.eq(to))
+    .execute(conn)?;
+    update(schema::servers::dsl::servers.filter(schema::servers::dsl::canonical_id.eq(from)))
+        .set(schema::servers::dsl::canonical_id.eq(to))
+        .execute(conn)?;
+    update(
+        schema::servers::dsl::servers
+            .find(to)
+            .filter(schema::servers::dsl::canonical_id.eq(to)),
+    )
+    .set(schema::servers::dsl::canonical_id.eq(None::<i32>))
+    .execute(conn)?;
+
+    // The survivor keeps the widest view of the server's life
+    sql_query(
+        "UPDATE servers s SET \
+             discovered_at = LEAST(s.discovered_at, d.discovered_at), \
+             last_seen = GREATEST(s.last_seen, d.last_seen), \
+             peak_online = GREATEST(s.peak_online, d.peak_online), \
+             peak_online_at = CASE WHEN d.peak_online > s.peak_online \
+                 THEN d.peak_online_at ELSE s.peak_online_at END \
+         FROM servers d WHERE s.id = $2 AND d.id = $1",
+    )
+    .bind::<Integer, _>(from)
+    .bind::<Integer, _>(to)
+    .execute(conn)?;
+
+    delete(schema::servers::dsl::servers.find(from)).execute(conn)?;
+    Ok(())
+}
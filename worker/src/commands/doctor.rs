@@ -0,0 +1,190 @@
+use std::{env, net::SocketAddr, path::Path, time::Duration};
+
+use db_schema::version::{self, Compatibility};
+use diesel::{sql_query, sql_types::Text, Connection, PgConnection, QueryableByName, RunQueryDsl};
+use hickory_resolver::TokioAsyncResolver;
+use tokio::{
+    net::{TcpStream, UdpSocket},
+    time::timeout,
+};
+
+use crate::fd_limit;
+
+enum Outcome {
+    Ok(String),
+    Warn(String),
+    Fail(String),
+}
+
+fn print(check: &str, outcome: Outcome) -> bool {
+    let (label, message, failed) = match outcome {
+        Outcome::Ok(m) => ("ok", m, false),
+        Outcome::Warn(m) => ("warn", m, false),
+        Outcome::Fail(m) => ("FAIL", m, true),
+    };
+    println!("[{:>4}] {:<12} {}", label, check, message);
+    failed
+}
+
+#[derive(QueryableByName)]
+struct Migration {
+    #[diesel(sql_type = Text)]
+    version: String,
+}
+
+/// Checks what a long scan depends on and prints one line per check with a
+/// hint when something needs attention. Exits non-zero when a check failed.
+pub async fn run() {
+    let mut failed = false;
+
+    failed |= print("database", database());
+    failed |= print("tcp", tcp().await);
+    failed |= print("udp", udp().await);
+    failed |= print("dns", dns().await);
+    failed |= print("fd limit", fd_limit());
+    failed |= print("geoip", geoip());
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn database() -> Outcome {
+    let Ok(url) = env::var("DATABASE_URL") else {
+        return Outcome::Fail("DATABASE_URL is not set".to_string());
+    };
+    let mut conn = match PgConnection::establish(&url) {
+        Ok(conn) => conn,
+        Err(e) => return Outcome::Fail(format!("cannot connect: {}", e)),
+    };
+
+    let latest =
+        sql_query("SELECT version FROM __diesel_schema_migrations ORDER BY version DESC LIMIT 1")
+            .load::<Migration>(&mut conn);
+    let latest = match latest {
+        Ok(rows) => match rows.into_iter().next() {
+            Some(row) => row.version,
+            None => return Outcome::Fail("no migrations applied, run `diesel setup`".to_string()),
+        },
+        Err(_) => return Outcome::Fail("schema not initialised, run `diesel setup`".to_string()),
+    };
+
+    match version::check(&mut conn) {
+        Ok(Compatibility::Current) => {}
+        Ok(Compatibility::Newer(live)) => {
+            return Outcome::Warn(format!(
+                "schema at migration {} is newer than this build, upgrade mine_search",
+                live
+            ))
+        }
+        Err(e) => return Outcome::Fail(e),
+    }
+
+    // Every table this binary writes must have the columns it expects
+    let probe = sql_query(
+        "SELECT 1 FROM servers, players, pings, runs, domains, saved_servers, server_activity, \
+         retry_queue, scan_checkpoints, watch_snapshots, service_fingerprints, virtual_hosts, mods \
+         LIMIT 0",
+    )
+    .execute(&mut conn);
+    match probe {
+        Ok(_) => Outcome::Ok(format!("connected, latest migration {}", latest)),
+        Err(e) => Outcome::Fail(format!(
+            "schema is behind (latest migration {}), run `diesel migration run`: {}",
+            latest, e
+        )),
+    }
+}
+
+async fn tcp() -> Outcome {
+    let addr: SocketAddr = "1.1.1.1:443".parse().unwrap();
+    match timeout(Duration::from_secs(3), TcpStream::connect(addr)).await {
+        Ok(Ok(_)) => Outcome::Ok(format!("outbound connections work ({})", addr)),
+        Ok(Err(e)) => Outcome::Fail(format!("cannot connect to {}: {}", addr, e)),
+        Err(_) => Outcome::Fail(format!(
+            "connecting to {} timed out, check firewall and egress rules",
+            addr
+        )),
+    }
+}
+
+/// Sends a DNS query for `.` straight to a public resolver, the same kind of
+/// outbound UDP the query protocol uses.
+async fn udp() -> Outcome {
+    let request = [
+        0x13, 0x37, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+        0x00, 0x01,
+    ];
+
+    let exchange = async {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.send_to(&request, "1.1.1.1:53").await?;
+        let mut buf = [0u8; 512];
+        socket.recv(&mut buf).await
+    };
+
+    match timeout(Duration::from_secs(3), exchange).await {
+        Ok(Ok(_)) => Outcome::Ok("outbound UDP works".to_string()),
+        Ok(Err(e)) => Outcome::Warn(format!("UDP failed ({}), query data won't be collected", e)),
+        Err(_) => {
+            Outcome::Warn("no UDP reply within 3s, query data won't be collected".to_string())
+        }
+    }
+}
+
+async fn dns() -> Outcome {
+    let resolver = match TokioAsyncResolver::tokio_from_system_conf() {
+        Ok(resolver) => resolver,
+        Err(e) => return Outcome::Fail(format!("cannot read resolver config: {}", e)),
+    };
+
+    match timeout(Duration::from_secs(5), resolver.lookup_ip("example.com")).await {
+        Ok(Ok(_)) => Outcome::Ok("system resolver answers".to_string()),
+        Ok(Err(e)) => Outcome::Warn(format!(
+            "lookup failed ({}), domains, import and rDNS features will suffer",
+            e
+        )),
+        Err(_) => Outcome::Warn("lookup timed out, check /etc/resolv.conf".to_string()),
+    }
+}
+
+fn fd_limit() -> Outcome {
+    let threads: u64 = env::var("THREADS")
+        .unwrap_or("150".to_string())
+        .parse()
+        .unwrap_or(150);
+    let needed = fd_limit::needed(threads);
+
+    let Ok((soft, hard)) = fd_limit::current() else {
+        return Outcome::Warn("could not read the open file limit".to_string());
+    };
+
+    if hard < needed {
+        Outcome::Fail(format!(
+            "hard limit {} is below the ~{} needed for THREADS={}, scans run at most {} workers; raise it in limits.conf or with `ulimit -Hn {}`",
+            hard,
+            needed,
+            threads,
+            fd_limit::max_threads(hard),
+            needed
+        ))
+    } else if soft < needed {
+        Outcome::Ok(format!(
+            "soft limit {} is raised toward the hard limit {} at startup (THREADS={} needs ~{})",
+            soft, hard, threads, needed
+        ))
+    } else {
+        Outcome::Ok(format!(
+            "soft limit {} (THREADS={} needs ~{})",
+            soft, threads, needed
+        ))
+    }
+}
+
+fn geoip() -> Outcome {
+    match env::var("GEOIP_DATABASE") {
+        Ok(path) if Path::new(&path).is_file() => Outcome::Ok(format!("database at {}", path)),
+        Ok(path) => Outcome::Fail(format!("GEOIP_DATABASE points to missing file {}", path)),
+        Err(_) => Outcome::Warn("GEOIP_DATABASE not set, countries won't be recorded".to_string()),
+    }
+}
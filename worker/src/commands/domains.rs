@@ -0,0 +1,169 @@
+use std::{fs, net::IpAddr, sync::Arc, time::Duration};
+
+use chrono::{Local, Timelike};
+use diesel::{dsl::insert_into, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use hickory_resolver::TokioAsyncResolver;
+use mine_search::connect;
+use tokio::{sync::Semaphore, time::timeout};
+
+use crate::{
+    conn_wrapper::Connection,
+    database::{DiscoverySource, DomainUpsert, Edition},
+    enrich, etiquette, handle_valid_ip,
+    reporter::Pass,
+    scanner::Exclusions,
+    schema,
+    server_actions::without_connection::{get_status_on, Status},
+    Context,
+};
+
+/// Resolves every domain in `file` (SRV first, then A/AAAA), probes it and
+/// links it to the stored server with the same IP. Reachable domains whose IP
+/// is not stored yet are imported when they listen on the default port.
+pub async fn run(file: &str, concurrency: usize) {
+    let domains: Vec<String> = fs::read_to_string(file)
+        .unwrap_or_else(|e| panic!("Error reading {}: {}", file, e))
+        .lines()
+        .map(|l| l.trim().trim_end_matches('.').to_lowercase())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .collect();
+
+    let ctx = Arc::new(Context::establish());
+    enrich::start(&ctx);
+
+    println!("Domains: {}", domains.len());
+    ctx.reporter.pass_started(Pass::Domains);
+
+    let exclusions = Arc::new(Exclusions::new(None));
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+
+    let mut handles = vec![];
+    for domain in domains {
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let th_ctx = ctx.clone();
+        let th_exclusions = exclusions.clone();
+
+        handles.push(tokio::spawn(async move {
+            let _permit = permit;
+            check_domain(&domain, &th_exclusions, &th_ctx).await;
+        }));
+    }
+
+    for handle in handles {
+        let _ = handle.await;
+    }
+
+    ctx.reporter.pass_finished(Pass::Domains);
+    ctx.finish_run().await;
+}
+
+pub async fn resolve(domain: &str, resolver: &TokioAsyncResolver) -> Option<(IpAddr, u16, bool)> {
+    if let Ok(srv) = resolver
+        .srv_lookup(format!("_minecraft._tcp.{}", domain))
+        .await
+    {
+        if let Some(record) = srv.iter().next() {
+            let target = record.target().to_utf8();
+            if let Some(ip) = resolver
+                .lookup_ip(target.as_str())
+                .await
+                .ok()
+                .and_then(|r| r.iter().next())
+            {
+                return Some((ip, record.port(), true));
+            }
+        }
+    }
+
+    let ip = resolver.lookup_ip(domain).await.ok()?.iter().next()?;
+    Some((ip, 25565, false))
+}
+
+pub async fn probe(domain: &str, ip: IpAddr, port: u16) -> Option<Status> {
+    timeout(Duration::from_secs(3), async {
+        let mut conn = Connection::connect((ip, port)).await?;
+        get_status_on(&mut conn, domain, port).await
+    })
+    .await
+    .ok()?
+    .ok()
+}
+
+pub async fn find_server(ip: &IpAddr, ctx: &Context) -> Option<i32> {
+    schema::servers::dsl::servers
+        .filter(schema::servers::dsl::ip.eq(ip.to_string()))
+        .filter(schema::servers::dsl::edition.eq(Edition::Java.as_str()))
+        .select(schema::servers::dsl::id)
+        .first(&mut ctx.pool().get().await.conn)
+        .optional()
+        .unwrap()
+}
+
+async fn check_domain(domain: &str, exclusions: &Exclusions, ctx: &Arc<Context>) {
+    let resolved = ctx.lookups.resolve(domain).await;
+    if let Some(own) = resolved.and_then(|(ip, _, _)| exclusions.own_address(&ip)) {
+        eprintln!(
+            "[!] {} resolves into own network {}, not probed",
+            domain, own
+        );
+        return;
+    }
+
+    let status = match resolved {
+        Some((ip, port, _)) => probe(domain, ip, port).await,
+        None => None,
+    };
+
+    let mut server_id = match resolved {
+        Some((ip, _, _)) => find_server(&ip, ctx).await,
+        None => None,
+    };
+
+    if let (Some((ip, 25565, _)), Some(_), None) = (resolved, &status, server_id) {
+        let connected = if etiquette::allowed(&ip.to_string()) {
+            connect(&ip, 25565).await.map(Connection::from_stream)
+        } else {
+            None
+        };
+        if let Some(Ok(conn)) = connected {
+            let _ = timeout(
+                Duration::from_secs(10),
+                handle_valid_ip(conn, &ip, 25565, DiscoverySource::Import, ctx),
+            )
+            .await;
+            server_id = find_server(&ip, ctx).await;
+        }
+    }
+
+    let upsert = DomainUpsert {
+        domain,
+        ip: resolved.map(|(ip, _, _)| ip.to_string()),
+        port: resolved.map(|(_, port, _)| port as i32),
+        srv: resolved.is_some_and(|(_, _, srv)| srv),
+        reachable: status.is_some(),
+        version_name: status.as_ref().map(|s| s.version.name.as_str()),
+        server_id,
+        last_checked: Local::now().naive_local().with_nanosecond(0).unwrap(),
+    };
+
+    insert_into(schema::domains::dsl::domains)
+        .values(&upsert)
+        .on_conflict(schema::domains::dsl::domain)
+        .do_update()
+        .set(&upsert)
+        .execute(&mut ctx.pool().get().await.conn)
+        .unwrap();
+
+    println!(
+        "{} -> {} | {}",
+        domain,
+        resolved
+            .map(|(ip, port, _)| format!("{}:{}", ip, port))
+            .unwrap_or("unresolved".to_string()),
+        match (&status, server_id) {
+            (Some(_), Some(id)) => format!("linked to #{}", id),
+            (Some(_), None) => "reachable".to_string(),
+            (None, _) => "unreachable".to_string(),
+        }
+    );
+}
@@ -0,0 +1,168 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+};
+
+use clap::ValueEnum;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl, SelectableHelper};
+use mine_search::{fields::Selection, filter::Filter};
+
+use crate::{
+    database::{DatabaseWrapper, Edition, ServerModel, SERVER_FIELDS},
+    nbt::{self, Tag},
+    schema,
+};
+
+/// Columns of the CSV export without `--fields`.
+const CSV_FIELDS: &str = "id,ip,edition,online,max,peak_online,version,protocol,license,white_list,software,motd_plain,source,last_seen";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// Client multiplayer list (NBT)
+    Dat,
+    /// One JSON object per server and line
+    Jsonl,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn from_path(path: &str) -> Self {
+        match path.rsplit_once('.').map(|(_, ext)| ext) {
+            Some("jsonl" | "json" | "ndjson") => ExportFormat::Jsonl,
+            Some("csv") => ExportFormat::Csv,
+            _ => ExportFormat::Dat,
+        }
+    }
+}
+
+/// Writes the stored servers matching `filter` into `out`: as a client
+/// `servers.dat`, named after their version so they are easy to tell apart in
+/// the multiplayer list, or as JSON lines or CSV for other tools. `fields`
+/// picks the columns of both; JSON lines hold the whole row without it.
+pub fn run(filter: Option<&str>, out: &str, format: Option<ExportFormat>, fields: Option<&str>) {
+    let filter = filter.map(|f| Filter::parse(f).unwrap_or_else(|e| panic!("{}", e)));
+    let format = format.unwrap_or_else(|| ExportFormat::from_path(out));
+
+    let mut db = DatabaseWrapper::establish_read();
+    let mut query = schema::servers::dsl::servers
+        .select(ServerModel::as_select())
+        .order(schema::servers::dsl::id)
+        .into_boxed();
+    // Java clients can't join Bedrock servers
+    if format == ExportFormat::Dat {
+        query = query.filter(schema::servers::dsl::edition.eq(Edition::Java.as_str()));
+    }
+    let servers: Vec<ServerModel> = query
+        .load(&mut db.conn)
+        .unwrap()
+        .into_iter()
+        .filter(|server| filter.as_ref().is_none_or(|f| f.matches(server)))
+        .collect();
+
+    write(&servers, out, format, fields);
+}
+
+/// Writes `servers` into `out` like `run` does.
+pub fn write(servers: &[ServerModel], out: &str, format: ExportFormat, fields: Option<&str>) {
+    if format == ExportFormat::Dat && fields.is_some() {
+        panic!("--fields applies to jsonl and csv exports");
+    }
+    let fields = match (fields, format) {
+        (Some(list), _) => Some(list),
+        (None, ExportFormat::Csv) => Some(CSV_FIELDS),
+        (None, _) => None,
+    }
+    .map(|list| Selection::parse(list, SERVER_FIELDS).unwrap_or_else(|e| panic!("{}", e)));
+
+    let exported = match format {
+        ExportFormat::Dat => {
+            let entries: Vec<Tag> = servers
+                .iter()
+                .filter(|server| server.edition() == Edition::Java)
+                .map(entry)
+                .collect();
+            let exported = entries.len();
+            let root = Tag::Compound(vec![("servers".to_string(), Tag::List(entries))]);
+
+            fs::write(out, nbt::write(&root, ""))
+                .unwrap_or_else(|e| panic!("Error writing {}: {}", out, e));
+            exported
+        }
+        ExportFormat::Jsonl | ExportFormat::Csv => {
+            let written = if out == "-" {
+                write_rows(&mut io::stdout().lock(), servers, format, fields.as_ref())
+            } else {
+                let file =
+                    File::create(out).unwrap_or_else(|e| panic!("Error writing {}: {}", out, e));
+                write_rows(&mut BufWriter::new(file), servers, format, fields.as_ref())
+            };
+            written.unwrap_or_else(|e| panic!("Error writing {}: {}", out, e));
+            servers.len()
+        }
+    };
+
+    // Keep standard output clean for piping
+    if out != "-" {
+        println!("Exported {} servers to {}", exported, out);
+    }
+}
+
+fn write_rows(
+    out: &mut impl Write,
+    servers: &[ServerModel],
+    format: ExportFormat,
+    fields: Option<&Selection>,
+) -> io::Result<()> {
+    if let (ExportFormat::Csv, Some(fields)) = (format, fields) {
+        writeln!(out, "{}", csv_row(fields.names()))?;
+    }
+
+    for server in servers {
+        match (format, fields) {
+            (ExportFormat::Csv, Some(fields)) => {
+                writeln!(out, "{}", csv_row(&fields.to_text(server)))?
+            }
+            (_, Some(fields)) => writeln!(out, "{}", fields.to_json(server))?,
+            (_, None) => writeln!(out, "{}", serde_json::to_string(server)?)?,
+        }
+    }
+    out.flush()
+}
+
+fn csv_row(fields: &[String]) -> String {
+    fields
+        .iter()
+        .map(|field| csv_field(field))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Quotes a field containing separators, quotes or line breaks (RFC 4180).
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn entry(server: &ServerModel) -> Tag {
+    let mut fields = vec![
+        (
+            "name".to_string(),
+            Tag::String(format!("{} | {}", server.version_name, server.ip)),
+        ),
+        ("ip".to_string(), Tag::String(server.ip.clone())),
+    ];
+
+    // The client stores icons as bare base64 PNG
+    if let Some(icon) = server
+        .favicon
+        .as_deref()
+        .and_then(|f| f.strip_prefix("data:image/png;base64,"))
+    {
+        fields.push(("icon".to_string(), Tag::String(icon.replace('\n', ""))));
+    }
+
+    Tag::Compound(fields)
+}
@@ -0,0 +1,143 @@
+use std::fs;
+
+use diesel::{sql_query, RunQueryDsl};
+use serde_json::{json, Value};
+
+use crate::database::DatabaseWrapper;
+
+/// Views shaped for Grafana's Postgres datasource: `time`, then `metric`
+/// naming the series, then the value.
+const VIEWS: &[(&str, &str)] = &[
+    (
+        "grafana_player_counts",
+        "SELECT date_bin('5 minutes', taken_at, TIMESTAMP '2000-01-01') AS time,
+                address AS metric,
+                avg(online)::float8 AS online
+         FROM watch_snapshots
+         WHERE reachable
+         GROUP BY 1, 2",
+    ),
+    (
+        "grafana_availability",
+        "SELECT date_trunc('hour', taken_at) AS time,
+                address AS metric,
+                avg(CASE WHEN reachable THEN 1.0 ELSE 0.0 END)::float8 AS availability
+         FROM watch_snapshots
+         GROUP BY 1, 2",
+    ),
+    (
+        "grafana_discovery_rate",
+        "SELECT date_trunc('hour', discovered_at) AS time,
+                discovery_source AS metric,
+                count(*) AS discovered
+         FROM servers
+         GROUP BY 1, 2",
+    ),
+    (
+        "grafana_total_players",
+        "SELECT time, 'players' AS metric, sum(online)::float8 AS online
+         FROM (
+             SELECT date_trunc('hour', checked_at) AS time, server_id, avg(online) AS online
+             FROM pings
+             GROUP BY 1, 2
+         ) hourly
+         GROUP BY 1, 2",
+    ),
+    (
+        "grafana_current_population",
+        "SELECT now() AS time,
+                version_name AS metric,
+                sum(online)::int8 AS online
+         FROM servers
+         GROUP BY 2",
+    ),
+];
+
+/// Creates (or replaces) the views and writes a dashboard for Grafana's file
+/// provisioning that reads from them through the datasource `datasource`.
+pub fn run(out: &str, datasource: &str) {
+    let mut db = DatabaseWrapper::establish();
+
+    for (name, query) in VIEWS {
+        sql_query(format!("CREATE OR REPLACE VIEW {} AS {}", name, query))
+            .execute(&mut db.conn)
+            .unwrap_or_else(|e| panic!("Error creating view {}: {}", name, e));
+        println!("[+] View {}", name);
+    }
+
+    let dashboard = serde_json::to_string_pretty(&dashboard(datasource)).unwrap();
+    fs::write(out, dashboard).unwrap_or_else(|e| panic!("Error writing {}: {}", out, e));
+    println!("Dashboard written to {}", out);
+}
+
+fn dashboard(datasource: &str) -> Value {
+    let datasource = json!({ "type": "grafana-postgresql-datasource", "uid": datasource });
+
+    let panel = |id: u32, title: &str, kind: &str, sql: &str, x: u32, y: u32| {
+        json!({
+            "id": id,
+            "title": title,
+            "type": kind,
+            "datasource": datasource,
+            "gridPos": { "h": 8, "w": 12, "x": x, "y": y },
+            "targets": [{
+                "refId": "A",
+                "datasource": datasource,
+                "format": if kind == "timeseries" { "time_series" } else { "table" },
+                "rawQuery": true,
+                "editorMode": "code",
+                "rawSql": sql,
+            }],
+        })
+    };
+
+    json!({
+        "uid": "mine-search",
+        "title": "mine_search",
+        "schemaVersion": 39,
+        "time": { "from": "now-7d", "to": "now" },
+        "refresh": "1m",
+        "panels": [
+            panel(
+                1,
+                "Players (watched servers)",
+                "timeseries",
+                "SELECT time, metric, online FROM grafana_player_counts WHERE $__timeFilter(time) ORDER BY 1",
+                0,
+                0,
+            ),
+            panel(
+                2,
+                "Availability (watched servers)",
+                "timeseries",
+                "SELECT time, metric, availability FROM grafana_availability WHERE $__timeFilter(time) ORDER BY 1",
+                12,
+                0,
+            ),
+            panel(
+                3,
+                "Discoveries per hour",
+                "timeseries",
+                "SELECT time, metric, discovered FROM grafana_discovery_rate WHERE $__timeFilter(time) ORDER BY 1",
+                0,
+                8,
+            ),
+            panel(
+                4,
+                "Players online by version",
+                "bargauge",
+                "SELECT metric AS version, online FROM grafana_current_population ORDER BY online DESC LIMIT 15",
+                12,
+                8,
+            ),
+            panel(
+                5,
+                "Players on all known servers",
+                "timeseries",
+                "SELECT time, metric, online FROM grafana_total_players WHERE $__timeFilter(time) ORDER BY 1",
+                0,
+                16,
+            ),
+        ],
+    })
+}
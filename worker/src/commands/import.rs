@@ -0,0 +1,189 @@
+use std::{fs, net::IpAddr, sync::Arc, time::Duration};
+
+use chrono::{Local, Timelike};
+use diesel::{dsl::insert_into, RunQueryDsl};
+use hickory_resolver::TokioAsyncResolver;
+use mine_search::connect;
+use tokio::{sync::Semaphore, time::timeout};
+
+use crate::{
+    commands::domains::{find_server, probe, resolve},
+    conn_wrapper::Connection,
+    database::{DiscoverySource, SavedServerUpsert},
+    enrich, etiquette, handle_valid_ip,
+    nbt::{self, Tag},
+    reporter::Pass,
+    scanner::Exclusions,
+    schema, Context,
+};
+
+/// Probes every entry of a client `servers.dat` and records it in
+/// `saved_servers` under its saved name, linked to the stored server. Entries
+/// on the default port that are not stored yet go through the full discovery
+/// pipeline, like imported domains.
+pub async fn run(file: &str, concurrency: usize) {
+    let data = fs::read(file).unwrap_or_else(|e| panic!("Error reading {}: {}", file, e));
+    let entries = entries(&data).unwrap_or_else(|| panic!("{} is not a servers.dat file", file));
+
+    let resolver = Arc::new(TokioAsyncResolver::tokio_from_system_conf().unwrap());
+    let ctx = Arc::new(Context::establish());
+    enrich::start(&ctx);
+
+    println!("Saved servers: {}", entries.len());
+    ctx.reporter.pass_started(Pass::Import);
+
+    let exclusions = Arc::new(Exclusions::new(None));
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+
+    let mut handles = vec![];
+    for (name, address) in entries {
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let th_ctx = ctx.clone();
+        let th_resolver = resolver.clone();
+        let th_exclusions = exclusions.clone();
+
+        handles.push(tokio::spawn(async move {
+            let _permit = permit;
+            check_entry(&name, &address, &th_resolver, &th_exclusions, &th_ctx).await;
+        }));
+    }
+
+    for handle in handles {
+        let _ = handle.await;
+    }
+
+    ctx.reporter.pass_finished(Pass::Import);
+    ctx.finish_run().await;
+}
+
+/// `(name, address)` of every entry. Hidden entries are included.
+fn entries(data: &[u8]) -> Option<Vec<(String, String)>> {
+    let (root, _) = nbt::read(data, false).ok()?;
+    let Some(Tag::List(servers)) = root.get("servers") else {
+        return None;
+    };
+
+    Some(
+        servers
+            .iter()
+            .filter_map(|server| {
+                let Some(Tag::String(address)) = server.get("ip") else {
+                    return None;
+                };
+                let name = match server.get("name") {
+                    Some(Tag::String(name)) => name.clone(),
+                    _ => address.clone(),
+                };
+                Some((name, address.trim().to_string()))
+            })
+            .filter(|(_, address)| !address.is_empty())
+            .collect(),
+    )
+}
+
+/// Splits `host[:port]`, keeping bracketed IPv6 literals intact.
+pub fn split_address(address: &str) -> (&str, Option<u16>) {
+    if let Some(rest) = address.strip_prefix('[') {
+        if let Some((host, port)) = rest.split_once(']') {
+            return (host, port.strip_prefix(':').and_then(|p| p.parse().ok()));
+        }
+    }
+    match address.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => (host, port.parse().ok()),
+        _ => (address, None),
+    }
+}
+
+pub async fn locate(address: &str, resolver: &TokioAsyncResolver) -> Option<(IpAddr, u16)> {
+    let (host, port) = split_address(address);
+
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Some((ip, port.unwrap_or(25565)));
+    }
+    // The client only looks up SRV records when no port is given
+    match port {
+        Some(port) => Some((resolver.lookup_ip(host).await.ok()?.iter().next()?, port)),
+        None => resolve(&host.to_lowercase(), resolver)
+            .await
+            .map(|(ip, port, _)| (ip, port)),
+    }
+}
+
+async fn check_entry(
+    name: &str,
+    address: &str,
+    resolver: &TokioAsyncResolver,
+    exclusions: &Exclusions,
+    ctx: &Arc<Context>,
+) {
+    let located = locate(address, resolver).await;
+    if let Some(own) = located.and_then(|(ip, _)| exclusions.own_address(&ip)) {
+        eprintln!(
+            "[!] {} ({}) is in own network {}, not probed",
+            name, address, own
+        );
+        return;
+    }
+    let host = split_address(address).0;
+
+    let status = match located {
+        Some((ip, port)) => probe(host, ip, port).await,
+        None => None,
+    };
+
+    let mut server_id = match located {
+        Some((ip, _)) => find_server(&ip, ctx).await,
+        None => None,
+    };
+
+    if let (Some((ip, 25565)), Some(_), None) = (located, &status, server_id) {
+        let connected = if etiquette::allowed(&ip.to_string()) {
+            connect(&ip, 25565).await.map(Connection::from_stream)
+        } else {
+            None
+        };
+        if let Some(Ok(conn)) = connected {
+            let _ = timeout(
+                Duration::from_secs(10),
+                handle_valid_ip(conn, &ip, 25565, DiscoverySource::Import, ctx),
+            )
+            .await;
+            server_id = find_server(&ip, ctx).await;
+        }
+    }
+
+    let upsert = SavedServerUpsert {
+        name,
+        address,
+        ip: located.map(|(ip, _)| ip.to_string()),
+        port: located.map(|(_, port)| port as i32),
+        reachable: status.is_some(),
+        server_id,
+        last_checked: Local::now().naive_local().with_nanosecond(0).unwrap(),
+    };
+
+    insert_into(schema::saved_servers::dsl::saved_servers)
+        .values(&upsert)
+        .on_conflict((
+            schema::saved_servers::dsl::name,
+            schema::saved_servers::dsl::address,
+        ))
+        .do_update()
+        .set(&upsert)
+        .execute(&mut ctx.pool().get().await.conn)
+        .unwrap();
+
+    println!(
+        "{} ({}) -> {} | {}",
+        name,
+        address,
+        located
+            .map(|(ip, port)| format!("{}:{}", ip, port))
+            .unwrap_or("unresolved".to_string()),
+        match (&status, server_id) {
+            (Some(_), Some(id)) => format!("linked to #{}", id),
+            (Some(_), None) => "reachable".to_string(),
+            (None, _) => "unreachable".to_string(),
+        }
+    );
+}
@@ -0,0 +1,384 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::NaiveDateTime;
+use diesel::{
+    dsl::{insert_into, sql, update},
+    prelude::{AsChangeset, Insertable, Queryable},
+    sql_query,
+    sql_types::{Integer, Text, Timestamp},
+    upsert::excluded,
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, QueryableByName,
+    RunQueryDsl, Selectable, SelectableHelper,
+};
+use serde_json::Value;
+
+use crate::{database::DatabaseWrapper, schema};
+
+/// Rows per insert statement, well below the bind parameter limit.
+const CHUNK: usize = 1000;
+
+/// A stored server without the columns that only make sense in the database
+/// it came from (`id`, `run_id`, `canonical_id`) and the ones merged from both
+/// sides (`peak_online`, `peak_online_at`, `discovered_at`).
+#[derive(Queryable, Selectable, Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::servers)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(treat_none_as_null = true)]
+struct ServerRow {
+    ip: String,
+    edition: String,
+    online: i32,
+    max: i32,
+    version_name: String,
+    protocol: i32,
+    license: Option<bool>,
+    white_list: Option<bool>,
+    last_seen: NaiveDateTime,
+    description: Value,
+    discovery_source: String,
+    tags: Vec<String>,
+    query_map: Option<String>,
+    query_game_type: Option<String>,
+    query_game_id: Option<String>,
+    query_host_port: Option<i32>,
+    tps: Option<f32>,
+    queue_server: bool,
+    hop_count: Option<i32>,
+    last_hop_network: Option<String>,
+    raw_status: Option<String>,
+    anomalous_response: bool,
+    status_class: Option<String>,
+    on_demand_host: Option<String>,
+    lifecycle: Option<String>,
+    lifecycle_changed_at: Option<NaiveDateTime>,
+    lifecycle_checked_at: Option<NaiveDateTime>,
+    modpack: Option<String>,
+    modpack_version: Option<String>,
+    proxy_protocol: bool,
+    favicon: Option<String>,
+    appeal_contact: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::players)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct PlayerRow {
+    uuid: String,
+    name: String,
+    last_seen: NaiveDateTime,
+    server_id: Option<i32>,
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::motd_history)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct MotdRow {
+    server_id: i32,
+    old_value: Value,
+    new_value: Value,
+    observed_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::version_history)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct VersionRow {
+    server_id: i32,
+    old_version_name: String,
+    new_version_name: String,
+    old_protocol: i32,
+    new_protocol: i32,
+    observed_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::white_list_history)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct WhiteListRow {
+    server_id: i32,
+    old_license: Option<bool>,
+    new_license: bool,
+    old_white_list: Option<bool>,
+    new_white_list: Option<bool>,
+    observed_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::pings)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct PingRow {
+    server_id: i32,
+    online: i32,
+    max: i32,
+    version_name: String,
+    protocol: i32,
+    latency_ms: Option<i32>,
+    checked_at: NaiveDateTime,
+    samples: i32,
+}
+
+#[derive(QueryableByName)]
+struct Migration {
+    #[diesel(sql_type = Text)]
+    version: String,
+}
+
+fn schema_version(conn: &mut PgConnection) -> Option<String> {
+    sql_query("SELECT version FROM __diesel_schema_migrations ORDER BY version DESC LIMIT 1")
+        .get_result::<Migration>(conn)
+        .optional()
+        .ok()
+        .flatten()
+        .map(|m| m.version)
+}
+
+#[derive(Default)]
+struct Counts {
+    new_servers: usize,
+    updated_servers: usize,
+    players: usize,
+    history: usize,
+    pings: usize,
+}
+
+/// Merges servers, players, history and pings from the database at `url`
+/// (another instance, e.g. one scanning from a second machine) into this one,
+/// in one transaction. Servers are matched by address and edition; the more
+/// recently seen side wins, while the earliest discovery and highest peak
+/// are kept from either. History rows already present are skipped, so the
+/// same source can be merged again later.
+pub fn run(url: &str) {
+    let mut remote = PgConnection::establish(url)
+        .unwrap_or_else(|e| panic!("Error connecting to {}: {}", url, e));
+    let mut db = DatabaseWrapper::establish();
+
+    let (theirs, ours) = (schema_version(&mut remote), schema_version(&mut db.conn));
+    if theirs != ours {
+        panic!(
+            "Schema versions differ (source {}, this database {}), run the migrations on both first",
+            theirs.as_deref().unwrap_or("none"),
+            ours.as_deref().unwrap_or("none")
+        );
+    }
+
+    let servers: Vec<(i32, ServerRow, i32, NaiveDateTime, NaiveDateTime)> =
+        schema::servers::dsl::servers
+            .select((
+                schema::servers::dsl::id,
+                ServerRow::as_select(),
+                schema::servers::dsl::peak_online,
+                schema::servers::dsl::peak_online_at,
+                schema::servers::dsl::discovered_at,
+            ))
+            .load(&mut remote)
+            .unwrap();
+    let players: Vec<PlayerRow> = schema::players::dsl::players
+        .select(PlayerRow::as_select())
+        .load(&mut remote)
+        .unwrap();
+    let motd: Vec<MotdRow> = schema::motd_history::dsl::motd_history
+        .select(MotdRow::as_select())
+        .load(&mut remote)
+        .unwrap();
+    let versions: Vec<VersionRow> = schema::version_history::dsl::version_history
+        .select(VersionRow::as_select())
+        .load(&mut remote)
+        .unwrap();
+    let white_list: Vec<WhiteListRow> = schema::white_list_history::dsl::white_list_history
+        .select(WhiteListRow::as_select())
+        .load(&mut remote)
+        .unwrap();
+    let pings: Vec<PingRow> = schema::pings::dsl::pings
+        .select(PingRow::as_select())
+        .load(&mut remote)
+        .unwrap();
+    println!(
+        "Read {} servers, {} players, {} history rows and {} pings from the source",
+        servers.len(),
+        players.len(),
+        motd.len() + versions.len() + white_list.len(),
+        pings.len()
+    );
+
+    let counts = db
+        .conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            let mut counts = Counts::default();
+
+            // Source server id to the id of the same server here
+            let mut ids: HashMap<i32, i32> = HashMap::new();
+            for (remote_id, row, peak_online, peak_online_at, discovered_at) in &servers {
+                let local: Option<(i32, NaiveDateTime)> = schema::servers::dsl::servers
+                    .filter(schema::servers::dsl::ip.eq(&row.ip))
+                    .filter(schema::servers::dsl::edition.eq(&row.edition))
+                    .select((schema::servers::dsl::id, schema::servers::dsl::last_seen))
+                    .first(conn)
+                    .optional()?;
+
+                let id = match local {
+                    None => {
+                        counts.new_servers += 1;
+                        insert_into(schema::servers::dsl::servers)
+                            .values((
+                                row,
+                                schema::servers::dsl::peak_online.eq(peak_online),
+                                schema::servers::dsl::peak_online_at.eq(peak_online_at),
+                                schema::servers::dsl::discovered_at.eq(discovered_at),
+                            ))
+                            .returning(schema::servers::dsl::id)
+                            .get_result(conn)?
+                    }
+                    Some((id, last_seen)) => {
+                        if row.last_seen > last_seen {
+                            counts.updated_servers += 1;
+                            update(schema::servers::dsl::servers.find(id))
+                                .set(row)
+                                .execute(conn)?;
+                        }
+                        sql_query(
+                            "UPDATE servers SET \
+                                 discovered_at = LEAST(discovered_at, $2), \
+                                 peak_online_at = CASE WHEN $3 > peak_online \
+                                     THEN $4 ELSE peak_online_at END, \
+                                 peak_online = GREATEST(peak_online, $3) \
+                             WHERE id = $1",
+                        )
+                        .bind::<Integer, _>(id)
+                        .bind::<Timestamp, _>(discovered_at)
+                        .bind::<Integer, _>(peak_online)
+                        .bind::<Timestamp, _>(peak_online_at)
+                        .execute(conn)?;
+                        id
+                    }
+                };
+                ids.insert(*remote_id, id);
+            }
+
+            let players: Vec<PlayerRow> = players
+                .into_iter()
+                .filter_map(|player| {
+                    let server_id = *ids.get(&player.server_id?)?;
+                    Some(PlayerRow {
+                        server_id: Some(server_id),
+                        ..player
+                    })
+                })
+                .collect();
+            for chunk in players.chunks(CHUNK) {
+                counts.players += insert_into(schema::players::dsl::players)
+                    .values(chunk)
+                    .on_conflict((schema::players::dsl::name, schema::players::dsl::server_id))
+                    .do_update()
+                    .set((
+                        schema::players::dsl::uuid.eq(excluded(schema::players::dsl::uuid)),
+                        // Whichever side saw the player last
+                        schema::players::dsl::last_seen.eq(sql::<Timestamp>(
+                            "GREATEST(players.last_seen, excluded.last_seen)",
+                        )),
+                    ))
+                    .execute(conn)?;
+            }
+
+            let mut known: HashSet<(i32, NaiveDateTime)> = schema::motd_history::dsl::motd_history
+                .select((
+                    schema::motd_history::dsl::server_id,
+                    schema::motd_history::dsl::observed_at,
+                ))
+                .load::<(i32, NaiveDateTime)>(conn)?
+                .into_iter()
+                .collect();
+            let motd: Vec<MotdRow> = motd
+                .into_iter()
+                .filter_map(|row| {
+                    let server_id = *ids.get(&row.server_id)?;
+                    known
+                        .insert((server_id, row.observed_at))
+                        .then_some(MotdRow { server_id, ..row })
+                })
+                .collect();
+            for chunk in motd.chunks(CHUNK) {
+                counts.history += insert_into(schema::motd_history::dsl::motd_history)
+                    .values(chunk)
+                    .execute(conn)?;
+            }
+
+            let mut known: HashSet<(i32, NaiveDateTime)> =
+                schema::version_history::dsl::version_history
+                    .select((
+                        schema::version_history::dsl::server_id,
+                        schema::version_history::dsl::observed_at,
+                    ))
+                    .load::<(i32, NaiveDateTime)>(conn)?
+                    .into_iter()
+                    .collect();
+            let versions: Vec<VersionRow> = versions
+                .into_iter()
+                .filter_map(|row| {
+                    let server_id = *ids.get(&row.server_id)?;
+                    known
+                        .insert((server_id, row.observed_at))
+                        .then_some(VersionRow { server_id, ..row })
+                })
+                .collect();
+            for chunk in versions.chunks(CHUNK) {
+                counts.history += insert_into(schema::version_history::dsl::version_history)
+                    .values(chunk)
+                    .execute(conn)?;
+            }
+
+            let mut known: HashSet<(i32, NaiveDateTime)> =
+                schema::white_list_history::dsl::white_list_history
+                    .select((
+                        schema::white_list_history::dsl::server_id,
+                        schema::white_list_history::dsl::observed_at,
+                    ))
+                    .load::<(i32, NaiveDateTime)>(conn)?
+                    .into_iter()
+                    .collect();
+            let white_list: Vec<WhiteListRow> = white_list
+                .into_iter()
+                .filter_map(|row| {
+                    let server_id = *ids.get(&row.server_id)?;
+                    known
+                        .insert((server_id, row.observed_at))
+                        .then_some(WhiteListRow { server_id, ..row })
+                })
+                .collect();
+            for chunk in white_list.chunks(CHUNK) {
+                counts.history += insert_into(schema::white_list_history::dsl::white_list_history)
+                    .values(chunk)
+                    .execute(conn)?;
+            }
+
+            let mut known: HashSet<(i32, NaiveDateTime)> = schema::pings::dsl::pings
+                .select((
+                    schema::pings::dsl::server_id,
+                    schema::pings::dsl::checked_at,
+                ))
+                .load::<(i32, NaiveDateTime)>(conn)?
+                .into_iter()
+                .collect();
+            let pings: Vec<PingRow> = pings
+                .into_iter()
+                .filter_map(|row| {
+                    let server_id = *ids.get(&row.server_id)?;
+                    known
+                        .insert((server_id, row.checked_at))
+                        .then_some(PingRow { server_id, ..row })
+                })
+                .collect();
+            for chunk in pings.chunks(CHUNK) {
+                counts.pings += insert_into(schema::pings::dsl::pings)
+                    .values(chunk)
+                    .execute(conn)?;
+            }
+
+            Ok(counts)
+        })
+        .unwrap();
+
+    println!(
+        "Merged {} new and {} updated servers, {} players, {} history rows and {} pings",
+        counts.new_servers, counts.updated_servers, counts.players, counts.history, counts.pings
+    );
+}
@@ -0,0 +1,119 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+};
+
+use diesel::{Connection, ExpressionMethods, QueryDsl, RunQueryDsl, SelectableHelper};
+use mine_search::description_to_str;
+
+use crate::{
+    database::{DatabaseWrapper, ServerModel},
+    schema,
+};
+
+/// Player names two records must share to be linked without a favicon.
+const MIN_SHARED_PLAYERS: usize = 3;
+
+/// Links records that are the same logical server on several addresses
+/// (anycast, proxies, networks with many entry IPs). Candidates need the same
+/// MOTD, max players and favicon; identical defaults are common, so they are
+/// linked only when the favicon is custom or their player samples overlap.
+/// The earliest discovered record of each group becomes canonical and the
+/// others point at it in `canonical_id`.
+pub fn run() {
+    let mut db = DatabaseWrapper::establish();
+
+    let servers: Vec<ServerModel> = schema::servers::dsl::servers
+        .select(ServerModel::as_select())
+        .order(schema::servers::dsl::id)
+        .load(&mut db.conn)
+        .unwrap();
+
+    let mut players: HashMap<i32, HashSet<String>> = HashMap::new();
+    for (server_id, name) in schema::players::dsl::players
+        .select((schema::players::dsl::server_id, schema::players::dsl::name))
+        .load::<(Option<i32>, String)>(&mut db.conn)
+        .unwrap()
+    {
+        if let Some(server_id) = server_id {
+            players.entry(server_id).or_default().insert(name);
+        }
+    }
+
+    let mut candidates: HashMap<(String, i32, Option<u64>), Vec<&ServerModel>> = HashMap::new();
+    for server in &servers {
+        let motd = description_to_str(server.description["payload"].clone()).unwrap_or_default();
+        candidates
+            .entry((motd, server.max, server.favicon.as_deref().map(hash)))
+            .or_default()
+            .push(server);
+    }
+
+    let mut links: Vec<(i32, i32)> = vec![];
+    for ((motd, _, favicon), group) in candidates {
+        if group.len() < 2 {
+            continue;
+        }
+
+        // A custom icon with the same MOTD and slots is enough on its own
+        if favicon.is_some() && !motd.trim().is_empty() {
+            links.extend(group[1..].iter().map(|server| (server.id, group[0].id)));
+            continue;
+        }
+
+        // Otherwise only records with known players can be compared. Records
+        // are sorted by id, so each one links to the earliest matching root.
+        let known: Vec<i32> = group
+            .iter()
+            .map(|server| server.id)
+            .filter(|id| players.contains_key(id))
+            .collect();
+        let mut roots: HashMap<i32, i32> = HashMap::new();
+        for (i, a) in known.iter().enumerate() {
+            for b in &known[i + 1..] {
+                if !roots.contains_key(b) && shared_players(&players, *a, *b) >= MIN_SHARED_PLAYERS
+                {
+                    let root = roots.get(a).copied().unwrap_or(*a);
+                    roots.insert(*b, root);
+                }
+            }
+        }
+        links.extend(roots);
+    }
+
+    db.conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            diesel::update(schema::servers::dsl::servers)
+                .set(schema::servers::dsl::canonical_id.eq(None::<i32>))
+                .execute(conn)?;
+
+            for (id, canonical_id) in &links {
+                diesel::update(schema::servers::dsl::servers)
+                    .filter(schema::servers::dsl::id.eq(id))
+                    .set(schema::servers::dsl::canonical_id.eq(canonical_id))
+                    .execute(conn)?;
+            }
+            Ok(())
+        })
+        .unwrap();
+
+    let groups: HashSet<i32> = links.iter().map(|(_, root)| *root).collect();
+    println!(
+        "Linked {} records to {} canonical servers",
+        links.len(),
+        groups.len()
+    );
+}
+
+fn hash(value: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn shared_players(players: &HashMap<i32, HashSet<String>>, a: i32, b: i32) -> usize {
+    match (players.get(&a), players.get(&b)) {
+        (Some(a), Some(b)) => a.intersection(b).count(),
+        _ => 0,
+    }
+}
@@ -0,0 +1,20 @@
+pub mod active;
+pub mod backfill;
+pub mod bench;
+pub mod census;
+pub mod compact;
+pub mod dedupe;
+pub mod doctor;
+pub mod domains;
+pub mod export;
+pub mod grafana;
+pub mod import;
+pub mod merge;
+pub mod mirrors;
+pub mod pipeline;
+pub mod report;
+pub mod rescan;
+pub mod show;
+pub mod sla;
+pub mod store;
+pub mod watch;
@@ -0,0 +1,389 @@
+use std::{
+    collections::HashSet,
+    fs,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use chrono::{Local, Timelike};
+use diesel::{
+    dsl::insert_into, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl, SelectableHelper,
+};
+use mine_search::filter::Filter;
+use serde_json::Value;
+use tokio::sync::Semaphore;
+
+use crate::{
+    commands::export::{self, ExportFormat},
+    database::{DiscoverySource, ServerModel},
+    enrich, jobs, profiles,
+    reporter::Pass,
+    scanner::Exclusions,
+    schema,
+    webhooks::{self, Event},
+    Context,
+};
+
+/// Stages in the order they run, each recorded once finished.
+const STAGES: &[&str] = &["import", "probe", "export", "notify"];
+/// Targets per insert of the import stage.
+const INSERT_CHUNK: usize = 10_000;
+const DEFAULT_PORT: u16 = 25565;
+
+/// Stages declared in `[pipeline.<name>]` of the config file.
+struct Pipeline {
+    name: String,
+    /// masscan output or a file of addresses
+    import: String,
+    /// Scan profile applied before probing
+    profile: Option<String>,
+    concurrency: usize,
+    exclude: Option<String>,
+    /// Narrows what is exported and notified about
+    filter: Option<Filter>,
+    export: Option<String>,
+    fields: Option<String>,
+    notify: Vec<String>,
+}
+
+impl Pipeline {
+    fn read(config: &str, name: &str) -> Result<Self, String> {
+        let mut pipeline = Pipeline {
+            name: name.to_string(),
+            import: String::new(),
+            profile: None,
+            concurrency: 100,
+            exclude: None,
+            filter: None,
+            export: None,
+            fields: None,
+            notify: vec![],
+        };
+
+        for (number, key, value) in profiles::section(config, &format!("pipeline.{}", name))? {
+            let at = |message: String| format!("{}:{}: {}", config, number, message);
+            match key.as_str() {
+                "import" => pipeline.import = value,
+                "profile" => pipeline.profile = Some(value),
+                "concurrency" => {
+                    pipeline.concurrency = value
+                        .parse()
+                        .ok()
+                        .filter(|concurrency| *concurrency > 0)
+                        .ok_or_else(|| at(format!("bad concurrency {}", value)))?
+                }
+                "exclude" => pipeline.exclude = Some(value),
+                "filter" => {
+                    pipeline.filter = Some(Filter::parse(&value).map_err(|e| at(e.to_string()))?)
+                }
+                "export" => pipeline.export = Some(value),
+                "fields" => pipeline.fields = Some(value),
+                "notify" => {
+                    pipeline.notify = value
+                        .split(',')
+                        .map(|url| url.trim().to_string())
+                        .filter(|url| !url.is_empty())
+                        .collect()
+                }
+                _ => return Err(at(format!("unknown pipeline setting {:?}", key))),
+            }
+        }
+
+        if pipeline.import.is_empty() {
+            return Err(format!("[pipeline.{}] needs an import file", name));
+        }
+        Ok(pipeline)
+    }
+}
+
+/// Runs pipeline `name` of `config`, continuing after the last stage an
+/// interrupted run of it finished unless `restart` is set.
+pub async fn run(config: &str, name: &str, restart: bool) {
+    let pipeline =
+        Pipeline::read(config, name).unwrap_or_else(|e| panic!("Pipeline {}: {}", name, e));
+    if let Some(profile) = &pipeline.profile {
+        profiles::apply(config, profile);
+    }
+
+    let ctx = Arc::new(Context::establish());
+    enrich::start(&ctx);
+
+    let unfinished: Option<(i32, Option<String>)> = schema::pipeline_runs::dsl::pipeline_runs
+        .filter(schema::pipeline_runs::dsl::name.eq(name))
+        .filter(schema::pipeline_runs::dsl::finished_at.is_null())
+        .order(schema::pipeline_runs::dsl::id.desc())
+        .select((
+            schema::pipeline_runs::dsl::id,
+            schema::pipeline_runs::dsl::stage,
+        ))
+        .first(&mut ctx.pool().get().await.conn)
+        .optional()
+        .unwrap();
+    let (id, done) = match unfinished.filter(|_| !restart) {
+        Some((id, stage)) => {
+            match &stage {
+                Some(stage) => println!(
+                    "[+] Resuming pipeline {} (#{}) after the {} stage",
+                    name, id, stage
+                ),
+                None => println!("[+] Resuming pipeline {} (#{})", name, id),
+            }
+            let done = stage.map_or(0, |stage| {
+                STAGES.iter().position(|s| *s == stage).unwrap_or(0) + 1
+            });
+            (id, done)
+        }
+        None => {
+            let id: i32 = insert_into(schema::pipeline_runs::dsl::pipeline_runs)
+                .values(schema::pipeline_runs::dsl::name.eq(name))
+                .returning(schema::pipeline_runs::dsl::id)
+                .get_result(&mut ctx.pool().get().await.conn)
+                .unwrap();
+            println!("[+] Starting pipeline {} (#{})", name, id);
+            (id, 0)
+        }
+    };
+
+    for stage in &STAGES[done..] {
+        match *stage {
+            "import" => import(&pipeline, id, &ctx).await,
+            "probe" => probe(&pipeline, id, &ctx).await,
+            "export" => {
+                if let Some(out) = &pipeline.export {
+                    let servers = matched(&pipeline, id, &ctx).await;
+                    export::write(
+                        &servers,
+                        out,
+                        ExportFormat::from_path(out),
+                        pipeline.fields.as_deref(),
+                    );
+                }
+            }
+            "notify" => {
+                if !pipeline.notify.is_empty() {
+                    notify(&pipeline, id, &ctx).await;
+                }
+            }
+            _ => unreachable!(),
+        }
+
+        diesel::update(schema::pipeline_runs::dsl::pipeline_runs.find(id))
+            .set(schema::pipeline_runs::dsl::stage.eq(stage))
+            .execute(&mut ctx.pool().get().await.conn)
+            .unwrap();
+    }
+
+    diesel::update(schema::pipeline_runs::dsl::pipeline_runs.find(id))
+        .set(
+            schema::pipeline_runs::dsl::finished_at
+                .eq(Local::now().naive_local().with_nanosecond(0).unwrap()),
+        )
+        .execute(&mut ctx.pool().get().await.conn)
+        .unwrap();
+    println!("[+] Pipeline {} finished", name);
+    ctx.finish_run().await;
+}
+
+/// Stores the targets of the import file, leaving out excluded addresses.
+async fn import(pipeline: &Pipeline, id: i32, ctx: &Context) {
+    let content = fs::read_to_string(&pipeline.import)
+        .unwrap_or_else(|e| panic!("Error reading {}: {}", pipeline.import, e));
+    let targets = parse_targets(&content)
+        .unwrap_or_else(|e| panic!("Error reading {}: {}", pipeline.import, e));
+
+    let exclusions = Exclusions::new(pipeline.exclude.as_deref());
+    let targets: Vec<(IpAddr, u16)> = targets
+        .into_iter()
+        .filter(|(ip, _)| exclusions.own_address(ip).is_none())
+        .filter(|(ip, _)| !matches!(ip, IpAddr::V4(v4) if exclusions.contains((*v4).into())))
+        .collect();
+
+    for chunk in targets.chunks(INSERT_CHUNK) {
+        let rows: Vec<_> = chunk
+            .iter()
+            .map(|(ip, port)| {
+                (
+                    schema::pipeline_targets::dsl::pipeline_run_id.eq(id),
+                    schema::pipeline_targets::dsl::ip.eq(ip.to_string()),
+                    schema::pipeline_targets::dsl::port.eq(*port as i32),
+                )
+            })
+            .collect();
+        insert_into(schema::pipeline_targets::dsl::pipeline_targets)
+            .values(rows)
+            .on_conflict_do_nothing()
+            .execute(&mut ctx.pool().get().await.conn)
+            .unwrap();
+    }
+    println!(
+        "Imported {} targets from {}",
+        targets.len(),
+        pipeline.import
+    );
+}
+
+/// Probes the targets not probed yet, so an interrupted stage continues
+/// where it stopped.
+async fn probe(pipeline: &Pipeline, id: i32, ctx: &Arc<Context>) {
+    let targets: Vec<(String, i32)> = schema::pipeline_targets::dsl::pipeline_targets
+        .filter(schema::pipeline_targets::dsl::pipeline_run_id.eq(id))
+        .filter(schema::pipeline_targets::dsl::probed.eq(false))
+        .select((
+            schema::pipeline_targets::dsl::ip,
+            schema::pipeline_targets::dsl::port,
+        ))
+        .load(&mut ctx.pool().get().await.conn)
+        .unwrap();
+    println!("Targets to probe: {}", targets.len());
+    ctx.reporter.pass_started(Pass::Pipeline);
+
+    let found = Arc::new(AtomicUsize::new(0));
+    let semaphore = Arc::new(Semaphore::new(pipeline.concurrency));
+    let mut handles = vec![];
+    for (ip, port) in targets {
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let ctx = ctx.clone();
+        let found = found.clone();
+
+        handles.push(tokio::spawn(async move {
+            let _permit = permit;
+            let server_id = match ip.parse::<IpAddr>() {
+                Ok(addr) => jobs::probe(&addr, port as u16, DiscoverySource::Import, &ctx).await,
+                Err(_) => None,
+            };
+            if server_id.is_some() {
+                found.fetch_add(1, Ordering::Relaxed);
+            }
+
+            diesel::update(schema::pipeline_targets::dsl::pipeline_targets.find((id, &ip, port)))
+                .set((
+                    schema::pipeline_targets::dsl::probed.eq(true),
+                    schema::pipeline_targets::dsl::server_id.eq(server_id),
+                ))
+                .execute(&mut ctx.pool().get().await.conn)
+                .unwrap();
+        }));
+    }
+
+    for handle in handles {
+        let _ = handle.await;
+    }
+    ctx.reporter.pass_finished(Pass::Pipeline);
+    println!("Servers found: {}", found.load(Ordering::Relaxed));
+}
+
+/// Servers the probe stage found, narrowed by the pipeline filter.
+async fn matched(pipeline: &Pipeline, id: i32, ctx: &Context) -> Vec<ServerModel> {
+    let ids: HashSet<i32> = schema::pipeline_targets::dsl::pipeline_targets
+        .filter(schema::pipeline_targets::dsl::pipeline_run_id.eq(id))
+        .select(schema::pipeline_targets::dsl::server_id)
+        .load::<Option<i32>>(&mut ctx.pool().get().await.conn)
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect();
+
+    schema::servers::dsl::servers
+        .filter(schema::servers::dsl::id.eq_any(ids))
+        .order(schema::servers::dsl::id)
+        .select(ServerModel::as_select())
+        .load(&mut ctx.pool().get().await.conn)
+        .unwrap()
+        .into_iter()
+        .filter(|server| pipeline.filter.as_ref().is_none_or(|f| f.matches(server)))
+        .collect()
+}
+
+async fn notify(pipeline: &Pipeline, id: i32, ctx: &Context) {
+    let events: Vec<Event> = matched(pipeline, id, ctx)
+        .await
+        .into_iter()
+        .map(|server| Event::PipelineMatched {
+            pipeline: pipeline.name.clone(),
+            ip: server.ip,
+            version_name: server.version_name,
+            online: server.online,
+            max: server.max,
+        })
+        .collect();
+
+    webhooks::send(&pipeline.notify, &events).await;
+    println!(
+        "Notified {} URLs of {} matches",
+        pipeline.notify.len(),
+        events.len()
+    );
+}
+
+/// Open TCP ports of masscan output in the list (`-oL`), grepable (`-oG`)
+/// or JSON (`-oJ`) format, or addresses one per line as `ip` or `ip:port`.
+fn parse_targets(content: &str) -> Result<Vec<(IpAddr, u16)>, String> {
+    let mut targets = vec![];
+
+    for (number, line) in content.lines().enumerate() {
+        let at = |message: String| format!("line {}: {}", number + 1, message);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line == "[" || line == "]" {
+            continue;
+        }
+
+        // JSON holds one object per line, separated by commas
+        if line.starts_with('{') {
+            let value: Value =
+                serde_json::from_str(line.trim_end_matches(',')).map_err(|e| at(e.to_string()))?;
+            let ip: IpAddr = value["ip"]
+                .as_str()
+                .and_then(|ip| ip.parse().ok())
+                .ok_or_else(|| at("no ip".to_string()))?;
+            for port in value["ports"].as_array().into_iter().flatten() {
+                // Banner records carry a service instead of a status
+                if port["proto"] == "tcp" && port["status"] == "open" {
+                    let port = port["port"]
+                        .as_u64()
+                        .and_then(|port| u16::try_from(port).ok())
+                        .ok_or_else(|| at("bad port".to_string()))?;
+                    targets.push((ip, port));
+                }
+            }
+            continue;
+        }
+
+        if let Some(rest) = line.split_once("Host: ").map(|(_, rest)| rest) {
+            let ip: IpAddr = rest
+                .split_whitespace()
+                .next()
+                .and_then(|ip| ip.parse().ok())
+                .ok_or_else(|| at("bad host".to_string()))?;
+            let Some((_, ports)) = rest.split_once("Ports: ") else {
+                continue;
+            };
+            for port in ports.split(',') {
+                let fields: Vec<&str> = port.trim().split('/').collect();
+                if let [port, "open", "tcp", ..] = fields[..] {
+                    targets.push((ip, port.parse().map_err(|_| at("bad port".to_string()))?));
+                }
+            }
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields[..] {
+            ["open", "tcp", port, ip, ..] => targets.push((
+                ip.parse().map_err(|_| at(format!("bad address {}", ip)))?,
+                port.parse().map_err(|_| at(format!("bad port {}", port)))?,
+            )),
+            ["open" | "closed", ..] => {}
+            [target] => match (target.parse::<SocketAddr>(), target.parse::<IpAddr>()) {
+                (Ok(addr), _) => targets.push((addr.ip(), addr.port())),
+                (_, Ok(ip)) => targets.push((ip, DEFAULT_PORT)),
+                _ => return Err(at(format!("bad address {}", target))),
+            },
+            _ => return Err(at(format!("unrecognised line {:?}", line))),
+        }
+    }
+
+    Ok(targets)
+}
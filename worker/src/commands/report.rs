@@ -0,0 +1,239 @@
+use std::{collections::HashMap, fmt::Write, fs, path::Path};
+
+use chrono::Local;
+use diesel::{QueryDsl, RunQueryDsl, SelectableHelper};
+use mine_search::description_to_str;
+
+use crate::{
+    database::{DatabaseWrapper, ServerModel},
+    schema,
+};
+
+/// Versions shown in the chart; the rest are summed up as "other".
+const CHART_VERSIONS: usize = 15;
+
+/// Writes `index.html` into `out`: summary numbers, charts and a sortable
+/// table of every stored server. Everything is inlined so the directory can be
+/// shared or opened without a server.
+pub fn run(out: &str) {
+    let mut db = DatabaseWrapper::establish_read();
+    let servers: Vec<ServerModel> = schema::servers::dsl::servers
+        .select(ServerModel::as_select())
+        .load(&mut db.conn)
+        .unwrap();
+
+    fs::create_dir_all(out).unwrap_or_else(|e| panic!("Error creating {}: {}", out, e));
+    let path = Path::new(out).join("index.html");
+    fs::write(&path, render(&servers))
+        .unwrap_or_else(|e| panic!("Error writing {}: {}", path.display(), e));
+
+    println!(
+        "Report of {} servers written to {}",
+        servers.len(),
+        path.display()
+    );
+}
+
+fn render(servers: &[ServerModel]) -> String {
+    let mut html = String::new();
+
+    let players: i64 = servers.iter().map(|s| s.online as i64).sum();
+    let cracked = servers.iter().filter(|s| s.license == Some(false)).count();
+    let white_listed = servers
+        .iter()
+        .filter(|s| s.white_list == Some(true))
+        .count();
+    let queues = servers.iter().filter(|s| s.queue_server).count();
+
+    write!(
+        html,
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>mine_search report</title>
+<style>{}</style>
+</head>
+<body>
+<h1>mine_search report</h1>
+<p class="muted">Generated {}</p>
+<div class="cards">"#,
+        STYLE,
+        Local::now().format("%Y-%m-%d %H:%M:%S")
+    )
+    .unwrap();
+
+    for (label, value) in [
+        ("Servers", servers.len() as i64),
+        ("Players online", players),
+        ("Cracked", cracked as i64),
+        ("Whitelisted", white_listed as i64),
+        ("Queue servers", queues as i64),
+    ] {
+        write!(
+            html,
+            r#"<div class="card"><div class="value">{}</div><div class="muted">{}</div></div>"#,
+            value, label
+        )
+        .unwrap();
+    }
+    html.push_str("</div>");
+
+    html.push_str("<h2>Versions</h2>");
+    html.push_str(&bar_chart(&version_counts(servers)));
+
+    html.push_str("<h2>License</h2>");
+    html.push_str(&bar_chart(&[
+        ("cracked".to_string(), cracked),
+        (
+            "licensed".to_string(),
+            servers.iter().filter(|s| s.license == Some(true)).count(),
+        ),
+        (
+            "unknown".to_string(),
+            servers.iter().filter(|s| s.license.is_none()).count(),
+        ),
+    ]));
+
+    html.push_str(
+        r#"<h2>Servers</h2>
+<table id="servers">
+<thead><tr><th></th><th>IP</th><th>Version</th><th data-type="number">Protocol</th><th data-type="number">Online</th><th data-type="number">Max</th><th data-type="number">Peak</th><th>License</th><th>Whitelist</th><th>MOTD</th><th>Last seen</th></tr></thead>
+<tbody>"#,
+    );
+
+    for server in servers {
+        let favicon = server
+            .favicon
+            .as_deref()
+            .filter(|f| f.starts_with("data:image/png;base64,"))
+            .map(|f| format!(r#"<img src="{}" alt="">"#, escape(f)))
+            .unwrap_or_default();
+        let motd = description_to_str(server.description["payload"].clone()).unwrap_or_default();
+
+        writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            favicon,
+            escape(&server.ip),
+            escape(&server.version_name),
+            server.protocol,
+            server.online,
+            server.max,
+            server.peak_online,
+            yes_no(server.license),
+            yes_no(server.white_list),
+            escape(&motd),
+            server.last_seen.format("%Y-%m-%d %H:%M")
+        )
+        .unwrap();
+    }
+
+    writeln!(
+        html,
+        "</tbody></table>\n<script>{}</script>\n</body>\n</html>",
+        SCRIPT
+    )
+    .unwrap();
+    html
+}
+
+fn version_counts(servers: &[ServerModel]) -> Vec<(String, usize)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for server in servers {
+        *counts.entry(server.version_name.as_str()).or_default() += 1;
+    }
+
+    let mut counts: Vec<(String, usize)> = counts
+        .into_iter()
+        .map(|(version, count)| (version.to_string(), count))
+        .collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    if counts.len() > CHART_VERSIONS {
+        let other = counts.split_off(CHART_VERSIONS).iter().map(|c| c.1).sum();
+        counts.push(("other".to_string(), other));
+    }
+    counts
+}
+
+/// Horizontal bars as inline SVG.
+fn bar_chart(bars: &[(String, usize)]) -> String {
+    let max = bars.iter().map(|b| b.1).max().unwrap_or(0).max(1);
+    let row = 22;
+    let mut svg = format!(
+        r#"<svg class="chart" width="720" height="{}" xmlns="http://www.w3.org/2000/svg">"#,
+        bars.len() * row
+    );
+
+    for (i, (label, count)) in bars.iter().enumerate() {
+        let y = i * row;
+        let width = count * 480 / max;
+        write!(
+            svg,
+            r#"<text x="170" y="{}" text-anchor="end">{}</text><rect x="180" y="{}" width="{}" height="16" rx="2"></rect><text x="{}" y="{}">{}</text>"#,
+            y + 13,
+            escape(label),
+            y + 2,
+            width,
+            186 + width,
+            y + 13,
+            count
+        )
+        .unwrap();
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+fn yes_no(value: Option<bool>) -> &'static str {
+    match value {
+        Some(true) => "yes",
+        Some(false) => "no",
+        None => "?",
+    }
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+pub const STYLE: &str = r#"
+body { font-family: system-ui, sans-serif; margin: 2rem; color: #1d1d1f; background: #fafafa; }
+.muted { color: #6e6e73; }
+.cards { display: flex; gap: 1rem; flex-wrap: wrap; }
+.card { background: #fff; border: 1px solid #ddd; border-radius: 8px; padding: 1rem 1.5rem; min-width: 8rem; }
+.value { font-size: 1.8rem; font-weight: 600; }
+.chart text { font-size: 12px; fill: #1d1d1f; }
+.chart rect { fill: #3a7bd5; }
+table { border-collapse: collapse; width: 100%; background: #fff; font-size: 14px; }
+th, td { border-bottom: 1px solid #eee; padding: 4px 8px; text-align: left; vertical-align: middle; }
+th { cursor: pointer; user-select: none; position: sticky; top: 0; background: #f0f0f0; }
+th.asc::after { content: " \25B2"; }
+th.desc::after { content: " \25BC"; }
+td img { width: 32px; height: 32px; image-rendering: pixelated; }
+"#;
+
+pub const SCRIPT: &str = r##"
+document.querySelectorAll("#servers th").forEach((th, column) => {
+  th.addEventListener("click", () => {
+    const body = document.querySelector("#servers tbody");
+    const numeric = th.dataset.type === "number";
+    const asc = !th.classList.contains("asc");
+    document.querySelectorAll("#servers th").forEach(h => h.classList.remove("asc", "desc"));
+    th.classList.add(asc ? "asc" : "desc");
+    const rows = Array.from(body.rows);
+    rows.sort((a, b) => {
+      const x = a.cells[column].textContent, y = b.cells[column].textContent;
+      const order = numeric ? Number(x) - Number(y) : x.localeCompare(y);
+      return asc ? order : -order;
+    });
+    rows.forEach(r => body.appendChild(r));
+  });
+});
+"##;
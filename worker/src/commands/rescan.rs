@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use diesel::{QueryDsl, RunQueryDsl, SelectableHelper};
+use mine_search::filter::Filter;
+use tokio::sync::Semaphore;
+
+use crate::{
+    database::ServerModel, recheck_access, reporter::Pass, schema, update_server, Context,
+};
+
+pub async fn run(filter: &str, license: bool, concurrency: usize) {
+    let filter = Filter::parse(filter).unwrap_or_else(|e| panic!("{}", e));
+    let ctx = Arc::new(Context::establish());
+
+    let servers: Vec<ServerModel> = schema::servers::dsl::servers
+        .select(ServerModel::as_select())
+        .load(&mut ctx.pool().get().await.conn)
+        .unwrap()
+        .into_iter()
+        .filter(|server| filter.matches(server))
+        .collect();
+
+    println!("Matching servers: {}", servers.len());
+    ctx.reporter.pass_started(Pass::Rescan);
+
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+
+    let mut handles = vec![];
+    for value in servers {
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let th_ctx = ctx.clone();
+
+        handles.push(tokio::spawn(async move {
+            let _permit = permit;
+            if license {
+                recheck_access(&value, value.protocol, &th_ctx).await;
+            }
+            update_server(value, &th_ctx, None).await;
+        }));
+    }
+
+    for handle in handles {
+        let _ = handle.await;
+    }
+
+    ctx.reporter.pass_finished(Pass::Rescan);
+    ctx.finish_run().await;
+}
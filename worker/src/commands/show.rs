@@ -0,0 +1,291 @@
+use diesel::{
+    sql_query,
+    sql_types::{Integer, Jsonb},
+    ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, QueryableByName, RunQueryDsl,
+};
+use hickory_resolver::TokioAsyncResolver;
+use serde_json::{json, Value};
+
+use crate::{
+    commands::import::locate,
+    database::{DatabaseWrapper, Edition},
+    schema,
+    serve::address_key,
+};
+
+#[derive(QueryableByName)]
+struct Document {
+    #[diesel(sql_type = Jsonb)]
+    doc: Value,
+}
+
+/// History lists of the document: key, query selecting the rows of server
+/// `$1`, newest first, at most `{limit}` of them.
+const HISTORY: &[(&str, &str)] = &[
+    (
+        "pings",
+        "SELECT online, max, version_name, protocol, latency_ms, checked_at FROM pings \
+         WHERE server_id = $1 ORDER BY checked_at DESC LIMIT {limit}",
+    ),
+    (
+        "liveness",
+        "SELECT reachable, latency_ms, checked_at FROM liveness \
+         WHERE server_id = $1 ORDER BY checked_at DESC LIMIT {limit}",
+    ),
+    (
+        "motd_history",
+        "SELECT old_value, new_value, observed_at FROM motd_history \
+         WHERE server_id = $1 ORDER BY observed_at DESC LIMIT {limit}",
+    ),
+    (
+        "version_history",
+        "SELECT old_version_name, new_version_name, old_protocol, new_protocol, observed_at \
+         FROM version_history WHERE server_id = $1 ORDER BY observed_at DESC LIMIT {limit}",
+    ),
+    (
+        "white_list_history",
+        "SELECT old_license, new_license, old_white_list, new_white_list, observed_at \
+         FROM white_list_history WHERE server_id = $1 ORDER BY observed_at DESC LIMIT {limit}",
+    ),
+    (
+        "watch_snapshots",
+        "SELECT address, reachable, online, max, version_name, players, latency_ms, taken_at \
+         FROM watch_snapshots WHERE server_id = $1 ORDER BY taken_at DESC LIMIT {limit}",
+    ),
+];
+
+/// Everything else known about the server, unlimited.
+const RELATED: &[(&str, &str)] = &[
+    (
+        "players",
+        "SELECT uuid, name, last_seen FROM players WHERE server_id = $1 ORDER BY last_seen DESC",
+    ),
+    (
+        "mods",
+        "SELECT mod_id, version, source, last_seen FROM mods WHERE server_id = $1 ORDER BY mod_id",
+    ),
+    (
+        "virtual_hosts",
+        "SELECT hostname, version_name, description, differs_from_ip, observed_at \
+         FROM virtual_hosts WHERE server_id = $1 ORDER BY hostname",
+    ),
+    (
+        "domains",
+        "SELECT domain, port, srv, reachable, last_checked FROM domains \
+         WHERE server_id = $1 ORDER BY domain",
+    ),
+    (
+        "saved_servers",
+        "SELECT name, address, reachable, last_checked FROM saved_servers \
+         WHERE server_id = $1 ORDER BY name",
+    ),
+    (
+        "mirrors",
+        "SELECT id, ip, edition, last_seen FROM servers WHERE canonical_id = $1 ORDER BY id",
+    ),
+];
+
+/// Rows of `query` as a JSON array.
+fn rows(conn: &mut PgConnection, query: &str, id: i32) -> Value {
+    sql_query(format!(
+        "SELECT COALESCE(jsonb_agg(t), '[]'::jsonb) AS doc FROM ({}) t",
+        query
+    ))
+    .bind::<Integer, _>(id)
+    .get_result::<Document>(conn)
+    .unwrap()
+    .doc
+}
+
+/// The row of `table` for server `id` as a JSON object.
+fn row(conn: &mut PgConnection, table: &str, key: &str, id: i32) -> Option<Value> {
+    sql_query(format!(
+        "SELECT to_jsonb(t) AS doc FROM {} t WHERE {} = $1",
+        table, key
+    ))
+    .bind::<Integer, _>(id)
+    .get_result::<Document>(conn)
+    .optional()
+    .unwrap()
+    .map(|d| d.doc)
+}
+
+/// `servers.ip` of an address, resolving host names like the client does.
+async fn resolve(address: &str, edition: Edition) -> Option<String> {
+    if let Some(key) = address_key(address, edition) {
+        return Some(key);
+    }
+    if edition != Edition::Java {
+        return None;
+    }
+
+    let resolver = TokioAsyncResolver::tokio_from_system_conf().ok()?;
+    let (ip, port) = locate(address, &resolver).await?;
+    Some(edition.key(&ip, port))
+}
+
+/// One document with everything stored about a server: its row with the GeoIP
+/// location, population curve, players, mods, virtual hosts, domains, mirrors and the
+/// latest `history` entries of every history table.
+fn document(conn: &mut PgConnection, id: i32, history: i64) -> Value {
+    let mut server = row(conn, "servers", "id", id).unwrap();
+
+    // The raw status repeats the parsed columns
+    if let Some(object) = server.as_object_mut() {
+        object.remove("raw_status");
+    }
+
+    let mut doc = json!({
+        "server": server,
+        "activity": row(conn, "server_activity", "server_id", id),
+    });
+    for (key, query) in RELATED {
+        doc[key] = rows(conn, query, id);
+    }
+    for (key, query) in HISTORY {
+        doc[key] = rows(conn, &query.replace("{limit}", &history.to_string()), id);
+    }
+    doc
+}
+
+/// Prints a stored server, as a single JSON document with `json`.
+pub async fn run(address: &str, edition: Edition, json: bool, history: i64) {
+    let Some(key) = resolve(address, edition).await else {
+        panic!("Could not resolve {}", address);
+    };
+
+    let mut db = DatabaseWrapper::establish_read();
+    let Some(id) = schema::servers::dsl::servers
+        .filter(schema::servers::dsl::ip.eq(&key))
+        .filter(schema::servers::dsl::edition.eq(edition.as_str()))
+        .select(schema::servers::dsl::id)
+        .first::<i32>(&mut db.conn)
+        .optional()
+        .unwrap()
+    else {
+        eprintln!("{} is not a stored {} server", key, edition.as_str());
+        std::process::exit(1);
+    };
+
+    let doc = document(&mut db.conn, id, history);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&doc).unwrap());
+    } else {
+        print_summary(&doc);
+    }
+}
+
+fn print_summary(doc: &Value) {
+    let server = &doc["server"];
+    let text = |value: &Value| match value {
+        Value::Null => "unknown".to_string(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let count = |key: &str| doc[key].as_array().map_or(0, Vec::len);
+
+    println!(
+        "{} ({}) #{}",
+        text(&server["ip"]),
+        text(&server["edition"]),
+        text(&server["id"])
+    );
+    println!(
+        "  version     {} (protocol {})",
+        text(&server["version_name"]),
+        text(&server["protocol"])
+    );
+    println!("  software    {}", text(&server["software"]));
+    if !server["modpack"].is_null() {
+        println!(
+            "  modpack     {} {}",
+            text(&server["modpack"]),
+            text(&server["modpack_version"])
+        );
+    }
+    println!(
+        "  motd        {}",
+        text(&server["motd_plain"]).replace('\n', " / ")
+    );
+    println!(
+        "  players     {}/{} online, peak {}",
+        text(&server["online"]),
+        text(&server["max"]),
+        text(&server["peak_online"])
+    );
+    println!(
+        "  access      license {}, whitelist {}",
+        text(&server["license"]),
+        text(&server["white_list"])
+    );
+    println!("  discovered  {}", text(&server["discovered_at"]));
+    println!("  last seen   {}", text(&server["last_seen"]));
+    if !server["era"].is_null() {
+        println!("  era         {}", text(&server["era"]));
+    }
+    if !server["blocked_at"].is_null() {
+        println!(
+            "  blocked     since {} ({})",
+            text(&server["blocked_at"]),
+            text(&server["block_kind"])
+        );
+    }
+    if !server["geo_checked_at"].is_null() {
+        println!(
+            "  location    {}, {}, AS{} {}",
+            text(&server["country"]),
+            text(&server["city"]),
+            text(&server["asn"]),
+            text(&server["isp"])
+        );
+    }
+    if let Some(region) = doc["activity"]["region"].as_str() {
+        println!("  audience    {}", region);
+    }
+
+    let mods: Vec<String> = doc["mods"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|m| text(&m["mod_id"]))
+        .collect();
+    if !mods.is_empty() {
+        println!("  mods        {}", mods.join(", "));
+    }
+    let players: Vec<String> = doc["players"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .take(20)
+        .map(|p| text(&p["name"]))
+        .collect();
+    if !players.is_empty() {
+        println!(
+            "  seen        {} ({} in total)",
+            players.join(", "),
+            count("players")
+        );
+    }
+    let checks = doc["liveness"].as_array().map_or(&[][..], Vec::as_slice);
+    if !checks.is_empty() {
+        let up = checks.iter().filter(|c| c["reachable"] == true).count();
+        println!(
+            "  liveness    up in {}/{} recent checks, last {}",
+            up,
+            checks.len(),
+            text(&checks[0]["checked_at"])
+        );
+    }
+    println!(
+        "  history     {} pings, {} MOTD, {} version and {} whitelist changes",
+        count("pings"),
+        count("motd_history"),
+        count("version_history"),
+        count("white_list_history")
+    );
+}
+
+/// Parses `--edition`.
+pub fn parse_edition(value: &str) -> Result<Edition, String> {
+    Edition::parse(value).ok_or_else(|| format!("{} is not java or bedrock", value))
+}
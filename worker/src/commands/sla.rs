@@ -0,0 +1,268 @@
+use std::{collections::HashMap, fmt::Write, fs};
+
+use chrono::{Datelike, Local, Months, NaiveDate, NaiveDateTime, TimeDelta};
+use clap::ValueEnum;
+use diesel::{
+    sql_query,
+    sql_types::{Bool, Int4, Timestamp},
+    ExpressionMethods, QueryDsl, QueryableByName, RunQueryDsl,
+};
+
+use crate::{
+    commands::{export::csv_field, report},
+    database::DatabaseWrapper,
+    schema,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SlaFormat {
+    Csv,
+    Html,
+}
+
+impl SlaFormat {
+    fn from_path(path: &str) -> Self {
+        match path.rsplit_once('.').map(|(_, ext)| ext) {
+            Some("html" | "htm") => SlaFormat::Html,
+            _ => SlaFormat::Csv,
+        }
+    }
+}
+
+/// Parses a month as `2025-01`.
+pub fn parse_month(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(&format!("{}-01", value.trim()), "%Y-%m-%d")
+        .map_err(|_| format!("{} is not a month like 2025-01", value))
+}
+
+/// Liveness checks and `watch` probes of stored servers, oldest first.
+const CHECKS: &str = "
+    SELECT server_id, reachable, checked_at, samples FROM liveness
+    WHERE checked_at >= $1 AND checked_at < $2
+    UNION ALL
+    SELECT server_id, reachable, taken_at, 1 FROM watch_snapshots
+    WHERE server_id IS NOT NULL AND taken_at >= $1 AND taken_at < $2
+    ORDER BY 1, 3";
+
+#[derive(QueryableByName)]
+struct Check {
+    #[diesel(sql_type = Int4)]
+    server_id: i32,
+    #[diesel(sql_type = Bool)]
+    reachable: bool,
+    #[diesel(sql_type = Timestamp)]
+    checked_at: NaiveDateTime,
+    #[diesel(sql_type = Int4)]
+    samples: i32,
+}
+
+struct Availability {
+    ip: String,
+    checks: i64,
+    up: i64,
+    outages: usize,
+    /// Time watched between the first and last check
+    observed: TimeDelta,
+    downtime: TimeDelta,
+    /// Start and length of the longest outage
+    longest: Option<(NaiveDateTime, TimeDelta)>,
+}
+
+impl Availability {
+    fn percent(&self) -> f64 {
+        self.up as f64 * 100.0 / self.checks.max(1) as f64
+    }
+
+    /// Mean time between outages: the time up, shared out over the outages.
+    fn mtbo(&self) -> Option<TimeDelta> {
+        (self.outages > 0).then(|| (self.observed - self.downtime) / self.outages as i32)
+    }
+}
+
+/// Monthly availability of every server with liveness checks or watch
+/// snapshots in `month`: the share of reachable checks, the number of
+/// outages and the mean time between them, and the longest outage. An
+/// outage lasts from its first failed check to the next reachable one, so
+/// its length is only as exact as the check interval.
+pub fn run(month: Option<NaiveDate>, out: &str, format: Option<SlaFormat>) {
+    // The last complete month by default
+    let start = month.unwrap_or_else(|| {
+        let today = Local::now().date_naive().with_day(1).unwrap();
+        today - Months::new(1)
+    });
+    let end = start + Months::new(1);
+    let format = format.unwrap_or_else(|| SlaFormat::from_path(out));
+
+    let mut db = DatabaseWrapper::establish_read();
+    let checks: Vec<Check> = sql_query(CHECKS)
+        .bind::<Timestamp, _>(start.and_hms_opt(0, 0, 0).unwrap())
+        .bind::<Timestamp, _>(end.and_hms_opt(0, 0, 0).unwrap())
+        .load(&mut db.conn)
+        .unwrap();
+    let mut ids: Vec<i32> = checks.iter().map(|check| check.server_id).collect();
+    ids.dedup();
+    let addresses: HashMap<i32, String> = schema::servers::dsl::servers
+        .filter(schema::servers::dsl::id.eq_any(ids))
+        .select((schema::servers::dsl::id, schema::servers::dsl::ip))
+        .load::<(i32, String)>(&mut db.conn)
+        .unwrap()
+        .into_iter()
+        .collect();
+
+    let mut servers: Vec<Availability> = checks
+        .chunk_by(|a, b| a.server_id == b.server_id)
+        .map(|checks| {
+            let id = checks[0].server_id;
+            let ip = addresses.get(&id).cloned().unwrap_or(id.to_string());
+            availability(ip, checks)
+        })
+        .collect();
+    servers.sort_by(|a, b| a.percent().total_cmp(&b.percent()).then(a.ip.cmp(&b.ip)));
+
+    let month = start.format("%Y-%m").to_string();
+    let written = match format {
+        SlaFormat::Csv => fs::write(out, csv(&servers)),
+        SlaFormat::Html => fs::write(out, html(&month, &servers)),
+    };
+    written.unwrap_or_else(|e| panic!("Error writing {}: {}", out, e));
+
+    println!(
+        "Availability of {} servers in {} written to {}",
+        servers.len(),
+        month,
+        out
+    );
+}
+
+fn availability(ip: String, checks: &[Check]) -> Availability {
+    let mut outages = 0;
+    let mut downtime = TimeDelta::zero();
+    let mut longest: Option<(NaiveDateTime, TimeDelta)> = None;
+    let mut down_since: Option<NaiveDateTime> = None;
+
+    let mut end_outage = |since: NaiveDateTime, until: NaiveDateTime| {
+        let length = until - since;
+        outages += 1;
+        downtime += length;
+        if longest.is_none_or(|(_, longest)| length > longest) {
+            longest = Some((since, length));
+        }
+    };
+    for check in checks {
+        match (check.reachable, down_since) {
+            (false, None) => down_since = Some(check.checked_at),
+            (true, Some(since)) => {
+                end_outage(since, check.checked_at);
+                down_since = None;
+            }
+            _ => {}
+        }
+    }
+    let last = checks.last().unwrap().checked_at;
+    // Still down at the end of the month
+    if let Some(since) = down_since {
+        end_outage(since, last);
+    }
+
+    Availability {
+        ip,
+        checks: checks.iter().map(|check| check.samples as i64).sum(),
+        up: checks
+            .iter()
+            .filter(|check| check.reachable)
+            .map(|check| check.samples as i64)
+            .sum(),
+        outages,
+        observed: last - checks[0].checked_at,
+        downtime,
+        longest,
+    }
+}
+
+fn human(delta: TimeDelta) -> String {
+    let minutes = delta.num_minutes();
+    match minutes {
+        m if m < 60 => format!("{}m", m),
+        m if m < 24 * 60 => format!("{}h {}m", m / 60, m % 60),
+        m => format!("{}d {}h", m / (24 * 60), m % (24 * 60) / 60),
+    }
+}
+
+fn csv(servers: &[Availability]) -> String {
+    let mut csv = String::from(
+        "ip,checks,availability,outages,mtbo_minutes,longest_outage_minutes,longest_outage_started\n",
+    );
+    for server in servers {
+        writeln!(
+            csv,
+            "{},{},{:.3},{},{},{},{}",
+            csv_field(&server.ip),
+            server.checks,
+            server.percent(),
+            server.outages,
+            server
+                .mtbo()
+                .map(|mtbo| mtbo.num_minutes().to_string())
+                .unwrap_or_default(),
+            server
+                .longest
+                .map(|(_, length)| length.num_minutes().to_string())
+                .unwrap_or_default(),
+            server
+                .longest
+                .map(|(since, _)| since.to_string())
+                .unwrap_or_default(),
+        )
+        .unwrap();
+    }
+    csv
+}
+
+fn html(month: &str, servers: &[Availability]) -> String {
+    let mut html = String::new();
+    write!(
+        html,
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>mine_search availability {month}</title>
+<style>{}</style>
+</head>
+<body>
+<h1>Availability {month}</h1>
+<p class="muted">Generated {}</p>
+<table id="servers">
+<thead><tr><th>IP</th><th data-type="number">Checks</th><th data-type="number">Availability %</th><th data-type="number">Outages</th><th>Mean time between outages</th><th>Longest outage</th><th>Started</th></tr></thead>
+<tbody>"#,
+        report::STYLE,
+        Local::now().format("%Y-%m-%d %H:%M:%S")
+    )
+    .unwrap();
+
+    for server in servers {
+        writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{:.3}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            report::escape(&server.ip),
+            server.checks,
+            server.percent(),
+            server.outages,
+            server.mtbo().map(human).unwrap_or_default(),
+            server.longest.map(|(_, length)| human(length)).unwrap_or_default(),
+            server
+                .longest
+                .map(|(since, _)| since.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default(),
+        )
+        .unwrap();
+    }
+
+    writeln!(
+        html,
+        "</tbody></table>\n<script>{}</script>\n</body>\n</html>",
+        report::SCRIPT
+    )
+    .unwrap();
+    html
+}
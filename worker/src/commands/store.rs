@@ -0,0 +1,54 @@
+use std::{env, net::SocketAddr, sync::Arc};
+
+use crate::{
+    enrich, forward, jobs,
+    journal::{self, Journal},
+    scanner::Exclusions,
+    serve, start_updaters,
+    vhosts::VhostScanner,
+    Context,
+};
+
+/// Runs the storage side of a split deployment: discoveries forwarded by
+/// scanners are stored and followed up here, so either side can be restarted
+/// or upgraded without stopping the other.
+pub async fn run(
+    listen: &str,
+    serve: Option<SocketAddr>,
+    vhost_scan: bool,
+    vhost_wordlist: Option<&str>,
+    trace_path: bool,
+) {
+    let mut ctx = Context::establish();
+    if vhost_scan {
+        ctx.vhosts = Some(VhostScanner::load(vhost_wordlist));
+        println!("[+] Virtual host scanning enabled");
+    }
+    ctx.trace_path = trace_path;
+    ctx.journal = Journal::from_env();
+    let ctx = Arc::new(ctx);
+    tokio::spawn(journal::replay(ctx.clone()));
+
+    enrich::start(&ctx);
+    tokio::spawn(enrich::run(ctx.clone()));
+    // Forwarding scanners leave the stored servers to this process
+    start_updaters(&ctx, false);
+    if let Some(addr) = serve {
+        let job_concurrency: usize = env::var("JOB_CONCURRENCY")
+            .unwrap_or("64".to_string())
+            .parse()
+            .unwrap();
+        tokio::spawn(serve::run(ctx.clone(), addr));
+        tokio::spawn(jobs::run(
+            ctx.clone(),
+            Exclusions::new(None),
+            job_concurrency,
+        ));
+    }
+
+    tokio::select! {
+        _ = forward::listen(ctx.clone(), listen) => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    ctx.finish_run().await;
+}
@@ -0,0 +1,157 @@
+use std::{
+    collections::BTreeSet,
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use diesel::{dsl::insert_into, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use hickory_resolver::TokioAsyncResolver;
+use tokio::time::{interval, timeout, MissedTickBehavior};
+
+use crate::{
+    commands::import::{locate, split_address},
+    conn_wrapper::Connection,
+    database::{DatabaseWrapper, Edition, WatchSnapshotInsert},
+    etiquette, schema,
+    server_actions::without_connection::{get_status_on, Status},
+};
+
+/// Parses durations like `500ms`, `30s`, `5m`, `1h` or `7d`; a bare number is seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration: {}", value))?;
+
+    Ok(match unit {
+        "ms" => Duration::from_millis(number),
+        "" | "s" => Duration::from_secs(number),
+        "m" => Duration::from_secs(number * 60),
+        "h" => Duration::from_secs(number * 3600),
+        "d" => Duration::from_secs(number * 86400),
+        _ => return Err(format!("unknown duration unit: {}", unit)),
+    })
+}
+
+/// Probes one server every `every` until interrupted, storing each result in
+/// `watch_snapshots` and printing player joins and leaves as they show up in
+/// the status sample. Large servers only send a part of their player list, so
+/// events there are a best effort.
+pub async fn run(address: &str, every: Duration) {
+    let resolver = TokioAsyncResolver::tokio_from_system_conf().unwrap();
+    let Some((ip, port)) = locate(address, &resolver).await else {
+        panic!("Could not resolve {}", address);
+    };
+    let host = split_address(address).0.to_string();
+
+    let mut db = DatabaseWrapper::establish();
+    etiquette::load(&mut db.conn);
+    println!("Watching {} ({}:{}) every {:?}", address, ip, port, every);
+
+    let mut ticker = interval(every.max(Duration::from_secs(1)));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let mut previous: Option<BTreeSet<String>> = None;
+    let mut was_reachable = None;
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = tokio::signal::ctrl_c() => break,
+        }
+
+        let admitted = etiquette::admit(&ip.to_string());
+        etiquette::flush(&mut db.conn);
+        if !admitted {
+            continue;
+        }
+        let started = Instant::now();
+        let status = timeout(Duration::from_secs(5), async {
+            let mut conn = Connection::connect((ip, port)).await?;
+            get_status_on(&mut conn, &host, port).await
+        })
+        .await
+        .ok()
+        .and_then(|r| r.ok());
+        let latency = started.elapsed();
+
+        let players: BTreeSet<String> = status
+            .iter()
+            .flat_map(|s| s.players.sample.iter().flatten())
+            .map(|p| p.name.clone())
+            .collect();
+
+        report(&status, latency, was_reachable, previous.as_ref(), &players);
+        was_reachable = Some(status.is_some());
+        if status.is_some() {
+            previous = Some(players.clone());
+        }
+
+        let server_id = find_stored(&ip.to_string(), &mut db);
+        insert_into(schema::watch_snapshots::dsl::watch_snapshots)
+            .values(WatchSnapshotInsert {
+                address,
+                server_id,
+                reachable: status.is_some(),
+                online: status.as_ref().map(|s| s.players.online as i32),
+                max: status.as_ref().map(|s| s.players.max as i32),
+                version_name: status.as_ref().map(|s| s.version.name.as_str()),
+                players: players.into_iter().collect(),
+                latency_ms: status.as_ref().map(|_| latency.as_millis() as i32),
+            })
+            .execute(&mut db.conn)
+            .unwrap();
+    }
+}
+
+fn report(
+    status: &Option<Status>,
+    latency: Duration,
+    was_reachable: Option<bool>,
+    previous: Option<&BTreeSet<String>>,
+    players: &BTreeSet<String>,
+) {
+    let time = Local::now().format("%H:%M:%S");
+
+    let Some(status) = status else {
+        if was_reachable != Some(false) {
+            println!("[{}] unreachable", time);
+        }
+        return;
+    };
+
+    if was_reachable == Some(false) {
+        println!("[{}] reachable again", time);
+    }
+    println!(
+        "[{}] {}/{} players | {} | {}ms",
+        time,
+        status.players.online,
+        status.players.max,
+        status.version.name,
+        latency.as_millis()
+    );
+
+    if let Some(previous) = previous {
+        for name in players.difference(previous) {
+            println!("[{}] + {} joined", time, name);
+        }
+        for name in previous.difference(players) {
+            println!("[{}] - {} left", time, name);
+        }
+    }
+}
+
+fn find_stored(ip: &str, db: &mut DatabaseWrapper) -> Option<i32> {
+    schema::servers::dsl::servers
+        .filter(schema::servers::dsl::ip.eq(ip))
+        .filter(schema::servers::dsl::edition.eq(Edition::Java.as_str()))
+        .select(schema::servers::dsl::id)
+        .first(&mut db.conn)
+        .optional()
+        .unwrap()
+}
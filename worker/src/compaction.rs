@@ -0,0 +1,183 @@
+use std::{env, sync::Arc, time::Duration};
+
+use chrono::{Local, NaiveDateTime, TimeDelta, Timelike};
+use diesel::{
+    sql_query,
+    sql_types::{BigInt, Timestamp},
+    PgConnection, QueryableByName, RunQueryDsl,
+};
+
+use crate::{commands::watch::parse_duration, Context};
+
+const DEFAULT_TIERS: &str = "5m:7d,1h:90d,1d";
+
+/// Resolution snapshots are kept at up to an age, the last tier without one.
+struct Tier {
+    resolution: Duration,
+    until: Option<Duration>,
+}
+
+/// Tiers of `SNAPSHOT_TIERS`, `<resolution>:<age>` separated by commas from
+/// the newest, e.g. `5m:7d,1h:90d,1d`; snapshots older than the last age use
+/// the last resolution.
+fn tiers() -> Vec<Tier> {
+    let value = env::var("SNAPSHOT_TIERS").unwrap_or(DEFAULT_TIERS.to_string());
+    let parse = |text: &str| {
+        parse_duration(text)
+            .unwrap_or_else(|e| panic!("Invalid SNAPSHOT_TIERS entry {:?}: {}", text, e))
+    };
+
+    let tiers: Vec<Tier> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once(':') {
+            Some((resolution, until)) => Tier {
+                resolution: parse(resolution),
+                until: Some(parse(until)),
+            },
+            None => Tier {
+                resolution: parse(entry),
+                until: None,
+            },
+        })
+        .collect();
+    if tiers.iter().any(|tier| tier.resolution.as_secs() == 0) {
+        panic!("SNAPSHOT_TIERS resolutions must be at least a second");
+    }
+    tiers
+}
+
+#[derive(QueryableByName, Default)]
+pub struct Compacted {
+    #[diesel(sql_type = BigInt)]
+    pub removed: i64,
+    #[diesel(sql_type = BigInt)]
+    pub written: i64,
+}
+
+impl Compacted {
+    fn add(&mut self, other: Compacted) {
+        self.removed += other.removed;
+        self.written += other.written;
+    }
+}
+
+/// Merges the snapshots in `pings` and `liveness` sharing a server and a
+/// time bucket of their tier into one row each, weighted by `samples`. Rows
+/// already alone in their bucket stay as they are, so passes can repeat.
+pub fn compact(conn: &mut PgConnection) -> (Compacted, Compacted) {
+    let now = Local::now().naive_local().with_nanosecond(0).unwrap();
+    let mut pings = Compacted::default();
+    let mut liveness = Compacted::default();
+
+    let mut newest = now;
+    for tier in tiers() {
+        let oldest = tier
+            .until
+            .map(|age| now - TimeDelta::from_std(age).unwrap());
+        let width = tier.resolution.as_secs();
+        pings.add(merge(conn, PINGS, width, newest, oldest));
+        liveness.add(merge(conn, LIVENESS, width, newest, oldest));
+
+        let Some(oldest) = oldest else {
+            break;
+        };
+        newest = oldest;
+    }
+    (pings, liveness)
+}
+
+/// Deletes the rows of crowded buckets and inserts one per bucket instead,
+/// in a single statement. `{bucket}` is the bucket of a row, `{range}` limits
+/// both to the tier.
+const PINGS: &str = "
+    WITH doomed AS (
+        DELETE FROM pings
+        WHERE {range} AND (server_id, {bucket}) IN (
+            SELECT server_id, {bucket} FROM pings
+            WHERE {range}
+            GROUP BY 1, 2
+            HAVING count(*) > 1
+        )
+        RETURNING *
+    ), merged AS (
+        INSERT INTO pings (server_id, online, max, version_name, protocol, latency_ms, checked_at, samples)
+        SELECT server_id,
+            round(sum(online * samples)::numeric / sum(samples))::int,
+            max(max),
+            (array_agg(version_name ORDER BY checked_at DESC))[1],
+            (array_agg(protocol ORDER BY checked_at DESC))[1],
+            round(avg(latency_ms))::int,
+            {bucket},
+            sum(samples)::int
+        FROM doomed
+        GROUP BY server_id, {bucket}
+        RETURNING 1
+    )
+    SELECT (SELECT count(*) FROM doomed) AS removed, (SELECT count(*) FROM merged) AS written";
+
+/// As `PINGS`, keeping reachable and unreachable checks apart so their
+/// `samples` still give the availability.
+const LIVENESS: &str = "
+    WITH doomed AS (
+        DELETE FROM liveness
+        WHERE {range} AND (server_id, reachable, {bucket}) IN (
+            SELECT server_id, reachable, {bucket} FROM liveness
+            WHERE {range}
+            GROUP BY 1, 2, 3
+            HAVING count(*) > 1
+        )
+        RETURNING *
+    ), merged AS (
+        INSERT INTO liveness (server_id, reachable, latency_ms, checked_at, samples)
+        SELECT server_id, reachable, round(avg(latency_ms))::int, {bucket}, sum(samples)::int
+        FROM doomed
+        GROUP BY server_id, reachable, {bucket}
+        RETURNING 1
+    )
+    SELECT (SELECT count(*) FROM doomed) AS removed, (SELECT count(*) FROM merged) AS written";
+
+fn merge(
+    conn: &mut PgConnection,
+    statement: &str,
+    width: u64,
+    newest: NaiveDateTime,
+    oldest: Option<NaiveDateTime>,
+) -> Compacted {
+    let bin = |column: &str| {
+        format!(
+            "date_bin('{} seconds', {}, TIMESTAMP '2000-01-01')",
+            width, column
+        )
+    };
+    // Bounds fall on bucket edges so no bucket is split between tiers
+    let range = match oldest {
+        Some(_) => format!("checked_at < {} AND checked_at >= {}", bin("$1"), bin("$2")),
+        None => format!("checked_at < {}", bin("$1")),
+    };
+    let statement = statement
+        .replace("{range}", &range)
+        .replace("{bucket}", &bin("checked_at"));
+
+    let query = sql_query(statement).bind::<Timestamp, _>(newest);
+    match oldest {
+        Some(oldest) => query.bind::<Timestamp, _>(oldest).get_result(conn),
+        None => query.get_result(conn),
+    }
+    .unwrap()
+}
+
+/// Compacts snapshots every `interval` seconds next to the scan.
+pub async fn compactor(ctx: Arc<Context>, interval: u64) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        let (pings, liveness) = compact(&mut ctx.pool().get().await.conn);
+        if pings.removed + liveness.removed > 0 {
+            println!(
+                "[+] Compacted {} pings into {} and {} liveness checks into {}",
+                pings.removed, pings.written, liveness.removed, liveness.written
+            );
+        }
+    }
+}
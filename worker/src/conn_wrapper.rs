@@ -1,9 +1,229 @@
-use std::io;
+use std::{
+    env,
+    future::Future,
+    io::{self, Error, ErrorKind, Read, Write},
+    pin::Pin,
+    sync::OnceLock,
+    task::{Context, Poll},
+    time::Duration,
+};
 
-use minecraft_protocol::Packet;
-use tokio::net::TcpStream;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use mine_search::source_port;
+use minecraft_protocol::{Packet, UncompressedPacket};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{Semaphore, SemaphorePermit},
+    time::{sleep, Instant, Sleep},
+};
 
-use crate::packets::PacketActions;
+use crate::{
+    overrides,
+    packets::PacketActions,
+    proxy_protocol::{self, ProxyVersion},
+    traffic::{record_received, record_sent},
+};
+
+struct SocketSettings {
+    idle_timeout: Duration,
+    abortive_close: bool,
+}
+
+fn socket_settings() -> &'static SocketSettings {
+    static SETTINGS: OnceLock<SocketSettings> = OnceLock::new();
+
+    SETTINGS.get_or_init(|| SocketSettings {
+        idle_timeout: Duration::from_millis(
+            env::var("IO_TIMEOUT_MS")
+                .unwrap_or("5000".to_string())
+                .parse()
+                .unwrap(),
+        ),
+        abortive_close: env::var("ABORTIVE_CLOSE")
+            .map(|v| v != "false" && v != "0")
+            .unwrap_or(true),
+    })
+}
+
+/// Stream wrapper adding every byte read or written to the traffic counters.
+/// Any read or write that makes no progress for `IO_TIMEOUT_MS` fails with
+/// `TimedOut`, so a peer that goes silent can't pin the socket forever.
+pub struct Metered<S> {
+    inner: S,
+    idle_timeout: Duration,
+    deadline: Pin<Box<Sleep>>,
+    prefix: Vec<u8>,
+    /// PROXY header still to be written before the first payload byte
+    pending_header: Vec<u8>,
+}
+
+/// Bytes of the peer's first reply kept for service fingerprinting.
+const PREFIX_SIZE: usize = 256;
+
+pub type Connection = Metered<TcpStream>;
+
+impl<S> Metered<S> {
+    pub fn new(inner: S) -> Self {
+        let idle_timeout = socket_settings().idle_timeout;
+
+        Self {
+            inner,
+            idle_timeout,
+            deadline: Box::pin(sleep(idle_timeout)),
+            prefix: vec![],
+            pending_header: vec![],
+        }
+    }
+
+    /// The first bytes received on this connection.
+    pub fn received_prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    fn progressed(&mut self) {
+        self.deadline
+            .as_mut()
+            .reset(Instant::now() + self.idle_timeout);
+    }
+
+    fn poll_deadline<T>(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<T>> {
+        match self.deadline.as_mut().poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(Error::new(
+                ErrorKind::TimedOut,
+                "connection idle for too long",
+            ))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Connection {
+    pub async fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Self::from_stream(source_port::connect_tcp_to(addr).await?)
+    }
+
+    /// Connects, sending a PROXY header when `proxy` is set even if it is not
+    /// enabled globally. A `PROBE_OVERRIDES` header for the address wins.
+    pub async fn connect_with<A: ToSocketAddrs>(
+        addr: A,
+        proxy: Option<ProxyVersion>,
+    ) -> io::Result<Self> {
+        let mut conn = Self::connect(addr).await?;
+        let forced = overrides::lookup(conn.inner.peer_addr()?.ip()).proxy;
+        if let Some(version) = proxy.filter(|_| forced.is_none()) {
+            conn.set_proxy_header(version)?;
+        }
+        Ok(conn)
+    }
+
+    fn set_proxy_header(&mut self, version: ProxyVersion) -> io::Result<()> {
+        self.pending_header =
+            proxy_protocol::header(version, self.inner.local_addr()?, self.inner.peer_addr()?);
+        Ok(())
+    }
+
+    /// With `ABORTIVE_CLOSE` (the default) the socket is reset on drop instead
+    /// of lingering in `TIME_WAIT`, so error paths release it right away.
+    /// The PROXY header comes from `PROBE_OVERRIDES`, then `PROXY_PROTOCOL`.
+    pub fn from_stream(stream: TcpStream) -> io::Result<Self> {
+        if socket_settings().abortive_close {
+            // A zero linger never blocks on drop
+            #[allow(deprecated)]
+            stream.set_linger(Some(Duration::ZERO))?;
+        }
+        stream.set_nodelay(true)?;
+
+        let forced = stream
+            .peer_addr()
+            .ok()
+            .and_then(|addr| overrides::lookup(addr.ip()).proxy);
+        let mut conn = Self::new(stream);
+        if let Some(version) = forced.or(proxy_protocol::always()) {
+            conn.set_proxy_header(version)?;
+        }
+        Ok(conn)
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Metered<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+
+        match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(result) => {
+                if result.is_ok() {
+                    let received = &buf.filled()[before..];
+                    record_received(received.len());
+
+                    let room = PREFIX_SIZE - this.prefix.len();
+                    this.prefix
+                        .extend_from_slice(&received[..received.len().min(room)]);
+                }
+                this.progressed();
+                Poll::Ready(result)
+            }
+            Poll::Pending => this.poll_deadline(cx),
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Metered<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        while !this.pending_header.is_empty() {
+            match Pin::new(&mut this.inner).poll_write(cx, &this.pending_header) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(ErrorKind::WriteZero.into())),
+                Poll::Ready(Ok(written)) => {
+                    record_sent(written);
+                    this.pending_header.drain(..written);
+                    this.progressed();
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return this.poll_deadline(cx),
+            }
+        }
+
+        match Pin::new(&mut this.inner).poll_write(cx, buf) {
+            Poll::Ready(result) => {
+                if let Ok(written) = result {
+                    record_sent(written);
+                }
+                this.progressed();
+                Poll::Ready(result)
+            }
+            Poll::Pending => this.poll_deadline(cx),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        match Pin::new(&mut this.inner).poll_flush(cx) {
+            Poll::Ready(result) => Poll::Ready(result),
+            Poll::Pending => this.poll_deadline(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        match Pin::new(&mut this.inner).poll_shutdown(cx) {
+            Poll::Ready(result) => Poll::Ready(result),
+            Poll::Pending => this.poll_deadline(cx),
+        }
+    }
+}
 
 pub trait ConnectionWrapper {
     async fn read_packet<T: PacketActions>(&mut self) -> io::Result<T>;
@@ -16,9 +236,9 @@ pub trait ConnectionWrapper {
     ) -> io::Result<()>;
 }
 
-impl ConnectionWrapper for TcpStream {
+impl ConnectionWrapper for Connection {
     async fn read_packet<T: PacketActions>(&mut self) -> io::Result<T> {
-        let packet = Packet::read_uncompressed(self).await?;
+        let packet = read_uncompressed(self).await?;
         T::deserialize(packet)
     }
 
@@ -36,3 +256,203 @@ impl ConnectionWrapper for TcpStream {
             .await
     }
 }
+
+/// Largest frame the protocol allows (a three byte VarInt length).
+pub const MAX_PACKET_SIZE: usize = 2_097_151;
+/// Largest uncompressed packet vanilla clients accept.
+pub const MAX_UNCOMPRESSED_SIZE: usize = 8_388_608;
+
+struct MemoryBudget {
+    semaphore: Semaphore,
+    kib: u32,
+}
+
+/// Bytes of frame buffers all connections may hold at once, so a flood of
+/// peers announcing large packets waits instead of exhausting memory.
+fn memory_budget() -> &'static MemoryBudget {
+    static BUDGET: OnceLock<MemoryBudget> = OnceLock::new();
+
+    BUDGET.get_or_init(|| {
+        let mb: u32 = env::var("MEMORY_BUDGET_MB")
+            .unwrap_or("256".to_string())
+            .parse()
+            .unwrap();
+        let kib = mb.max(1) * 1024;
+        MemoryBudget {
+            semaphore: Semaphore::new(kib as usize),
+            kib,
+        }
+    })
+}
+
+async fn reserve(bytes: usize) -> io::Result<SemaphorePermit<'static>> {
+    let budget = memory_budget();
+    let kib = (bytes.div_ceil(1024) as u32).clamp(1, budget.kib);
+
+    budget
+        .semaphore
+        .acquire_many(kib)
+        .await
+        .map_err(|_| Error::other("memory budget closed"))
+}
+
+pub fn encode_varint(mut value: u32, out: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Reads one length-prefixed frame after checking the announced length, and
+/// keeps its share of the memory budget until the permit is dropped.
+async fn read_raw_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+) -> io::Result<(Vec<u8>, SemaphorePermit<'static>)> {
+    let length = read_varint(reader).await?;
+    if length < 0 || length as usize > MAX_PACKET_SIZE {
+        return Err(Error::new(ErrorKind::InvalidData, "bad packet length"));
+    }
+
+    let permit = reserve(length as usize).await?;
+    let mut frame = Vec::with_capacity(length as usize + 3);
+    encode_varint(length as u32, &mut frame);
+    let header = frame.len();
+    frame.resize(header + length as usize, 0);
+    reader.read_exact(&mut frame[header..]).await?;
+
+    Ok((frame, permit))
+}
+
+/// `Packet::read_uncompressed` with the frame size checked up front.
+pub async fn read_uncompressed<R: AsyncRead + Unpin>(
+    reader: &mut R,
+) -> io::Result<UncompressedPacket> {
+    let (frame, _permit) = read_raw_frame(reader).await?;
+    Packet::read_uncompressed(&mut frame.as_slice()).await
+}
+
+/// `Packet::read` with the frame size and the announced uncompressed size
+/// checked up front.
+pub async fn read_packet<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    threshold: Option<i32>,
+) -> io::Result<Packet> {
+    let (frame, _permit) = read_raw_frame(reader).await?;
+
+    let _inflated = if threshold.is_some() {
+        let (_, header) = decode_varint(&frame)?;
+        let (data_length, _) = decode_varint(&frame[header..])?;
+        Some(reserve(checked_data_length(data_length)?).await?)
+    } else {
+        None
+    };
+
+    Packet::read(&mut frame.as_slice(), threshold).await
+}
+
+fn checked_data_length(data_length: i32) -> io::Result<usize> {
+    if data_length < 0 || data_length as usize > MAX_UNCOMPRESSED_SIZE {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "bad uncompressed length",
+        ));
+    }
+    Ok(data_length as usize)
+}
+
+pub async fn read_varint<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<i32> {
+    let mut value = 0i32;
+
+    for i in 0..5 {
+        let byte = reader.read_u8().await?;
+        value |= ((byte & 0x7F) as i32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(Error::new(ErrorKind::InvalidData, "VarInt is too big"))
+}
+
+pub fn decode_varint(data: &[u8]) -> io::Result<(i32, usize)> {
+    let mut value = 0i32;
+
+    for (i, byte) in data.iter().take(5).enumerate() {
+        value |= ((byte & 0x7F) as i32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+
+    Err(Error::new(ErrorKind::InvalidData, "bad VarInt"))
+}
+
+/// Reads a single frame as `(packet_id, payload)`, inflating it when compression
+/// is enabled. Used for play-state packets the probes only need to skim.
+pub async fn read_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    threshold: Option<i32>,
+) -> io::Result<(i32, Vec<u8>)> {
+    let (mut frame, _permit) = read_raw_frame(reader).await?;
+    let (_, header) = decode_varint(&frame)?;
+    let mut frame = frame.split_off(header);
+
+    let data = if threshold.is_some() {
+        let (data_length, read) = decode_varint(&frame)?;
+        if data_length == 0 {
+            frame.split_off(read)
+        } else {
+            let data_length = checked_data_length(data_length)?;
+            let _inflated = reserve(data_length).await?;
+            let mut data = Vec::with_capacity(data_length);
+            ZlibDecoder::new(&frame[read..])
+                .take(data_length as u64)
+                .read_to_end(&mut data)?;
+            data
+        }
+    } else {
+        frame
+    };
+
+    let (packet_id, read) = decode_varint(&data)?;
+    Ok((packet_id, data[read..].to_vec()))
+}
+
+/// Writes `packet_id` and `payload` as a single frame, compressing it once the
+/// payload reaches the threshold. Counterpart of `read_frame`.
+pub async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    threshold: Option<i32>,
+    packet_id: i32,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut data = vec![];
+    encode_varint(packet_id as u32, &mut data);
+    data.extend_from_slice(payload);
+
+    let body = match threshold {
+        Some(threshold) if threshold >= 0 && data.len() >= threshold as usize => {
+            let mut body = vec![];
+            encode_varint(data.len() as u32, &mut body);
+            let mut encoder = ZlibEncoder::new(body, Compression::default());
+            encoder.write_all(&data)?;
+            encoder.finish()?
+        }
+        Some(_) => {
+            let mut body = vec![0];
+            body.extend(data);
+            body
+        }
+        None => data,
+    };
+
+    let mut frame = vec![];
+    encode_varint(body.len() as u32, &mut frame);
+    frame.extend(body);
+    writer.write_all(&frame).await
+}
@@ -1,27 +1,217 @@
-use std::env;
+use std::{
+    env, io,
+    net::{IpAddr, SocketAddr},
+    process,
+    sync::Once,
+    thread,
+    time::Duration,
+};
 
 use chrono::NaiveDateTime;
+use db_schema::version::{self, Compatibility, SCHEMA_VERSION};
 use diesel::{
     prelude::{AsChangeset, Associations, Identifiable, Insertable, Queryable},
+    r2d2::{self, ConnectionManager},
     Connection, PgConnection, Selectable,
 };
+use mine_search::{
+    description_to_str,
+    filter::{FieldValue, Record},
+};
+use serde::Serialize;
 use serde_json::Value;
+use tokio::{
+    runtime::{Handle, RuntimeFlavor},
+    sync::{Semaphore, SemaphorePermit},
+    task,
+};
+
+use crate::{proxy_protocol::ProxyVersion, server_actions::bedrock::BEDROCK_PORT};
+
+const MAX_CONNECT_DELAY: Duration = Duration::from_secs(30);
 
 pub struct DatabaseWrapper {
     pub conn: PgConnection,
 }
 
 impl DatabaseWrapper {
+    /// Connects to `DATABASE_URL`. The first connection of a process checks
+    /// that its schema has every migration this build expects, and exits
+    /// otherwise.
     pub fn establish() -> Self {
+        Self::try_establish().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like `establish`, returning the error once the retries are used up.
+    pub fn try_establish() -> io::Result<Self> {
+        static CHECKED: Once = Once::new();
+
         let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-        let conn = PgConnection::establish(&database_url)
-            .unwrap_or_else(|_| panic!("Error connecting to {}", database_url));
+        let mut db = Self::connect(&database_url)?;
+        CHECKED.call_once(|| match version::check(&mut db.conn) {
+            Ok(Compatibility::Current) => {}
+            Ok(Compatibility::Newer(live)) => eprintln!(
+                "[!] The database schema is at migration {}, newer than the {} of this build",
+                live, SCHEMA_VERSION
+            ),
+            Err(e) => {
+                eprintln!("[!] Refusing to start: {}", e);
+                process::exit(1);
+            }
+        });
+        Ok(db)
+    }
+
+    /// Connection for queries that only read: the replica at
+    /// `DATABASE_READ_URL` if one is configured, the primary otherwise.
+    pub fn establish_read() -> Self {
+        match env::var("DATABASE_READ_URL") {
+            Ok(url) => Self::connect(&url).unwrap_or_else(|e| panic!("{}", e)),
+            Err(_) => Self::establish(),
+        }
+    }
 
-        Self { conn }
+    /// Connects to `database_url`, retrying `DB_CONNECT_RETRIES` times
+    /// (default 10) with delays doubling from a second up to 30 while the
+    /// database is still starting.
+    fn connect(database_url: &str) -> io::Result<Self> {
+        let retries: u32 = env::var("DB_CONNECT_RETRIES")
+            .unwrap_or("10".to_string())
+            .parse()
+            .expect("DB_CONNECT_RETRIES is not a number");
+        let target = redact(database_url);
+
+        let mut delay = Duration::from_secs(1);
+        let mut attempt = 0;
+        loop {
+            match PgConnection::establish(database_url) {
+                Ok(conn) => return Ok(Self { conn }),
+                Err(e) if attempt < retries => {
+                    eprintln!(
+                        "[!] Database at {} not reachable ({}), retrying in {}s ({}/{})",
+                        target,
+                        e.to_string().lines().next().unwrap_or_default(),
+                        delay.as_secs(),
+                        attempt + 1,
+                        retries
+                    );
+                    wait(delay);
+                    delay = (delay * 2).min(MAX_CONNECT_DELAY);
+                    attempt += 1;
+                }
+                Err(e) => {
+                    return Err(io::Error::other(format!(
+                        "Error connecting to {} after {} retries: {}",
+                        target,
+                        retries,
+                        e.to_string().lines().next().unwrap_or_default()
+                    )))
+                }
+            }
+        }
     }
 }
 
-#[derive(Queryable, Selectable, Identifiable)]
+/// Sleeps for a retry delay. Connections are opened from async tasks too, so
+/// on a runtime worker thread its other tasks move elsewhere meanwhile.
+fn wait(delay: Duration) {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            task::block_in_place(|| thread::sleep(delay))
+        }
+        _ => thread::sleep(delay),
+    }
+}
+
+/// A connection string without its password, for messages.
+fn redact(database_url: &str) -> String {
+    let Some((scheme, rest)) = database_url.split_once("://") else {
+        return database_url.to_string();
+    };
+    match rest.split_once('@') {
+        Some((credentials, host)) => {
+            let user = credentials.split(':').next().unwrap_or_default();
+            format!("{}://{}@{}", scheme, user, host)
+        }
+        None => database_url.to_string(),
+    }
+}
+
+/// Fixed set of connections shared by the scan tasks, so a slow query only
+/// holds up the task that issued it. Connections are r2d2's, checked and
+/// replaced when the database dropped them; tasks wait for a free one
+/// without blocking their thread.
+pub struct Pool {
+    connections: r2d2::Pool<ConnectionManager<PgConnection>>,
+    available: Semaphore,
+    size: usize,
+}
+
+impl Pool {
+    /// `DB_POOL_SIZE` connections (default 8) to `DATABASE_URL`.
+    pub fn from_env() -> Self {
+        let size: usize = env::var("DB_POOL_SIZE")
+            .unwrap_or("8".to_string())
+            .parse()
+            .unwrap();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+
+        Self::new(&database_url, size)
+    }
+
+    /// `DB_READ_POOL_SIZE` connections (default 4) to the replica at
+    /// `DATABASE_READ_URL`, if set.
+    pub fn replica_from_env() -> Option<Self> {
+        let database_url = env::var("DATABASE_READ_URL").ok()?;
+        let size: usize = env::var("DB_READ_POOL_SIZE")
+            .unwrap_or("4".to_string())
+            .parse()
+            .unwrap();
+
+        Some(Self::new(&database_url, size))
+    }
+
+    fn new(database_url: &str, size: usize) -> Self {
+        let size = size.max(1);
+        let connections = r2d2::Pool::builder()
+            .max_size(size as u32)
+            .connection_timeout(MAX_CONNECT_DELAY)
+            .build(ConnectionManager::new(database_url))
+            .unwrap_or_else(|e| panic!("Error connecting to {}: {}", redact(database_url), e));
+
+        Self {
+            connections,
+            available: Semaphore::new(size),
+            size,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Waits for an idle connection; it returns to the pool when dropped.
+    pub async fn get(&self) -> PooledConnection<'_> {
+        let permit = self.available.acquire().await.unwrap();
+        // With a permit one is idle, or r2d2 replaces one the database dropped
+        let conn = self
+            .connections
+            .get()
+            .unwrap_or_else(|e| panic!("No database connection: {}", e));
+
+        PooledConnection {
+            conn,
+            _permit: permit,
+        }
+    }
+}
+
+pub struct PooledConnection<'a> {
+    pub conn: r2d2::PooledConnection<ConnectionManager<PgConnection>>,
+    _permit: SemaphorePermit<'a>,
+}
+
+#[derive(Queryable, Selectable, Identifiable, Serialize, Clone)]
 #[diesel(table_name = crate::schema::servers)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct ServerModel {
@@ -31,12 +221,152 @@ pub struct ServerModel {
     pub max: i32,
     pub version_name: String,
     pub protocol: i32,
-    pub license: bool,
+    pub license: Option<bool>,
     pub white_list: Option<bool>,
+    pub last_seen: NaiveDateTime,
     pub description: Value,
+    pub discovery_source: String,
+    pub run_id: Option<i32>,
+    pub peak_online: i32,
+    pub query_map: Option<String>,
+    pub query_game_type: Option<String>,
+    pub queue_server: bool,
+    pub proxy_protocol: bool,
+    /// Data URL of the icon, left out of JSON listings for its size
+    #[serde(skip_serializing)]
+    pub favicon: Option<String>,
+    pub canonical_id: Option<i32>,
+    pub edition: String,
+    pub software: Option<String>,
+    pub motd_plain: Option<String>,
+    pub country: Option<String>,
+    pub asn: Option<i64>,
+    /// Status over the length or sample limits of `packets`
+    pub anomalous_response: bool,
+    /// `StatusClass` of the last answer, if it was not a plain live server
+    pub status_class: Option<String>,
+    /// `OnDemandHost` running the server, once one answered for it
+    pub on_demand_host: Option<String>,
+    /// `Lifecycle` of on-demand servers
+    pub lifecycle: Option<String>,
+    pub lifecycle_changed_at: Option<NaiveDateTime>,
+    pub lifecycle_checked_at: Option<NaiveDateTime>,
+    /// Pack recognised from the mods by `modpacks.json`
+    pub modpack: Option<String>,
+    /// Instant rejections of status probes in a row
+    pub rejections: i32,
+    /// Since when the server appears to block this scanner
+    pub blocked_at: Option<NaiveDateTime>,
+    /// `refused`, `reset` or `login_ban`
+    pub block_kind: Option<String>,
+    /// `Era` of servers that only answered a pre-1.7 handshake
+    pub era: Option<String>,
+}
+
+impl ServerModel {
+    pub fn edition(&self) -> Edition {
+        Edition::parse(&self.edition).unwrap_or(Edition::Java)
+    }
+
+    /// Host and port to probe, split from `ip`.
+    pub fn address(&self) -> (String, u16) {
+        match self.ip.parse::<SocketAddr>() {
+            Ok(addr) => (addr.ip().to_string(), addr.port()),
+            Err(_) => (self.ip.clone(), self.edition().default_port()),
+        }
+    }
+
+    /// PROXY header version to use when probing this server.
+    pub fn proxy_header(&self) -> Option<ProxyVersion> {
+        self.proxy_protocol.then_some(ProxyVersion::V1)
+    }
 }
 
-#[derive(Queryable, Selectable, Identifiable, Associations)]
+/// Fields of a stored server, for filters and `--fields` selections.
+pub const SERVER_FIELDS: &[&str] = &[
+    "id",
+    "ip",
+    "addr",
+    "port",
+    "edition",
+    "online",
+    "max",
+    "peak_online",
+    "version",
+    "protocol",
+    "motd",
+    "motd_plain",
+    "license",
+    "white_list",
+    "source",
+    "run_id",
+    "map",
+    "game_type",
+    "queue",
+    "canonical",
+    "software",
+    "country",
+    "asn",
+    "anomalous",
+    "status_class",
+    "on_demand_host",
+    "lifecycle",
+    "modpack",
+    "blocked",
+    "era",
+    "last_seen",
+    "players.online",
+    "players.max",
+];
+
+impl Record for ServerModel {
+    fn field(&self, name: &str) -> Option<FieldValue> {
+        Some(match name {
+            "id" => self.id.into(),
+            "ip" => self.ip.as_str().into(),
+            "addr" => {
+                let (host, port) = self.address();
+                match host.parse::<IpAddr>() {
+                    Ok(ip) => SocketAddr::new(ip, port).to_string().into(),
+                    Err(_) => format!("{}:{}", host, port).into(),
+                }
+            }
+            "port" => (self.address().1 as i64).into(),
+            "online" | "players.online" => self.online.into(),
+            "max" | "players.max" => self.max.into(),
+            "peak_online" => self.peak_online.into(),
+            "version" => self.version_name.as_str().into(),
+            "protocol" => self.protocol.into(),
+            "motd" => description_to_str(self.description["payload"].clone())
+                .unwrap_or_default()
+                .into(),
+            "motd_plain" => self.motd_plain.as_deref().into(),
+            "license" => self.license.into(),
+            "white_list" => self.white_list.into(),
+            "source" => self.discovery_source.as_str().into(),
+            "run_id" => self.run_id.into(),
+            "map" => self.query_map.as_deref().into(),
+            "game_type" => self.query_game_type.as_deref().into(),
+            "queue" => self.queue_server.into(),
+            "canonical" => self.canonical_id.is_none().into(),
+            "edition" => self.edition.as_str().into(),
+            "software" => self.software.as_deref().into(),
+            "country" => self.country.as_deref().into(),
+            "asn" => self.asn.into(),
+            "anomalous" => self.anomalous_response.into(),
+            "status_class" => self.status_class.as_deref().into(),
+            "on_demand_host" => self.on_demand_host.as_deref().into(),
+            "lifecycle" => self.lifecycle.as_deref().into(),
+            "modpack" => self.modpack.as_deref().into(),
+            "blocked" => self.blocked_at.is_some().into(),
+            "era" => self.era.as_deref().into(),
+            "last_seen" => self.last_seen.to_string().into(),
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Queryable, Selectable, Identifiable, Associations, Serialize, Clone)]
 #[diesel(table_name = crate::schema::players)]
 #[diesel(belongs_to(ServerModel, foreign_key = server_id))]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -51,15 +381,48 @@ pub struct PlayerModel {
 #[derive(Insertable)]
 #[diesel(table_name = crate::schema::servers)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
-pub struct ServerInsert<'a> {
-    pub ip: &'a str,
+pub struct ServerInsert {
+    pub ip: String,
     pub online: i32,
     pub max: i32,
-    pub version_name: &'a str,
+    pub version_name: String,
     pub protocol: i32,
-    pub license: bool,
+    pub license: Option<bool>,
     pub white_list: Option<bool>,
-    pub description: &'a Value,
+    pub description: Value,
+    pub discovery_source: String,
+    pub run_id: Option<i32>,
+    pub peak_online: i32,
+    pub tags: Vec<String>,
+    pub query_map: Option<String>,
+    pub query_game_type: Option<String>,
+    pub query_game_id: Option<String>,
+    pub query_host_port: Option<i32>,
+    pub tps: Option<f32>,
+    pub queue_server: bool,
+    pub raw_status: Option<String>,
+    pub favicon: Option<String>,
+    pub appeal_contact: Option<String>,
+    pub edition: String,
+    pub motd: Option<String>,
+    pub motd_plain: Option<String>,
+    pub software: Option<String>,
+    pub anomalous_response: bool,
+    pub status_class: Option<String>,
+    pub on_demand_host: Option<String>,
+    pub lifecycle: Option<String>,
+    pub era: Option<String>,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = crate::schema::servers)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(treat_none_as_null = true)]
+pub struct QueryUpdate<'a> {
+    pub query_map: Option<&'a str>,
+    pub query_game_type: Option<&'a str>,
+    pub query_game_id: Option<&'a str>,
+    pub query_host_port: Option<i32>,
 }
 
 #[derive(Insertable, AsChangeset)]
@@ -71,13 +434,285 @@ pub struct ServerUpdate<'a> {
     pub version_name: &'a str,
     pub protocol: i32,
     pub description: &'a Value,
+    pub motd: Option<&'a str>,
+    pub motd_plain: Option<&'a str>,
+    pub software: Option<&'a str>,
 }
 
-#[derive(Insertable, AsChangeset)]
+#[derive(Insertable)]
 #[diesel(table_name = crate::schema::players)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
-pub struct PlayerInsert<'a> {
-    pub uuid: &'a str,
-    pub name: &'a str,
+pub struct PlayerInsert {
+    pub uuid: String,
+    pub name: String,
+    pub server_id: i32,
+    pub last_seen: NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Serialize, Clone)]
+#[diesel(table_name = crate::schema::pings)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PingModel {
+    pub id: i32,
+    pub server_id: i32,
+    pub online: i32,
+    pub max: i32,
+    pub version_name: String,
+    pub protocol: i32,
+    pub latency_ms: Option<i32>,
+    pub checked_at: NaiveDateTime,
+    /// Snapshots compacted into this one
+    pub samples: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::pings)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PingInsert {
+    pub server_id: i32,
+    pub online: i32,
+    pub max: i32,
+    pub version_name: String,
+    pub protocol: i32,
+    pub latency_ms: Option<i32>,
+    pub checked_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::liveness)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct LivenessInsert {
+    pub server_id: i32,
+    pub reachable: bool,
+    pub latency_ms: Option<i32>,
+    pub checked_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::motd_history)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MotdHistoryInsert<'a> {
+    pub server_id: i32,
+    pub old_value: &'a Value,
+    pub new_value: &'a Value,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::version_history)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct VersionHistoryInsert<'a> {
+    pub server_id: i32,
+    pub old_version_name: &'a str,
+    pub new_version_name: &'a str,
+    pub old_protocol: i32,
+    pub new_protocol: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::white_list_history)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct WhiteListHistoryInsert {
+    pub server_id: i32,
+    pub old_license: Option<bool>,
+    pub new_license: bool,
+    pub old_white_list: Option<bool>,
+    pub new_white_list: Option<bool>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::virtual_hosts)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct VirtualHostInsert<'a> {
+    pub server_id: i32,
+    pub hostname: &'a str,
+    pub version_name: &'a str,
+    pub description: &'a Value,
+    pub differs_from_ip: bool,
+}
+
+/// Population curve of a server over the hours of the day (UTC). Index `h`
+/// covers `h:00`-`h:59`.
+#[derive(Queryable, Selectable, Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::server_activity)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(treat_none_as_null = true)]
+pub struct ServerActivityModel {
+    pub server_id: i32,
+    pub online_sum: Vec<f64>,
+    pub samples: Vec<i32>,
+    pub average_online: Vec<f64>,
+    pub peak_hour: i32,
+    pub peak_online: f64,
+    pub updated_at: NaiveDateTime,
+    /// Offset of the player base's likely timezone, inferred from the curve
+    pub utc_offset: Option<i32>,
+    pub region: Option<String>,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = crate::schema::retry_queue)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RetryModel {
+    pub id: i32,
+    pub ip: String,
+    pub port: i32,
+    pub discovery_source: String,
+    pub attempts: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::retry_queue)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RetryInsert<'a> {
+    pub ip: &'a str,
+    pub port: i32,
+    pub discovery_source: &'a str,
+    pub last_error: &'a str,
+    pub next_attempt_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::watch_snapshots)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct WatchSnapshotInsert<'a> {
+    pub address: &'a str,
+    pub server_id: Option<i32>,
+    pub reachable: bool,
+    pub online: Option<i32>,
+    pub max: Option<i32>,
+    pub version_name: Option<&'a str>,
+    pub players: Vec<String>,
+    pub latency_ms: Option<i32>,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::domains)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(treat_none_as_null = true)]
+pub struct DomainUpsert<'a> {
+    pub domain: &'a str,
+    pub ip: Option<String>,
+    pub port: Option<i32>,
+    pub srv: bool,
+    pub reachable: bool,
+    pub version_name: Option<&'a str>,
+    pub server_id: Option<i32>,
+    pub last_checked: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::mods)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ModUpsert<'a> {
     pub server_id: i32,
+    pub mod_id: &'a str,
+    pub version: Option<&'a str>,
+    pub source: &'a str,
+    pub last_seen: NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::saved_servers)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(treat_none_as_null = true)]
+pub struct SavedServerUpsert<'a> {
+    pub name: &'a str,
+    pub address: &'a str,
+    pub ip: Option<String>,
+    pub port: Option<i32>,
+    pub reachable: bool,
+    pub server_id: Option<i32>,
+    pub last_checked: NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::service_fingerprints)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ServiceFingerprintUpsert<'a> {
+    pub ip: &'a str,
+    pub port: i32,
+    pub protocol: &'a str,
+    pub banner: &'a [u8],
+    pub observed_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Identifiable)]
+#[diesel(table_name = crate::schema::runs)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RunModel {
+    pub id: i32,
+    pub started_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edition {
+    Java,
+    Bedrock,
+}
+
+impl Edition {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Edition::Java => "java",
+            Edition::Bedrock => "bedrock",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value {
+            "java" => Edition::Java,
+            "bedrock" => Edition::Bedrock,
+            _ => return None,
+        })
+    }
+
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Edition::Java => 25565,
+            Edition::Bedrock => BEDROCK_PORT,
+        }
+    }
+
+    /// `servers.ip` of a server: the bare address on the edition's default
+    /// port, `addr:port` on any other.
+    pub fn key(&self, ip: &IpAddr, port: u16) -> String {
+        if port == self.default_port() {
+            ip.to_string()
+        } else {
+            SocketAddr::new(*ip, port).to_string()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscoverySource {
+    RandomScan,
+    RangeScan,
+    Import,
+    Manual,
+    /// A scan job submitted to the query API
+    Job,
+}
+
+impl DiscoverySource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiscoverySource::RandomScan => "random",
+            DiscoverySource::RangeScan => "range",
+            DiscoverySource::Import => "import",
+            DiscoverySource::Manual => "manual",
+            DiscoverySource::Job => "job",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value {
+            "random" => DiscoverySource::RandomScan,
+            "range" => DiscoverySource::RangeScan,
+            "import" => DiscoverySource::Import,
+            "manual" => DiscoverySource::Manual,
+            "job" => DiscoverySource::Job,
+            _ => return None,
+        })
+    }
 }
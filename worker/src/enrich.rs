@@ -0,0 +1,451 @@
+use std::{
+    env,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{Local, NaiveDateTime, Timelike};
+use diesel::{
+    dsl::insert_into, Connection, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
+};
+use tokio::sync::{mpsc, Semaphore};
+
+use crate::{
+    database::{Edition, ServerModel},
+    geoip::GeoIp,
+    mods,
+    proxy_protocol::ProxyVersion,
+    record_path, schema,
+    server_actions::without_connection::Status,
+    vhosts,
+    webhooks::Event,
+    Context,
+};
+
+/// Servers looked up per batch.
+const BATCH: i64 = 500;
+
+/// Post-discovery work, in the order a new server passes through it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Country, city, ASN and ISP from the GeoIP databases
+    GeoIp,
+    /// Reverse DNS name of the address
+    Rdns,
+    /// Whether sample players are real Mojang accounts
+    Mojang,
+    /// Virtual hosts, traceroute and the Forge handshake
+    DeepProbe,
+    /// The `discovered` webhook, with what the earlier stages found
+    Notify,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 5] = [
+        Stage::GeoIp,
+        Stage::Rdns,
+        Stage::Mojang,
+        Stage::DeepProbe,
+        Stage::Notify,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::GeoIp => "geoip",
+            Stage::Rdns => "rdns",
+            Stage::Mojang => "mojang",
+            Stage::DeepProbe => "deep_probe",
+            Stage::Notify => "notify",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Stage::ALL
+            .into_iter()
+            .find(|stage| stage.as_str() == value.trim())
+    }
+
+    /// Servers handled at once when `ENRICH_CONCURRENCY` doesn't say.
+    pub fn default_concurrency(&self) -> usize {
+        match self {
+            Stage::GeoIp => 4,
+            Stage::Rdns => 16,
+            Stage::Mojang => 2,
+            Stage::DeepProbe => 16,
+            Stage::Notify => 4,
+        }
+    }
+}
+
+/// A newly stored server on its way through the stages.
+pub struct Job {
+    pub server: ServerModel,
+    pub ip: IpAddr,
+    pub port: u16,
+    pub edition: Edition,
+    pub status: Status,
+    pub proxy: Option<ProxyVersion>,
+    /// Filled in by the stages
+    pub country: Option<String>,
+    pub rdns: Option<String>,
+}
+
+struct Queue {
+    stage: Stage,
+    concurrency: usize,
+    sender: mpsc::Sender<Job>,
+    receiver: Mutex<Option<mpsc::Receiver<Job>>>,
+}
+
+/// The enabled stages of `ENRICH_STAGES`, each with its own queue and
+/// concurrency, so a slow stage holds up neither discovery nor the others'
+/// workers beyond its queue.
+pub struct Pipeline {
+    queues: Vec<Queue>,
+}
+
+impl Pipeline {
+    /// No stages, every job dropped.
+    pub fn none() -> Self {
+        Self { queues: vec![] }
+    }
+
+    pub fn from_env() -> Self {
+        let stages: Vec<Stage> = match env::var("ENRICH_STAGES") {
+            Ok(list) => list
+                .split(',')
+                .filter(|s| !s.trim().is_empty())
+                .map(|s| {
+                    Stage::parse(s).unwrap_or_else(|| panic!("Unknown enrichment stage {}", s))
+                })
+                .collect(),
+            Err(_) => Stage::ALL
+                .into_iter()
+                .filter(|stage| *stage != Stage::Mojang)
+                .collect(),
+        };
+        let concurrency = env::var("ENRICH_CONCURRENCY").unwrap_or_default();
+        let queue_size: usize = env::var("ENRICH_QUEUE")
+            .unwrap_or("10000".to_string())
+            .parse()
+            .unwrap();
+
+        let queues = Stage::ALL
+            .into_iter()
+            .filter(|stage| stages.contains(stage))
+            .map(|stage| {
+                let concurrency = concurrency
+                    .split(',')
+                    .filter_map(|entry| entry.split_once('='))
+                    .find(|(name, _)| name.trim() == stage.as_str())
+                    .map(|(_, n)| n.trim().parse().expect("ENRICH_CONCURRENCY count"))
+                    .unwrap_or(stage.default_concurrency());
+                let (sender, receiver) = mpsc::channel(queue_size.max(1));
+                Queue {
+                    stage,
+                    concurrency: concurrency.max(1),
+                    sender,
+                    receiver: Mutex::new(Some(receiver)),
+                }
+            })
+            .collect();
+
+        Self { queues }
+    }
+
+    pub fn describe(&self) -> String {
+        if self.queues.is_empty() {
+            return "none".to_string();
+        }
+        self.queues
+            .iter()
+            .map(|q| format!("{} ({})", q.stage.as_str(), q.concurrency))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Queues a new server for the first enabled stage, dropping it rather
+    /// than making discovery wait when that queue is full.
+    pub fn submit(&self, job: Job) {
+        let Some(first) = self.queues.first() else {
+            return;
+        };
+        if first.sender.try_send(job).is_err() {
+            eprintln!(
+                "[-] Enrichment queue full, {} skipped",
+                first.stage.as_str()
+            );
+        }
+    }
+
+    pub fn has(&self, stage: Stage) -> bool {
+        self.queues.iter().any(|q| q.stage == stage)
+    }
+}
+
+/// Starts the workers of every enabled stage. Jobs submitted before wait in
+/// their queue.
+pub fn start(ctx: &Arc<Context>) {
+    let queues = &ctx.enrichment.queues;
+    for (i, queue) in queues.iter().enumerate() {
+        let Some(receiver) = queue.receiver.lock().unwrap().take() else {
+            continue;
+        };
+        let next = queues.get(i + 1).map(|q| q.sender.clone());
+        tokio::spawn(run_stage(
+            ctx.clone(),
+            queue.stage,
+            queue.concurrency,
+            receiver,
+            next,
+        ));
+    }
+}
+
+async fn run_stage(
+    ctx: Arc<Context>,
+    stage: Stage,
+    concurrency: usize,
+    mut receiver: mpsc::Receiver<Job>,
+    next: Option<mpsc::Sender<Job>>,
+) {
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+
+    while let Some(mut job) = receiver.recv().await {
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let ctx = ctx.clone();
+        let next = next.clone();
+
+        tokio::spawn(async move {
+            let _permit = permit;
+            process(stage, &mut job, &ctx).await;
+            if let Some(next) = next {
+                let _ = next.send(job).await;
+            }
+        });
+    }
+}
+
+async fn process(stage: Stage, job: &mut Job, ctx: &Arc<Context>) {
+    match stage {
+        Stage::GeoIp => {
+            if ctx.geoip.is_enabled() {
+                job.country = store_location(
+                    &mut ctx.pool().get().await.conn,
+                    &ctx.geoip,
+                    job.server.id,
+                    &job.server.ip,
+                    now(),
+                );
+            }
+        }
+        Stage::Rdns => job.rdns = store_rdns(ctx, job.server.id, job.ip).await,
+        Stage::Mojang => check_profiles(&job.status, ctx).await,
+        Stage::DeepProbe => {
+            if job.edition == Edition::Java {
+                deep_probe(job, ctx).await;
+            }
+        }
+        Stage::Notify => ctx.webhooks.fire(Event::Discovered {
+            ip: job.server.ip.clone(),
+            version_name: job.server.version_name.clone(),
+            protocol: job.server.protocol,
+            license: job.server.license,
+            white_list: job.server.white_list,
+            country: job.country.clone(),
+            rdns: job.rdns.clone(),
+        }),
+    }
+}
+
+fn now() -> NaiveDateTime {
+    Local::now().naive_local().with_nanosecond(0).unwrap()
+}
+
+/// Address of a `servers.ip`, which is `addr:port` for servers off the
+/// default port.
+pub fn row_ip(ip: &str) -> Option<IpAddr> {
+    ip.parse::<SocketAddr>()
+        .map(|addr| addr.ip())
+        .or_else(|_| ip.parse::<IpAddr>())
+        .ok()
+}
+
+/// Stores the reverse DNS name of a server row, returning it.
+pub async fn store_rdns(ctx: &Context, id: i32, ip: IpAddr) -> Option<String> {
+    let rdns = ctx.lookups.reverse_dns(ip).await;
+    diesel::update(schema::servers::dsl::servers.find(id))
+        .set((
+            schema::servers::dsl::rdns.eq(&rdns),
+            schema::servers::dsl::rdns_checked_at.eq(now()),
+        ))
+        .execute(&mut ctx.pool().get().await.conn)
+        .unwrap();
+    rdns
+}
+
+/// Stores the GeoIP location of a server row, returning its country.
+pub fn store_location(
+    conn: &mut PgConnection,
+    geoip: &GeoIp,
+    id: i32,
+    ip: &str,
+    now: NaiveDateTime,
+) -> Option<String> {
+    let location = row_ip(ip).map(|ip| geoip.locate(ip)).unwrap_or_default();
+    let country = location.country.clone();
+
+    diesel::update(schema::servers::dsl::servers.find(id))
+        .set((
+            schema::servers::dsl::country.eq(location.country),
+            schema::servers::dsl::city.eq(location.city),
+            schema::servers::dsl::asn.eq(location.asn.map(i64::from)),
+            schema::servers::dsl::isp.eq(location.isp),
+            schema::servers::dsl::geo_checked_at.eq(now),
+        ))
+        .execute(conn)
+        .unwrap();
+    country
+}
+
+/// Records in `mojang_profiles` whether the sample players not seen before
+/// are Mojang accounts, stopping at the session server's rate limit.
+async fn check_profiles(status: &Status, ctx: &Context) {
+    for player in status.players.sample.iter().flatten() {
+        let Some(uuid) = profile_uuid(&player.id) else {
+            continue;
+        };
+        let known = schema::mojang_profiles::dsl::mojang_profiles
+            .find(&uuid)
+            .count()
+            .get_result::<i64>(&mut ctx.pool().get().await.conn)
+            .unwrap()
+            > 0;
+        if !known && !check_profile(&uuid, ctx).await {
+            return;
+        }
+    }
+}
+
+/// A player id as `mojang_profiles` keys it: 32 lowercase hex digits.
+pub fn profile_uuid(id: &str) -> Option<String> {
+    let uuid = id.replace('-', "").to_lowercase();
+    (uuid.len() == 32 && uuid.chars().all(|c| c.is_ascii_hexdigit())).then_some(uuid)
+}
+
+/// Records whether `uuid` is a Mojang account. Offline-mode UUIDs (version 3)
+/// never are; the others are asked of the session server. Returns `false`
+/// without recording anything when it could not answer.
+pub async fn check_profile(uuid: &str, ctx: &Context) -> bool {
+    let premium = if &uuid[12..13] != "4" {
+        false
+    } else {
+        match ctx.lookups.mojang_account(uuid).await {
+            Some(premium) => premium,
+            None => return false,
+        }
+    };
+
+    insert_into(schema::mojang_profiles::dsl::mojang_profiles)
+        .values((
+            schema::mojang_profiles::dsl::uuid.eq(uuid),
+            schema::mojang_profiles::dsl::premium.eq(premium),
+            schema::mojang_profiles::dsl::checked_at.eq(now()),
+        ))
+        .on_conflict(schema::mojang_profiles::dsl::uuid)
+        .do_update()
+        .set((
+            schema::mojang_profiles::dsl::premium.eq(premium),
+            schema::mojang_profiles::dsl::checked_at.eq(now()),
+        ))
+        .execute(&mut ctx.pool().get().await.conn)
+        .unwrap();
+    true
+}
+
+/// Virtual hosts, network path and the full Forge mod list of a new Java
+/// server, each as far as it is enabled.
+async fn deep_probe(job: &Job, ctx: &Arc<Context>) {
+    let server = &job.server;
+
+    if ctx.vhosts.is_some() {
+        vhosts::scan(
+            ctx.clone(),
+            server.id,
+            job.ip,
+            job.port,
+            server.description.clone(),
+            server.version_name.clone(),
+        )
+        .await;
+    }
+
+    if ctx.trace_path {
+        record_path(ctx.clone(), server.id, job.ip).await;
+    }
+
+    // The status lists a capped number of mods, the handshake all of them
+    if let Some(fml_version) = mods::fml_version(&job.status) {
+        if ctx.forge_handshake && !ctx.status_only {
+            mods::probe_handshake(
+                ctx.clone(),
+                server,
+                job.ip,
+                job.port,
+                fml_version,
+                job.proxy,
+            )
+            .await;
+        }
+    }
+}
+
+/// Stores the GeoIP location of every server whose row has none yet, a
+/// backfill for rows stored before the geoip stage ran or while its queue was
+/// full. The lookups stay out of the scan path, which only writes the row.
+/// Off with the geoip stage left out of `ENRICH_STAGES`.
+pub async fn run(ctx: Arc<Context>) {
+    if !ctx.geoip.is_enabled() || !ctx.enrichment.has(Stage::GeoIp) {
+        return;
+    }
+
+    let pending: i64 = schema::servers::dsl::servers
+        .filter(schema::servers::dsl::geo_checked_at.is_null())
+        .count()
+        .get_result(&mut ctx.pool().get().await.conn)
+        .unwrap();
+    if pending > 0 {
+        println!("[+] GeoIP enrichment: {} servers to look up", pending);
+    }
+
+    loop {
+        if enrich_batch(&ctx).await < BATCH as usize {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        }
+    }
+}
+
+/// Looks up one batch of servers, returning how many there were.
+async fn enrich_batch(ctx: &Context) -> usize {
+    let mut db = ctx.pool().get().await;
+    let rows: Vec<(i32, String)> = schema::servers::dsl::servers
+        .filter(schema::servers::dsl::geo_checked_at.is_null())
+        .order(schema::servers::dsl::id)
+        .limit(BATCH)
+        .select((schema::servers::dsl::id, schema::servers::dsl::ip))
+        .load(&mut db.conn)
+        .unwrap();
+
+    let now = now();
+    db.conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            for (id, ip) in &rows {
+                store_location(conn, &ctx.geoip, *id, ip, now);
+            }
+            Ok(())
+        })
+        .unwrap();
+
+    rows.len()
+}
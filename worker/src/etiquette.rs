@@ -0,0 +1,253 @@
+use std::{
+    collections::HashMap,
+    env, mem,
+    net::IpAddr,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+
+use chrono::{Local, NaiveDate};
+use diesel::{
+    dsl::insert_into, upsert::excluded, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
+};
+
+use crate::{database::Pool, schema};
+
+/// How often counted probes are written to `probe_counts`.
+const PERSIST_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Probes any one server may get per day from `PROBE_DAILY_CAP`, counted
+/// across the scan workers, retries, jobs, the updaters and `watch` in
+/// `probe_counts`. Unset or 0 leaves probing unlimited.
+pub fn daily_cap() -> Option<i32> {
+    static CAP: OnceLock<Option<i32>> = OnceLock::new();
+
+    *CAP.get_or_init(|| {
+        let value = env::var("PROBE_DAILY_CAP").ok()?;
+        let cap: i32 = value
+            .trim()
+            .parse()
+            .unwrap_or_else(|_| panic!("Invalid PROBE_DAILY_CAP {:?}", value));
+        (cap > 0).then_some(cap)
+    })
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Count {
+    probes: i32,
+    refused: i32,
+}
+
+const PROBE: Count = Count {
+    probes: 1,
+    refused: 0,
+};
+const REFUSAL: Count = Count {
+    probes: 0,
+    refused: 1,
+};
+
+impl Count {
+    fn add(&mut self, other: Count) {
+        self.probes += other.probes;
+        self.refused += other.refused;
+    }
+
+    fn max(&mut self, other: Count) {
+        self.probes = self.probes.max(other.probes);
+        self.refused = self.refused.max(other.refused);
+    }
+}
+
+/// Counts of the process, so probes are admitted without a query each.
+#[derive(Default)]
+struct Counts {
+    day: Option<NaiveDate>,
+    /// Today's counts of the servers probed, stored and pending
+    known: HashMap<String, Count>,
+    /// Counted but not yet written, by target and day
+    pending: HashMap<(String, NaiveDate), Count>,
+}
+
+impl Counts {
+    /// Today, forgetting yesterday's counts once it has passed.
+    fn today(&mut self) -> NaiveDate {
+        let day = Local::now().date_naive();
+        if self.day != Some(day) {
+            self.known.clear();
+            self.day = Some(day);
+        }
+        day
+    }
+
+    fn add(&mut self, target: String, day: NaiveDate, count: Count) {
+        self.known.entry(target.clone()).or_default().add(count);
+        self.pending.entry((target, day)).or_default().add(count);
+    }
+}
+
+fn counts() -> &'static Mutex<Counts> {
+    static COUNTS: OnceLock<Mutex<Counts>> = OnceLock::new();
+    COUNTS.get_or_init(Mutex::default)
+}
+
+/// Whether `host` is still within the cap, to check before connecting to
+/// it. Nothing is counted until it is probed with [`admit`].
+pub fn allowed(host: &str) -> bool {
+    check(host, false)
+}
+
+/// Counts a probe of `host` and whether it is still within the cap. The
+/// first refusal of a day is printed, the rest only counted.
+pub fn admit(host: &str) -> bool {
+    check(host, true)
+}
+
+fn check(host: &str, count: bool) -> bool {
+    let Some(cap) = daily_cap() else {
+        return true;
+    };
+    let target = target(host);
+    let mut counts = counts().lock().unwrap();
+    let day = counts.today();
+    let known = counts.known.get(&target).copied().unwrap_or_default();
+
+    if known.probes < cap {
+        if count {
+            counts.add(target, day, PROBE);
+        }
+        return true;
+    }
+
+    if known.refused == 0 {
+        eprintln!(
+            "[!] {} reached the daily cap of {} probes, leaving it alone until tomorrow",
+            target, cap
+        );
+    }
+    counts.add(target, day, REFUSAL);
+    false
+}
+
+/// Reads today's counts from `probe_counts`, those of other processes
+/// included. Without them every server starts the day at zero.
+pub fn load(conn: &mut PgConnection) {
+    if daily_cap().is_none() {
+        return;
+    }
+    let day = counts().lock().unwrap().today();
+
+    let rows: Vec<(String, i32, i32)> = match schema::probe_counts::dsl::probe_counts
+        .filter(schema::probe_counts::dsl::day.eq(day))
+        .select((
+            schema::probe_counts::dsl::target,
+            schema::probe_counts::dsl::probes,
+            schema::probe_counts::dsl::refused,
+        ))
+        .load(conn)
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            eprintln!("[!] Could not load probe counts, counting from zero: {}", e);
+            return;
+        }
+    };
+
+    let mut counts = counts().lock().unwrap();
+    if counts.today() != day {
+        return;
+    }
+    for (target, probes, refused) in rows {
+        counts
+            .known
+            .entry(target)
+            .or_default()
+            .max(Count { probes, refused });
+    }
+}
+
+/// Writes the probes counted since the last call to `probe_counts`, taking
+/// the totals of other processes for the servers written. Counts that could
+/// not be written are kept for the next call.
+pub fn flush(conn: &mut PgConnection) {
+    let pending = mem::take(&mut counts().lock().unwrap().pending);
+    let mut stored = vec![];
+    let mut unwritten = vec![];
+    let mut error = None;
+
+    for ((target, day), count) in pending {
+        if error.is_some() {
+            unwritten.push((target, day, count));
+            continue;
+        }
+        let total = insert_into(schema::probe_counts::dsl::probe_counts)
+            .values((
+                schema::probe_counts::dsl::target.eq(&target),
+                schema::probe_counts::dsl::day.eq(day),
+                schema::probe_counts::dsl::probes.eq(count.probes),
+                schema::probe_counts::dsl::refused.eq(count.refused),
+            ))
+            .on_conflict((
+                schema::probe_counts::dsl::target,
+                schema::probe_counts::dsl::day,
+            ))
+            .do_update()
+            .set((
+                schema::probe_counts::dsl::probes
+                    .eq(schema::probe_counts::dsl::probes
+                        + excluded(schema::probe_counts::dsl::probes)),
+                schema::probe_counts::dsl::refused.eq(schema::probe_counts::dsl::refused
+                    + excluded(schema::probe_counts::dsl::refused)),
+            ))
+            .returning((
+                schema::probe_counts::dsl::probes,
+                schema::probe_counts::dsl::refused,
+            ))
+            .get_result::<(i32, i32)>(conn);
+        match total {
+            Ok((probes, refused)) => stored.push((target, day, Count { probes, refused })),
+            Err(e) => {
+                error = Some(e);
+                unwritten.push((target, day, count));
+            }
+        }
+    }
+
+    let mut counts = counts().lock().unwrap();
+    let today = counts.today();
+    for (target, day, total) in stored {
+        if day == today {
+            counts.known.entry(target).or_default().max(total);
+        }
+    }
+    if let Some(e) = error {
+        eprintln!(
+            "[!] Could not store probe counts of {} servers, retrying later: {}",
+            unwritten.len(),
+            e
+        );
+        for (target, day, count) in unwritten {
+            counts.pending.entry((target, day)).or_default().add(count);
+        }
+    }
+}
+
+/// Writes counted probes every few seconds for the life of the process.
+pub async fn persist(db: Arc<Pool>) {
+    loop {
+        tokio::time::sleep(PERSIST_INTERVAL).await;
+        flush(&mut db.get().await.conn);
+    }
+}
+
+/// Key of a host in `probe_counts`, the same for an IPv4 address and its
+/// mapped IPv6 form.
+fn target(host: &str) -> String {
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(v6)) => v6
+            .to_ipv4_mapped()
+            .map(|v4| v4.to_string())
+            .unwrap_or_else(|| v6.to_string()),
+        _ => host.to_string(),
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    env,
+    io::{IsTerminal, Read},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::read::ZlibDecoder;
+
+/// Favicons are 64x64; anything bigger is not worth decoding for a preview.
+const MAX_DIMENSION: u32 = 1024;
+/// Sixel images can't be scaled by the terminal, so they are drawn at roughly
+/// the height of one text row.
+const SIXEL_SIZE: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Graphics {
+    Kitty,
+    Iterm,
+    Sixel,
+}
+
+impl Graphics {
+    /// Reads `FAVICONS`: `off` (default), `auto`, `kitty`, `iterm` or `sixel`.
+    /// `auto` recognises kitty and iTerm2 compatible terminals; sixel support
+    /// can't be detected without querying the terminal and must be requested.
+    pub fn from_env() -> Option<Self> {
+        let graphics = match env::var("FAVICONS").as_deref() {
+            Ok("off") | Ok("false") | Ok("0") | Err(_) => return None,
+            Ok("auto") => Self::detect()?,
+            Ok("kitty") => Graphics::Kitty,
+            Ok("iterm") => Graphics::Iterm,
+            Ok("sixel") => Graphics::Sixel,
+            Ok(other) => panic!("Unknown FAVICONS: {}", other),
+        };
+
+        std::io::stdout().is_terminal().then_some(graphics)
+    }
+
+    fn detect() -> Option<Self> {
+        if env::var("KITTY_WINDOW_ID").is_ok() || env::var("TERM").as_deref() == Ok("xterm-kitty") {
+            return Some(Graphics::Kitty);
+        }
+        match env::var("TERM_PROGRAM").as_deref() {
+            Ok("iTerm.app") | Ok("WezTerm") => Some(Graphics::Iterm),
+            _ => None,
+        }
+    }
+
+    /// Escape sequence drawing the favicon two cells wide, or nothing when the
+    /// status has no usable PNG favicon.
+    pub fn render(&self, favicon: &str) -> Option<String> {
+        let encoded = favicon.strip_prefix("data:image/png;base64,")?;
+        let encoded: String = encoded.chars().filter(|c| !c.is_whitespace()).collect();
+        let png = STANDARD.decode(&encoded).ok()?;
+
+        match self {
+            Graphics::Kitty => Some(kitty(&STANDARD.encode(&png))),
+            Graphics::Iterm => Some(format!(
+                "\x1b]1337;File=inline=1;size={};width=2;height=1;preserveAspectRatio=1:{}\x07",
+                png.len(),
+                STANDARD.encode(&png)
+            )),
+            Graphics::Sixel => Some(sixel(&decode_png(&png)?)),
+        }
+    }
+}
+
+/// The kitty protocol takes at most 4096 bytes of payload per escape.
+fn kitty(encoded: &str) -> String {
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(4096).collect();
+    let mut out = String::new();
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = if i + 1 < chunks.len() { 1 } else { 0 };
+        let chunk = std::str::from_utf8(chunk).unwrap();
+        if i == 0 {
+            out.push_str(&format!(
+                "\x1b_Ga=T,f=100,c=2,r=1,q=2,m={};{}\x1b\\",
+                more, chunk
+            ));
+        } else {
+            out.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, chunk));
+        }
+    }
+
+    out
+}
+
+struct Image {
+    width: u32,
+    height: u32,
+    /// RGBA rows
+    pixels: Vec<u8>,
+}
+
+/// Decodes the 8-bit, non-interlaced PNGs servers send as favicons.
+fn decode_png(data: &[u8]) -> Option<Image> {
+    let mut pos = 8;
+    if data.get(..8)? != b"\x89PNG\r\n\x1a\n" {
+        return None;
+    }
+
+    let (mut width, mut height, mut color_type) = (0, 0, 0);
+    let mut palette: &[u8] = &[];
+    let mut transparency: &[u8] = &[];
+    let mut compressed = vec![];
+
+    while pos + 8 <= data.len() {
+        let len = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        let body = data.get(pos + 8..(pos + 8).checked_add(len)?)?;
+        pos += 12 + len;
+
+        match kind {
+            b"IHDR" if body.len() >= 13 => {
+                width = u32::from_be_bytes(body[0..4].try_into().unwrap());
+                height = u32::from_be_bytes(body[4..8].try_into().unwrap());
+                color_type = body[9];
+                // Bit depth 8, no interlacing
+                if body[8] != 8 || body[12] != 0 {
+                    return None;
+                }
+            }
+            b"PLTE" => palette = body,
+            b"tRNS" => transparency = body,
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return None;
+    }
+    let channels = match color_type {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => return None,
+    };
+
+    let stride = width as usize * channels;
+    let expected = (stride + 1) * height as usize;
+    let mut raw = Vec::with_capacity(expected);
+    ZlibDecoder::new(&compressed[..])
+        .take(expected as u64)
+        .read_to_end(&mut raw)
+        .ok()?;
+    if raw.len() < expected {
+        return None;
+    }
+
+    let rows = unfilter(&raw, stride, height as usize, channels)?;
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+    for px in rows.chunks_exact(channels) {
+        match color_type {
+            0 => pixels.extend_from_slice(&[px[0], px[0], px[0], 255]),
+            2 => pixels.extend_from_slice(&[px[0], px[1], px[2], 255]),
+            3 => {
+                let i = px[0] as usize;
+                let rgb = palette.get(i * 3..i * 3 + 3)?;
+                pixels.extend_from_slice(rgb);
+                pixels.push(*transparency.get(i).unwrap_or(&255));
+            }
+            4 => pixels.extend_from_slice(&[px[0], px[0], px[0], px[1]]),
+            _ => pixels.extend_from_slice(px),
+        }
+    }
+
+    Some(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+fn unfilter(raw: &[u8], stride: usize, height: usize, bpp: usize) -> Option<Vec<u8>> {
+    let mut out = vec![0u8; stride * height];
+
+    for y in 0..height {
+        let filter = raw[y * (stride + 1)];
+        let line = &raw[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+
+        for x in 0..stride {
+            let a = if x >= bpp {
+                out[y * stride + x - bpp]
+            } else {
+                0
+            };
+            let b = if y > 0 { out[(y - 1) * stride + x] } else { 0 };
+            let c = if x >= bpp && y > 0 {
+                out[(y - 1) * stride + x - bpp]
+            } else {
+                0
+            };
+
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return None,
+            };
+            out[y * stride + x] = line[x].wrapping_add(predicted);
+        }
+    }
+
+    Some(out)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Nearest-neighbour scales the image to `SIXEL_SIZE` and maps it onto a
+/// 6x6x6 colour cube. Mostly transparent pixels are left unpainted.
+fn sixel(image: &Image) -> String {
+    let size = SIXEL_SIZE as usize;
+    let mut indexes = vec![None; size * size];
+    for y in 0..size {
+        for x in 0..size {
+            let sx = (x as u32 * image.width / SIXEL_SIZE) as usize;
+            let sy = (y as u32 * image.height / SIXEL_SIZE) as usize;
+            let px = &image.pixels[(sy * image.width as usize + sx) * 4..][..4];
+            if px[3] >= 128 {
+                let level = |v: u8| (v as usize * 5 + 127) / 255;
+                indexes[y * size + x] = Some(level(px[0]) * 36 + level(px[1]) * 6 + level(px[2]));
+            }
+        }
+    }
+
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", size, size);
+    let mut defined = [false; 216];
+
+    for band in (0..size).step_by(6) {
+        let mut colors: Vec<usize> = indexes[band * size..((band + 6).min(size)) * size]
+            .iter()
+            .flatten()
+            .copied()
+            .collect();
+        colors.sort_unstable();
+        colors.dedup();
+
+        for color in colors {
+            if !defined[color] {
+                defined[color] = true;
+                let percent = |v: usize| v * 100 / 5;
+                out.push_str(&format!(
+                    "#{};2;{};{};{}",
+                    color,
+                    percent(color / 36),
+                    percent(color / 6 % 6),
+                    percent(color % 6)
+                ));
+            }
+            out.push_str(&format!("#{}", color));
+            for x in 0..size {
+                let mut bits = 0u8;
+                for row in 0..6 {
+                    let y = band + row;
+                    if y < size && indexes[y * size + x] == Some(color) {
+                        bits |= 1 << row;
+                    }
+                }
+                out.push((63 + bits) as char);
+            }
+            out.push('$');
+        }
+        out.push('-');
+    }
+
+    out.push_str("\x1b\\");
+    out
+}
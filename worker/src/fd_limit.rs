@@ -0,0 +1,52 @@
+use std::io;
+
+/// Sockets a worker may hold at once: the status connection, the login probe
+/// and a query socket.
+pub const FDS_PER_THREAD: u64 = 3;
+/// Descriptors kept free for the database pool, background passes and the API.
+pub const RESERVED: u64 = 64;
+/// Soft limit asked for when the hard limit is unlimited.
+const CEILING: u64 = 1 << 20;
+
+/// Soft and hard `RLIMIT_NOFILE` of this process.
+pub fn current() -> io::Result<(u64, u64)> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // Only writes the struct it is given
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((limit.rlim_cur, limit.rlim_max))
+}
+
+/// Raises the soft limit to the hard one, returning the soft limit in effect.
+pub fn raise() -> io::Result<u64> {
+    let (soft, hard) = current()?;
+    let target = hard.min(CEILING);
+    if soft >= target {
+        return Ok(soft);
+    }
+
+    let limit = libc::rlimit {
+        rlim_cur: target as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    };
+    // Only reads the struct it is given
+    if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(target)
+}
+
+/// Descriptors `threads` scan workers need.
+pub fn needed(threads: u64) -> u64 {
+    threads * FDS_PER_THREAD + RESERVED
+}
+
+/// Most scan workers a soft limit of `limit` descriptors keeps busy without
+/// connects failing with "too many open files".
+pub fn max_threads(limit: u64) -> u64 {
+    (limit.saturating_sub(RESERVED) / FDS_PER_THREAD).max(1)
+}
@@ -0,0 +1,70 @@
+use serde_json::{Map, Value};
+
+use crate::filter::{FieldValue, Record};
+
+/// Record fields picked by name, e.g. `ip,port,version,online,country`, the
+/// columns of an export or API listing in every format.
+pub struct Selection(Vec<String>);
+
+impl Selection {
+    /// Parses a comma-separated field list, refusing names outside `known`.
+    pub fn parse(list: &str, known: &[&str]) -> Result<Self, String> {
+        let names: Vec<String> = list
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        if names.is_empty() {
+            return Err("no fields selected".to_string());
+        }
+        if let Some(unknown) = names.iter().find(|name| !known.contains(&name.as_str())) {
+            return Err(format!(
+                "unknown field {}, expected one of {}",
+                unknown,
+                known.join(", ")
+            ));
+        }
+        Ok(Self(names))
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.0
+    }
+
+    /// The selected fields as one JSON object.
+    pub fn to_json(&self, record: &impl Record) -> Value {
+        let object: Map<String, Value> = self
+            .0
+            .iter()
+            .map(|name| (name.clone(), json_value(field(record, name))))
+            .collect();
+        Value::Object(object)
+    }
+
+    /// The selected fields as text, empty for `null`.
+    pub fn to_text(&self, record: &impl Record) -> Vec<String> {
+        self.0
+            .iter()
+            .map(|name| match field(record, name) {
+                FieldValue::Int(n) => n.to_string(),
+                FieldValue::Str(s) => s,
+                FieldValue::Bool(b) => b.to_string(),
+                FieldValue::Null => String::new(),
+            })
+            .collect()
+    }
+}
+
+fn field(record: &impl Record, name: &str) -> FieldValue {
+    record.field(name).unwrap_or(FieldValue::Null)
+}
+
+fn json_value(value: FieldValue) -> Value {
+    match value {
+        FieldValue::Int(n) => n.into(),
+        FieldValue::Str(s) => s.into(),
+        FieldValue::Bool(b) => b.into(),
+        FieldValue::Null => Value::Null,
+    }
+}
@@ -0,0 +1,612 @@
+use std::{cmp::Ordering, error::Error, fmt};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    Int(i64),
+    Str(String),
+    Bool(bool),
+    Null,
+}
+
+impl From<i64> for FieldValue {
+    fn from(value: i64) -> Self {
+        FieldValue::Int(value)
+    }
+}
+
+impl From<i32> for FieldValue {
+    fn from(value: i32) -> Self {
+        FieldValue::Int(value as i64)
+    }
+}
+
+impl From<bool> for FieldValue {
+    fn from(value: bool) -> Self {
+        FieldValue::Bool(value)
+    }
+}
+
+impl From<&str> for FieldValue {
+    fn from(value: &str) -> Self {
+        FieldValue::Str(value.to_string())
+    }
+}
+
+impl From<String> for FieldValue {
+    fn from(value: String) -> Self {
+        FieldValue::Str(value)
+    }
+}
+
+impl<T: Into<FieldValue>> From<Option<T>> for FieldValue {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(FieldValue::Null)
+    }
+}
+
+/// Anything a filter can be evaluated against. Unknown fields return `None`
+/// and compare like `null`.
+pub trait Record {
+    fn field(&self, name: &str) -> Option<FieldValue>;
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    message: String,
+    /// Character offset into the filter the error was found at
+    position: Option<usize>,
+}
+
+impl ParseError {
+    pub fn new(message: impl Into<String>) -> Self {
+        ParseError {
+            message: message.into(),
+            position: None,
+        }
+    }
+
+    fn at(message: impl Into<String>, position: usize) -> Self {
+        ParseError {
+            message: message.into(),
+            position: Some(position),
+        }
+    }
+
+    pub fn position(&self) -> Option<usize> {
+        self.position
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some(position) => write!(
+                f,
+                "invalid filter: {} at column {}",
+                self.message,
+                position + 1
+            ),
+            None => write!(f, "invalid filter: {}", self.message),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Like,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Value(FieldValue),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Cmp {
+        field: String,
+        op: Op,
+        value: FieldValue,
+    },
+}
+
+/// A boolean expression over record fields, e.g.
+/// `protocol >= 760 && (license == false || version ~ '1.20*')`.
+///
+/// `~` matches a glob pattern where `*` is any sequence and `?` is any character.
+/// Strings order with their runs of digits compared as numbers, so versions
+/// compare like `1.9 < 1.20 < 1.20.1`; unquoted versions are strings.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    source: String,
+    expr: Expr,
+}
+
+impl Filter {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: input.chars().count(),
+        };
+        let expr = parser.parse_or()?;
+
+        if let Some(token) = parser.peek() {
+            return Err(ParseError::at(
+                format!("unexpected token {:?}", token),
+                parser.position(parser.pos),
+            ));
+        }
+
+        Ok(Filter {
+            source: input.trim().to_string(),
+            expr,
+        })
+    }
+
+    pub fn matches(&self, record: &impl Record) -> bool {
+        eval(&self.expr, record)
+    }
+
+    pub fn fields(&self) -> Vec<&str> {
+        let mut fields = vec![];
+        collect_fields(&self.expr, &mut fields);
+        fields
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn collect_fields<'a>(expr: &'a Expr, fields: &mut Vec<&'a str>) {
+    match expr {
+        Expr::And(a, b) | Expr::Or(a, b) => {
+            collect_fields(a, fields);
+            collect_fields(b, fields);
+        }
+        Expr::Not(a) => collect_fields(a, fields),
+        Expr::Cmp { field, .. } => {
+            if !fields.contains(&field.as_str()) {
+                fields.push(field);
+            }
+        }
+    }
+}
+
+fn eval(expr: &Expr, record: &impl Record) -> bool {
+    match expr {
+        Expr::And(a, b) => eval(a, record) && eval(b, record),
+        Expr::Or(a, b) => eval(a, record) || eval(b, record),
+        Expr::Not(a) => !eval(a, record),
+        Expr::Cmp { field, op, value } => {
+            let actual = record.field(field).unwrap_or(FieldValue::Null);
+            compare(&actual, *op, value)
+        }
+    }
+}
+
+fn compare(actual: &FieldValue, op: Op, expected: &FieldValue) -> bool {
+    let ordering = match (actual, expected) {
+        (FieldValue::Int(a), FieldValue::Int(b)) => Some(a.cmp(b)),
+        // Equal only when identical, `1.020` is not `1.20`
+        (FieldValue::Str(a), FieldValue::Str(b)) => Some(natural_cmp(a, b).then_with(|| a.cmp(b))),
+        (FieldValue::Bool(a), FieldValue::Bool(b)) => Some(a.cmp(b)),
+        (FieldValue::Null, FieldValue::Null) => Some(Ordering::Equal),
+        _ => None,
+    };
+
+    match op {
+        Op::Eq => ordering == Some(Ordering::Equal),
+        Op::Ne => ordering != Some(Ordering::Equal),
+        Op::Lt => ordering == Some(Ordering::Less),
+        Op::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        Op::Gt => ordering == Some(Ordering::Greater),
+        Op::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        Op::Like => match (actual, expected) {
+            (FieldValue::Str(a), FieldValue::Str(pattern)) => glob_match(pattern, a),
+            _ => false,
+        },
+    }
+}
+
+/// Orders strings with their runs of digits compared as numbers.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (Some(x), Some(y)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+        if x.is_ascii_digit() && y.is_ascii_digit() {
+            let a_end = a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len());
+            let b_end = b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len());
+            let a_digits = a[..a_end].trim_start_matches('0');
+            let b_digits = b[..b_end].trim_start_matches('0');
+            let ordering = a_digits
+                .len()
+                .cmp(&b_digits.len())
+                .then_with(|| a_digits.cmp(b_digits));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            a = &a[a_end..];
+            b = &b[b_end..];
+        } else if x != y {
+            return x.cmp(&y);
+        } else {
+            a = &a[x.len_utf8()..];
+            b = &b[y.len_utf8()..];
+        }
+    }
+}
+
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((sp, st)) = star {
+            p = sp + 1;
+            t = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Tokens of `input` with the character offsets they start at.
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        match c {
+            ' ' | '\t' | '\n' => i += 1,
+            '(' => {
+                tokens.push((start, Token::LParen));
+                i += 1;
+            }
+            ')' => {
+                tokens.push((start, Token::RParen));
+                i += 1;
+            }
+            '&' if next == Some('&') => {
+                tokens.push((start, Token::And));
+                i += 2;
+            }
+            '|' if next == Some('|') => {
+                tokens.push((start, Token::Or));
+                i += 2;
+            }
+            '=' if next == Some('=') => {
+                tokens.push((start, Token::Op(Op::Eq)));
+                i += 2;
+            }
+            '!' if next == Some('=') => {
+                tokens.push((start, Token::Op(Op::Ne)));
+                i += 2;
+            }
+            '!' => {
+                tokens.push((start, Token::Not));
+                i += 1;
+            }
+            '<' if next == Some('=') => {
+                tokens.push((start, Token::Op(Op::Le)));
+                i += 2;
+            }
+            '<' => {
+                tokens.push((start, Token::Op(Op::Lt)));
+                i += 1;
+            }
+            '>' if next == Some('=') => {
+                tokens.push((start, Token::Op(Op::Ge)));
+                i += 2;
+            }
+            '>' => {
+                tokens.push((start, Token::Op(Op::Gt)));
+                i += 1;
+            }
+            '~' => {
+                tokens.push((start, Token::Op(Op::Like)));
+                i += 1;
+            }
+            '\'' | '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|ch| *ch == c)
+                    .ok_or_else(|| ParseError::at("unterminated string", start))?;
+                let value: String = chars[i + 1..i + 1 + end].iter().collect();
+                tokens.push((start, Token::Value(FieldValue::Str(value))));
+                i += end + 2;
+            }
+            c if c.is_ascii_digit() || (c == '-' && next.is_some_and(|n| n.is_ascii_digit())) => {
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                // A version such as 1.20.4
+                let mut version = false;
+                while chars.get(i) == Some(&'.')
+                    && chars.get(i + 1).is_some_and(char::is_ascii_digit)
+                {
+                    version = true;
+                    i += 2;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+                let number: String = chars[start..i].iter().collect();
+                if version {
+                    tokens.push((start, Token::Value(FieldValue::Str(number))));
+                    continue;
+                }
+                let number = number
+                    .parse()
+                    .map_err(|_| ParseError::at(format!("invalid number {}", number), start))?;
+                tokens.push((start, Token::Value(FieldValue::Int(number))));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+                {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                let token = match word.as_str() {
+                    "true" => Token::Value(FieldValue::Bool(true)),
+                    "false" => Token::Value(FieldValue::Bool(false)),
+                    "null" => Token::Value(FieldValue::Null),
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Ident(word),
+                };
+                tokens.push((start, token));
+            }
+            _ => {
+                return Err(ParseError::at(
+                    format!("unexpected character '{}'", c),
+                    start,
+                ))
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    /// Length of the input, where errors past the last token point
+    end: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    /// Character offset of token `index`, the end of the input past the last.
+    fn position(&self, index: usize) -> usize {
+        self.tokens
+            .get(index)
+            .map(|(position, _)| *position)
+            .unwrap_or(self.end)
+    }
+
+    /// Error about the token just taken with `next`.
+    fn error(&self, message: String) -> ParseError {
+        ParseError::at(message, self.position(self.pos - 1))
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, ParseError> {
+        let mut left = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            let right = self.parse_and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, ParseError> {
+        let mut left = self.parse_not()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            let right = self.parse_not()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, ParseError> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
+        match self.next() {
+            Some(Token::LParen) => {
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err(self.error("expected ')'".to_string())),
+                }
+            }
+            Some(Token::Ident(field)) => {
+                let op = match self.next() {
+                    Some(Token::Op(op)) => op,
+                    other => {
+                        return Err(self.error(format!(
+                            "expected operator after {}, found {:?}",
+                            field, other
+                        )))
+                    }
+                };
+                let value = match self.next() {
+                    Some(Token::Value(value)) => value,
+                    // Bare words on the right-hand side are treated as strings
+                    Some(Token::Ident(word)) => FieldValue::Str(word),
+                    other => {
+                        return Err(self
+                            .error(format!("expected value after {}, found {:?}", field, other)))
+                    }
+                };
+                Ok(Expr::Cmp { field, op, value })
+            }
+            other => Err(self.error(format!("unexpected {:?}", other))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    struct Fields(HashMap<&'static str, FieldValue>);
+
+    impl Record for Fields {
+        fn field(&self, name: &str) -> Option<FieldValue> {
+            self.0.get(name).cloned()
+        }
+    }
+
+    fn server() -> Fields {
+        Fields(HashMap::from([
+            ("protocol", 763.into()),
+            ("online", 0.into()),
+            ("license", false.into()),
+            ("version", "1.20.1".into()),
+            ("motd", "A Minecraft Server".into()),
+            ("country", FieldValue::Null),
+        ]))
+    }
+
+    fn matches(filter: &str) -> bool {
+        Filter::parse(filter).unwrap().matches(&server())
+    }
+
+    fn error_at(filter: &str) -> Option<usize> {
+        Filter::parse(filter).unwrap_err().position()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert!(matches("protocol == 1 && online == 1 || license == false"));
+        assert!(matches("license == false || protocol == 1 && online == 1"));
+        assert!(!matches(
+            "(license == false || protocol == 1) && online == 1"
+        ));
+        assert!(matches(
+            "protocol > 1 and (online > 0 or not license == true)"
+        ));
+    }
+
+    #[test]
+    fn not_applies_to_the_next_term() {
+        assert!(!matches("!license == false && online == 0"));
+        assert!(matches("!(license == true && online == 0)"));
+        assert!(matches("not not license == false"));
+    }
+
+    #[test]
+    fn parses_quoted_and_bare_strings() {
+        assert!(matches("motd == 'A Minecraft Server'"));
+        assert!(matches("motd == \"A Minecraft Server\""));
+        assert!(matches("motd ~ 'A * Server'"));
+        assert!(matches("motd ~ \"it's\" || version == '1.20.1'"));
+        assert!(!matches("motd == Server"));
+    }
+
+    #[test]
+    fn compares_numbers_and_nulls() {
+        assert!(matches("protocol >= 763 && protocol <= 763"));
+        assert!(matches("protocol > -1 && protocol < 1000"));
+        assert!(!matches("protocol > 763"));
+        assert!(matches("country == null && missing == null"));
+        assert!(!matches("country != null"));
+        // Different kinds never order
+        assert!(!matches("protocol > '1'") && !matches("protocol < '1'"));
+    }
+
+    #[test]
+    fn compares_versions_by_their_numbers() {
+        assert!(matches("version >= 1.20"));
+        assert!(matches("version > '1.9'"));
+        assert!(matches("version < 1.20.10"));
+        assert!(!matches("version < 1.20"));
+        assert!(matches("version == 1.20.1 && version != 1.20.01"));
+        assert!(!matches("version == 1.20"));
+    }
+
+    #[test]
+    fn reports_where_parsing_failed() {
+        assert_eq!(error_at("online > 0 && license == 'yes"), Some(25));
+        assert_eq!(error_at("online > 0 # 1"), Some(11));
+        assert_eq!(error_at("online 0"), Some(7));
+        assert_eq!(error_at("online >"), Some(8));
+        assert_eq!(error_at("(online > 0"), Some(11));
+        assert_eq!(error_at("online > 0)"), Some(10));
+        assert_eq!(error_at("&& online > 0"), Some(0));
+        assert_eq!(
+            Filter::parse("online 0").unwrap_err().to_string(),
+            "invalid filter: expected operator after online, found Some(Value(Int(0))) at column 8"
+        );
+    }
+
+    #[test]
+    fn lists_each_field_once() {
+        let filter = Filter::parse("online > 0 || (online < 5 && !license == true)").unwrap();
+        assert_eq!(filter.fields(), ["online", "license"]);
+    }
+}
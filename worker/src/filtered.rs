@@ -0,0 +1,219 @@
+use std::{
+    collections::HashMap,
+    env,
+    io::ErrorKind,
+    net::Ipv4Addr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{Local, Timelike};
+use diesel::{
+    dsl::insert_into, Connection, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
+};
+use rand::Rng;
+
+use crate::{scanner::parse_cidr, schema, Context};
+
+/// Probes of a prefix before its answers are trusted.
+const MIN_PROBES: i32 = 16;
+/// Share of filtered answers that marks a prefix as filtered.
+const FILTERED_SHARE: f64 = 0.9;
+/// Share of addresses in filtered prefixes still probed, so a prefix that
+/// stops filtering is noticed.
+const SAMPLE_SHARE: f64 = 0.05;
+
+#[derive(Default)]
+struct Counts {
+    probes: i32,
+    filtered: i32,
+    /// Not yet stored, added to the stored counts on save
+    new_probes: i32,
+    new_filtered: i32,
+}
+
+impl Counts {
+    fn is_filtered(&self) -> bool {
+        self.probes >= MIN_PROBES && self.filtered as f64 >= self.probes as f64 * FILTERED_SHARE
+    }
+}
+
+/// Map of /24 prefixes that answer connects with refusals, resets or ICMP
+/// unreachable (administratively prohibited) errors instead of silence or a
+/// server. Learned from the scan itself and kept in `filtered_prefixes`
+/// across runs, so generation spends little on space a firewall answers for.
+pub struct FilteredSpace {
+    enabled: bool,
+    prefixes: Mutex<HashMap<u32, Counts>>,
+}
+
+/// Whether a failed connect was answered by something filtering the address.
+pub fn is_filtered_error(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::HostUnreachable
+            | ErrorKind::NetworkUnreachable
+    )
+}
+
+/// Whether `FILTERED_SPACE` leaves the map on.
+fn enabled() -> bool {
+    env::var("FILTERED_SPACE")
+        .map(|v| v != "false" && v != "0")
+        .unwrap_or(true)
+}
+
+fn prefix(ip: u32) -> u32 {
+    ip >> 8
+}
+
+impl FilteredSpace {
+    /// Loads the stored map, unless `FILTERED_SPACE` disables it.
+    pub fn load(conn: &mut PgConnection) -> Self {
+        let enabled = enabled();
+        let mut prefixes = HashMap::new();
+        if enabled {
+            let rows: Vec<(String, i32, i32)> = schema::filtered_prefixes::dsl::filtered_prefixes
+                .select((
+                    schema::filtered_prefixes::dsl::prefix,
+                    schema::filtered_prefixes::dsl::probes,
+                    schema::filtered_prefixes::dsl::filtered,
+                ))
+                .load(conn)
+                .unwrap();
+            for (cidr, probes, filtered) in rows {
+                let Ok((start, _)) = parse_cidr(&cidr) else {
+                    continue;
+                };
+                prefixes.insert(
+                    prefix(start),
+                    Counts {
+                        probes,
+                        filtered,
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+
+        Self {
+            enabled,
+            prefixes: Mutex::new(prefixes),
+        }
+    }
+
+    /// A map that starts empty and is learned from this process only.
+    pub fn empty() -> Self {
+        Self {
+            enabled: enabled(),
+            prefixes: Mutex::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Prefixes currently treated as filtered.
+    pub fn filtered_count(&self) -> usize {
+        let prefixes = self.prefixes.lock().unwrap();
+        prefixes.values().filter(|c| c.is_filtered()).count()
+    }
+
+    /// Counts the outcome of a connect to `ip`. Prefixes are only tracked from
+    /// their first filtered answer on, which keeps the silent majority of the
+    /// address space out of the map.
+    pub fn record(&self, ip: Ipv4Addr, filtered: bool) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut prefixes = self.prefixes.lock().unwrap();
+        let key = prefix(ip.into());
+        if !filtered && !prefixes.contains_key(&key) {
+            return;
+        }
+        let counts = prefixes.entry(key).or_default();
+        counts.probes += 1;
+        counts.new_probes += 1;
+        if filtered {
+            counts.filtered += 1;
+            counts.new_filtered += 1;
+        }
+    }
+
+    /// Whether generation should pass over `ip`: most addresses of filtered
+    /// prefixes are skipped, a few are still sampled.
+    pub fn skip(&self, ip: u32) -> bool {
+        if !self.enabled {
+            return false;
+        }
+
+        let filtered = {
+            let prefixes = self.prefixes.lock().unwrap();
+            prefixes.get(&prefix(ip)).is_some_and(Counts::is_filtered)
+        };
+        filtered && rand::thread_rng().gen::<f64>() >= SAMPLE_SHARE
+    }
+
+    /// Adds the counts gathered since the last save to the stored ones, so
+    /// several scanners share one map.
+    pub fn save(&self, conn: &mut PgConnection) {
+        let pending: Vec<(u32, i32, i32)> = {
+            let mut prefixes = self.prefixes.lock().unwrap();
+            prefixes
+                .iter_mut()
+                .filter(|(_, c)| c.new_probes > 0)
+                .map(|(prefix, c)| {
+                    let pending = (*prefix, c.new_probes, c.new_filtered);
+                    c.new_probes = 0;
+                    c.new_filtered = 0;
+                    pending
+                })
+                .collect()
+        };
+        if pending.is_empty() {
+            return;
+        }
+
+        let now = Local::now().naive_local().with_nanosecond(0).unwrap();
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            for (prefix, probes, filtered) in pending {
+                let cidr = format!("{}/24", Ipv4Addr::from(prefix << 8));
+                insert_into(schema::filtered_prefixes::dsl::filtered_prefixes)
+                    .values((
+                        schema::filtered_prefixes::dsl::prefix.eq(cidr),
+                        schema::filtered_prefixes::dsl::probes.eq(probes),
+                        schema::filtered_prefixes::dsl::filtered.eq(filtered),
+                        schema::filtered_prefixes::dsl::updated_at.eq(now),
+                    ))
+                    .on_conflict(schema::filtered_prefixes::dsl::prefix)
+                    .do_update()
+                    .set((
+                        schema::filtered_prefixes::dsl::probes
+                            .eq(schema::filtered_prefixes::dsl::probes + probes),
+                        schema::filtered_prefixes::dsl::filtered
+                            .eq(schema::filtered_prefixes::dsl::filtered + filtered),
+                        schema::filtered_prefixes::dsl::updated_at.eq(now),
+                    ))
+                    .execute(conn)?;
+            }
+            Ok(())
+        })
+        .unwrap();
+    }
+}
+
+/// Stores the learned map every 60 seconds.
+pub async fn persist(ctx: Arc<Context>) {
+    let Some(db) = ctx.db.clone().filter(|_| ctx.filtered.is_enabled()) else {
+        return;
+    };
+
+    loop {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        ctx.filtered.save(&mut db.get().await.conn);
+    }
+}
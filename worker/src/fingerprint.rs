@@ -0,0 +1,71 @@
+use std::net::IpAddr;
+
+use chrono::{Local, Timelike};
+use diesel::{dsl::insert_into, RunQueryDsl};
+
+use crate::{database::ServiceFingerprintUpsert, schema, Context};
+
+/// Stores what a non-Minecraft service sent back, keyed by address.
+pub async fn record(ip: &IpAddr, port: u16, banner: &[u8], ctx: &Context) {
+    let upsert = ServiceFingerprintUpsert {
+        ip: &ip.to_string(),
+        port: port as i32,
+        protocol: guess_protocol(banner),
+        banner,
+        observed_at: Local::now().naive_local().with_nanosecond(0).unwrap(),
+    };
+
+    insert_into(schema::service_fingerprints::dsl::service_fingerprints)
+        .values(&upsert)
+        .on_conflict((
+            schema::service_fingerprints::dsl::ip,
+            schema::service_fingerprints::dsl::port,
+        ))
+        .do_update()
+        .set(&upsert)
+        .execute(&mut ctx.pool().get().await.conn)
+        .unwrap();
+}
+
+/// Guesses the protocol of a service that answered a status request with
+/// something other than a status response.
+pub fn guess_protocol(banner: &[u8]) -> &'static str {
+    let text = String::from_utf8_lossy(banner).to_lowercase();
+
+    if text.contains("\"version\"") || text.contains("\"description\"") {
+        // A status response we could not parse
+        "minecraft"
+    } else if banner.starts_with(b"HTTP/") || text.contains("<html") {
+        "http"
+    } else if banner.starts_with(b"SSH-") {
+        "ssh"
+    } else if banner.len() >= 2 && banner[0] == 0x16 && banner[1] == 0x03 {
+        "tls"
+    } else if banner.len() >= 2 && banner[0] == 0x15 && banner[1] == 0x03 {
+        "tls_alert"
+    } else if banner.starts_with(b"220") && text.contains("ftp") {
+        "ftp"
+    } else if banner.starts_with(b"220") && text.contains("smtp") {
+        "smtp"
+    } else if banner.starts_with(b"220") {
+        "ftp_or_smtp"
+    } else if banner.starts_with(b"+OK") {
+        "pop3"
+    } else if banner.starts_with(b"* OK") {
+        "imap"
+    } else if banner.starts_with(b"RFB ") {
+        "vnc"
+    } else if banner.starts_with(b"-ERR") || banner.starts_with(b"-NOAUTH") {
+        "redis"
+    } else if banner.first() == Some(&0xFF) && banner.get(1) == Some(&0xFF) {
+        // Kick packet of pre-1.7 servers
+        "minecraft_legacy"
+    } else if banner
+        .iter()
+        .all(|b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+    {
+        "text"
+    } else {
+        "binary"
+    }
+}
@@ -0,0 +1,363 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{self, ErrorKind},
+    net::IpAddr,
+    os::unix::fs::FileTypeExt,
+    sync::Arc,
+    time::Duration,
+};
+
+use prost::Message;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{UnixListener, UnixStream},
+    sync::{mpsc, oneshot, Semaphore},
+    time::timeout,
+};
+
+use crate::{
+    conn_wrapper::read_varint,
+    database::{DiscoverySource, Edition},
+    finish_discovery,
+    proxy_protocol::ProxyVersion,
+    server_actions::{
+        legacy::Era,
+        query::QueryData,
+        with_connection::ExtraData,
+        without_connection::{parse_status, Status},
+    },
+    Context,
+};
+
+/// Discoveries a scanner holds while the storage process is unreachable;
+/// the oldest are dropped beyond that.
+const BUFFER: usize = 100_000;
+/// Largest message the storage process accepts.
+const MAX_MESSAGE: usize = 1 << 20;
+/// Forwarded discoveries stored at once across scanners; reading waits
+/// beyond that, so scanners buffer instead of the storage process.
+const STORING: usize = 256;
+
+/// Everything a probe learned about a server, finished by `finish_discovery`
+/// either in the scanning process or in the storage process it forwards to.
+pub struct Found {
+    pub ip: IpAddr,
+    pub port: u16,
+    pub edition: Edition,
+    pub source: DiscoverySource,
+    pub status: Status,
+    pub query: Option<QueryData>,
+    pub extra_data: Option<ExtraData>,
+    pub latency: Duration,
+    /// PROXY header the server turned out to need
+    pub proxy: Option<ProxyVersion>,
+    /// Run of the scanner that found it, unknown in messages of older
+    /// scanners and journals
+    pub run_id: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct DiscoveryMessage {
+    #[prost(string, tag = "1")]
+    ip: String,
+    #[prost(uint32, tag = "2")]
+    port: u32,
+    #[prost(string, tag = "3")]
+    edition: String,
+    #[prost(string, tag = "4")]
+    source: String,
+    /// Status response as JSON
+    #[prost(string, tag = "5")]
+    status: String,
+    #[prost(uint32, tag = "6")]
+    latency_ms: u32,
+    /// PROXY protocol version, 1 or 2
+    #[prost(uint32, optional, tag = "7")]
+    proxy_version: Option<u32>,
+    #[prost(message, optional, tag = "8")]
+    query: Option<QueryMessage>,
+    #[prost(message, optional, tag = "9")]
+    extra_data: Option<ExtraDataMessage>,
+    /// Set when the status tripped a guard of `packets`
+    #[prost(bool, tag = "10")]
+    anomalous: bool,
+    /// `Era` of a server that only answered a pre-1.7 handshake
+    #[prost(string, optional, tag = "11")]
+    era: Option<String>,
+    #[prost(int32, optional, tag = "12")]
+    run_id: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct QueryMessage {
+    #[prost(string, optional, tag = "1")]
+    map: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    game_type: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    game_id: Option<String>,
+    #[prost(int32, optional, tag = "4")]
+    host_port: Option<i32>,
+    #[prost(string, optional, tag = "5")]
+    plugins: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ExtraDataMessage {
+    #[prost(bool, tag = "1")]
+    license: bool,
+    #[prost(bool, optional, tag = "2")]
+    white_list: Option<bool>,
+    #[prost(float, optional, tag = "3")]
+    tps: Option<f32>,
+    #[prost(bool, tag = "4")]
+    queue_hint: bool,
+    #[prost(string, repeated, tag = "5")]
+    registry_namespaces: Vec<String>,
+    #[prost(string, optional, tag = "6")]
+    appeal_contact: Option<String>,
+    #[prost(bool, tag = "7")]
+    banned: bool,
+}
+
+impl From<&Found> for DiscoveryMessage {
+    fn from(found: &Found) -> Self {
+        // The raw body keeps what the lenient parse could not
+        let status = match &found.status.raw {
+            Some(raw) => raw.clone(),
+            None => serde_json::to_string(&found.status).unwrap(),
+        };
+
+        DiscoveryMessage {
+            ip: found.ip.to_string(),
+            port: found.port as u32,
+            edition: found.edition.as_str().to_string(),
+            source: found.source.as_str().to_string(),
+            status,
+            anomalous: found.status.anomalous,
+            era: found.status.era.map(|era| era.as_str().to_string()),
+            latency_ms: found.latency.as_millis() as u32,
+            run_id: found.run_id,
+            proxy_version: found.proxy.map(|version| match version {
+                ProxyVersion::V1 => 1,
+                ProxyVersion::V2 => 2,
+            }),
+            query: found.query.as_ref().map(|q| QueryMessage {
+                map: q.map.clone(),
+                game_type: q.game_type.clone(),
+                game_id: q.game_id.clone(),
+                host_port: q.host_port,
+                plugins: q.plugins.clone(),
+            }),
+            extra_data: found.extra_data.as_ref().map(|e| ExtraDataMessage {
+                license: e.license,
+                white_list: e.white_list,
+                tps: e.tps,
+                queue_hint: e.queue_hint,
+                registry_namespaces: e.registry_namespaces.clone(),
+                appeal_contact: e.appeal_contact.clone(),
+                banned: e.banned,
+            }),
+        }
+    }
+}
+
+impl TryFrom<DiscoveryMessage> for Found {
+    type Error = io::Error;
+
+    fn try_from(message: DiscoveryMessage) -> io::Result<Self> {
+        let invalid = |what: &str| io::Error::new(ErrorKind::InvalidData, what.to_string());
+
+        Ok(Found {
+            ip: message.ip.parse().map_err(|_| invalid("bad address"))?,
+            port: u16::try_from(message.port).map_err(|_| invalid("bad port"))?,
+            edition: Edition::parse(&message.edition).ok_or_else(|| invalid("bad edition"))?,
+            source: DiscoverySource::parse(&message.source)
+                .ok_or_else(|| invalid("bad discovery source"))?,
+            status: parse_status(&message.status).map(|mut status| {
+                status.anomalous |= message.anomalous;
+                status.era = message.era.as_deref().and_then(Era::parse);
+                status
+            })?,
+            query: message.query.map(|q| QueryData {
+                map: q.map,
+                game_type: q.game_type,
+                game_id: q.game_id,
+                host_port: q.host_port,
+                plugins: q.plugins,
+            }),
+            extra_data: message.extra_data.map(|e| ExtraData {
+                license: e.license,
+                white_list: e.white_list,
+                tps: e.tps,
+                queue_hint: e.queue_hint,
+                registry_namespaces: e.registry_namespaces,
+                appeal_contact: e.appeal_contact,
+                banned: e.banned,
+            }),
+            latency: Duration::from_millis(message.latency_ms as u64),
+            proxy: match message.proxy_version {
+                None => None,
+                Some(1) => Some(ProxyVersion::V1),
+                Some(2) => Some(ProxyVersion::V2),
+                Some(_) => return Err(invalid("bad proxy version")),
+            },
+            run_id: message.run_id,
+        })
+    }
+}
+
+/// A discovery as the protobuf message scanners forward, kept by `journal`.
+pub fn encode(found: &Found) -> Vec<u8> {
+    DiscoveryMessage::from(found).encode_to_vec()
+}
+
+pub fn decode(buf: &[u8]) -> io::Result<Found> {
+    DiscoveryMessage::decode(buf)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+        .and_then(Found::try_from)
+}
+
+enum Frame {
+    Message(Vec<u8>),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Sends discoveries of a scanning process to a storage process over a Unix
+/// socket as length-delimited protobuf messages, reconnecting and buffering
+/// while it is down or being upgraded.
+pub struct Forwarder {
+    tx: mpsc::Sender<Frame>,
+}
+
+impl Forwarder {
+    pub fn start(path: String) -> Self {
+        let (tx, rx) = mpsc::channel(1024);
+        tokio::spawn(forward(path, rx));
+        Self { tx }
+    }
+
+    pub async fn send(&self, found: Found) {
+        let message = DiscoveryMessage::from(&found).encode_length_delimited_to_vec();
+        let _ = self.tx.send(Frame::Message(message)).await;
+    }
+
+    /// Waits until everything sent so far reached the storage process.
+    pub async fn flush(&self) {
+        let (done, rx) = oneshot::channel();
+        if self.tx.send(Frame::Flush(done)).await.is_ok() {
+            let _ = rx.await;
+        }
+    }
+}
+
+async fn forward(path: String, mut rx: mpsc::Receiver<Frame>) {
+    let mut pending: VecDeque<Frame> = VecDeque::new();
+    let mut dropped = 0;
+    let mut connected = true;
+
+    loop {
+        match UnixStream::connect(&path).await {
+            Ok(mut stream) => {
+                println!("[+] Forwarding discoveries to {}", path);
+                connected = true;
+
+                loop {
+                    let frame = match pending.pop_front() {
+                        Some(frame) => frame,
+                        None => match rx.recv().await {
+                            Some(frame) => frame,
+                            None => return,
+                        },
+                    };
+                    let message = match frame {
+                        Frame::Message(message) => message,
+                        Frame::Flush(done) => {
+                            let _ = done.send(());
+                            continue;
+                        }
+                    };
+                    if stream.write_all(&message).await.is_err() {
+                        pending.push_front(Frame::Message(message));
+                        break;
+                    }
+                }
+            }
+            Err(e) if connected => {
+                eprintln!("[!] Storage process at {} unreachable: {}", path, e);
+                connected = false;
+            }
+            Err(_) => {}
+        }
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        while let Ok(frame) = rx.try_recv() {
+            if pending.len() >= BUFFER {
+                pending.pop_front();
+                dropped += 1;
+            }
+            pending.push_back(frame);
+        }
+        if dropped > 0 {
+            eprintln!("[!] Dropped {} discoveries, storage buffer full", dropped);
+            dropped = 0;
+        }
+    }
+}
+
+/// Stores the discoveries scanning processes send to the socket at `path`.
+pub async fn listen(ctx: Arc<Context>, path: &str) {
+    // A socket left behind by a previous storage process
+    if fs::metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        let _ = fs::remove_file(path);
+    }
+    let listener =
+        UnixListener::bind(path).unwrap_or_else(|e| panic!("Could not listen on {}: {}", path, e));
+    println!("[+] Storing discoveries sent to {}", path);
+    let slots = Arc::new(Semaphore::new(STORING));
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(receive(ctx.clone(), stream, slots.clone()));
+            }
+            Err(e) => eprintln!("[!] Could not accept a scanner: {}", e),
+        }
+    }
+}
+
+async fn receive(ctx: Arc<Context>, mut stream: UnixStream, slots: Arc<Semaphore>) {
+    loop {
+        let Ok(len) = read_varint(&mut stream).await else {
+            return;
+        };
+        let len = len as usize;
+        if len > MAX_MESSAGE {
+            eprintln!("[!] Closing scanner connection: {} byte message", len);
+            return;
+        }
+        let mut buf = vec![0; len];
+        if stream.read_exact(&mut buf).await.is_err() {
+            return;
+        }
+
+        let found = match decode(&buf) {
+            Ok(found) => found,
+            Err(e) => {
+                eprintln!("[!] Skipping a forwarded discovery: {}", e);
+                continue;
+            }
+        };
+
+        // One failing discovery must not end the stream
+        let Ok(slot) = slots.clone().acquire_owned().await else {
+            return;
+        };
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            let _ = timeout(Duration::from_secs(30), finish_discovery(found, &ctx)).await;
+            drop(slot);
+        });
+    }
+}
@@ -0,0 +1,529 @@
+use std::{
+    env, fs,
+    io::{self, Error, ErrorKind},
+    net::IpAddr,
+};
+
+/// Precedes the metadata map at the end of every MaxMind DB file.
+const METADATA_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+/// Zero bytes between the search tree and the data section.
+const DATA_SEPARATOR: usize = 16;
+/// Deepest nesting of maps, arrays and pointers decoded, far beyond what
+/// real databases use, so crafted files can't recurse without end.
+const MAX_DEPTH: usize = 32;
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// Decoded value of the MaxMind DB data section. Types no lookup reads
+/// (floats, booleans, bytes and arrays) are skipped over as `Other`.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Str(String),
+    Uint(u64),
+    Int(i64),
+    Map(Vec<(String, Value)>),
+    Other,
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Follows a path of map keys, e.g. `["country", "iso_code"]`.
+    pub fn path(&self, keys: &[&str]) -> Option<&Value> {
+        keys.iter().try_fold(self, |value, key| value.get(key))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Uint(n) => Some(*n),
+            Value::Int(n) => u64::try_from(*n).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Reader for MaxMind DB files (GeoLite2 Country, City and ASN), loaded into
+/// memory once.
+pub struct Reader {
+    data: Vec<u8>,
+    node_count: u64,
+    record_size: u64,
+    ip_version: u64,
+    /// Node IPv4 lookups start from in an IPv6 tree
+    ipv4_start: u64,
+}
+
+impl Reader {
+    pub fn open(path: &str) -> io::Result<Self> {
+        Self::from_bytes(fs::read(path)?)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> io::Result<Self> {
+        let marker = data
+            .windows(METADATA_MARKER.len())
+            .rposition(|window| window == METADATA_MARKER)
+            .ok_or_else(|| invalid("not a MaxMind DB file"))?;
+        let (metadata, _) = decode(&data, marker + METADATA_MARKER.len(), 0, 0)?;
+
+        let field = |name: &str| {
+            metadata
+                .get(name)
+                .and_then(Value::as_u64)
+                .ok_or_else(|| invalid(&format!("metadata lacks {}", name)))
+        };
+        let node_count = field("node_count")?;
+        let record_size = field("record_size")?;
+        let ip_version = field("ip_version")?;
+        if !matches!(record_size, 24 | 28 | 32) {
+            return Err(invalid("unsupported record size"));
+        }
+
+        let mut reader = Self {
+            data,
+            node_count,
+            record_size,
+            ip_version,
+            ipv4_start: 0,
+        };
+        let tree_end = node_count
+            .checked_mul(record_size / 4)
+            .and_then(|size| usize::try_from(size).ok())
+            .and_then(|size| size.checked_add(DATA_SEPARATOR));
+        if tree_end.is_none_or(|end| end > marker) {
+            return Err(invalid("search tree exceeds the file"));
+        }
+
+        // IPv4 addresses live under ::/96 in IPv6 databases
+        if ip_version == 6 {
+            let mut node = 0;
+            for _ in 0..96 {
+                if node >= node_count {
+                    break;
+                }
+                node = reader.record(node, 0)?;
+            }
+            reader.ipv4_start = node;
+        }
+        Ok(reader)
+    }
+
+    fn tree_size(&self) -> usize {
+        (self.node_count * self.record_size / 4) as usize
+    }
+
+    fn record(&self, node: u64, bit: u8) -> io::Result<u64> {
+        let size = (self.record_size / 4) as usize;
+        let offset = node as usize * size;
+        let bytes = self
+            .data
+            .get(offset..offset + size)
+            .ok_or_else(|| invalid("node outside the search tree"))?;
+        let be = |b: &[u8]| b.iter().fold(0u64, |acc, b| acc << 8 | *b as u64);
+
+        Ok(match (self.record_size, bit) {
+            (24, 0) => be(&bytes[..3]),
+            (24, _) => be(&bytes[3..]),
+            (28, 0) => ((bytes[3] as u64 & 0xF0) << 20) | be(&bytes[..3]),
+            (28, _) => ((bytes[3] as u64 & 0x0F) << 24) | be(&bytes[4..]),
+            (_, 0) => be(&bytes[..4]),
+            (_, _) => be(&bytes[4..]),
+        })
+    }
+
+    /// The record stored for the network containing `ip`.
+    pub fn lookup(&self, ip: IpAddr) -> io::Result<Option<Value>> {
+        let (bits, mut node) = match ip {
+            IpAddr::V4(ip) => (ip.octets().to_vec(), self.ipv4_start),
+            IpAddr::V6(_) if self.ip_version == 4 => return Ok(None),
+            IpAddr::V6(ip) => (ip.octets().to_vec(), 0),
+        };
+
+        for i in 0..bits.len() * 8 {
+            if node >= self.node_count {
+                break;
+            }
+            let bit = (bits[i / 8] >> (7 - i % 8)) & 1;
+            node = self.record(node, bit)?;
+        }
+
+        if node == self.node_count {
+            return Ok(None);
+        }
+        if node < self.node_count {
+            return Err(invalid("search tree ended inside a node"));
+        }
+
+        let data_start = self.tree_size() + DATA_SEPARATOR;
+        let offset = usize::try_from(node - self.node_count)
+            .ok()
+            .and_then(|offset| offset.checked_sub(DATA_SEPARATOR))
+            .ok_or_else(|| invalid("record points into the data separator"))?;
+        let pos = data_start
+            .checked_add(offset)
+            .ok_or_else(|| invalid("record points outside the file"))?;
+        Ok(Some(decode(&self.data, pos, data_start, 0)?.0))
+    }
+}
+
+fn byte(data: &[u8], pos: usize) -> io::Result<u8> {
+    data.get(pos)
+        .copied()
+        .ok_or_else(|| invalid("value outside the file"))
+}
+
+fn uint(data: &[u8], pos: usize, len: usize) -> io::Result<u64> {
+    let bytes = data
+        .get(pos..pos.saturating_add(len))
+        .ok_or_else(|| invalid("value outside the file"))?;
+    Ok(bytes.iter().fold(0u64, |acc, b| acc << 8 | *b as u64))
+}
+
+/// Decodes the value at `pos`, returning it and the position after it.
+/// Pointers are relative to `base`, the start of the data section; `depth`
+/// counts the maps, arrays and pointers the value is inside of.
+fn decode(data: &[u8], pos: usize, base: usize, depth: usize) -> io::Result<(Value, usize)> {
+    if depth > MAX_DEPTH {
+        return Err(invalid("values nested too deep"));
+    }
+    let control = byte(data, pos)?;
+    let mut pos = pos + 1;
+
+    let mut kind = control >> 5;
+    if kind == 1 {
+        let extra = ((control >> 3) & 0x3) as usize + 1;
+        let value = (control & 0x7) as u64;
+        let pointer = match extra {
+            1 => (value << 8 | uint(data, pos, 1)?) as usize,
+            2 => (value << 16 | uint(data, pos, 2)?) as usize + 2048,
+            3 => (value << 24 | uint(data, pos, 3)?) as usize + 526336,
+            _ => uint(data, pos, 4)? as usize,
+        };
+        let (value, _) = decode(data, base.saturating_add(pointer), base, depth + 1)?;
+        return Ok((value, pos + extra));
+    }
+    if kind == 0 {
+        kind = 7 + byte(data, pos)?;
+        pos += 1;
+    }
+
+    let mut size = (control & 0x1F) as usize;
+    if size >= 29 {
+        let extra = size - 28;
+        size = match extra {
+            1 => 29 + uint(data, pos, 1)? as usize,
+            2 => 285 + uint(data, pos, 2)? as usize,
+            _ => 65821 + uint(data, pos, 3)? as usize,
+        };
+        pos += extra;
+    }
+
+    let bytes = |pos: usize| {
+        data.get(pos..pos.saturating_add(size))
+            .ok_or_else(|| invalid("value outside the file"))
+    };
+
+    Ok(match kind {
+        2 => (
+            Value::Str(String::from_utf8_lossy(bytes(pos)?).to_string()),
+            pos + size,
+        ),
+        3 => (Value::Other, pos + 8),
+        4 => (Value::Other, pos + size),
+        5 | 6 | 9 | 10 => (Value::Uint(uint(data, pos, size.min(8))?), pos + size),
+        7 => {
+            let mut entries = Vec::with_capacity(size.min(64));
+            for _ in 0..size {
+                let (key, next) = decode(data, pos, base, depth + 1)?;
+                let (value, next) = decode(data, next, base, depth + 1)?;
+                entries.push((key.as_str().unwrap_or_default().to_string(), value));
+                pos = next;
+            }
+            (Value::Map(entries), pos)
+        }
+        8 => {
+            let value = uint(data, pos, size)? as u32;
+            (Value::Int(value as i32 as i64), pos + size)
+        }
+        11 => {
+            for _ in 0..size {
+                pos = decode(data, pos, base, depth + 1)?.1;
+            }
+            (Value::Other, pos)
+        }
+        14 => (Value::Other, pos),
+        15 => (Value::Other, pos + 4),
+        _ => return Err(invalid("unknown data type")),
+    })
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Location {
+    /// ISO 3166-1 alpha-2 code
+    pub country: Option<String>,
+    /// English name, City databases only
+    pub city: Option<String>,
+    pub asn: Option<u32>,
+    /// ISP from a GeoIP2 ISP database, the ASN's organisation otherwise
+    pub isp: Option<String>,
+}
+
+/// Flag emoji of an ISO 3166-1 alpha-2 code, made of two regional indicators.
+pub fn flag(country: &str) -> Option<String> {
+    if country.len() != 2 || !country.bytes().all(|b| b.is_ascii_alphabetic()) {
+        return None;
+    }
+    country
+        .to_ascii_uppercase()
+        .bytes()
+        .map(|b| char::from_u32(0x1F1E6 + (b - b'A') as u32))
+        .collect()
+}
+
+/// Location lookups from `GEOIP_DATABASE` (a GeoLite2 Country or City
+/// database) and `GEOIP_ASN_DATABASE` (GeoLite2 ASN or GeoIP2 ISP).
+pub struct GeoIp {
+    country: Option<Reader>,
+    asn: Option<Reader>,
+}
+
+impl GeoIp {
+    pub fn from_env() -> Self {
+        let open = |var: &str| {
+            let path = env::var(var).ok()?;
+            Some(Reader::open(&path).unwrap_or_else(|e| panic!("{} ({}): {}", var, path, e)))
+        };
+
+        Self {
+            country: open("GEOIP_DATABASE"),
+            asn: open("GEOIP_ASN_DATABASE"),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.country.is_some() || self.asn.is_some()
+    }
+
+    pub fn has_asn(&self) -> bool {
+        self.asn.is_some()
+    }
+
+    pub fn locate(&self, ip: IpAddr) -> Location {
+        let find = |reader: &Option<Reader>| reader.as_ref()?.lookup(ip).ok().flatten();
+
+        let place = find(&self.country);
+        let country = place.as_ref().and_then(|record| {
+            record
+                .path(&["country", "iso_code"])
+                .or_else(|| record.path(&["registered_country", "iso_code"]))
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        });
+        let city = place.as_ref().and_then(|record| {
+            record
+                .path(&["city", "names", "en"])
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        });
+        let network = find(&self.asn);
+        let asn = network.as_ref().and_then(|record| {
+            record
+                .get("autonomous_system_number")
+                .and_then(Value::as_u64)
+                .map(|n| n as u32)
+        });
+        let isp = network.as_ref().and_then(|record| {
+            ["isp", "autonomous_system_organization"]
+                .iter()
+                .find_map(|key| record.get(key).and_then(Value::as_str))
+                .map(str::to_string)
+        });
+
+        Location {
+            country,
+            city,
+            asn,
+            isp,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Vec<u8> {
+        let mut out = vec![2 << 5 | s.len() as u8];
+        out.extend(s.as_bytes());
+        out
+    }
+
+    fn uint16(n: u16) -> Vec<u8> {
+        let mut out = vec![5 << 5 | 2];
+        out.extend(n.to_be_bytes());
+        out
+    }
+
+    fn uint32(n: u32) -> Vec<u8> {
+        let mut out = vec![6 << 5 | 4];
+        out.extend(n.to_be_bytes());
+        out
+    }
+
+    fn map(entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut out = vec![7 << 5 | entries.len() as u8];
+        for (key, value) in entries {
+            out.extend(string(key));
+            out.extend(value);
+        }
+        out
+    }
+
+    /// Pointer to `offset` of the data section, in its two byte form.
+    fn pointer(offset: u16) -> Vec<u8> {
+        vec![1 << 5 | (offset >> 8) as u8, offset as u8]
+    }
+
+    /// Tree record of the value at `offset` of the data section of a
+    /// one-node database.
+    fn record(offset: u32) -> u32 {
+        1 + DATA_SEPARATOR as u32 + offset
+    }
+
+    /// A one-node IPv4 database with 24-bit records: addresses in 0.0.0.0/1
+    /// lead to `left`, the others to no record.
+    fn database(left: u32, data: &[u8], node_count: u32) -> Vec<u8> {
+        let mut file = left.to_be_bytes()[1..].to_vec();
+        file.extend(&1u32.to_be_bytes()[1..]);
+        file.extend([0; DATA_SEPARATOR]);
+        file.extend(data);
+        file.extend(METADATA_MARKER);
+        file.extend(map(&[
+            ("node_count", uint32(node_count)),
+            ("record_size", uint16(24)),
+            ("ip_version", uint16(4)),
+        ]));
+        file
+    }
+
+    fn lookup(file: Vec<u8>, ip: &str) -> io::Result<Option<Value>> {
+        Reader::from_bytes(file)?.lookup(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn finds_the_record_of_a_network() {
+        let data = map(&[
+            ("country", map(&[("iso_code", string("DE"))])),
+            ("autonomous_system_number", uint32(3320)),
+        ]);
+        let file = database(record(0), &data, 1);
+
+        let found = lookup(file.clone(), "10.1.2.3").unwrap().unwrap();
+        assert_eq!(
+            found.path(&["country", "iso_code"]).and_then(Value::as_str),
+            Some("DE")
+        );
+        assert_eq!(
+            found
+                .get("autonomous_system_number")
+                .and_then(Value::as_u64),
+            Some(3320)
+        );
+        assert!(lookup(file.clone(), "200.1.2.3").unwrap().is_none());
+        assert!(lookup(file, "2001:db8::1").unwrap().is_none());
+    }
+
+    #[test]
+    fn follows_pointers_into_the_data_section() {
+        let mut data = string("FR");
+        let at = data.len() as u32;
+        data.extend(map(&[("country", map(&[("iso_code", pointer(0))]))]));
+
+        let found = lookup(database(record(at), &data, 1), "10.0.0.1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            found.path(&["country", "iso_code"]).and_then(Value::as_str),
+            Some("FR")
+        );
+    }
+
+    #[test]
+    fn decodes_extended_and_skipped_types() {
+        // int32 -5, extended type 8
+        let (value, next) = decode(&[0x04, 0x01, 0xFF, 0xFF, 0xFF, 0xFB], 0, 0, 0).unwrap();
+        assert!(matches!(value, Value::Int(-5)));
+        assert_eq!(next, 6);
+
+        // uint64, extended type 9
+        let (value, _) = decode(&[0x02, 0x02, 0x01, 0x00], 0, 0, 0).unwrap();
+        assert_eq!(value.as_u64(), Some(256));
+
+        // An array of two strings is skipped as a whole, then a boolean
+        let mut bytes = vec![0x02, 0x04];
+        bytes.extend(string("a"));
+        bytes.extend(string("bc"));
+        let end = bytes.len();
+        bytes.extend([0x01, 0x07]);
+        let (value, next) = decode(&bytes, 0, 0, 0).unwrap();
+        assert!(matches!(value, Value::Other));
+        assert_eq!(next, end);
+        let (value, next) = decode(&bytes, end, 0, 0).unwrap();
+        assert!(matches!(value, Value::Other));
+        assert_eq!(next, bytes.len());
+    }
+
+    #[test]
+    fn refuses_pointer_loops_and_deep_nesting() {
+        let looping = database(record(0), &pointer(0), 1);
+        assert!(lookup(looping, "10.0.0.1").is_err());
+
+        let mut nested = string("deep");
+        for _ in 0..MAX_DEPTH + 1 {
+            let mut outer = vec![7 << 5 | 1];
+            outer.extend(string("k"));
+            outer.extend(nested);
+            nested = outer;
+        }
+        assert!(lookup(database(record(0), &nested, 1), "10.0.0.1").is_err());
+    }
+
+    #[test]
+    fn refuses_records_outside_the_data_section() {
+        let data = string("DE");
+        // Between the node count and the end of the separator
+        assert!(lookup(database(1 + 5, &data, 1), "10.0.0.1").is_err());
+        assert!(lookup(database(record(1000), &data, 1), "10.0.0.1").is_err());
+    }
+
+    #[test]
+    fn refuses_broken_files() {
+        assert!(Reader::from_bytes(b"not a database".to_vec()).is_err());
+        // A search tree larger than the file
+        assert!(Reader::from_bytes(database(record(0), &string("DE"), u32::MAX)).is_err());
+        // Metadata cut off
+        let mut file = database(record(0), &string("DE"), 1);
+        file.truncate(file.len() - 3);
+        assert!(Reader::from_bytes(file).is_err());
+    }
+
+    #[test]
+    fn flags_country_codes() {
+        assert_eq!(flag("de").as_deref(), Some("🇩🇪"));
+        assert_eq!(flag("D1"), None);
+        assert_eq!(flag("DEU"), None);
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    collections::HashMap,
+    io,
+    net::{Ipv4Addr, SocketAddr, UdpSocket as StdUdpSocket},
+    os::fd::FromRawFd,
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::{net::UdpSocket, sync::oneshot, time::timeout};
+
+const ECHO_REQUEST: u8 = 8;
+const ECHO_REPLY: u8 = 0;
+
+type Waiting = Arc<Mutex<HashMap<(Ipv4Addr, u16), oneshot::Sender<()>>>>;
+
+/// Echo requests on one ICMP socket shared by all scan workers, with a task
+/// handing each reply to the ping waiting for it. Prefers an unprivileged
+/// datagram socket (`net.ipv4.ping_group_range`) and falls back to a raw one,
+/// which needs `CAP_NET_RAW`.
+pub struct Pinger {
+    socket: Arc<UdpSocket>,
+    /// Identifier of our requests; the kernel picks it on datagram sockets
+    id: u16,
+    sequence: AtomicU16,
+    wait: Duration,
+    waiting: Waiting,
+}
+
+impl Pinger {
+    pub fn open(wait: Duration) -> io::Result<Self> {
+        let (socket, raw) = match open_socket(libc::SOCK_DGRAM) {
+            Ok(socket) => (socket, false),
+            Err(_) => (open_socket(libc::SOCK_RAW)?, true),
+        };
+        socket.set_nonblocking(true)?;
+        let socket = Arc::new(UdpSocket::from_std(socket)?);
+        let waiting: Waiting = Arc::default();
+        let id = rand::random();
+
+        // Raw sockets also see replies to other programs' pings
+        tokio::spawn(receive(socket.clone(), raw.then_some(id), waiting.clone()));
+        Ok(Self {
+            socket,
+            id,
+            sequence: AtomicU16::new(0),
+            wait,
+            waiting,
+        })
+    }
+
+    /// Whether `ip` answered an echo request in time.
+    pub async fn ping(&self, ip: Ipv4Addr) -> bool {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        let (done, reply) = oneshot::channel();
+        self.waiting.lock().unwrap().insert((ip, sequence), done);
+
+        let request = echo_request(self.id, sequence);
+        let answered = match self
+            .socket
+            .send_to(&request, SocketAddr::new(ip.into(), 0))
+            .await
+        {
+            Ok(_) => matches!(timeout(self.wait, reply).await, Ok(Ok(()))),
+            Err(_) => false,
+        };
+        self.waiting.lock().unwrap().remove(&(ip, sequence));
+        answered
+    }
+}
+
+/// An ICMP socket wrapped as a UDP one, whose `send_to` and `recv_from` are
+/// the plain datagram calls either kind of socket takes.
+fn open_socket(kind: libc::c_int) -> io::Result<StdUdpSocket> {
+    // Returns a new descriptor or -1, touching no memory
+    let fd = unsafe { libc::socket(libc::AF_INET, kind | libc::SOCK_CLOEXEC, libc::IPPROTO_ICMP) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // The descriptor was just created and is owned by nothing else
+    Ok(unsafe { StdUdpSocket::from_raw_fd(fd) })
+}
+
+fn echo_request(id: u16, sequence: u16) -> Vec<u8> {
+    let mut packet = vec![ECHO_REQUEST, 0, 0, 0];
+    packet.extend(id.to_be_bytes());
+    packet.extend(sequence.to_be_bytes());
+    packet.extend(b"mine_search");
+
+    let checksum = checksum(&packet);
+    packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+    packet
+}
+
+/// Internet checksum of RFC 1071.
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+async fn receive(socket: Arc<UdpSocket>, raw_id: Option<u16>, waiting: Waiting) {
+    let mut buf = [0; 1500];
+    loop {
+        let Ok((len, SocketAddr::V4(from))) = socket.recv_from(&mut buf).await else {
+            continue;
+        };
+        // Raw sockets see the IP header too
+        let header = match raw_id {
+            Some(_) => (buf[0] & 0x0f) as usize * 4,
+            None => 0,
+        };
+        let Some(reply) = buf[..len].get(header..header + 8) else {
+            continue;
+        };
+        let id = u16::from_be_bytes([reply[4], reply[5]]);
+        if reply[0] != ECHO_REPLY || raw_id.is_some_and(|ours| ours != id) {
+            continue;
+        }
+
+        let sequence = u16::from_be_bytes([reply[6], reply[7]]);
+        if let Some(done) = waiting.lock().unwrap().remove(&(*from.ip(), sequence)) {
+            let _ = done.send(());
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::{
+    env,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+};
+
+/// Who a login probe claims to be.
+pub struct Identity {
+    pub name: String,
+    /// Random version 4 UUID; offline servers derive their own from the name
+    pub uuid: u128,
+    pub locale: String,
+    pub view_distance: i8,
+}
+
+struct Settings {
+    names: Vec<String>,
+    locales: Vec<String>,
+    view_distance: i8,
+    next: AtomicUsize,
+}
+
+/// Probe identities from `PROBE_USERNAMES` and `PROBE_LOCALES`, comma-separated
+/// lists taken in turn so no single name shows up in every server's logs, and
+/// `PROBE_VIEW_DISTANCE`.
+fn settings() -> &'static Settings {
+    static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+    SETTINGS.get_or_init(|| {
+        let list = |key: &str, default: &str| -> Vec<String> {
+            env::var(key)
+                .unwrap_or(default.to_string())
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+
+        let names = list("PROBE_USERNAMES", "LookupPlayer");
+        if let Some(name) = names.iter().find(|name| !valid_name(name)) {
+            panic!(
+                "Invalid PROBE_USERNAMES entry {:?}: 3 to 16 letters, digits or _",
+                name
+            );
+        }
+        let locales = list("PROBE_LOCALES", "en_us");
+        let view_distance = env::var("PROBE_VIEW_DISTANCE")
+            .unwrap_or("12".to_string())
+            .parse()
+            .expect("PROBE_VIEW_DISTANCE is not a number between 2 and 127");
+        if names.is_empty() || locales.is_empty() {
+            panic!("PROBE_USERNAMES and PROBE_LOCALES need at least one entry");
+        }
+
+        Settings {
+            names,
+            locales,
+            view_distance,
+            next: AtomicUsize::new(0),
+        }
+    })
+}
+
+fn valid_name(name: &str) -> bool {
+    (3..=16).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+pub fn describe() -> String {
+    let settings = settings();
+    format!(
+        "{} ({}), view distance {}",
+        settings.names.join(", "),
+        settings.locales.join(", "),
+        settings.view_distance
+    )
+}
+
+/// The identity of the next login probe.
+pub fn next() -> Identity {
+    let settings = settings();
+    let turn = settings.next.fetch_add(1, Ordering::Relaxed);
+
+    let uuid = rand::random::<u128>();
+    // Version 4, RFC 4122 variant
+    let uuid = (uuid & !(0xf << 76)) | (0x4 << 76);
+    let uuid = (uuid & !(0x3 << 62)) | (0x2 << 62);
+
+    Identity {
+        name: settings.names[turn % settings.names.len()].clone(),
+        uuid,
+        locale: settings.locales[turn % settings.locales.len()].clone(),
+        view_distance: settings.view_distance,
+    }
+}
@@ -0,0 +1,352 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use chrono::{Local, NaiveDateTime, Timelike};
+use diesel::{
+    dsl::insert_into, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, QueryResult,
+    Queryable, RunQueryDsl, Selectable, SelectableHelper,
+};
+use hickory_resolver::TokioAsyncResolver;
+use mine_search::try_connect;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{sync::Semaphore, time::timeout};
+
+use crate::{
+    commands::import::locate,
+    conn_wrapper::Connection,
+    database::{DiscoverySource, Edition},
+    etiquette, handle_valid_ip,
+    reporter::Pass,
+    scanner::{parse_cidr, Exclusions},
+    schema, Context,
+};
+
+/// Addresses times ports a single job may probe.
+const MAX_PROBES: u64 = 1 << 16;
+/// Probes between progress updates of the job row.
+const PROGRESS_EVERY: i32 = 256;
+
+/// A submitted job: exactly one of `cidr`, `targets` and `host`.
+#[derive(Deserialize, Serialize)]
+pub struct JobSpec {
+    pub cidr: Option<String>,
+    /// Addresses and CIDR ranges
+    pub targets: Option<Vec<String>>,
+    /// Host name or address, `host[:port]`, resolved like the client does
+    pub host: Option<String>,
+    /// Java ports of `cidr` and `targets` jobs, `25565` by default
+    pub ports: Option<Vec<u16>>,
+}
+
+#[derive(Queryable, Selectable, Serialize)]
+#[diesel(table_name = crate::schema::scan_jobs)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct JobModel {
+    pub id: i32,
+    pub spec: Value,
+    pub status: String,
+    pub total: i32,
+    pub probed: i32,
+    pub found: i32,
+    pub error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub started_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+}
+
+impl JobSpec {
+    fn ports(&self) -> Vec<u16> {
+        self.ports.clone().unwrap_or_else(|| vec![25565])
+    }
+
+    /// Ranges of a `cidr` or `targets` job, `None` for a host job.
+    fn ranges(&self) -> Result<Option<Vec<(u32, u32)>>, String> {
+        match (&self.cidr, &self.targets, &self.host) {
+            (Some(cidr), None, None) => Ok(Some(vec![parse_cidr(cidr)?])),
+            (None, Some(targets), None) => targets
+                .iter()
+                .map(|target| parse_cidr(target))
+                .collect::<Result<_, _>>()
+                .map(Some),
+            (None, None, Some(_)) => Ok(None),
+            _ => Err("a job needs exactly one of cidr, targets and host".to_string()),
+        }
+    }
+
+    /// Probes the job will make, or why it can't run.
+    pub fn validate(&self) -> Result<u64, String> {
+        let ports = self.ports();
+        if ports.is_empty() || ports.contains(&0) {
+            return Err("ports must be non-empty and non-zero".to_string());
+        }
+
+        let total = match self.ranges()? {
+            Some(ranges) => {
+                let addresses: u64 = ranges.iter().map(|(s, e)| (e - s) as u64 + 1).sum();
+                addresses * ports.len() as u64
+            }
+            None => 1,
+        };
+        if total > MAX_PROBES {
+            return Err(format!("{} probes, at most {} per job", total, MAX_PROBES));
+        }
+        Ok(total)
+    }
+}
+
+/// Queues a job of `total` probes, see `JobSpec::validate`.
+pub fn submit(
+    conn: &mut PgConnection,
+    spec: &JobSpec,
+    total: u64,
+) -> Result<JobModel, diesel::result::Error> {
+    insert_into(schema::scan_jobs::dsl::scan_jobs)
+        .values((
+            schema::scan_jobs::dsl::spec.eq(serde_json::to_value(spec).unwrap()),
+            schema::scan_jobs::dsl::total.eq(total as i32),
+        ))
+        .returning(JobModel::as_returning())
+        .get_result(conn)
+}
+
+/// Runs queued jobs one at a time, oldest first, with `JOB_CONCURRENCY`
+/// probes in flight. Jobs interrupted by a restart start over. Database
+/// errors fail the job they hit, the queue keeps running.
+pub async fn run(ctx: Arc<Context>, exclusions: Exclusions, concurrency: usize) {
+    let requeued = diesel::update(schema::scan_jobs::dsl::scan_jobs)
+        .filter(schema::scan_jobs::dsl::status.eq("running"))
+        .set((
+            schema::scan_jobs::dsl::status.eq("queued"),
+            schema::scan_jobs::dsl::probed.eq(0),
+            schema::scan_jobs::dsl::found.eq(0),
+        ))
+        .execute(&mut ctx.pool().get().await.conn);
+    if let Err(e) = requeued {
+        eprintln!("[!] Could not requeue interrupted scan jobs: {}", e);
+    }
+
+    loop {
+        let next: QueryResult<Option<JobModel>> = schema::scan_jobs::dsl::scan_jobs
+            .filter(schema::scan_jobs::dsl::status.eq("queued"))
+            .order(schema::scan_jobs::dsl::id)
+            .select(JobModel::as_select())
+            .first(&mut ctx.pool().get().await.conn)
+            .optional();
+
+        match next {
+            Ok(Some(job)) => {
+                let id = job.id;
+                if let Err(e) = run_job(&ctx, job, &exclusions, concurrency).await {
+                    eprintln!("[!] Scan job {} failed: {}", id, e);
+                    if let Err(e) = finish(&ctx, id, "failed", Some(e.to_string())).await {
+                        eprintln!("[!] Could not mark scan job {} failed: {}", id, e);
+                        tokio::time::sleep(Duration::from_secs(2)).await;
+                    }
+                }
+            }
+            Ok(None) => tokio::time::sleep(Duration::from_secs(2)).await,
+            Err(e) => {
+                eprintln!("[!] Could not read the scan job queue: {}", e);
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }
+        }
+    }
+}
+
+fn now() -> NaiveDateTime {
+    Local::now().naive_local().with_nanosecond(0).unwrap()
+}
+
+/// Runs one job to its end, returning the error of a job row it could not
+/// write.
+async fn run_job(
+    ctx: &Arc<Context>,
+    job: JobModel,
+    exclusions: &Exclusions,
+    concurrency: usize,
+) -> QueryResult<()> {
+    diesel::update(schema::scan_jobs::dsl::scan_jobs.find(job.id))
+        .set((
+            schema::scan_jobs::dsl::status.eq("running"),
+            schema::scan_jobs::dsl::started_at.eq(now()),
+        ))
+        .execute(&mut ctx.pool().get().await.conn)?;
+
+    ctx.reporter.pass_started(Pass::Job);
+    let result = match serde_json::from_value::<JobSpec>(job.spec) {
+        Ok(spec) => probe_all(ctx, job.id, &spec, exclusions, concurrency).await,
+        Err(e) => Err(e.to_string()),
+    };
+    ctx.reporter.pass_finished(Pass::Job);
+
+    match result {
+        Ok(()) => finish(ctx, job.id, "done", None).await,
+        Err(e) => finish(ctx, job.id, "failed", Some(e)).await,
+    }
+}
+
+async fn finish(ctx: &Context, job: i32, status: &str, error: Option<String>) -> QueryResult<()> {
+    diesel::update(schema::scan_jobs::dsl::scan_jobs.find(job))
+        .set((
+            schema::scan_jobs::dsl::status.eq(status),
+            schema::scan_jobs::dsl::error.eq(error),
+            schema::scan_jobs::dsl::finished_at.eq(now()),
+        ))
+        .execute(&mut ctx.pool().get().await.conn)
+        .map(|_| ())
+}
+
+/// Progress of a running job.
+struct Progress {
+    job: i32,
+    probed: AtomicI32,
+    found: AtomicI32,
+}
+
+impl Progress {
+    async fn store(&self, ctx: &Context) -> QueryResult<usize> {
+        diesel::update(schema::scan_jobs::dsl::scan_jobs.find(self.job))
+            .set((
+                schema::scan_jobs::dsl::probed.eq(self.probed.load(Ordering::Relaxed)),
+                schema::scan_jobs::dsl::found.eq(self.found.load(Ordering::Relaxed)),
+            ))
+            .execute(&mut ctx.pool().get().await.conn)
+    }
+}
+
+async fn probe_all(
+    ctx: &Arc<Context>,
+    job: i32,
+    spec: &JobSpec,
+    exclusions: &Exclusions,
+    concurrency: usize,
+) -> Result<(), String> {
+    let ports = spec.ports();
+    let targets: Vec<(IpAddr, u16)> = match (spec.ranges()?, &spec.host) {
+        (Some(ranges), _) => {
+            if let Some(own) = ranges
+                .iter()
+                .find_map(|range| exclusions.own_network(*range))
+            {
+                return Err(format!("targets overlap own network {}", own));
+            }
+            ranges
+                .iter()
+                .flat_map(|&(start, end)| start..=end)
+                .filter(|ip| !exclusions.contains(*ip))
+                .flat_map(|ip| {
+                    ports
+                        .iter()
+                        .map(move |port| (IpAddr::V4(Ipv4Addr::from(ip)), *port))
+                })
+                .collect()
+        }
+        (None, Some(host)) => {
+            let resolver =
+                TokioAsyncResolver::tokio_from_system_conf().map_err(|e| e.to_string())?;
+            let (ip, port) = locate(host, &resolver)
+                .await
+                .ok_or_else(|| format!("could not resolve {}", host))?;
+            if let Some(own) = exclusions.own_address(&ip) {
+                return Err(format!("{} is in own network {}", ip, own));
+            }
+            if matches!(ip, IpAddr::V4(v4) if exclusions.contains(v4.into())) {
+                return Err(format!("{} is excluded from scanning", ip));
+            }
+            vec![(ip, port)]
+        }
+        (None, None) => unreachable!("checked by JobSpec::validate"),
+    };
+    // Excluded addresses are never probed
+    diesel::update(schema::scan_jobs::dsl::scan_jobs.find(job))
+        .set(schema::scan_jobs::dsl::total.eq(targets.len() as i32))
+        .execute(&mut ctx.pool().get().await.conn)
+        .map_err(|e| e.to_string())?;
+
+    let progress = Arc::new(Progress {
+        job,
+        probed: AtomicI32::new(0),
+        found: AtomicI32::new(0),
+    });
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+
+    let mut handles = vec![];
+    for (ip, port) in targets {
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let ctx = ctx.clone();
+        let progress = progress.clone();
+
+        handles.push(tokio::spawn(async move {
+            let _permit = permit;
+            if let Some(server_id) = probe(&ip, port, DiscoverySource::Job, &ctx).await {
+                let found = insert_into(schema::scan_job_servers::dsl::scan_job_servers)
+                    .values((
+                        schema::scan_job_servers::dsl::job_id.eq(progress.job),
+                        schema::scan_job_servers::dsl::server_id.eq(server_id),
+                    ))
+                    .on_conflict_do_nothing()
+                    .execute(&mut ctx.pool().get().await.conn);
+                match found {
+                    Ok(_) => {
+                        progress.found.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(e) => eprintln!(
+                        "[!] Could not add server {} to scan job {}: {}",
+                        server_id, progress.job, e
+                    ),
+                }
+            }
+            if progress.probed.fetch_add(1, Ordering::Relaxed) % PROGRESS_EVERY == 0 {
+                if let Err(e) = progress.store(&ctx).await {
+                    eprintln!(
+                        "[!] Could not store the progress of scan job {}: {}",
+                        progress.job, e
+                    );
+                }
+            }
+        }));
+    }
+
+    for handle in handles {
+        let _ = handle.await;
+    }
+    progress.store(ctx).await.map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Probes one address like a scan worker, returning the stored server.
+pub async fn probe(
+    ip: &IpAddr,
+    port: u16,
+    source: DiscoverySource,
+    ctx: &Arc<Context>,
+) -> Option<i32> {
+    if !etiquette::allowed(&ip.to_string()) {
+        return None;
+    }
+    let conn = Connection::from_stream(try_connect(ip, port).await.ok()?).ok()?;
+    timeout(
+        Duration::from_secs(10),
+        handle_valid_ip(conn, ip, port, source, ctx),
+    )
+    .await
+    .ok()?
+    .ok()?;
+
+    schema::servers::dsl::servers
+        .filter(schema::servers::dsl::ip.eq(Edition::Java.key(ip, port)))
+        .filter(schema::servers::dsl::edition.eq(Edition::Java.as_str()))
+        .select(schema::servers::dsl::id)
+        .first(&mut ctx.pool().get().await.conn)
+        .optional()
+        .inspect_err(|e| eprintln!("[!] Could not look up the server at {}: {}", ip, e))
+        .ok()
+        .flatten()
+}
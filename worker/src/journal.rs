@@ -0,0 +1,223 @@
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    mem,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use prost::Message;
+
+use crate::{
+    finish_discovery,
+    forward::{self, Found},
+    Context,
+};
+
+/// Journal size past which it is rewritten with only the pending entries.
+const REWRITE_AT: u64 = 8 << 20;
+
+/// A discovery written before it is finished, or with `discovery` unset the
+/// acknowledgement that entry `seq` was.
+#[derive(Clone, PartialEq, Message)]
+struct Entry {
+    #[prost(uint64, tag = "1")]
+    seq: u64,
+    #[prost(bytes = "vec", optional, tag = "2")]
+    discovery: Option<Vec<u8>>,
+}
+
+struct State {
+    file: File,
+    written: u64,
+    next: u64,
+    /// Encoded discoveries not acknowledged yet
+    pending: HashMap<u64, Vec<u8>>,
+    /// Entries earlier runs left pending, until replayed
+    recovered: Vec<u64>,
+}
+
+/// Append-only file of the discoveries being finished. Each is written before
+/// its database insert and acknowledged after, so the ones a crash or a
+/// database outage interrupted can be finished at the next start.
+pub struct Journal {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl Journal {
+    /// Opens the journal at `JOURNAL_PATH`, if set.
+    pub fn from_env() -> Option<Self> {
+        let path = PathBuf::from(env::var("JOURNAL_PATH").ok()?);
+        let journal =
+            Self::open(path.clone()).unwrap_or_else(|e| panic!("Journal {:?}: {}", path, e));
+        Some(journal)
+    }
+
+    fn open(path: PathBuf) -> io::Result<Self> {
+        let mut pending = HashMap::new();
+        let mut next = 0;
+
+        match fs::read(&path) {
+            Ok(bytes) => {
+                let mut buf = &bytes[..];
+                // A torn last entry is one the crash interrupted
+                while let Ok(entry) = Entry::decode_length_delimited(&mut buf) {
+                    next = next.max(entry.seq + 1);
+                    match entry.discovery {
+                        Some(discovery) => pending.insert(entry.seq, discovery),
+                        None => pending.remove(&entry.seq),
+                    };
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let (file, written) = rewrite(&path, &pending)?;
+        let mut recovered: Vec<u64> = pending.keys().copied().collect();
+        recovered.sort();
+        Ok(Self {
+            path,
+            state: Mutex::new(State {
+                file,
+                written,
+                next,
+                pending,
+                recovered,
+            }),
+        })
+    }
+
+    /// Writes `found` down, returning the entry to acknowledge.
+    pub fn record(&self, found: &Found) -> u64 {
+        let discovery = forward::encode(found);
+        let mut state = self.state.lock().unwrap();
+        let seq = state.next;
+        state.next += 1;
+
+        append(
+            &mut state,
+            Entry {
+                seq,
+                discovery: Some(discovery.clone()),
+            },
+        );
+        state.pending.insert(seq, discovery);
+        seq
+    }
+
+    /// Marks entry `seq` finished.
+    pub fn ack(&self, seq: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state.pending.remove(&seq).is_none() {
+            return;
+        }
+
+        if state.pending.is_empty() {
+            match state.file.set_len(0) {
+                Ok(()) => state.written = 0,
+                Err(e) => eprintln!("[!] Could not truncate the journal: {}", e),
+            }
+        } else if state.written > REWRITE_AT {
+            match rewrite(&self.path, &state.pending) {
+                Ok((file, written)) => {
+                    state.file = file;
+                    state.written = written;
+                }
+                Err(e) => eprintln!("[!] Could not rewrite the journal: {}", e),
+            }
+        } else {
+            append(
+                &mut state,
+                Entry {
+                    seq,
+                    discovery: None,
+                },
+            );
+        }
+    }
+
+    /// Pending discoveries of earlier runs, handed out once.
+    fn recovered(&self) -> Vec<(u64, Vec<u8>)> {
+        let mut state = self.state.lock().unwrap();
+        let seqs = mem::take(&mut state.recovered);
+        seqs.into_iter()
+            .filter_map(|seq| Some((seq, state.pending.get(&seq)?.clone())))
+            .collect()
+    }
+}
+
+/// Writes an entry, synced to disk when it is a discovery so one the insert
+/// then loses to a crash is still there at the next start. A lost
+/// acknowledgement only finishes a discovery twice.
+fn append(state: &mut State, entry: Entry) {
+    let bytes = entry.encode_length_delimited_to_vec();
+    if let Err(e) = state.file.write_all(&bytes) {
+        eprintln!("[!] Could not write to the journal: {}", e);
+        return;
+    }
+    state.written += bytes.len() as u64;
+    if entry.discovery.is_some() {
+        if let Err(e) = state.file.sync_data() {
+            eprintln!("[!] Could not sync the journal: {}", e);
+        }
+    }
+}
+
+/// Replaces the journal with one holding only `pending`, returning it opened
+/// for appending.
+fn rewrite(path: &PathBuf, pending: &HashMap<u64, Vec<u8>>) -> io::Result<(File, u64)> {
+    let mut bytes = vec![];
+    let mut seqs: Vec<&u64> = pending.keys().collect();
+    seqs.sort();
+    for seq in seqs {
+        Entry {
+            seq: *seq,
+            discovery: Some(pending[seq].clone()),
+        }
+        .encode_length_delimited(&mut bytes)
+        .unwrap();
+    }
+
+    let temporary = path.with_extension("tmp");
+    let mut file = File::create(&temporary)?;
+    file.write_all(&bytes)?;
+    file.sync_data()?;
+    fs::rename(&temporary, path)?;
+    let file = OpenOptions::new().append(true).open(path)?;
+    Ok((file, bytes.len() as u64))
+}
+
+/// Finishes the discoveries a previous run left pending. Each is journaled
+/// again before its old entry is acknowledged, so a crash during replay
+/// loses nothing either.
+pub async fn replay(ctx: Arc<Context>) {
+    let Some(journal) = &ctx.journal else {
+        return;
+    };
+    let recovered = journal.recovered();
+    if recovered.is_empty() {
+        return;
+    }
+    println!("[+] Replaying {} journaled discoveries", recovered.len());
+
+    let mut finished = 0;
+    for (seq, discovery) in recovered {
+        let found = match forward::decode(&discovery) {
+            Ok(found) => found,
+            Err(e) => {
+                eprintln!("[!] Skipping a journaled discovery: {}", e);
+                journal.ack(seq);
+                continue;
+            }
+        };
+        if finish_discovery(found, &ctx).await.is_ok() {
+            finished += 1;
+        }
+        journal.ack(seq);
+    }
+    println!("[+] Replayed {} journaled discoveries", finished);
+}
@@ -1,6 +1,7 @@
 use std::{
-    net::{IpAddr, Ipv4Addr},
-    time::Duration,
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
 };
 
 use rand::Rng;
@@ -8,12 +9,117 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tokio::{net::TcpStream, time::timeout};
 
-pub async fn check_server(ip: &IpAddr, port: u16) -> bool {
-    let addr = format!("{}:{}", ip, port);
+pub mod fields;
+pub mod filter;
+pub mod model;
+pub mod source_port;
 
-    match timeout(Duration::from_secs(2), TcpStream::connect(&addr)).await {
-        Ok(t) => t.is_ok(),
-        Err(_) => false,
+/// Offline message ID that marks RakNet packets sent outside a connection.
+pub const RAKNET_MAGIC: [u8; 16] = [
+    0x00, 0xFF, 0xFF, 0x00, 0xFE, 0xFE, 0xFE, 0xFE, 0xFD, 0xFD, 0xFD, 0xFD, 0x12, 0x34, 0x56, 0x78,
+];
+
+/// Transports `check_server` tries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Probe {
+    /// TCP connect, what Java Edition listens on
+    Tcp,
+    /// RakNet unconnected ping over UDP, what Bedrock Edition answers
+    Raknet,
+    Both,
+}
+
+/// What a `check_server` probe got back.
+#[derive(Debug, Default)]
+pub struct Answered {
+    /// Outcome of the TCP connect, `None` when it wasn't tried. The stream is
+    /// kept so the status request can reuse it.
+    pub tcp: Option<io::Result<TcpStream>>,
+    /// The unconnected pong, which holds the Bedrock status
+    pub raknet: Option<Pong>,
+}
+
+impl Answered {
+    pub fn any(&self) -> bool {
+        matches!(self.tcp, Some(Ok(_))) || self.raknet.is_some()
+    }
+}
+
+/// An unconnected pong and how long it took to arrive.
+#[derive(Debug, Clone)]
+pub struct Pong {
+    pub packet: Vec<u8>,
+    pub latency: Duration,
+}
+
+/// Bytes of the unconnected ping `check_server` sends.
+pub const RAKNET_PING_LEN: usize = 33;
+
+/// Checks which of the `probe` transports a server answers on `port`, both at
+/// once for `Probe::Both`.
+pub async fn check_server(ip: &IpAddr, port: u16, probe: Probe) -> Answered {
+    let tcp = async {
+        match probe {
+            Probe::Raknet => None,
+            Probe::Tcp | Probe::Both => Some(try_connect(ip, port).await),
+        }
+    };
+    let raknet = async {
+        if probe == Probe::Tcp {
+            return None;
+        }
+        let started = Instant::now();
+        // Silence is the usual answer, so it isn't waited for long
+        let packet = timeout(
+            Duration::from_secs(1),
+            raknet_ping(SocketAddr::new(*ip, port)),
+        )
+        .await
+        .ok()?
+        .ok()?;
+        Some(Pong {
+            packet,
+            latency: started.elapsed(),
+        })
+    };
+
+    let (tcp, raknet) = tokio::join!(tcp, raknet);
+    Answered { tcp, raknet }
+}
+
+/// Sends a RakNet unconnected ping and waits for any unconnected pong.
+async fn raknet_ping(remote: SocketAddr) -> io::Result<Vec<u8>> {
+    let socket = source_port::bind_udp(&remote).await?;
+    socket.connect(remote).await?;
+
+    let mut ping = vec![0x01];
+    ping.extend(0i64.to_be_bytes());
+    ping.extend(RAKNET_MAGIC);
+    ping.extend(rand::thread_rng().gen::<i64>().to_be_bytes());
+    socket.send(&ping).await?;
+
+    let mut buf = [0; 2048];
+    loop {
+        let len = socket.recv(&mut buf).await?;
+        if len >= 33 && buf[0] == 0x1C && buf[17..33] == RAKNET_MAGIC {
+            return Ok(buf[..len].to_vec());
+        }
+    }
+}
+
+/// Connects with the same timeout as `check_server`, keeping the stream so it
+/// can be reused for the status request.
+pub async fn connect(ip: &IpAddr, port: u16) -> Option<TcpStream> {
+    try_connect(ip, port).await.ok()
+}
+
+/// `connect` keeping the error, `TimedOut` when nothing answered in time.
+pub async fn try_connect(ip: &IpAddr, port: u16) -> io::Result<TcpStream> {
+    let addr = SocketAddr::new(*ip, port);
+
+    match timeout(Duration::from_secs(2), source_port::connect_tcp(addr)).await {
+        Ok(t) => t,
+        Err(_) => Err(io::ErrorKind::TimedOut.into()),
     }
 }
 
@@ -63,20 +169,402 @@ fn is_private_ip(ip: &Ipv4Addr) -> bool {
     false
 }
 
+/// Queue and lobby front-ends (2b2t and its imitators) advertise themselves in
+/// the MOTD or chat with a handful of recurring phrases.
+pub fn mentions_queue(text: &str) -> bool {
+    let text = text.to_lowercase();
+
+    ["queue", "position in line", "place in line", "2b2t"]
+        .iter()
+        .any(|pattern| text.contains(pattern))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kick {
+    WhiteList,
+    Ban,
+}
+
+/// Sorts a login disconnect message into the reasons we care about. Plugins
+/// word them freely, so this goes by recurring phrases rather than exact text.
+pub fn classify_kick(text: &str) -> Option<Kick> {
+    let text = text.to_lowercase();
+
+    if ["whitelist", "white-list", "white list", "not on the list"]
+        .iter()
+        .any(|pattern| text.contains(pattern))
+    {
+        Some(Kick::WhiteList)
+    } else if ["banned", "ban id", "you are ban", "appeal"]
+        .iter()
+        .any(|pattern| text.contains(pattern))
+    {
+        Some(Kick::Ban)
+    } else {
+        None
+    }
+}
+
+/// What a status response stands for when it is not simply a live server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusClass {
+    /// Answer of a host or proxy standing in for a stopped or sleeping server
+    /// (Aternos, Minehut, exaroton and the like)
+    Offline,
+    /// Hosting-provider page for a suspended or expired server
+    Suspended,
+    /// A live server whose MOTD keeps changing, as MOTD randomizers do
+    RotatingMotd,
+}
+
+impl StatusClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StatusClass::Offline => "offline",
+            StatusClass::Suspended => "suspended",
+            StatusClass::RotatingMotd => "rotating_motd",
+        }
+    }
+
+    /// Whether a server answering like this is actually running.
+    pub fn is_live(&self) -> bool {
+        *self == StatusClass::RotatingMotd
+    }
+}
+
+/// Recognizes placeholder status responses by the version name and MOTD
+/// hosts put in them. Suspension pages often say offline as well, so they are
+/// checked first. An on-demand host answering for a server that isn't running
+/// (`lifecycle`) is offline too, whatever it says.
+pub fn classify_placeholder(
+    version_name: &str,
+    motd: &str,
+    lifecycle: Option<Lifecycle>,
+) -> Option<StatusClass> {
+    let version = strip_formatting(version_name).to_lowercase();
+    let motd = strip_formatting(motd).to_lowercase();
+
+    if [
+        "suspended",
+        "has expired",
+        "renew your server",
+        "unpaid",
+        "overdue",
+    ]
+    .iter()
+    .any(|pattern| motd.contains(pattern) || version.contains(pattern))
+    {
+        Some(StatusClass::Suspended)
+    } else if version.contains("offline")
+        || version.contains("sleeping")
+        || [
+            "server is offline",
+            "server is currently offline",
+            "server is sleeping",
+            "server is starting",
+            "join to start",
+            "to start the server",
+            "server not found",
+        ]
+        .iter()
+        .any(|pattern| motd.contains(pattern))
+    {
+        Some(StatusClass::Offline)
+    } else {
+        lifecycle
+            .filter(|lifecycle| *lifecycle != Lifecycle::Running)
+            .map(|_| StatusClass::Offline)
+    }
+}
+
+/// Hosts that start a server only when a player asks for it and answer for it
+/// in the meantime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnDemandHost {
+    Aternos,
+    Exaroton,
+    Minehut,
+}
+
+impl OnDemandHost {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OnDemandHost::Aternos => "aternos",
+            OnDemandHost::Exaroton => "exaroton",
+            OnDemandHost::Minehut => "minehut",
+        }
+    }
+
+    /// The host named in a status. Its placeholders advertise it; a running
+    /// server shows its own MOTD, so this is only known from a placeholder.
+    pub fn detect(version_name: &str, motd: &str) -> Option<Self> {
+        let text = format!("{} {}", version_name, motd).to_lowercase();
+
+        [
+            ("aternos", OnDemandHost::Aternos),
+            ("exaroton", OnDemandHost::Exaroton),
+            ("minehut", OnDemandHost::Minehut),
+        ]
+        .into_iter()
+        .find(|(name, _)| text.contains(name))
+        .map(|(_, host)| host)
+    }
+}
+
+/// Where a server of an on-demand host is between sleeping and running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lifecycle {
+    Sleeping,
+    /// Waiting for a free slot on the host to start
+    Queued,
+    Starting,
+    Running,
+}
+
+impl Lifecycle {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Lifecycle::Sleeping => "sleeping",
+            Lifecycle::Queued => "queued",
+            Lifecycle::Starting => "starting",
+            Lifecycle::Running => "running",
+        }
+    }
+
+    /// State an on-demand host's answer reports, `Running` when it is the
+    /// server's own status.
+    pub fn of_on_demand(version_name: &str, motd: &str) -> Self {
+        let version = strip_formatting(version_name).to_lowercase();
+        let motd = strip_formatting(motd).to_lowercase();
+        let says = |patterns: &[&str]| {
+            patterns
+                .iter()
+                .any(|pattern| version.contains(pattern) || motd.contains(pattern))
+        };
+
+        if says(&["in queue", "waiting in line", "queue to start"]) {
+            Lifecycle::Queued
+        } else if says(&["starting", "loading", "preparing"]) {
+            Lifecycle::Starting
+        } else if says(&["offline", "sleeping", "stopped", "hibernat"]) {
+            Lifecycle::Sleeping
+        } else {
+            Lifecycle::Running
+        }
+    }
+}
+
+/// First Discord invite or website mentioned in a kick message, where staff
+/// usually point players to apply or appeal.
+pub fn contact_url(text: &str) -> Option<String> {
+    let words = text
+        .split(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>' | '(' | ')'))
+        .map(|word| word.trim_matches(|c: char| matches!(c, '.' | ',' | '!' | '?' | ':' | ';')))
+        .filter(|word| !word.is_empty() && word.is_ascii());
+
+    let mut website = None;
+    for word in words {
+        let lower = word.to_lowercase();
+        let bare = lower
+            .trim_start_matches("https://")
+            .trim_start_matches("http://");
+
+        if [
+            "discord.gg/",
+            "discord.com/invite/",
+            "discordapp.com/invite/",
+        ]
+        .iter()
+        .any(|prefix| bare.starts_with(prefix) && bare.len() > prefix.len())
+        {
+            return Some(format!("https://{}", &word[word.len() - bare.len()..]));
+        }
+
+        if website.is_none() && is_website(bare) {
+            website = Some(if lower.starts_with("http") {
+                word.to_string()
+            } else {
+                format!("https://{}", word)
+            });
+        }
+    }
+
+    website
+}
+
+fn is_website(word: &str) -> bool {
+    let host = word.split('/').next().unwrap_or_default();
+    let Some((name, tld)) = host.rsplit_once('.') else {
+        return false;
+    };
+
+    // Rules out version numbers, IPs and sentence fragments like "e.g"
+    !name.is_empty()
+        && tld.len() >= 2
+        && tld.chars().all(|c| c.is_ascii_alphabetic())
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+        && (word.starts_with("www.") || word.contains('/') || host.matches('.').count() == 1)
+}
+
 pub fn description_to_str(description: Value) -> Result<String, serde_json::Error> {
     let chat_object: ChatObject = serde_json::from_value(description)?;
     Ok(chat_object.get_motd())
 }
 
+/// The description as legacy text, with colors and styles of the chat
+/// component turned into `§` codes.
+pub fn description_to_legacy(description: Value) -> Result<String, serde_json::Error> {
+    let chat_object: ChatObject = serde_json::from_value(description)?;
+    let mut result = String::new();
+    chat_object.write_legacy(&Style::default(), &mut Style::default(), &mut result);
+    Ok(result)
+}
+
+/// Removes `§` formatting codes.
+pub fn strip_formatting(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{a7}' {
+            chars.next();
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Style {
+    color: Option<char>,
+    bold: bool,
+    italic: bool,
+    underlined: bool,
+    strikethrough: bool,
+    obfuscated: bool,
+}
+
+impl Style {
+    fn inherit(&self, object: &ChatComponentObject) -> Self {
+        Self {
+            color: object
+                .color
+                .as_ref()
+                .and_then(Value::as_str)
+                .and_then(color_code)
+                .or(self.color),
+            bold: flag(&object.bold).unwrap_or(self.bold),
+            italic: flag(&object.italic).unwrap_or(self.italic),
+            underlined: flag(&object.underlined).unwrap_or(self.underlined),
+            strikethrough: flag(&object.strikethrough).unwrap_or(self.strikethrough),
+            obfuscated: flag(&object.obfuscated).unwrap_or(self.obfuscated),
+        }
+    }
+
+    fn codes(&self) -> String {
+        let mut codes = String::new();
+        if let Some(color) = self.color {
+            codes.extend(['\u{a7}', color]);
+        }
+        for (set, code) in [
+            (self.obfuscated, 'k'),
+            (self.bold, 'l'),
+            (self.strikethrough, 'm'),
+            (self.underlined, 'n'),
+            (self.italic, 'o'),
+        ] {
+            if set {
+                codes.extend(['\u{a7}', code]);
+            }
+        }
+        codes
+    }
+}
+
+/// Style flags are booleans, but some servers send `"true"`.
+fn flag(value: &Option<Value>) -> Option<bool> {
+    match value.as_ref()? {
+        Value::Bool(b) => Some(*b),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Legacy code of a named color. Hex colors of 1.16+ have none and are left
+/// out.
+fn color_code(color: &str) -> Option<char> {
+    Some(match color {
+        "black" => '0',
+        "dark_blue" => '1',
+        "dark_green" => '2',
+        "dark_aqua" => '3',
+        "dark_red" => '4',
+        "dark_purple" => '5',
+        "gold" => '6',
+        "gray" => '7',
+        "dark_gray" => '8',
+        "blue" => '9',
+        "green" => 'a',
+        "aqua" => 'b',
+        "red" => 'c',
+        "light_purple" => 'd',
+        "yellow" => 'e',
+        "white" => 'f',
+        _ => return None,
+    })
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum ChatObject {
-    Object(ChatComponentObject),
+    Object(Box<ChatComponentObject>),
     Array(Vec<ChatObject>),
     JsonPrimitive(Value),
 }
 
 impl ChatObject {
+    /// Appends the text in `style`, emitting codes only where the style
+    /// changes from the `current` one of the output.
+    fn write_legacy(&self, style: &Style, current: &mut Style, out: &mut String) {
+        let mut text = |text: &str, style: &Style, current: &mut Style| {
+            if text.is_empty() {
+                return;
+            }
+            if style != current {
+                if *current != Style::default() {
+                    out.push_str("\u{a7}r");
+                }
+                out.push_str(&style.codes());
+                *current = style.clone();
+            }
+            out.push_str(text);
+        };
+
+        match self {
+            ChatObject::Object(object) => {
+                let style = style.inherit(object);
+                if let Some(value) = &object.text {
+                    text(value, &style, current);
+                }
+                for child in object.extra.iter().flatten() {
+                    child.write_legacy(&style, current, out);
+                }
+            }
+            ChatObject::Array(vec) => {
+                for object in vec {
+                    object.write_legacy(style, current, out);
+                }
+            }
+            ChatObject::JsonPrimitive(value) => {
+                if let Some(value) = value.as_str() {
+                    text(value, style, current);
+                }
+            }
+        }
+    }
+
     pub fn get_motd(&self) -> String {
         match self {
             ChatObject::Object(chat_component_object) => {
@@ -115,4 +603,16 @@ impl ChatObject {
 pub struct ChatComponentObject {
     pub text: Option<String>,
     pub extra: Option<Vec<ChatObject>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bold: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub italic: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub underlined: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strikethrough: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub obfuscated: Option<Value>,
 }
@@ -0,0 +1,56 @@
+use std::{env, future::Future};
+
+use tokio::sync::Semaphore;
+
+use crate::lookups::Limiter;
+
+/// Bounds on login probes, which owners see in their logs unlike status
+/// pings: at most `LOGIN_CONCURRENCY` at once and `LOGIN_RATE` started per
+/// second, across scan workers, jobs and the updater's access rechecks.
+pub struct Logins {
+    slots: Option<Semaphore>,
+    limiter: Limiter,
+    concurrency: usize,
+    rate: f64,
+}
+
+impl Logins {
+    pub fn from_env() -> Self {
+        let concurrency = env::var("LOGIN_CONCURRENCY")
+            .unwrap_or("0".to_string())
+            .parse()
+            .expect("LOGIN_CONCURRENCY count");
+        let rate = env::var("LOGIN_RATE")
+            .unwrap_or("0".to_string())
+            .parse()
+            .expect("LOGIN_RATE per second");
+
+        Self {
+            slots: (concurrency > 0).then(|| Semaphore::new(concurrency)),
+            limiter: Limiter::new(rate),
+            concurrency,
+            rate,
+        }
+    }
+
+    pub fn describe(&self) -> Option<String> {
+        let mut limits = vec![];
+        if self.concurrency > 0 {
+            limits.push(format!("{} at once", self.concurrency));
+        }
+        if self.rate > 0.0 {
+            limits.push(format!("{}/s", self.rate));
+        }
+        (!limits.is_empty()).then(|| limits.join(", "))
+    }
+
+    /// Runs the login probe `probe` once a slot is free and the rate allows.
+    pub async fn run<F: Future>(&self, probe: F) -> F::Output {
+        let _permit = match &self.slots {
+            Some(slots) => Some(slots.acquire().await.unwrap()),
+            None => None,
+        };
+        self.limiter.wait().await;
+        probe.await
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::HashMap,
+    env,
+    future::Future,
+    hash::Hash,
+    net::IpAddr,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+
+use hickory_resolver::TokioAsyncResolver;
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::{sleep_until, timeout, Instant},
+};
+
+use crate::commands::domains;
+
+/// Lookups waiting per kind before callers wait to queue more.
+const QUEUE: usize = 10_000;
+/// Results cached per kind; expired ones are dropped first beyond that.
+const MAX_CACHED: usize = 100_000;
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Kinds of lookups, each with its own workers, rate and cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Rdns,
+    Srv,
+    Mojang,
+}
+
+impl Kind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Rdns => "rdns",
+            Kind::Srv => "srv",
+            Kind::Mojang => "mojang",
+        }
+    }
+
+    fn default_workers(&self) -> usize {
+        match self {
+            Kind::Rdns => 16,
+            Kind::Srv => 8,
+            Kind::Mojang => 2,
+        }
+    }
+
+    /// Lookups per second, the session server allows about 600 per 10 minutes.
+    fn default_rate(&self) -> f64 {
+        match self {
+            Kind::Rdns => 100.0,
+            Kind::Srv => 50.0,
+            Kind::Mojang => 1.0,
+        }
+    }
+}
+
+/// `kind=value` of a `LOOKUP_*` list.
+fn setting(var: &str, kind: Kind) -> Option<String> {
+    env::var(var)
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|entry| entry.split_once('='))
+        .find(|(name, _)| name.trim() == kind.as_str())
+        .map(|(_, value)| value.trim().to_string())
+}
+
+/// Spaces requests out to a rate, `None` for no limit.
+pub struct Limiter {
+    interval: Option<Duration>,
+    next: Mutex<Instant>,
+}
+
+impl Limiter {
+    /// `per_second` of `0` or less for no limit.
+    pub fn new(per_second: f64) -> Self {
+        Self {
+            interval: (per_second > 0.0).then(|| Duration::from_secs_f64(1.0 / per_second)),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub async fn wait(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let at = {
+            let mut next = self.next.lock().unwrap();
+            let at = (*next).max(Instant::now());
+            *next = at + interval;
+            at
+        };
+        sleep_until(at).await;
+    }
+}
+
+/// Workers answering lookups of one kind from a queue, in front of a cache.
+struct Pool<K, V> {
+    sender: mpsc::Sender<(K, oneshot::Sender<V>)>,
+    cache: Mutex<HashMap<K, (Instant, V)>>,
+    ttl: Duration,
+    /// Whether a result may be cached, failures are asked again
+    keep: fn(&V) -> bool,
+}
+
+impl<K, V> Pool<K, V>
+where
+    K: Eq + Hash + Clone + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    fn start<F, Fut>(kind: Kind, ttl: Duration, keep: fn(&V) -> bool, lookup: F) -> Self
+    where
+        F: Fn(K) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = V> + Send,
+    {
+        let workers = setting("LOOKUP_WORKERS", kind)
+            .map(|n| n.parse().expect("LOOKUP_WORKERS count"))
+            .unwrap_or(kind.default_workers())
+            .max(1);
+        let rate = setting("LOOKUP_RATES", kind)
+            .map(|n| n.parse().expect("LOOKUP_RATES rate"))
+            .unwrap_or(kind.default_rate());
+
+        let (sender, receiver) = mpsc::channel::<(K, oneshot::Sender<V>)>(QUEUE);
+        let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+        let limiter = Arc::new(Limiter::new(rate));
+        let lookup = Arc::new(lookup);
+        for _ in 0..workers {
+            let receiver = receiver.clone();
+            let limiter = limiter.clone();
+            let lookup = lookup.clone();
+            tokio::spawn(async move {
+                loop {
+                    let Some((key, reply)) = receiver.lock().await.recv().await else {
+                        return;
+                    };
+                    limiter.wait().await;
+                    let _ = reply.send(lookup(key).await);
+                }
+            });
+        }
+
+        Self {
+            sender,
+            cache: Mutex::new(HashMap::new()),
+            ttl,
+            keep,
+        }
+    }
+
+    /// The cached result for `key`, or the one of a worker once it gets to
+    /// it. `None` only when the workers are gone.
+    async fn get(&self, key: K) -> Option<V> {
+        if let Some((at, value)) = self.cache.lock().unwrap().get(&key) {
+            if at.elapsed() < self.ttl {
+                return Some(value.clone());
+            }
+        }
+
+        let (reply, answer) = oneshot::channel();
+        self.sender.send((key.clone(), reply)).await.ok()?;
+        let value = answer.await.ok()?;
+
+        if (self.keep)(&value) {
+            let mut cache = self.cache.lock().unwrap();
+            if cache.len() >= MAX_CACHED {
+                cache.retain(|_, (at, _)| at.elapsed() < self.ttl);
+                if cache.len() >= MAX_CACHED {
+                    cache.clear();
+                }
+            }
+            cache.insert(key, (Instant::now(), value.clone()));
+        }
+        Some(value)
+    }
+}
+
+/// Reverse DNS, SRV and Mojang profile lookups, run by workers of their own
+/// with `LOOKUP_WORKERS` and `LOOKUP_RATES` per kind and results cached for
+/// `LOOKUP_CACHE_TTL`, so that slow resolvers or a rate-limited session
+/// server hold up neither scanning nor each other.
+pub struct Lookups {
+    rdns: Pool<IpAddr, Option<String>>,
+    srv: Pool<String, Option<(IpAddr, u16, bool)>>,
+    mojang: Pool<String, Option<bool>>,
+}
+
+impl Lookups {
+    pub fn from_env() -> Self {
+        let ttl = Duration::from_secs(
+            env::var("LOOKUP_CACHE_TTL")
+                .unwrap_or("3600".to_string())
+                .parse()
+                .unwrap(),
+        );
+        let resolver = Arc::new(TokioAsyncResolver::tokio_from_system_conf().unwrap());
+
+        let rdns_resolver = resolver.clone();
+        let srv_resolver = resolver;
+        Self {
+            rdns: Pool::start(
+                Kind::Rdns,
+                ttl,
+                |_| true,
+                move |ip| {
+                    let resolver = rdns_resolver.clone();
+                    async move { reverse_dns(&resolver, ip).await }
+                },
+            ),
+            srv: Pool::start(
+                Kind::Srv,
+                ttl,
+                |_| true,
+                move |domain: String| {
+                    let resolver = srv_resolver.clone();
+                    async move {
+                        timeout(LOOKUP_TIMEOUT, domains::resolve(&domain, &resolver))
+                            .await
+                            .ok()
+                            .flatten()
+                    }
+                },
+            ),
+            mojang: Pool::start(
+                Kind::Mojang,
+                ttl,
+                Option::is_some,
+                |uuid: String| async move { mojang_account(&uuid).await },
+            ),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        [Kind::Rdns, Kind::Srv, Kind::Mojang]
+            .iter()
+            .map(|kind| {
+                let workers =
+                    setting("LOOKUP_WORKERS", *kind).unwrap_or(kind.default_workers().to_string());
+                let rate =
+                    setting("LOOKUP_RATES", *kind).unwrap_or(kind.default_rate().to_string());
+                format!("{} ({} workers, {}/s)", kind.as_str(), workers, rate)
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Reverse DNS name of `ip`, lowercased and without the trailing dot.
+    pub async fn reverse_dns(&self, ip: IpAddr) -> Option<String> {
+        self.rdns.get(ip).await.flatten()
+    }
+
+    /// Address and port `domain` resolves to like the client does, SRV
+    /// record first, and whether it came from one.
+    pub async fn resolve(&self, domain: &str) -> Option<(IpAddr, u16, bool)> {
+        self.srv.get(domain.to_string()).await.flatten()
+    }
+
+    /// Whether the v4 UUID `uuid` is a Mojang account, `None` when the
+    /// session server could not answer.
+    pub async fn mojang_account(&self, uuid: &str) -> Option<bool> {
+        self.mojang.get(uuid.to_string()).await.flatten()
+    }
+}
+
+async fn reverse_dns(resolver: &TokioAsyncResolver, ip: IpAddr) -> Option<String> {
+    let lookup = timeout(LOOKUP_TIMEOUT, resolver.reverse_lookup(ip))
+        .await
+        .ok()?
+        .ok()?;
+    let hostname = lookup.iter().next()?.0.to_utf8();
+    Some(hostname.trim_end_matches('.').to_lowercase())
+}
+
+fn http() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(LOOKUP_TIMEOUT)
+            .build()
+            .unwrap()
+    })
+}
+
+async fn mojang_account(uuid: &str) -> Option<bool> {
+    let url = format!(
+        "https://sessionserver.mojang.com/session/minecraft/profile/{}",
+        uuid
+    );
+    match http().get(url).send().await.map(|r| r.status()) {
+        Ok(status) if status.is_success() && status.as_u16() != 204 => Some(true),
+        Ok(status) if status.as_u16() == 204 || status.as_u16() == 404 => Some(false),
+        _ => None,
+    }
+}
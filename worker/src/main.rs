@@ -1,231 +1,2171 @@
 use std::{
     env,
     io::{self, ErrorKind},
-    net::IpAddr,
+    net::{IpAddr, SocketAddr},
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use chrono::{Local, Timelike};
-use colored::Colorize;
-use database::{DatabaseWrapper, PlayerInsert, ServerInsert, ServerModel, ServerUpdate};
-use diesel::{dsl::insert_into, ExpressionMethods, QueryDsl, RunQueryDsl, SelectableHelper};
-use mine_search::{check_server, description_to_str, generate_random_ip};
+use alerts::{AlertRecord, AlertRules};
+use chrono::{Local, NaiveDate, NaiveDateTime, Timelike};
+use clap::{error::ErrorKind as ClapErrorKind, Args, CommandFactory, Parser, Subcommand};
+use commands::{export::ExportFormat, sla::SlaFormat};
+use conn_wrapper::Connection;
+use database::{
+    DatabaseWrapper, DiscoverySource, Edition, LivenessInsert, MotdHistoryInsert, PingInsert,
+    PlayerInsert, Pool, RunModel, ServerInsert, ServerModel, VersionHistoryInsert,
+};
+use diesel::{
+    dsl::insert_into, BoolExpressionMethods, ExpressionMethods, QueryDsl, RunQueryDsl,
+    SelectableHelper,
+};
+use enrich::{Job, Pipeline, Stage};
+use filtered::FilteredSpace;
+use forward::{Forwarder, Found};
+use geoip::GeoIp;
+use icmp::Pinger;
+use journal::Journal;
+use logins::Logins;
+use lookups::Lookups;
+use mine_search::{
+    check_server, classify_placeholder, description_to_legacy, description_to_str, mentions_queue,
+    strip_formatting, Lifecycle, OnDemandHost, Pong, Probe, StatusClass, RAKNET_PING_LEN,
+};
+use persistence::{DiscoveryRecord, PersistAction, PersistRules};
+use plugins::Plugins;
+use proxy_protocol::ProxyVersion;
+use reporter::{Discovery, Pass, Reporter};
+use scanner::{Exclusions, Order, Ports, Scanner};
 use serde_json::json;
-use server_actions::{with_connection::get_extra_data, without_connection::get_status};
-use tokio::{
-    sync::{Mutex, Semaphore},
-    time::timeout,
+use server_actions::{
+    bedrock::{get_bedrock_status, status_of_pong, BedrockStatus},
+    legacy,
+    path::trace_path,
+    query::{get_query, QueryData},
+    with_connection::get_extra_data,
+    without_connection::{check_alive, get_status, get_status_on, Status},
 };
+use sinks::Sinks;
+use stats::{RunStats, WorkerStats};
+use status_cache::StatusCache;
+use storage::{
+    memory::MemoryStorage, postgres::PgStorage, Access, LifecycleUpdate, StatusUpdate, Storage,
+};
+use tokio::{net::TcpStream, sync::Semaphore, task::JoinHandle, time::timeout};
+use traffic::Traffic;
+use vhosts::VhostScanner;
+use webhooks::{Event, Webhooks};
+use writer::Writers;
 
 use db_schema::schema;
 
+mod activity;
+mod alerts;
+mod blocking;
+mod commands;
+mod compaction;
 mod conn_wrapper;
 mod database;
+mod enrich;
+mod etiquette;
+mod favicon;
+mod fd_limit;
+mod filtered;
+mod fingerprint;
+mod forward;
+mod geoip;
+mod icmp;
+mod identity;
+mod jobs;
+mod journal;
+mod logins;
+mod lookups;
+mod modpacks;
+mod mods;
+mod nbt;
+mod overrides;
 mod packets;
+mod persistence;
+mod plugins;
+mod profiles;
+mod protocol;
+mod proxy_protocol;
+mod quotas;
+mod reporter;
+mod retry;
+mod scanner;
+mod serve;
 mod server_actions;
+mod sinks;
+mod stats;
+mod status_cache;
+mod storage;
+mod traffic;
+mod vhosts;
+mod webhooks;
+mod writer;
+
+pub struct Context {
+    /// Connections to the primary, none with `STORAGE=memory`
+    pub db: Option<Arc<Pool>>,
+    /// Sharded writers for discovery inserts
+    pub writers: Arc<Writers>,
+    /// Where discovered servers, their players and snapshots are stored
+    pub storage: Box<dyn Storage>,
+    /// NDJSON file and Kafka destinations of discoveries
+    pub sinks: Sinks,
+    pub webhooks: Webhooks,
+    pub rules: PersistRules,
+    pub alerts: AlertRules,
+    pub plugins: Plugins,
+    pub reporter: Box<dyn Reporter>,
+    /// Row of this process in `runs`, none with `STORAGE=memory`
+    pub run_id: Option<i32>,
+    pub status_only: bool,
+    pub vhosts: Option<VhostScanner>,
+    pub trace_path: bool,
+    pub stats: RunStats,
+    pub fingerprint_services: bool,
+    /// Tries pre-1.7 handshakes on ports that kicked the modern one
+    pub legacy_ladder: bool,
+    pub detect_proxy_protocol: bool,
+    /// Sends a GameSpy4 query to new and updated Java servers
+    pub query_probes: bool,
+    /// Probes per target in the retry queue, `0` disables it
+    pub retry_attempts: u32,
+    pub status_cache: StatusCache,
+    /// Runs the FML handshake on Forge servers to list their mods
+    pub forge_handshake: bool,
+    pub geoip: Arc<GeoIp>,
+    /// Prefixes answering with resets or ICMP unreachable errors
+    pub filtered: Arc<FilteredSpace>,
+    /// Storage process that finishes the discoveries of this one, if any
+    pub forwarder: Option<Forwarder>,
+    /// Discoveries being finished, kept on disk until they are
+    pub journal: Option<Journal>,
+    /// Connections to the read replica, if one is configured
+    pub replica: Option<Arc<Pool>>,
+    /// Stages new servers pass through after being stored
+    pub enrichment: Pipeline,
+    /// rDNS, SRV and Mojang lookups, off the probe workers
+    pub lookups: Lookups,
+    /// Concurrency and rate of login probes
+    pub logins: Logins,
+    /// Echo requests sent before probing random addresses
+    pub pinger: Option<Pinger>,
+}
 
 pub async fn handle_valid_ip(
+    mut conn: Connection,
     ip: &IpAddr,
     port: u16,
-    db: Arc<Mutex<DatabaseWrapper>>,
+    source: DiscoverySource,
+    ctx: &Arc<Context>,
 ) -> io::Result<()> {
-    let status = get_status(&format!("{}", ip), port).await?;
-
-    let extra_data =
-        get_extra_data(format!("{}", ip), port, status.version.protocol as i32).await?;
-
-    let server_insert = ServerInsert {
-        ip: &format!("{}", ip),
-        online: status.players.online as i32,
-        max: status.players.max as i32,
-        version_name: &status.version.name,
-        protocol: status.version.protocol as i32,
-        license: extra_data.license,
-        white_list: extra_data.white_list,
-        description: &json!({
-            "payload": status.description
-        }),
-    };
-
-    let server: ServerModel = insert_into(schema::servers::dsl::servers)
-        .values(server_insert)
-        .on_conflict(schema::servers::dsl::ip)
-        .do_nothing()
-        .returning(ServerModel::as_returning())
-        .get_result(&mut db.lock().await.conn)
-        .map_err(|_| ErrorKind::InvalidInput)?;
-
-    for player in status.players.sample.unwrap_or_default() {
-        let player_model = PlayerInsert {
-            uuid: &player.id,
-            name: &player.name,
-            server_id: server.id,
+    let (result, latency) = match ctx.status_cache.get(&ip.to_string(), port) {
+        Some((status, latency)) => (Ok(status), latency),
+        None => {
+            if !etiquette::admit(&ip.to_string()) {
+                return Err(io::Error::new(
+                    ErrorKind::QuotaExceeded,
+                    "daily probe cap reached",
+                ));
+            }
+            let started = Instant::now();
+            let result = get_status_on(&mut conn, &format!("{}", ip), port).await;
+            (result, started.elapsed())
+        }
+    };
+    let banner = conn.received_prefix().to_vec();
+    drop(conn);
+
+    let mut proxy = None;
+    let status = match result {
+        Ok(status) => status,
+        Err(_) if ctx.legacy_ladder && legacy::speaks_legacy(&banner) => {
+            match legacy::get_status(&ip.to_string(), port, None).await {
+                Ok(status) => status,
+                Err(e) => {
+                    if ctx.fingerprint_services {
+                        fingerprint::record(ip, port, &banner, ctx).await;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Err(e) if !banner.is_empty() => {
+            // Something answered, just not a Minecraft server
+            if ctx.fingerprint_services {
+                fingerprint::record(ip, port, &banner, ctx).await;
+            }
+            return Err(e);
+        }
+        Err(e) => match retry_with_proxy_header(ip, port, &e, ctx).await {
+            Some(status) => {
+                proxy = Some(ProxyVersion::V1);
+                status
+            }
+            None => return Err(e),
+        },
+    };
+    ctx.status_cache
+        .insert(&ip.to_string(), port, &status, latency);
+    let country = ctx.geoip.locate(*ip).country;
+    ctx.stats
+        .responded(*ip, port, &status.version.name, country.as_deref());
+    let query = match ctx.query_probes {
+        true => get_query(&ip.to_string(), port).await.ok(),
+        false => None,
+    };
+
+    if ctx.rules.decidable_without_extra_data() {
+        let record = DiscoveryRecord {
+            ip,
+            port,
+            edition: Edition::Java,
+            status: &status,
+            extra_data: None,
+            country: country.as_deref(),
         };
+        if ctx.rules.decide(&record) == PersistAction::Drop {
+            return Ok(());
+        }
+    }
 
-        insert_into(schema::players::dsl::players)
-            .values(&player_model)
-            .on_conflict_do_nothing()
-            .execute(&mut db.lock().await.conn)
-            .unwrap();
+    // Pre-1.7 logins are not spoken by the login probe
+    let extra_data = if ctx.status_only || overrides::lookup(*ip).no_login || status.era.is_some() {
+        None
+    } else {
+        Some(
+            ctx.logins
+                .run(get_extra_data(
+                    format!("{}", ip),
+                    port,
+                    status.version.protocol as i32,
+                    proxy,
+                ))
+                .await?,
+        )
+    };
+    if let Some(extra_data) = &extra_data {
+        ctx.stats.licensed(*ip, port, extra_data.license);
     }
 
-    let timestamp = Local::now().format("%H:%M:%S").to_string();
-
-    println!(
-        "[{}] {} {} | {} {} | {} {}/{} | {} | {} {}",
-        timestamp,
-        "🌐",
-        ip.to_string().blue(),
-        "🛠 ",
-        status.version.name.yellow(),
-        "👥",
-        status.players.online.to_string().green(),
-        status.players.max,
-        if extra_data.license {
-            "yes".red()
-        } else {
-            "no".green()
+    dispatch(
+        Found {
+            ip: *ip,
+            port,
+            edition: Edition::Java,
+            source,
+            status,
+            query,
+            extra_data,
+            latency,
+            proxy,
+            run_id: ctx.run_id,
         },
-        "🚀",
-        description_to_str(status.description).unwrap_or("".to_string())
+        ctx,
+    )
+    .await
+}
+
+/// Bedrock counterpart of `handle_valid_ip` for an address that answered the
+/// RakNet ping. The pong is all a Bedrock server tells without joining, so
+/// there is no login probe.
+pub async fn handle_bedrock(
+    ip: &IpAddr,
+    port: u16,
+    bedrock: BedrockStatus,
+    latency: Duration,
+    source: DiscoverySource,
+    ctx: &Arc<Context>,
+) -> io::Result<()> {
+    let status = bedrock.to_status();
+    ctx.stats.responded(
+        *ip,
+        port,
+        &status.version.name,
+        ctx.geoip.locate(*ip).country.as_deref(),
     );
+
+    dispatch(
+        Found {
+            ip: *ip,
+            port,
+            edition: Edition::Bedrock,
+            source,
+            status,
+            query: Some(bedrock.to_query()),
+            extra_data: None,
+            latency,
+            proxy: None,
+            run_id: ctx.run_id,
+        },
+        ctx,
+    )
+    .await
+}
+
+/// Hands a discovery to the storage process when forwarding, or finishes it
+/// here.
+async fn dispatch(found: Found, ctx: &Arc<Context>) -> io::Result<()> {
+    match &ctx.forwarder {
+        Some(forwarder) => {
+            forwarder.send(found).await;
+            Ok(())
+        }
+        None => finish_discovery(found, ctx).await,
+    }
+}
+
+/// Finishes a discovery, journaled until it has been.
+pub async fn finish_discovery(found: Found, ctx: &Arc<Context>) -> io::Result<()> {
+    let Some(journal) = &ctx.journal else {
+        return settle_discovery(found, ctx).await;
+    };
+
+    let entry = journal.record(&found);
+    let result = settle_discovery(found, ctx).await;
+    if result.is_ok() {
+        journal.ack(entry);
+    }
+    result
+}
+
+/// Decides on, stores and reports a discovery, starting the follow-up probes
+/// of new Java servers.
+async fn settle_discovery(found: Found, ctx: &Arc<Context>) -> io::Result<()> {
+    let Found {
+        ip,
+        port,
+        edition,
+        source,
+        status,
+        query,
+        extra_data,
+        latency,
+        proxy,
+        run_id,
+    } = found;
+
+    let country = ctx.geoip.locate(ip).country;
+    let record = DiscoveryRecord {
+        ip: &ip,
+        port,
+        edition,
+        status: &status,
+        extra_data: extra_data.as_ref(),
+        country: country.as_deref(),
+    };
+    let action = ctx.rules.decide(&record);
+    if action == PersistAction::Drop {
+        return Ok(());
+    }
+    let (action, tags) = apply_plugins(&record, action, ctx);
+
+    if action == PersistAction::Store {
+        let run_id = run_id.or(ctx.run_id);
+        let server = store_discovery(&record, source, query.as_ref(), tags, run_id, ctx).await?;
+        ctx.stats.stored(server.is_some());
+        // Already known, the updater keeps it fresh
+        let Some(server) = server else {
+            return Ok(());
+        };
+
+        ctx.storage
+            .record_snapshot(PingInsert {
+                server_id: server.id,
+                online: server.online,
+                max: server.max,
+                version_name: server.version_name.clone(),
+                protocol: server.protocol,
+                latency_ms: Some(latency.as_millis() as i32),
+                checked_at: server.last_seen,
+            })
+            .await;
+
+        if proxy.is_some() {
+            ctx.storage.record_proxy_protocol(server.id).await;
+        }
+
+        let location = ctx.geoip.locate(ip);
+        let discovery = Discovery {
+            ip: &ip,
+            port,
+            edition,
+            status: &status,
+            extra_data: extra_data.as_ref(),
+            stored: true,
+            latency: Some(latency),
+            country: location.country.as_deref(),
+        };
+        ctx.reporter.discovery(&discovery);
+        ctx.sinks.discovery(&discovery).await;
+        ctx.enrichment.submit(Job {
+            server,
+            ip,
+            port,
+            edition,
+            status,
+            proxy,
+            country: None,
+            rdns: None,
+        });
+        return Ok(());
+    }
+
+    let location = ctx.geoip.locate(ip);
+    let discovery = Discovery {
+        ip: &ip,
+        port,
+        edition,
+        status: &status,
+        extra_data: extra_data.as_ref(),
+        stored: false,
+        latency: Some(latency),
+        country: location.country.as_deref(),
+    };
+    ctx.reporter.discovery(&discovery);
+    ctx.sinks.discovery(&discovery).await;
     Ok(())
 }
 
-async fn worker(db: Arc<Mutex<DatabaseWrapper>>) {
+/// Lets plugins keep a discovery out of the database and send notifications,
+/// returning the final action and the tags to store.
+fn apply_plugins(
+    record: &DiscoveryRecord,
+    mut action: PersistAction,
+    ctx: &Context,
+) -> (PersistAction, Vec<String>) {
+    let outcome = ctx.plugins.on_discovery(record);
+    if outcome.store == Some(false) {
+        action = PersistAction::Log;
+    }
+    if let Some(message) = outcome.notify {
+        ctx.webhooks.fire(Event::PluginNotification {
+            ip: record.ip.to_string(),
+            message,
+        });
+    }
+
+    (action, outcome.tags)
+}
+
+/// Backends that expect a PROXY header close the connection without sending a
+/// byte when it is missing, so retry those once with a v1 header.
+async fn retry_with_proxy_header(
+    ip: &IpAddr,
+    port: u16,
+    error: &io::Error,
+    ctx: &Context,
+) -> Option<Status> {
+    if !ctx.detect_proxy_protocol
+        || proxy_protocol::always().is_some()
+        || !matches!(
+            error.kind(),
+            ErrorKind::UnexpectedEof | ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted
+        )
+    {
+        return None;
+    }
+
+    let mut conn = Connection::connect_with((*ip, port), Some(ProxyVersion::V1))
+        .await
+        .ok()?;
+    get_status_on(&mut conn, &ip.to_string(), port).await.ok()
+}
+
+async fn store_discovery(
+    record: &DiscoveryRecord<'_>,
+    source: DiscoverySource,
+    query: Option<&QueryData>,
+    tags: Vec<String>,
+    run_id: Option<i32>,
+    ctx: &Context,
+) -> io::Result<Option<ServerModel>> {
+    let status = record.status;
+    let extra_data = record.extra_data;
+    let ip = record.edition.key(record.ip, record.port);
+    let edition = record.edition.as_str();
+    let online = status.players.online as i32;
+    let max = status.players.max as i32;
+    let version_name = status.version.name.clone();
+    let protocol = status.version.protocol as i32;
+    let license = extra_data.map(|e| e.license);
+    let white_list = extra_data.and_then(|e| e.white_list);
+    let description = json!({
+        "payload": status.description
+    });
+    let discovery_source = source.as_str();
+    let query_map = query.and_then(|q| q.map.clone());
+    let query_game_type = query.and_then(|q| q.game_type.clone());
+    let query_game_id = query.and_then(|q| q.game_id.clone());
+    let query_host_port = query.and_then(|q| q.host_port);
+    let tps = extra_data.and_then(|e| e.tps);
+    let appeal_contact = extra_data.and_then(|e| e.appeal_contact.clone());
+    let motd_text = description_to_str(status.description.clone()).unwrap_or_default();
+    let queue_server = mentions_queue(&motd_text) || extra_data.is_some_and(|e| e.queue_hint);
+    let on_demand_host = OnDemandHost::detect(&version_name, &motd_text);
+    let lifecycle = on_demand_host.map(|_| Lifecycle::of_on_demand(&version_name, &motd_text));
+    let status_class =
+        classify_placeholder(&version_name, &motd_text, lifecycle).map(|c| c.as_str());
+    let raw_status = status.raw.clone();
+    let anomalous_response = status.anomalous;
+    let era = status.era.map(|era| era.as_str());
+    let favicon = status.favicon.clone();
+    let motd = description_to_legacy(status.description.clone()).ok();
+    let motd_plain = motd.as_deref().map(strip_formatting);
+
+    let mut mods = mods::from_status(status);
+    if let Some(extra_data) = extra_data {
+        mods.extend(mods::from_namespaces(&extra_data.registry_namespaces));
+    }
+    if let Some(query) = query {
+        mods.extend(mods::from_query(query));
+    }
+    let software = mods::detect_software(status, query, &mods);
+    let players: Vec<(String, String)> = status
+        .players
+        .sample
+        .iter()
+        .flatten()
+        .map(|p| (p.id.clone(), p.name.clone()))
+        .collect();
+
+    let server = ctx
+        .storage
+        .upsert_server(ServerInsert {
+            ip,
+            online,
+            max,
+            version_name,
+            protocol,
+            license,
+            white_list,
+            description,
+            discovery_source: discovery_source.to_string(),
+            run_id,
+            peak_online: online,
+            tags,
+            query_map,
+            query_game_type,
+            query_game_id,
+            query_host_port,
+            tps,
+            queue_server,
+            raw_status,
+            anomalous_response,
+            status_class: status_class.map(str::to_string),
+            on_demand_host: on_demand_host.map(|host| host.as_str().to_string()),
+            lifecycle: lifecycle.map(|lifecycle| lifecycle.as_str().to_string()),
+            favicon,
+            appeal_contact,
+            edition: edition.to_string(),
+            motd,
+            motd_plain,
+            software: software.map(str::to_string),
+            era: era.map(str::to_string),
+        })
+        .await?;
+
+    let Some(server) = server else {
+        return Ok(None);
+    };
+
+    mods::record(server.id, &mods, &server.version_name, ctx).await;
+
+    let now = Local::now().naive_local().with_nanosecond(0).unwrap();
+    ctx.storage
+        .record_players(
+            players
+                .into_iter()
+                .map(|(uuid, name)| PlayerInsert {
+                    uuid,
+                    name,
+                    server_id: server.id,
+                    last_seen: now,
+                })
+                .collect(),
+        )
+        .await;
+
+    Ok(Some(server))
+}
+
+async fn record_path(ctx: Arc<Context>, server_id: i32, ip: IpAddr) {
+    let Ok(Ok(path)) = timeout(Duration::from_secs(60), trace_path(&ip)).await else {
+        return;
+    };
+
+    ctx.storage.record_path(server_id, path).await;
+}
+
+async fn worker(ctx: Arc<Context>, stats: Arc<WorkerStats>, scanner: Arc<Scanner>) {
+    let source = scanner.source();
+
+    // Ranges and targets were chosen, random space is mostly empty
+    let pinger = ctx
+        .pinger
+        .as_ref()
+        .filter(|_| source == DiscoverySource::RandomScan);
+
+    while let Some(ip) = scanner.next() {
+        if let Some(pinger) = pinger {
+            if !pinger.ping(ip).await {
+                ctx.stats.error("NoEchoReply");
+                stats.active();
+                continue;
+            }
+        }
+        let ip = IpAddr::V4(ip);
+        // Over the cap, not even the handshake reaches it
+        if !etiquette::allowed(&ip.to_string()) {
+            ctx.stats.error("QuotaExceeded");
+            stats.active();
+            continue;
+        }
+        for (port, probe) in scanner.ports().probes() {
+            let answered = check_server(&ip, port, probe).await;
+            if let Some(connected) = answered.tcp {
+                probe_java(&ip, port, connected, source, &ctx, &stats).await;
+            }
+            if probe != Probe::Tcp {
+                probe_bedrock(&ip, port, answered.raknet, source, &ctx, &stats).await;
+            }
+        }
+    }
+}
+
+async fn probe_java(
+    ip: &IpAddr,
+    port: u16,
+    connected: io::Result<TcpStream>,
+    source: DiscoverySource,
+    ctx: &Arc<Context>,
+    stats: &WorkerStats,
+) {
+    ctx.stats.attempted();
+    stats.attempted();
+
+    let stream = match connected {
+        Ok(stream) => stream,
+        Err(e) => {
+            record_filtered(ip, filtered::is_filtered_error(e.kind()), ctx);
+            stats.active();
+            return;
+        }
+    };
+    let Ok(conn) = Connection::from_stream(stream) else {
+        stats.active();
+        return;
+    };
+
+    ctx.stats.reachable();
+    let result = timeout(
+        Duration::from_secs(10),
+        handle_valid_ip(conn, ip, port, source, ctx),
+    )
+    .await;
+    // A middlebox resetting accepted connections filters as well
+    record_filtered(
+        ip,
+        matches!(&result, Ok(Err(e)) if e.kind() == ErrorKind::ConnectionReset),
+        ctx,
+    );
+
+    stats.finished(matches!(result, Ok(Ok(()))));
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            let kind = format!("{:?}", e.kind());
+            if retry::is_transient(e.kind()) {
+                retry::enqueue(ip, port, source, &kind, ctx).await;
+            }
+            ctx.stats.error(&kind);
+        }
+        Err(_) => {
+            retry::enqueue(ip, port, source, "Timeout", ctx).await;
+            ctx.stats.error("Timeout");
+        }
+    }
+}
+
+fn record_filtered(ip: &IpAddr, filtered: bool, ctx: &Context) {
+    if let IpAddr::V4(ip) = ip {
+        ctx.filtered.record(*ip, filtered);
+    }
+}
+
+async fn probe_bedrock(
+    ip: &IpAddr,
+    port: u16,
+    pong: Option<Pong>,
+    source: DiscoverySource,
+    ctx: &Arc<Context>,
+    stats: &WorkerStats,
+) {
+    ctx.stats.attempted();
+    stats.attempted();
+    traffic::record_sent(RAKNET_PING_LEN);
+
+    // UDP has no handshake, silence is the usual answer
+    let Some(pong) = pong else {
+        stats.active();
+        return;
+    };
+    traffic::record_received(pong.packet.len());
+    let Ok(bedrock) = status_of_pong(&pong.packet) else {
+        stats.active();
+        return;
+    };
+    let latency = pong.latency;
+
+    ctx.stats.reachable();
+    let result = timeout(
+        Duration::from_secs(10),
+        handle_bedrock(ip, port, bedrock, latency, source, ctx),
+    )
+    .await;
+
+    stats.finished(matches!(result, Ok(Ok(()))));
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => ctx.stats.error(&format!("{:?}", e.kind())),
+        Err(_) => ctx.stats.error("Timeout"),
+    }
+}
+
+/// Reports workers that stopped finishing attempts. A worker in a quiet range
+/// still completes failed connects, so only a wedged one goes idle.
+async fn worker_watchdog(ctx: Arc<Context>, workers: Vec<Arc<WorkerStats>>, threshold: Duration) {
     loop {
-        let ip = IpAddr::V4(generate_random_ip());
+        tokio::time::sleep(Duration::from_secs(60)).await;
 
-        if check_server(&ip, 25565).await {
-            let _ = timeout(
-                Duration::from_secs(5),
-                handle_valid_ip(&ip, 25565, db.clone()),
-            )
-            .await;
+        let stuck: Vec<_> = workers
+            .iter()
+            .filter(|w| w.idle() >= threshold)
+            .map(|w| w.snapshot())
+            .collect();
+        if !stuck.is_empty() {
+            ctx.reporter.stuck_workers(&stuck);
         }
     }
 }
 
-async fn updater(db: Arc<Mutex<DatabaseWrapper>>) {
+async fn traffic_reporter(ctx: Arc<Context>, workers: Vec<Arc<Traffic>>) {
     loop {
-        println!("Updating...");
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        ctx.flush_traffic().await;
+        ctx.reporter.traffic(&traffic::RUN, &workers);
+    }
+}
+
+/// Refreshes every stored server every ten minutes. Sleeping servers of
+/// on-demand hosts only every `on_demand_recheck` seconds, as their host
+/// answers the same until a player starts them.
+async fn updater(ctx: Arc<Context>, interval: u64, on_demand_recheck: u64) {
+    let mut previous_pass: Option<NaiveDateTime> = None;
 
+    loop {
+        ctx.reporter.pass_started(Pass::Update);
+        let pass_started = Local::now().naive_local().with_nanosecond(0).unwrap();
+
+        let asleep_since = pass_started - chrono::Duration::seconds(on_demand_recheck as i64);
         let servers: Vec<ServerModel> = schema::servers::dsl::servers
             .select(ServerModel::as_select())
-            .load(&mut db.lock().await.conn)
-            .unwrap();
+            .load(&mut ctx.pool().get().await.conn)
+            .unwrap()
+            .into_iter()
+            .filter(|server| {
+                server.lifecycle.as_deref() != Some(Lifecycle::Sleeping.as_str())
+                    || server
+                        .lifecycle_checked_at
+                        .is_none_or(|at| at <= asleep_since)
+            })
+            .collect();
 
         let semaphore = Arc::new(Semaphore::new(50));
 
-        let handles: Vec<_> = servers
-            .into_iter()
-            .map(|value| {
-                let permit = semaphore.clone().acquire_owned();
-                let th_db = db.clone();
+        let mut handles = vec![];
+        for value in servers {
+            let permit = semaphore.clone().acquire_owned().await.unwrap();
+            let th_ctx = ctx.clone();
 
-                tokio::spawn(async move {
-                    let _permit = permit.await;
-                    update_server(value, th_db).await;
-                })
-            })
-            .collect();
+            handles.push(tokio::spawn(async move {
+                let _permit = permit;
+                update_server(value, &th_ctx, previous_pass).await;
+            }));
+        }
 
         for handle in handles {
             let _ = handle.await;
         }
 
-        println!("Updating: {}", "DONE".red());
-        tokio::time::sleep(Duration::from_secs(600)).await;
+        previous_pass = Some(pass_started);
+        ctx.reporter.pass_finished(Pass::Update);
+        let (servers, asns) = blocking::census(&mut ctx.pool().get().await.conn);
+        if servers > 0 {
+            ctx.reporter.blocked(servers, asns);
+        }
+        tokio::time::sleep(Duration::from_secs(interval)).await;
     }
 }
 
-async fn update_server(server: ServerModel, db: Arc<Mutex<DatabaseWrapper>>) {
-    let status = match timeout(Duration::from_secs(2), get_status(&server.ip, 25565)).await {
-        Ok(t) => match t {
-            Ok(b) => b,
-            Err(_) => return,
-        },
-        Err(_) => return,
+async fn record_lifecycle(server: &ServerModel, host: &str, lifecycle: Lifecycle, ctx: &Context) {
+    let now = Local::now().naive_local().with_nanosecond(0).unwrap();
+    let changed_at = if server.lifecycle.as_deref() == Some(lifecycle.as_str()) {
+        server.lifecycle_changed_at
+    } else {
+        Some(now)
     };
 
-    let server_update = ServerUpdate {
-        online: status.players.online as i32,
-        max: status.players.max as i32,
-        version_name: &status.version.name,
-        protocol: status.version.protocol as i32,
-        description: &json!({
-            "payload": status.description,
-        }),
+    ctx.storage
+        .record_lifecycle(
+            server.id,
+            LifecycleUpdate {
+                on_demand_host: host.to_string(),
+                lifecycle,
+                changed_at,
+                checked_at: now,
+            },
+        )
+        .await;
+}
+
+/// MOTD changes within a day from which a server counts as rotating its MOTD.
+const ROTATING_MOTD_CHANGES: i64 = 6;
+
+async fn update_server(server: ServerModel, ctx: &Context, previous_pass: Option<NaiveDateTime>) {
+    let (host, port) = server.address();
+    let edition = server.edition();
+    let mut bedrock_query = None;
+    let cached = match edition {
+        Edition::Java => ctx.status_cache.get(&host, port),
+        Edition::Bedrock => None,
+    };
+    let (status, latency) = match cached {
+        Some(cached) => cached,
+        None => {
+            if !etiquette::admit(&host) {
+                return;
+            }
+            let started = Instant::now();
+            let result = match edition {
+                Edition::Java if server.era.is_some() => {
+                    timeout(
+                        Duration::from_secs(10),
+                        legacy::get_status(&host, port, server.proxy_header()),
+                    )
+                    .await
+                }
+                Edition::Java => {
+                    timeout(
+                        Duration::from_secs(2),
+                        get_status(&host, port, server.proxy_header()),
+                    )
+                    .await
+                }
+                Edition::Bedrock => {
+                    timeout(Duration::from_secs(2), get_bedrock_status(&host, port))
+                        .await
+                        .map(|result| {
+                            result.map(|bedrock| {
+                                bedrock_query = Some(bedrock.to_query());
+                                bedrock.to_status()
+                            })
+                        })
+                }
+            };
+            match result {
+                Ok(Ok(b)) => {
+                    let latency = started.elapsed();
+                    if edition == Edition::Java {
+                        ctx.status_cache.insert(&host, port, &b, latency);
+                    }
+                    blocking::answered(&server, ctx).await;
+                    (b, latency)
+                }
+                _ => {
+                    if let Some(kind) = result
+                        .as_ref()
+                        .ok()
+                        .and_then(|r| r.as_ref().err())
+                        .and_then(|e| blocking::rejection(e.kind(), started.elapsed()))
+                    {
+                        blocking::rejected(&server, kind, ctx).await;
+                    }
+                    // Seen during the previous pass but not now
+                    if previous_pass.is_some_and(|p| server.last_seen >= p) {
+                        ctx.webhooks.fire(Event::WentOffline { ip: server.ip });
+                    }
+                    return;
+                }
+            }
+        }
     };
 
-    diesel::update(schema::servers::dsl::servers)
-        .filter(schema::servers::dsl::ip.eq(&server.ip))
-        .set((
-            server_update,
-            schema::servers::dsl::last_seen
-                .eq(Local::now().naive_local().with_nanosecond(0).unwrap()),
-        ))
-        .execute(&mut db.lock().await.conn)
-        .unwrap();
+    let motd_text = description_to_str(status.description.clone()).unwrap_or_default();
+    // Only placeholders name the host, so remember it
+    let on_demand_host = OnDemandHost::detect(&status.version.name, &motd_text)
+        .map(|host| host.as_str())
+        .or(server.on_demand_host.as_deref());
+    let lifecycle =
+        on_demand_host.map(|_| Lifecycle::of_on_demand(&status.version.name, &motd_text));
+    if let (Some(host), Some(lifecycle)) = (on_demand_host, lifecycle) {
+        record_lifecycle(&server, host, lifecycle, ctx).await;
+    }
+    let mut status_class = classify_placeholder(&status.version.name, &motd_text, lifecycle);
+    if let Some(class) = status_class.filter(|class| !class.is_live()) {
+        // The host answered for a server that is down; keep its last real
+        // status and leave it unseen
+        ctx.storage.record_status_class(server.id, class).await;
+        if previous_pass.is_some_and(|p| server.last_seen >= p) {
+            ctx.webhooks.fire(Event::WentOffline { ip: server.ip });
+        }
+        return;
+    }
+
+    for rule in ctx.alerts.evaluate(&AlertRecord {
+        server: &server,
+        status: &status,
+        latency,
+    }) {
+        ctx.webhooks.fire(Event::Alert {
+            ip: server.ip.clone(),
+            rule,
+            online: status.players.online,
+            max: status.players.max,
+            latency_ms: latency.as_millis() as u64,
+        });
+    }
+
+    let description = json!({
+        "payload": status.description,
+    });
+    let motd = description_to_legacy(status.description.clone()).ok();
+    let motd_plain = motd.as_deref().map(strip_formatting);
+    let now = Local::now().naive_local().with_nanosecond(0).unwrap();
+
+    let rotating = Some(StatusClass::RotatingMotd.as_str());
+    if status_class.is_none()
+        && (server.description != description || server.status_class.as_deref() == rotating)
+    {
+        let changes: i64 = schema::motd_history::dsl::motd_history
+            .filter(schema::motd_history::dsl::server_id.eq(server.id))
+            .filter(schema::motd_history::dsl::observed_at.gt(now - chrono::Duration::days(1)))
+            .count()
+            .get_result(&mut ctx.pool().get().await.conn)
+            .unwrap();
+        if changes >= ROTATING_MOTD_CHANGES {
+            status_class = Some(StatusClass::RotatingMotd);
+        }
+    }
+
+    let query = match edition {
+        Edition::Java if ctx.query_probes => get_query(&host, port).await.ok(),
+        Edition::Java => None,
+        Edition::Bedrock => bedrock_query,
+    };
+    let mut mods = mods::from_status(&status);
+    if let Some(query) = &query {
+        mods.extend(mods::from_query(query));
+    }
+
+    ctx.storage
+        .record_status(
+            server.id,
+            StatusUpdate {
+                online: status.players.online as i32,
+                max: status.players.max as i32,
+                version_name: status.version.name.clone(),
+                protocol: status.version.protocol as i32,
+                description: description.clone(),
+                motd,
+                motd_plain,
+                software: mods::detect_software(&status, query.as_ref(), &mods).map(str::to_string),
+                raw_status: status.raw.clone(),
+                anomalous: status.anomalous,
+                era: status.era.map(|era| era.as_str().to_string()),
+                status_class,
+                favicon: status.favicon.clone(),
+                query,
+                seen_at: now,
+            },
+        )
+        .await;
+
+    ctx.storage
+        .record_snapshot(PingInsert {
+            server_id: server.id,
+            online: status.players.online as i32,
+            max: status.players.max as i32,
+            version_name: status.version.name.clone(),
+            protocol: status.version.protocol as i32,
+            latency_ms: Some(latency.as_millis() as i32),
+            checked_at: now,
+        })
+        .await;
+    activity::record(server.id, status.players.online as i32, ctx).await;
+    mods::record(server.id, &mods, &status.version.name, ctx).await;
+
+    if server.description != description {
+        insert_into(schema::motd_history::dsl::motd_history)
+            .values(MotdHistoryInsert {
+                server_id: server.id,
+                old_value: &server.description,
+                new_value: &description,
+            })
+            .execute(&mut ctx.pool().get().await.conn)
+            .unwrap();
+    }
+    // A randomizer would fire this on every pass
+    if server.description != description && status_class.is_none() {
+        ctx.webhooks.fire(Event::MotdChanged {
+            ip: server.ip.clone(),
+            old_value: server.description.clone(),
+            new_value: description.clone(),
+        });
+    }
+
+    if server.version_name != status.version.name
+        || server.protocol != status.version.protocol as i32
+    {
+        insert_into(schema::version_history::dsl::version_history)
+            .values(VersionHistoryInsert {
+                server_id: server.id,
+                old_version_name: &server.version_name,
+                new_version_name: &status.version.name,
+                old_protocol: server.protocol,
+                new_protocol: status.version.protocol as i32,
+            })
+            .execute(&mut ctx.pool().get().await.conn)
+            .unwrap();
+
+        ctx.webhooks.fire(Event::VersionChanged {
+            ip: server.ip.clone(),
+            old_version_name: server.version_name.clone(),
+            new_version_name: status.version.name.clone(),
+            old_protocol: server.protocol,
+            new_protocol: status.version.protocol as i32,
+        });
+    }
+
+    ctx.storage
+        .record_players(
+            status
+                .players
+                .sample
+                .unwrap_or_default()
+                .into_iter()
+                .map(|player| PlayerInsert {
+                    uuid: player.id,
+                    name: player.name,
+                    server_id: server.id,
+                    last_seen: now,
+                })
+                .collect(),
+        )
+        .await;
+}
+
+/// Samples availability of every Java server between full status refreshes
+/// with a bare handshake every `interval` seconds.
+async fn liveness_updater(ctx: Arc<Context>, interval: u64, concurrency: usize) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+
+        // The host of an on-demand server accepts handshakes while it sleeps
+        let servers: Vec<ServerModel> = schema::servers::dsl::servers
+            .filter(schema::servers::dsl::edition.eq(Edition::Java.as_str()))
+            .filter(
+                schema::servers::dsl::lifecycle
+                    .is_null()
+                    .or(schema::servers::dsl::lifecycle.eq(Lifecycle::Running.as_str())),
+            )
+            .select(ServerModel::as_select())
+            .load(&mut ctx.pool().get().await.conn)
+            .unwrap();
+
+        let semaphore = Arc::new(Semaphore::new(concurrency));
+
+        let mut handles = vec![];
+        for value in servers {
+            let permit = semaphore.clone().acquire_owned().await.unwrap();
+            let th_ctx = ctx.clone();
+
+            handles.push(tokio::spawn(async move {
+                let _permit = permit;
+                check_liveness(&value, &th_ctx).await;
+            }));
+        }
+
+        for handle in handles {
+            let _ = handle.await;
+        }
+    }
+}
 
-    for player in status.players.sample.unwrap_or_default() {
-        let player_model = PlayerInsert {
-            uuid: &player.id,
-            name: &player.name,
+async fn check_liveness(server: &ServerModel, ctx: &Context) {
+    let (host, port) = server.address();
+    if !etiquette::admit(&host) {
+        return;
+    }
+    let started = Instant::now();
+    let reachable = matches!(
+        timeout(
+            Duration::from_secs(2),
+            check_alive(&host, port, server.proxy_header())
+        )
+        .await,
+        Ok(Ok(()))
+    );
+
+    ctx.writers
+        .liveness(LivenessInsert {
             server_id: server.id,
+            reachable,
+            latency_ms: reachable.then(|| started.elapsed().as_millis() as i32),
+            checked_at: Local::now().naive_local().with_nanosecond(0).unwrap(),
+        })
+        .await;
+}
+
+async fn access_updater(ctx: Arc<Context>, interval: u64, concurrency: usize, unknown_only: bool) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        ctx.reporter.pass_started(Pass::LicenseRecheck);
+
+        let mut query = schema::servers::dsl::servers
+            .select(ServerModel::as_select())
+            .into_boxed();
+        if unknown_only {
+            query = query.filter(schema::servers::dsl::license.is_null());
+        }
+        let servers: Vec<ServerModel> = query.load(&mut ctx.pool().get().await.conn).unwrap();
+
+        let semaphore = Arc::new(Semaphore::new(concurrency));
+
+        let mut handles = vec![];
+        for value in servers {
+            let permit = semaphore.clone().acquire_owned().await.unwrap();
+            let th_ctx = ctx.clone();
+
+            handles.push(tokio::spawn(async move {
+                let _permit = permit;
+                recheck_access(&value, value.protocol, &th_ctx).await;
+            }));
+        }
+
+        for handle in handles {
+            let _ = handle.await;
+        }
+
+        ctx.reporter.pass_finished(Pass::LicenseRecheck);
+    }
+}
+
+async fn recheck_access(server: &ServerModel, protocol: i32, ctx: &Context) {
+    // Bedrock has no login probe
+    if server.edition() == Edition::Bedrock {
+        return;
+    }
+
+    let (host, port) = server.address();
+    if overrides::for_host(&host).no_login || !etiquette::admit(&host) {
+        return;
+    }
+    let extra_data = match ctx
+        .logins
+        .run(timeout(
+            Duration::from_secs(10),
+            get_extra_data(host, port, protocol, server.proxy_header()),
+        ))
+        .await
+    {
+        Ok(Ok(t)) => t,
+        _ => return,
+    };
+
+    blocking::login(server, extra_data.banned, ctx).await;
+
+    let motd = description_to_str(server.description["payload"].clone()).unwrap_or_default();
+    let queue_server = mentions_queue(&motd) || extra_data.queue_hint;
+
+    ctx.storage
+        .record_access(
+            server.id,
+            Access {
+                license: extra_data.license,
+                white_list: extra_data.white_list,
+                tps: extra_data.tps,
+                queue_server,
+                appeal_contact: extra_data.appeal_contact.clone(),
+            },
+        )
+        .await;
+
+    if Some(extra_data.license) != server.license {
+        ctx.webhooks.fire(Event::LicenseToggled {
+            ip: server.ip.clone(),
+            license: extra_data.license,
+        });
+    }
+    if extra_data.white_list != server.white_list {
+        ctx.webhooks.fire(Event::WhiteListToggled {
+            ip: server.ip.clone(),
+            white_list: extra_data.white_list,
+        });
+    }
+}
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Minecraft server search engine",
+    args_conflicts_with_subcommands = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+impl Cli {
+    fn scan_args(&self) -> Option<&ScanArgs> {
+        match &self.command {
+            Some(Command::Scan(args)) => Some(args.as_ref()),
+            None => Some(&self.scan),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Args)]
+struct ScanArgs {
+    /// Only request the status, skipping the login probe. License and whitelist
+    /// are left unknown and backfilled by a background pass
+    #[arg(long, env = "FAST_MODE")]
+    fast: bool,
+    /// Contact every server as little as possible: one status ping per port
+    /// and day, no login, query or extra handshakes, daily revisits
+    #[arg(long, env = "HONEYPOT_SAFE")]
+    honeypot_safe: bool,
+    /// Retry the handshake of new servers with candidate hostnames from the
+    /// wordlist and reverse DNS, recording the ones that answer
+    #[arg(long, env = "VHOST_SCAN")]
+    vhost_scan: bool,
+    /// File with hostname prefixes (mc, play, ...) or full hostnames, one per line
+    #[arg(long, env = "VHOST_WORDLIST")]
+    vhost_wordlist: Option<String>,
+    /// Send a PROXY protocol header before every handshake
+    #[arg(long, env = "PROXY_PROTOCOL")]
+    proxy_protocol: Option<ProxyVersion>,
+    /// Ping addresses of a random scan first and skip the ones that don't
+    /// answer, cheap in sparse space but missing hosts that drop ICMP
+    #[arg(long, env = "ICMP_PRECHECK")]
+    icmp_precheck: bool,
+    /// How long to wait for the echo reply of `--icmp-precheck`
+    #[arg(long, env = "ICMP_TIMEOUT_MS", default_value_t = 1000)]
+    icmp_timeout_ms: u64,
+    /// Traceroute new servers and store the hop count and last-hop network
+    #[arg(long, env = "TRACE_PATH")]
+    trace_path: bool,
+    /// Scan these CIDR ranges or addresses instead of random ones
+    #[arg(
+        long,
+        env = "SCAN_CIDRS",
+        value_delimiter = ',',
+        conflicts_with = "sweep"
+    )]
+    cidr: Vec<String>,
+    /// File of target addresses or CIDR ranges, one per line
+    #[arg(long, env = "SCAN_TARGETS", conflicts_with = "sweep")]
+    targets: Option<String>,
+    /// Walk the whole IPv4 space once in a scattered order, resuming from the
+    /// stored checkpoint after a restart
+    #[arg(long, env = "SCAN_SWEEP")]
+    sweep: bool,
+    /// Seed of the address order of random scans and new sweeps, random by
+    /// default; stored in the run record
+    #[arg(long, env = "SCAN_SEED")]
+    seed: Option<u32>,
+    /// Skip this many addresses of the order, as stored in the `position` of
+    /// an earlier run with the same seed and targets, to continue it
+    #[arg(long, env = "SCAN_OFFSET", default_value_t = 0)]
+    offset: u64,
+    /// Probe only this share of the addresses of `--cidr`, `--targets` or
+    /// `--sweep`, e.g. `0.001`, and extrapolate the server count in the
+    /// summary
+    #[arg(long, env = "SCAN_SAMPLE", value_parser = scanner::parse_sample)]
+    sample: Option<f64>,
+    /// File of addresses or CIDR ranges never to probe, on top of the
+    /// reserved ranges
+    #[arg(long, env = "SCAN_EXCLUDE")]
+    exclude: Option<String>,
+    /// Java Edition ports to try on every address, e.g. `25565-25577`
+    #[arg(
+        long,
+        env = "SCAN_PORTS",
+        value_delimiter = ',',
+        default_value = "25565"
+    )]
+    ports: Vec<String>,
+    /// Also send a Bedrock Edition ping to these UDP ports, e.g. `19132`
+    #[arg(long, env = "BEDROCK_PORTS", value_delimiter = ',')]
+    bedrock_ports: Vec<String>,
+    /// Fill in settings not given otherwise from `[profile.<name>]` of the
+    /// config file
+    #[arg(long, env = "SCAN_PROFILE")]
+    profile: Option<String>,
+    /// TOML file with scan profiles
+    #[arg(long, env = "MINE_SEARCH_CONFIG", default_value = "mine_search.toml")]
+    config: String,
+    /// Serve the read-only query API on this address while scanning, e.g.
+    /// `127.0.0.1:8080`
+    #[arg(long, env = "SERVE_ADDR")]
+    serve: Option<SocketAddr>,
+    /// Only probe, and forward discoveries to the `store` process listening
+    /// on this Unix socket
+    #[arg(long, env = "FORWARD_SOCKET", conflicts_with = "serve")]
+    forward: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Scan for new servers and keep known ones up to date (default)
+    Scan(Box<ScanArgs>),
+    /// Re-check stored servers matching a filter right away
+    Rescan {
+        /// Filter expression, e.g. "protocol >= 760 && license == false"
+        #[arg(long)]
+        filter: String,
+        /// Also re-run the login probe for license and whitelist
+        #[arg(long)]
+        license: bool,
+        #[arg(long, default_value_t = 50)]
+        concurrency: usize,
+    },
+    /// Resolve a list of known server domains, probe them and link them to
+    /// stored servers
+    Domains {
+        /// File with one domain per line
+        file: String,
+        #[arg(long, default_value_t = 20)]
+        concurrency: usize,
+    },
+    /// Measure probe throughput against a local mock server to tune THREADS
+    /// and timeouts for this machine
+    Bench {
+        /// Worker counts to try
+        #[arg(long, value_delimiter = ',', default_values_t = [50, 150, 500])]
+        workers: Vec<usize>,
+        /// Probe timeouts to try, in milliseconds
+        #[arg(long, value_delimiter = ',', default_values_t = [500, 2000])]
+        timeouts: Vec<u64>,
+        /// Seconds per combination
+        #[arg(long, default_value_t = 5)]
+        duration: u64,
+        /// Delay before the mock server answers, in milliseconds
+        #[arg(long, default_value_t = 0)]
+        latency: u64,
+    },
+    /// Write stored servers into a client servers.dat multiplayer list, or
+    /// dump them as JSON lines or CSV
+    Export {
+        /// Only export servers matching this filter
+        #[arg(long)]
+        filter: Option<String>,
+        /// Output file, `-` for standard output
+        #[arg(long, default_value = "servers.dat")]
+        out: String,
+        /// Output format, guessed from the extension of `--out` by default
+        #[arg(long)]
+        format: Option<ExportFormat>,
+        /// Comma-separated fields to write for JSON lines and CSV, e.g.
+        /// `addr,version,online,country`
+        #[arg(long)]
+        fields: Option<String>,
+    },
+    /// Probe the entries of a client servers.dat and link them to stored
+    /// servers under their saved names, or merge another instance's database
+    Import {
+        /// Path to servers.dat
+        #[arg(required_unless_present = "from")]
+        file: Option<String>,
+        /// Merge servers, players and history from this database URL instead
+        #[arg(long, conflicts_with = "file")]
+        from: Option<String>,
+        #[arg(long, default_value_t = 20)]
+        concurrency: usize,
+    },
+    /// Print everything stored about one server
+    Show {
+        /// Server address, `host[:port]`
+        address: String,
+        /// Emit one JSON document for other tools
+        #[arg(long)]
+        json: bool,
+        #[arg(long, default_value = "java", value_parser = commands::show::parse_edition)]
+        edition: Edition,
+        /// Entries per history list (pings, MOTD, version and whitelist changes)
+        #[arg(long, default_value_t = 100)]
+        history: i64,
+    },
+    /// Probe a single server continuously and print player joins and leaves
+    Watch {
+        /// Server address, `host[:port]`
+        address: String,
+        /// Time between probes, e.g. `30s`, `5m`
+        #[arg(long, default_value = "30s", value_parser = commands::watch::parse_duration)]
+        interval: Duration,
+    },
+    /// Create SQL views for Grafana's Postgres datasource and write a
+    /// provisioning dashboard using them
+    Grafana {
+        #[arg(long, default_value = "mine_search_dashboard.json")]
+        out: String,
+        /// UID of the Postgres datasource in Grafana
+        #[arg(long, default_value = "mine_search")]
+        datasource: String,
+    },
+    /// List the servers most active at an hour of the day (UTC)
+    Active {
+        #[arg(long, value_parser = clap::value_parser!(u32).range(0..24))]
+        hour: u32,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Link records of the same logical server seen on several addresses to a
+    /// canonical record
+    Mirrors,
+    /// Check the database, network, DNS and limits before a long scan
+    Doctor,
+    /// Downsample old status snapshots to the resolutions of
+    /// `SNAPSHOT_TIERS`
+    Compact,
+    /// Report server rows stored twice for the same address and port, and
+    /// merge them
+    Dedupe {
+        /// Merge the duplicates instead of only listing them
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Render a self-contained static HTML report of the stored servers
+    Report {
+        /// Directory to write index.html into
+        #[arg(long, default_value = "report")]
+        out: String,
+    },
+    /// Estimate the server population: total, version shares, license share
+    /// and countries, with 95% intervals for runs that probed a `--sample`
+    Census {
+        /// Servers answering during this run, from its stored summary,
+        /// instead of the stored servers
+        #[arg(long)]
+        run: Option<i32>,
+        /// Stored servers seen within this many days
+        #[arg(long, default_value_t = 30)]
+        since_days: i64,
+        /// Also write the census as JSON to this file
+        #[arg(long)]
+        out: Option<String>,
+    },
+    /// Run `[pipeline.<name>]` of the config file: import masscan output,
+    /// probe it, export and notify about the matches, resuming after the
+    /// last finished stage
+    Pipeline {
+        name: String,
+        /// TOML file with the pipeline
+        #[arg(long, env = "MINE_SEARCH_CONFIG", default_value = "mine_search.toml")]
+        config: String,
+        /// Start over instead of resuming an unfinished run
+        #[arg(long)]
+        restart: bool,
+    },
+    /// Write monthly availability, mean time between outages and the
+    /// longest outage per server from liveness checks and watch snapshots
+    Sla {
+        /// Month as `2025-01`, the last complete month by default
+        #[arg(long, value_parser = commands::sla::parse_month)]
+        month: Option<NaiveDate>,
+        /// Output file
+        #[arg(long, default_value = "sla.csv")]
+        out: String,
+        /// Output format, guessed from the extension of `--out` by default
+        #[arg(long)]
+        format: Option<SlaFormat>,
+    },
+    /// Run one enrichment stage (geoip, rdns or mojang) over servers stored
+    /// before it was enabled
+    Backfill {
+        #[arg(value_parser = commands::backfill::parse_stage)]
+        stage: Stage,
+        /// Also redo servers the stage already handled
+        #[arg(long)]
+        all: bool,
+        /// Lookups at once, the stage's enrichment default otherwise
+        #[arg(long)]
+        concurrency: Option<usize>,
+    },
+    /// Store and enrich the discoveries of scanners started with `--forward`
+    Store {
+        /// Unix socket to accept scanners on
+        #[arg(long, env = "STORE_SOCKET", default_value = "mine_search.sock")]
+        listen: String,
+        /// Serve the query API and run scan jobs on this address
+        #[arg(long, env = "SERVE_ADDR")]
+        serve: Option<SocketAddr>,
+        /// Retry the handshake of new servers with candidate hostnames from
+        /// the wordlist and reverse DNS
+        #[arg(long, env = "VHOST_SCAN")]
+        vhost_scan: bool,
+        /// File with hostname prefixes or full hostnames, one per line
+        #[arg(long, env = "VHOST_WORDLIST")]
+        vhost_wordlist: Option<String>,
+        /// Traceroute new servers and store the hop count and last-hop network
+        #[arg(long, env = "TRACE_PATH")]
+        trace_path: bool,
+    },
+}
+
+impl Context {
+    pub fn establish() -> Self {
+        let rules = PersistRules::from_env();
+        for rule in rules.describe() {
+            println!("Persist rule: {}", rule);
+        }
+
+        let alerts = AlertRules::from_env();
+        for rule in alerts.describe() {
+            println!("Alert rule: {}", rule);
+        }
+
+        for entry in overrides::describe() {
+            println!("Probe override: {}", entry);
+        }
+        println!("[+] Login probes as {}", identity::describe());
+        if let Some(cap) = etiquette::daily_cap() {
+            println!("[+] At most {} probes per server and day", cap);
+        }
+
+        let in_memory = match env::var("STORAGE").as_deref() {
+            Ok("memory") => true,
+            Ok("postgres") | Err(_) => false,
+            Ok(other) => panic!("Unknown STORAGE {}, expected postgres or memory", other),
+        };
+        let mut db = (!in_memory).then(DatabaseWrapper::establish);
+        let mut run_id = None;
+        if let Some(db) = &mut db {
+            println!("[+] Connection to database established");
+            etiquette::load(&mut db.conn);
+
+            let count: i64 = schema::servers::dsl::servers
+                .select(diesel::dsl::count(schema::servers::dsl::id))
+                .first(&mut db.conn)
+                .unwrap();
+            println!("Servers in db: {}", count);
+
+            let run: RunModel = insert_into(schema::runs::dsl::runs)
+                .default_values()
+                .returning(RunModel::as_returning())
+                .get_result(&mut db.conn)
+                .unwrap();
+            println!("Run id: {}", run.id);
+            run_id = Some(run.id);
+        }
+
+        let webhooks = Webhooks::from_env();
+        for route in webhooks.describe() {
+            println!("Webhook route: {}", route);
+        }
+
+        let plugins = Plugins::from_env();
+        if plugins.is_enabled() {
+            println!("[+] Plugin script loaded");
+        }
+
+        let filtered = match &mut db {
+            Some(db) => FilteredSpace::load(&mut db.conn),
+            None => FilteredSpace::empty(),
+        };
+        if filtered.is_enabled() {
+            println!(
+                "[+] Filtered space: {} prefixes deprioritized",
+                filtered.filtered_count()
+            );
+        }
+
+        // Enrichment updates database rows
+        let enrichment = match in_memory {
+            true => Pipeline::none(),
+            false => Pipeline::from_env(),
+        };
+        println!("Enrichment stages: {}", enrichment.describe());
+        let lookups = Lookups::from_env();
+        println!("Lookups: {}", lookups.describe());
+        let logins = Logins::from_env();
+        if let Some(limits) = logins.describe() {
+            println!("[+] Login probes: {}", limits);
+        }
+
+        drop(db);
+        let db = (!in_memory).then(|| Arc::new(Pool::from_env()));
+        // Shards connect with their first job, which only PgStorage sends
+        let writers = Arc::new(Writers::from_env());
+        if let Some(db) = &db {
+            if etiquette::daily_cap().is_some() {
+                tokio::spawn(etiquette::persist(db.clone()));
+            }
+            println!(
+                "Database pool: {} connections, {} writers, {}",
+                db.size(),
+                writers.count(),
+                writers.batching().describe()
+            );
+        }
+        let sinks = Sinks::from_env();
+        if let Some(sinks) = sinks.describe() {
+            println!("[+] Sinks: {}", sinks);
+        }
+        let replica = Pool::replica_from_env()
+            .filter(|_| !in_memory)
+            .map(Arc::new);
+        if let Some(replica) = &replica {
+            println!("[+] Read replica: {} connections", replica.size());
+        }
+        let storage: Box<dyn Storage> = match &db {
+            Some(db) => Box::new(PgStorage::new(
+                writers.clone(),
+                replica.clone().unwrap_or(db.clone()),
+            )),
+            None => {
+                let storage = MemoryStorage::new();
+                println!(
+                    "[+] Storage: {}, discoveries are lost when the process exits",
+                    storage.name()
+                );
+                Box::new(storage)
+            }
         };
 
-        insert_into(schema::players::dsl::players)
-            .values(&player_model)
-            .on_conflict((schema::players::dsl::name, schema::players::dsl::server_id))
-            .do_update()
-            .set(
-                schema::players::dsl::last_seen
+        Context {
+            db,
+            writers,
+            storage,
+            sinks,
+            webhooks,
+            rules,
+            alerts,
+            plugins,
+            reporter: reporter::from_env(),
+            run_id,
+            status_only: false,
+            vhosts: None,
+            trace_path: false,
+            stats: RunStats::new(),
+            // Both keep their rows in the database only
+            fingerprint_services: !in_memory
+                && env::var("FINGERPRINT_SERVICES")
+                    .map(|v| v != "false" && v != "0")
+                    .unwrap_or(true),
+            legacy_ladder: env::var("LEGACY_HANDSHAKES")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            detect_proxy_protocol: env::var("PROXY_PROTOCOL_DETECT")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            query_probes: env::var("QUERY_PROBES")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            retry_attempts: match in_memory {
+                true => 0,
+                false => env::var("RETRY_MAX_ATTEMPTS")
+                    .unwrap_or("5".to_string())
+                    .parse()
+                    .unwrap(),
+            },
+            status_cache: StatusCache::from_env(),
+            forge_handshake: env::var("FORGE_HANDSHAKE")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            geoip: Arc::new(GeoIp::from_env()),
+            filtered: Arc::new(filtered),
+            forwarder: None,
+            journal: None,
+            replica,
+            pinger: None,
+            enrichment,
+            lookups,
+            logins,
+        }
+    }
+
+    /// The pool for API reads: the replica if configured, the primary
+    /// otherwise.
+    pub fn reads(&self) -> &Pool {
+        self.replica.as_deref().unwrap_or(self.pool())
+    }
+
+    /// Connections to the primary, for passes and commands that only work
+    /// on the database.
+    pub fn pool(&self) -> &Pool {
+        self.db
+            .as_deref()
+            .expect("STORAGE=memory has no database, this needs STORAGE=postgres")
+    }
+
+    /// Reports the run summary and stores it with the finish time.
+    pub async fn finish_run(&self) {
+        if let Some(forwarder) = &self.forwarder {
+            if timeout(Duration::from_secs(10), forwarder.flush())
+                .await
+                .is_err()
+            {
+                eprintln!("[!] Storage process unreachable, unsent discoveries lost");
+            }
+        }
+        self.storage.flush().await;
+        self.writers.flush().await;
+        self.sinks.flush().await;
+        let summary = self.stats.summary();
+        self.reporter.summary(&summary);
+
+        let (Some(db), Some(run_id)) = (&self.db, self.run_id) else {
+            return;
+        };
+        self.filtered.save(&mut db.get().await.conn);
+        etiquette::flush(&mut db.get().await.conn);
+        self.flush_traffic().await;
+        diesel::update(schema::runs::dsl::runs)
+            .filter(schema::runs::dsl::id.eq(run_id))
+            .set((
+                schema::runs::dsl::finished_at
                     .eq(Local::now().naive_local().with_nanosecond(0).unwrap()),
-            )
-            .execute(&mut db.lock().await.conn)
+                schema::runs::dsl::summary.eq(serde_json::to_value(&summary).unwrap()),
+            ))
+            .execute(&mut db.get().await.conn)
+            .unwrap();
+    }
+
+    /// Stores the traffic totals of this process in its run row.
+    pub async fn flush_traffic(&self) {
+        let (Some(db), Some(run_id)) = (&self.db, self.run_id) else {
+            return;
+        };
+        diesel::update(schema::runs::dsl::runs)
+            .filter(schema::runs::dsl::id.eq(run_id))
+            .set((
+                schema::runs::dsl::bytes_sent.eq(traffic::RUN.sent() as i64),
+                schema::runs::dsl::bytes_received.eq(traffic::RUN.received() as i64),
+            ))
+            .execute(&mut db.get().await.conn)
             .unwrap();
     }
 }
 
 #[tokio::main]
 async fn main() {
+    #[cfg(feature = "console")]
+    console_subscriber::init();
+
     colored::control::set_override(true);
 
+    match fd_limit::raise() {
+        Ok(limit) => println!("Open file limit: {}", limit),
+        Err(e) => eprintln!("[!] Could not raise the open file limit: {}", e),
+    }
+
+    let mut cli = Cli::parse();
+
+    // Profile and preset settings arrive as environment variables, so parse
+    // again
+    if let Some(args) = cli.scan_args() {
+        if let Some(profile) = &args.profile {
+            profiles::apply(&args.config, profile);
+            cli = Cli::parse();
+        }
+    }
+    if cli.scan_args().is_some_and(|args| args.honeypot_safe) {
+        profiles::honeypot_safe();
+        cli = Cli::parse();
+    }
+
+    match cli.command.unwrap_or(Command::Scan(Box::new(cli.scan))) {
+        Command::Scan(args) => scan(*args).await,
+        Command::Rescan {
+            filter,
+            license,
+            concurrency,
+        } => commands::rescan::run(&filter, license, concurrency).await,
+        Command::Domains { file, concurrency } => commands::domains::run(&file, concurrency).await,
+        Command::Bench {
+            workers,
+            timeouts,
+            duration,
+            latency,
+        } => commands::bench::run(&workers, &timeouts, duration, latency).await,
+        Command::Export {
+            filter,
+            out,
+            format,
+            fields,
+        } => commands::export::run(filter.as_deref(), &out, format, fields.as_deref()),
+        Command::Import {
+            file,
+            from,
+            concurrency,
+        } => match (file, from) {
+            (_, Some(url)) => commands::merge::run(&url),
+            (Some(file), None) => commands::import::run(&file, concurrency).await,
+            (None, None) => unreachable!("clap requires a file or --from"),
+        },
+        Command::Show {
+            address,
+            json,
+            edition,
+            history,
+        } => commands::show::run(&address, edition, json, history).await,
+        Command::Watch { address, interval } => commands::watch::run(&address, interval).await,
+        Command::Grafana { out, datasource } => commands::grafana::run(&out, &datasource),
+        Command::Active { hour, limit } => commands::active::run(hour, limit),
+        Command::Mirrors => commands::mirrors::run(),
+        Command::Doctor => commands::doctor::run().await,
+        Command::Compact => commands::compact::run(),
+        Command::Dedupe { apply } => commands::dedupe::run(apply),
+        Command::Report { out } => commands::report::run(&out),
+        Command::Census {
+            run,
+            since_days,
+            out,
+        } => commands::census::run(run, since_days, out.as_deref()),
+        Command::Pipeline {
+            name,
+            config,
+            restart,
+        } => commands::pipeline::run(&config, &name, restart).await,
+        Command::Sla { month, out, format } => commands::sla::run(month, &out, format),
+        Command::Backfill {
+            stage,
+            all,
+            concurrency,
+        } => commands::backfill::run(stage, all, concurrency).await,
+        Command::Store {
+            listen,
+            serve,
+            vhost_scan,
+            vhost_wordlist,
+            trace_path,
+        } => {
+            commands::store::run(
+                &listen,
+                serve,
+                vhost_scan,
+                vhost_wordlist.as_deref(),
+                trace_path,
+            )
+            .await
+        }
+    }
+}
+
+/// Starts the passes keeping stored servers fresh: the updater, license and
+/// liveness re-checks and compaction, and with `fast` the backfill of skipped
+/// login probes. Returns the updater.
+pub fn start_updaters(ctx: &Arc<Context>, fast: bool) -> JoinHandle<()> {
+    let license_recheck_interval: u64 = env::var("LICENSE_RECHECK_INTERVAL")
+        .unwrap_or("21600".to_string())
+        .parse()
+        .unwrap();
+    let license_recheck_concurrency: usize = env::var("LICENSE_RECHECK_CONCURRENCY")
+        .unwrap_or("10".to_string())
+        .parse()
+        .unwrap();
+    let liveness_interval: u64 = env::var("LIVENESS_INTERVAL")
+        .unwrap_or("60".to_string())
+        .parse()
+        .unwrap();
+    let liveness_concurrency: usize = env::var("LIVENESS_CONCURRENCY")
+        .unwrap_or("200".to_string())
+        .parse()
+        .unwrap();
+
+    let compact_interval: u64 = env::var("COMPACT_INTERVAL")
+        .unwrap_or("0".to_string())
+        .parse()
+        .unwrap();
+
+    let update_interval: u64 = env::var("UPDATE_INTERVAL")
+        .unwrap_or("600".to_string())
+        .parse()
+        .unwrap();
+
+    let on_demand_recheck: u64 = env::var("ON_DEMAND_RECHECK_SECS")
+        .unwrap_or("3600".to_string())
+        .parse()
+        .unwrap();
+
+    let updater_thread = tokio::spawn(updater(ctx.clone(), update_interval, on_demand_recheck));
+    if license_recheck_interval > 0 {
+        tokio::spawn(access_updater(
+            ctx.clone(),
+            license_recheck_interval,
+            license_recheck_concurrency,
+            false,
+        ));
+    }
+    if liveness_interval > 0 {
+        tokio::spawn(liveness_updater(
+            ctx.clone(),
+            liveness_interval,
+            liveness_concurrency,
+        ));
+    }
+    if compact_interval > 0 {
+        tokio::spawn(compaction::compactor(ctx.clone(), compact_interval));
+    }
+    if fast && license_recheck_interval > 0 {
+        tokio::spawn(access_updater(
+            ctx.clone(),
+            600,
+            license_recheck_concurrency,
+            true,
+        ));
+    }
+    updater_thread
+}
+
+async fn scan(args: ScanArgs) {
+    // Random scans are a sample of the address space already
+    if args.sample.is_some() && args.cidr.is_empty() && args.targets.is_none() && !args.sweep {
+        Cli::command()
+            .error(
+                ClapErrorKind::MissingRequiredArgument,
+                "--sample needs --cidr, --targets or --sweep to sample from",
+            )
+            .exit();
+    }
+    let ports = Ports::parse(&args.ports, &args.bedrock_ports);
+    if args.honeypot_safe {
+        let extra = [
+            (args.vhost_scan, "--vhost-scan"),
+            (args.trace_path, "--trace-path"),
+            (args.icmp_precheck, "--icmp-precheck"),
+            (args.proxy_protocol.is_some(), "--proxy-protocol"),
+            (
+                ports.java.len() + ports.bedrock.len() > 1,
+                "more than one port",
+            ),
+        ];
+        if let Some((_, what)) = extra.iter().find(|(given, _)| *given) {
+            Cli::command()
+                .error(
+                    ClapErrorKind::ArgumentConflict,
+                    format!("--honeypot-safe can't be combined with {}", what),
+                )
+                .exit();
+        }
+    }
+
     let now = Local::now();
     let time_string = now.format("%Y-%m-%d %H:%M:%S").to_string();
 
     println!("[{}] Minecarft Lookup | Started", time_string);
 
-    let threads: i32 = env::var("THREADS")
+    let mut threads: i32 = env::var("THREADS")
         .unwrap_or("150".to_string())
         .parse()
         .unwrap();
+    // Workers past the open file limit only see their connects fail
+    if let Ok((soft, _)) = fd_limit::current() {
+        let max = fd_limit::max_threads(soft);
+        if threads as u64 > max {
+            eprintln!(
+                "[!] Open file limit {} fits ~{} threads, lowering THREADS from {}; raise it with `ulimit -Hn {}`",
+                soft,
+                max,
+                threads,
+                fd_limit::needed(threads as u64)
+            );
+            threads = max as i32;
+        }
+    }
 
     println!("Threads: {}", threads);
 
-    let db = Arc::new(Mutex::new(DatabaseWrapper::establish()));
-    println!("[+] Connection to database established");
+    let job_concurrency: usize = env::var("JOB_CONCURRENCY")
+        .unwrap_or("64".to_string())
+        .parse()
+        .unwrap();
 
-    let count: i64 = schema::servers::dsl::servers
-        .select(diesel::dsl::count(schema::servers::dsl::id))
-        .first(&mut db.lock().await.conn)
+    let stuck_worker_secs: u64 = env::var("STUCK_WORKER_SECS")
+        .unwrap_or("180".to_string())
+        .parse()
         .unwrap();
-    println!("Servers in db: {}", count);
 
-    let updater_thread = tokio::spawn(updater(db.clone()));
+    let mut ctx = Context::establish();
+    ctx.status_only = args.fast;
+    if args.fast {
+        println!("[+] Fast mode: skipping login probes");
+    }
+    if args.vhost_scan {
+        ctx.vhosts = Some(VhostScanner::load(args.vhost_wordlist.as_deref()));
+        println!("[+] Virtual host scanning enabled");
+    }
+    ctx.trace_path = args.trace_path;
+    if args.icmp_precheck {
+        match Pinger::open(Duration::from_millis(args.icmp_timeout_ms)) {
+            Ok(pinger) => {
+                ctx.pinger = Some(pinger);
+                println!("[+] Pinging random addresses before probing them");
+            }
+            Err(e) => eprintln!("[!] No ICMP socket, probing without pinging first: {}", e),
+        }
+    }
+    if let Some(version) = args.proxy_protocol {
+        proxy_protocol::enable(version);
+        println!("[+] Sending PROXY protocol {:?} headers", version);
+    }
+    let seed: u32 = args.seed.unwrap_or_else(rand::random);
+    let scanner = Arc::new(
+        Scanner::new(
+            &args.cidr,
+            args.targets.as_deref(),
+            Order {
+                sweep: args.sweep,
+                seed,
+                offset: args.offset,
+                sample: args.sample,
+            },
+            args.exclude.as_deref(),
+            ports,
+            &ctx,
+            threads as u64,
+        )
+        .await,
+    );
+    match (args.seed, scanner.seed()) {
+        (Some(given), Some(stored)) if given != stored => Cli::command()
+            .error(
+                ClapErrorKind::ArgumentConflict,
+                format!(
+                    "--seed {} differs from the seed {} of the stored sweep, drop it to resume the sweep",
+                    given, stored
+                ),
+            )
+            .exit(),
+        (Some(_), None) => eprintln!("[!] The stored sweep has no recorded seed, ignoring --seed"),
+        _ => {}
+    }
+    match scanner.seed() {
+        Some(seed) => println!("[+] Address order seed: {}", seed),
+        None => println!("[+] Address order seed: unknown, resumed from an older checkpoint"),
+    }
+    if let (Some(db), Some(run_id)) = (&ctx.db, ctx.run_id) {
+        diesel::update(schema::runs::dsl::runs.find(run_id))
+            .set(schema::runs::dsl::seed.eq(scanner.seed().map(i64::from)))
+            .execute(&mut db.get().await.conn)
+            .unwrap();
+    }
+    match args.forward {
+        Some(path) => ctx.forwarder = Some(Forwarder::start(path)),
+        None => ctx.journal = Journal::from_env(),
+    }
+    let ctx = Arc::new(ctx);
+    tokio::spawn(journal::replay(ctx.clone()));
+
+    // Enrichment runs in the storage process when forwarding
+    if ctx.forwarder.is_none() {
+        enrich::start(&ctx);
+        tokio::spawn(enrich::run(ctx.clone()));
+    }
+    tokio::spawn(filtered::persist(ctx.clone()));
+    if let Some(addr) = args.serve {
+        tokio::spawn(serve::run(ctx.clone(), addr));
+        // Jobs are queued in the database
+        if ctx.db.is_some() {
+            tokio::spawn(jobs::run(
+                ctx.clone(),
+                Exclusions::new(args.exclude.as_deref()),
+                job_concurrency,
+            ));
+        }
+    }
+    // The storage process keeps the database fresh for forwarding scanners
+    let updater_thread = match (&ctx.forwarder, &ctx.db) {
+        (None, Some(_)) => Some(start_updaters(&ctx, args.fast)),
+        (None, None) => {
+            println!("[+] Storage: memory, stored servers are not re-checked");
+            None
+        }
+        (Some(_), _) => None,
+    };
     let mut workers = vec![];
+    let mut worker_traffic = vec![];
+    let mut worker_stats = vec![];
 
-    for _ in 0..threads {
-        workers.push(tokio::spawn(worker(db.clone())));
+    for id in 0..threads as usize {
+        let traffic = Arc::new(Traffic::new());
+        let stats = Arc::new(WorkerStats::new(id));
+        worker_traffic.push(traffic.clone());
+        worker_stats.push(stats.clone());
+        workers.push(tokio::spawn(
+            traffic::WORKER.scope(traffic, worker(ctx.clone(), stats, scanner.clone())),
+        ));
+    }
+    tokio::spawn(traffic_reporter(ctx.clone(), worker_traffic));
+    if ctx.retry_attempts > 0 {
+        tokio::spawn(retry::run(ctx.clone()));
+    }
+    if ctx.db.is_some() {
+        tokio::spawn(scanner::checkpoint(ctx.clone(), scanner.clone()));
     }
+    tokio::spawn(worker_watchdog(
+        ctx.clone(),
+        worker_stats,
+        Duration::from_secs(stuck_worker_secs),
+    ));
 
     println!("[+] All threads started");
 
-    for task in workers {
-        let _ = task.await;
+    tokio::select! {
+        _ = async {
+            for task in workers {
+                let _ = task.await;
+            }
+            // Targeted scans end once every address was probed
+            if scanner.is_finite() {
+                println!("[+] All targets probed");
+            }
+            // A served API keeps answering from data the updater keeps fresh
+            if let Some(updater_thread) = updater_thread {
+                if !scanner.is_finite() || args.serve.is_some() {
+                    let _ = updater_thread.await;
+                }
+            }
+        } => {}
+        _ = tokio::signal::ctrl_c() => {}
     }
 
-    updater_thread.await.unwrap();
+    scanner.save(&ctx).await;
+    if let Some(counts) = scanner.sample() {
+        ctx.stats.sampled(counts);
+    }
+    ctx.finish_run().await;
+    match scanner.seed() {
+        // The checkpoint keeps the position of a sweep
+        Some(seed) if args.sweep => println!(
+            "[+] Continue this sweep with --sweep --seed {}, from address {}",
+            seed,
+            scanner.position()
+        ),
+        None if args.sweep => println!("[+] Continue this sweep with --sweep"),
+        Some(seed) => println!(
+            "[+] Continue this scan with --seed {} --offset {}",
+            seed,
+            scanner.position()
+        ),
+        None => {}
+    }
 }
@@ -0,0 +1,102 @@
+//! Plain types of what the scanner finds, for consumers of its output (the
+//! NDJSON and Kafka sinks) that want to deserialize it without the database
+//! models.
+
+use std::net::IpAddr;
+
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Edition {
+    Java,
+    Bedrock,
+}
+
+/// Status response of a server, as the server list ping returns it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServerStatus {
+    #[serde(default)]
+    pub players: Players,
+    #[serde(default)]
+    pub version: Version,
+    /// Chat component of the MOTD
+    #[serde(default)]
+    pub description: Value,
+    /// PNG data URL of the server icon
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub favicon: Option<String>,
+    /// Mods and channels of Forge 1.13+ servers
+    #[serde(default, rename = "forgeData", skip_serializing_if = "Option::is_none")]
+    pub forge_data: Option<Value>,
+    /// Mod list of Forge 1.7 to 1.12 servers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modinfo: Option<Value>,
+    /// Protocol era of servers that only answered a pre-1.7 handshake:
+    /// `release`, `late_beta`, `beta` or `alpha`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub era: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Players {
+    #[serde(default)]
+    pub online: i64,
+    #[serde(default)]
+    pub max: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample: Option<Vec<Player>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Player {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Version {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub protocol: i64,
+}
+
+/// What the login probe learned about a Java server.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExtraData {
+    /// Whether the server authenticates players with Mojang
+    pub license: bool,
+    pub white_list: Option<bool>,
+    pub tps: Option<f32>,
+    /// Kicked with a message mentioning a queue
+    pub queue_hint: bool,
+    /// Non-vanilla namespaces in the registries sent during configuration
+    #[serde(default)]
+    pub registry_namespaces: Vec<String>,
+    /// Discord invite or website named in a whitelist or ban kick
+    pub appeal_contact: Option<String>,
+    /// Kicked as banned, most likely the scanner's address or probe name
+    #[serde(default)]
+    pub banned: bool,
+}
+
+/// A server that answered, with what was probed of it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Discovery {
+    pub time: DateTime<FixedOffset>,
+    pub ip: IpAddr,
+    pub port: u16,
+    pub edition: Edition,
+    /// ISO country code of the address, when known
+    pub country: Option<String>,
+    pub status: ServerStatus,
+    /// Missing when the login probe was skipped or failed
+    pub extra_data: Option<ExtraData>,
+    /// Time the status probe took
+    pub latency_ms: Option<u64>,
+    /// Whether it was stored, rather than only reported
+    pub stored: bool,
+}
@@ -0,0 +1,68 @@
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::{storage, Context};
+
+/// A well-known modpack, recognised by mods only it ships.
+#[derive(Deserialize)]
+struct Fingerprint {
+    name: String,
+    /// Minecraft version the pack is for, when releases differ by it
+    minecraft: Option<String>,
+    /// Mod ids that must all be present
+    required: Vec<String>,
+    /// Mods the server needs at least, for packs known by a common mod
+    #[serde(default)]
+    min_mods: usize,
+    /// Mod whose version is the pack version
+    version_from: Option<String>,
+}
+
+/// Fingerprints of `modpacks.json`, bundled into the binary.
+fn fingerprints() -> &'static [Fingerprint] {
+    static FINGERPRINTS: OnceLock<Vec<Fingerprint>> = OnceLock::new();
+
+    FINGERPRINTS.get_or_init(|| {
+        serde_json::from_str(include_str!("../modpacks.json")).expect("modpacks.json")
+    })
+}
+
+/// Name and version of the pack a mod list belongs to. The fingerprint with
+/// the most required mods wins, and one for the exact Minecraft version over
+/// the pack family.
+pub fn identify(
+    mods: &[(String, Option<String>)],
+    minecraft: &str,
+) -> Option<(String, Option<String>)> {
+    let has = |id: &str| mods.iter().any(|(mod_id, _)| mod_id == id);
+
+    let pack = fingerprints()
+        .iter()
+        .filter(|pack| pack.required.iter().all(|id| has(id)) && mods.len() >= pack.min_mods)
+        .filter(|pack| {
+            pack.minecraft
+                .as_deref()
+                .is_none_or(|version| minecraft.contains(version))
+        })
+        .max_by_key(|pack| (pack.required.len(), pack.minecraft.is_some()))?;
+
+    let version = pack.version_from.as_deref().and_then(|from| {
+        mods.iter()
+            .find(|(mod_id, _)| mod_id == from)
+            .and_then(|(_, version)| version.clone())
+    });
+    Some((pack.name.clone(), version))
+}
+
+/// Identifies the pack of a server from all the mods stored for it, which
+/// may have come in from the status and the handshake separately.
+pub async fn record(server_id: i32, stored: &[storage::Mod], minecraft: &str, ctx: &Context) {
+    let mods: Vec<(String, Option<String>)> = stored
+        .iter()
+        .map(|m| (m.mod_id.clone(), m.version.clone()))
+        .collect();
+    ctx.storage
+        .record_modpack(server_id, identify(&mods, minecraft))
+        .await;
+}
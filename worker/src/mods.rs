@@ -0,0 +1,209 @@
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use serde_json::Value;
+use tokio::time::timeout;
+
+use crate::{
+    database::ServerModel,
+    modpacks,
+    proxy_protocol::ProxyVersion,
+    schema,
+    server_actions::{forge::get_forge_mods, query::QueryData, without_connection::Status},
+    storage, Context,
+};
+
+/// Server software named in version strings, most specific first: forks
+/// mention what they are built on ("Purpur 1.20" runs Paper plugins).
+const BRANDS: &[(&str, &str)] = &[
+    ("velocity", "velocity"),
+    ("waterfall", "waterfall"),
+    ("bungeecord", "bungeecord"),
+    ("neoforge", "neoforge"),
+    ("forge", "forge"),
+    ("fabric", "fabric"),
+    ("quilt", "quilt"),
+    ("mohist", "mohist"),
+    ("arclight", "arclight"),
+    ("folia", "folia"),
+    ("purpur", "purpur"),
+    ("pufferfish", "pufferfish"),
+    ("paper", "paper"),
+    ("spigot", "spigot"),
+    ("craftbukkit", "bukkit"),
+    ("bukkit", "bukkit"),
+];
+
+#[derive(Debug, Clone)]
+pub struct Mod {
+    pub id: String,
+    pub version: Option<String>,
+    /// Where it was seen: `status`, `handshake`, `registry` or `query`
+    pub source: &'static str,
+}
+
+impl Mod {
+    fn new(id: &str, version: Option<&str>, source: &'static str) -> Self {
+        Self {
+            id: id.to_string(),
+            version: version.map(str::to_string),
+            source,
+        }
+    }
+}
+
+/// Mods listed in the status: `modinfo.modList` of Forge 1.7 to 1.12 and
+/// `forgeData.mods` of 1.13+. Servers with many mods send the 1.18+ list
+/// compressed in `forgeData.d` instead; those come from the handshake probe.
+pub fn from_status(status: &Status) -> Vec<Mod> {
+    let mut mods = vec![];
+
+    if let Some(Value::Array(list)) = status.modinfo.as_ref().map(|m| &m["modList"]) {
+        mods.extend(list.iter().filter_map(|m| {
+            Some(Mod::new(
+                m["modid"].as_str()?,
+                m["version"].as_str(),
+                "status",
+            ))
+        }));
+    }
+    if let Some(Value::Array(list)) = status.forge_data.as_ref().map(|f| &f["mods"]) {
+        mods.extend(list.iter().filter_map(|m| {
+            Some(Mod::new(
+                m["modId"].as_str()?,
+                m["modmarker"].as_str(),
+                "status",
+            ))
+        }));
+    }
+
+    mods
+}
+
+/// Non-vanilla registry namespaces seen during configuration are the ids of
+/// the mods that added them.
+pub fn from_namespaces(namespaces: &[String]) -> Vec<Mod> {
+    namespaces
+        .iter()
+        .map(|namespace| Mod::new(namespace, None, "registry"))
+        .collect()
+}
+
+/// Plugins from a query's `Software: Plugin 1.0; Other 2.1` list.
+pub fn from_query(query: &QueryData) -> Vec<Mod> {
+    let Some(plugins) = &query.plugins else {
+        return vec![];
+    };
+    let Some((_, list)) = plugins.split_once(':') else {
+        return vec![];
+    };
+
+    list.split(';')
+        .map(str::trim)
+        .filter(|plugin| !plugin.is_empty())
+        .map(|plugin| match plugin.rsplit_once(' ') {
+            Some((name, version)) => Mod::new(name, Some(version), "query"),
+            None => Mod::new(plugin, None, "query"),
+        })
+        .collect()
+}
+
+/// FML network version of a Forge 1.13+ server, needed for its handshake.
+pub fn fml_version(status: &Status) -> Option<i64> {
+    status.forge_data.as_ref()?["fmlNetworkVersion"].as_i64()
+}
+
+/// Best guess at the server software, from the version string, the query's
+/// software field and the mods seen.
+pub fn detect_software(
+    status: &Status,
+    query: Option<&QueryData>,
+    mods: &[Mod],
+) -> Option<&'static str> {
+    let names = [
+        Some(status.version.name.to_lowercase()),
+        query
+            .and_then(|q| q.plugins.as_deref())
+            .map(|p| p.split(':').next().unwrap_or_default().to_lowercase()),
+    ];
+    for name in names.iter().flatten() {
+        if let Some((_, brand)) = BRANDS.iter().find(|(needle, _)| name.contains(needle)) {
+            return Some(brand);
+        }
+    }
+
+    let has = |id: &str| mods.iter().any(|m| m.id == id);
+    if has("neoforge") {
+        return Some("neoforge");
+    }
+    if status.forge_data.is_some() || status.modinfo.is_some() || has("forge") {
+        return Some("forge");
+    }
+    if has("fabric") || has("fabricloader") || has("fabric-api") {
+        return Some("fabric");
+    }
+
+    // A bare version number and nothing else points at the vanilla jar
+    let version = &status.version.name;
+    (mods.is_empty()
+        && !version.is_empty()
+        && version.chars().all(|c| c.is_ascii_digit() || c == '.'))
+    .then_some("vanilla")
+}
+
+/// Stores the mods of a server, refreshing ones already known, and the
+/// modpack all of its stored mods identify on `minecraft`.
+pub async fn record(server_id: i32, mods: &[Mod], minecraft: &str, ctx: &Context) {
+    if mods.is_empty() {
+        return;
+    }
+
+    // One statement may not update the same row twice
+    let mut rows: Vec<storage::Mod> = vec![];
+    for m in mods {
+        if !rows.iter().any(|row| row.mod_id == m.id) {
+            rows.push(storage::Mod {
+                mod_id: m.id.clone(),
+                version: m.version.clone(),
+                source: m.source.to_string(),
+            });
+        }
+    }
+
+    let stored = ctx.storage.record_mods(server_id, rows).await;
+    modpacks::record(server_id, &stored, minecraft, ctx).await;
+}
+
+/// Runs the FML handshake against a Forge server and stores its full mod
+/// list, which the status only summarizes.
+pub async fn probe_handshake(
+    ctx: Arc<Context>,
+    server: &ServerModel,
+    ip: IpAddr,
+    port: u16,
+    fml_version: i64,
+    proxy: Option<ProxyVersion>,
+) {
+    let Ok(Ok(ids)) = timeout(
+        Duration::from_secs(10),
+        get_forge_mods(&ip.to_string(), port, server.protocol, fml_version, proxy),
+    )
+    .await
+    else {
+        return;
+    };
+
+    let mods: Vec<Mod> = ids
+        .iter()
+        .map(|id| Mod::new(id, None, "handshake"))
+        .collect();
+    record(server.id, &mods, &server.version_name, &ctx).await;
+
+    if !mods.is_empty() {
+        diesel::update(schema::servers::dsl::servers.find(server.id))
+            .filter(schema::servers::dsl::software.is_null())
+            .set(schema::servers::dsl::software.eq("forge"))
+            .execute(&mut ctx.pool().get().await.conn)
+            .unwrap();
+    }
+}
@@ -0,0 +1,381 @@
+use std::io::{self, Error, ErrorKind};
+
+use serde_json::{Map, Value};
+
+/// Deepest nesting accepted before the data is treated as hostile.
+const MAX_DEPTH: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(Vec<(String, Tag)>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    pub fn get(&self, key: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Converts to JSON the way text components map between both encodings.
+    pub fn to_json(&self) -> Value {
+        match self {
+            Tag::Byte(v) => Value::from(*v),
+            Tag::Short(v) => Value::from(*v),
+            Tag::Int(v) => Value::from(*v),
+            Tag::Long(v) => Value::from(*v),
+            Tag::Float(v) => Value::from(*v),
+            Tag::Double(v) => Value::from(*v),
+            Tag::ByteArray(v) => v.iter().map(|b| Value::from(*b)).collect(),
+            Tag::String(v) => Value::from(v.as_str()),
+            Tag::List(v) => v.iter().map(Tag::to_json).collect(),
+            Tag::Compound(entries) => Value::Object(
+                entries
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.to_json()))
+                    .collect::<Map<_, _>>(),
+            ),
+            Tag::IntArray(v) => v.iter().map(|i| Value::from(*i)).collect(),
+            Tag::LongArray(v) => v.iter().map(|l| Value::from(*l)).collect(),
+        }
+    }
+
+    fn kind(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    fn write_payload(&self, out: &mut Vec<u8>) {
+        match self {
+            Tag::Byte(v) => out.extend_from_slice(&v.to_be_bytes()),
+            Tag::Short(v) => out.extend_from_slice(&v.to_be_bytes()),
+            Tag::Int(v) => out.extend_from_slice(&v.to_be_bytes()),
+            Tag::Long(v) => out.extend_from_slice(&v.to_be_bytes()),
+            Tag::Float(v) => out.extend_from_slice(&v.to_be_bytes()),
+            Tag::Double(v) => out.extend_from_slice(&v.to_be_bytes()),
+            Tag::ByteArray(v) => {
+                out.extend_from_slice(&(v.len() as i32).to_be_bytes());
+                out.extend(v.iter().map(|b| *b as u8));
+            }
+            Tag::String(v) => write_string(out, v),
+            Tag::List(items) => {
+                // Empty lists are written as lists of End tags, like vanilla does
+                out.push(items.first().map_or(0, Tag::kind));
+                out.extend_from_slice(&(items.len() as i32).to_be_bytes());
+                for item in items {
+                    item.write_payload(out);
+                }
+            }
+            Tag::Compound(entries) => {
+                for (name, value) in entries {
+                    out.push(value.kind());
+                    write_string(out, name);
+                    value.write_payload(out);
+                }
+                out.push(0);
+            }
+            Tag::IntArray(v) => {
+                out.extend_from_slice(&(v.len() as i32).to_be_bytes());
+                for i in v {
+                    out.extend_from_slice(&i.to_be_bytes());
+                }
+            }
+            Tag::LongArray(v) => {
+                out.extend_from_slice(&(v.len() as i32).to_be_bytes());
+                for l in v {
+                    out.extend_from_slice(&l.to_be_bytes());
+                }
+            }
+        }
+    }
+}
+
+/// Writes `tag` as a named root in the file encoding used by `servers.dat`.
+pub fn write(tag: &Tag, name: &str) -> Vec<u8> {
+    let mut out = vec![tag.kind()];
+    write_string(&mut out, name);
+    tag.write_payload(&mut out);
+    out
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    let bytes = value.as_bytes();
+    let len = bytes.len().min(u16::MAX as usize);
+    out.extend_from_slice(&(len as u16).to_be_bytes());
+    out.extend_from_slice(&bytes[..len]);
+}
+
+/// Reads one root tag and returns it with the number of bytes consumed.
+/// `network` is the 1.20.2+ encoding where the root has no name; it may also
+/// be a bare string instead of a compound there.
+pub fn read(data: &[u8], network: bool) -> io::Result<(Tag, usize)> {
+    let mut reader = Reader { data, pos: 0 };
+
+    let kind = reader.u8()?;
+    if !network {
+        reader.string()?;
+    }
+    let tag = reader.payload(kind, 0)?;
+
+    Ok((tag, reader.pos))
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> io::Result<&[u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "NBT data ends early"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn length(&mut self) -> io::Result<usize> {
+        let len = i32::from_be_bytes(self.array()?);
+        // Every element takes at least a byte, so the rest of the data bounds it
+        if len < 0 || len as usize > self.data.len() - self.pos {
+            return Err(Error::new(ErrorKind::InvalidData, "bad NBT length"));
+        }
+        Ok(len as usize)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = u16::from_be_bytes(self.array()?) as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).to_string())
+    }
+
+    fn payload(&mut self, kind: u8, depth: usize) -> io::Result<Tag> {
+        if depth > MAX_DEPTH {
+            return Err(Error::new(ErrorKind::InvalidData, "NBT nested too deep"));
+        }
+
+        Ok(match kind {
+            1 => Tag::Byte(i8::from_be_bytes(self.array()?)),
+            2 => Tag::Short(i16::from_be_bytes(self.array()?)),
+            3 => Tag::Int(i32::from_be_bytes(self.array()?)),
+            4 => Tag::Long(i64::from_be_bytes(self.array()?)),
+            5 => Tag::Float(f32::from_be_bytes(self.array()?)),
+            6 => Tag::Double(f64::from_be_bytes(self.array()?)),
+            7 => {
+                let len = self.length()?;
+                Tag::ByteArray(self.take(len)?.iter().map(|b| *b as i8).collect())
+            }
+            8 => Tag::String(self.string()?),
+            9 => {
+                let element = self.u8()?;
+                let len = self.length()?;
+                let mut items = Vec::with_capacity(len.min(1024));
+                for _ in 0..len {
+                    items.push(self.payload(element, depth + 1)?);
+                }
+                Tag::List(items)
+            }
+            10 => {
+                let mut entries = vec![];
+                loop {
+                    let kind = self.u8()?;
+                    if kind == 0 {
+                        break;
+                    }
+                    let name = self.string()?;
+                    entries.push((name, self.payload(kind, depth + 1)?));
+                }
+                Tag::Compound(entries)
+            }
+            11 => {
+                let len = self.length()?;
+                Tag::IntArray(
+                    self.take(len.saturating_mul(4))?
+                        .chunks_exact(4)
+                        .map(|c| i32::from_be_bytes(c.try_into().unwrap()))
+                        .collect(),
+                )
+            }
+            12 => {
+                let len = self.length()?;
+                Tag::LongArray(
+                    self.take(len.saturating_mul(8))?
+                        .chunks_exact(8)
+                        .map(|c| i64::from_be_bytes(c.try_into().unwrap()))
+                        .collect(),
+                )
+            }
+            _ => return Err(Error::new(ErrorKind::InvalidData, "unknown NBT tag")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Tag {
+        Tag::Compound(vec![
+            ("byte".to_string(), Tag::Byte(-3)),
+            ("short".to_string(), Tag::Short(300)),
+            ("int".to_string(), Tag::Int(-70000)),
+            ("long".to_string(), Tag::Long(1 << 40)),
+            ("float".to_string(), Tag::Float(1.5)),
+            ("double".to_string(), Tag::Double(-0.25)),
+            ("bytes".to_string(), Tag::ByteArray(vec![1, -1, 127])),
+            ("name".to_string(), Tag::String("Sérvèr".to_string())),
+            (
+                "servers".to_string(),
+                Tag::List(vec![
+                    Tag::Compound(vec![("ip".to_string(), Tag::String("1.2.3.4".to_string()))]),
+                    Tag::Compound(vec![]),
+                ]),
+            ),
+            ("empty".to_string(), Tag::List(vec![])),
+            (
+                "ints".to_string(),
+                Tag::IntArray(vec![i32::MIN, 0, i32::MAX]),
+            ),
+            (
+                "longs".to_string(),
+                Tag::LongArray(vec![i64::MIN, i64::MAX]),
+            ),
+        ])
+    }
+
+    /// Network encoding of a compound whose int array `a` claims `len`
+    /// elements, followed by `rest`.
+    fn array_of(len: i32, rest: &[u8]) -> Vec<u8> {
+        let mut data = vec![10, 11];
+        write_string(&mut data, "a");
+        data.extend_from_slice(&len.to_be_bytes());
+        data.extend_from_slice(rest);
+        data
+    }
+
+    #[test]
+    fn round_trips_through_the_file_encoding() {
+        let data = write(&sample(), "root");
+        let (tag, used) = read(&data, false).unwrap();
+        assert_eq!(tag, sample());
+        assert_eq!(used, data.len());
+    }
+
+    #[test]
+    fn reads_the_unnamed_network_root() {
+        let mut data = vec![10];
+        sample().write_payload(&mut data);
+        // A second tag after the first is left for the caller
+        data.extend_from_slice(&[8, 0, 0]);
+        let (tag, used) = read(&data, true).unwrap();
+        assert_eq!(tag, sample());
+        assert_eq!(used, data.len() - 3);
+        assert_eq!(
+            read(&data[used..], true).unwrap().0,
+            Tag::String(String::new())
+        );
+    }
+
+    #[test]
+    fn refuses_truncated_input() {
+        let data = write(&sample(), "root");
+        // Cut inside an array its length no longer fits the data, the rest ends early
+        for end in 0..data.len() {
+            let e = read(&data[..end], false).unwrap_err();
+            assert!(
+                matches!(e.kind(), ErrorKind::UnexpectedEof | ErrorKind::InvalidData),
+                "cut at {}: {}",
+                end,
+                e
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_bad_lengths() {
+        let negative = array_of(-1, &[0]);
+        assert_eq!(
+            read(&negative, true).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+
+        // Longer than the rest of the data could hold
+        let oversized = array_of(i32::MAX, &[0; 16]);
+        assert_eq!(
+            read(&oversized, true).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+
+        let mut list = vec![9, 1];
+        list.extend_from_slice(&(-5i32).to_be_bytes());
+        assert_eq!(
+            read(&list, true).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+
+        // Within the data, but each element takes four bytes
+        let short = array_of(3, &[0; 8]);
+        assert_eq!(
+            read(&short, true).unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn refuses_nesting_past_max_depth() {
+        let nested = |depth: usize| {
+            let mut tag = Tag::Int(1);
+            for _ in 0..depth {
+                tag = Tag::List(vec![tag]);
+            }
+            write(&tag, "")
+        };
+
+        assert!(read(&nested(MAX_DEPTH), false).is_ok());
+        let e = read(&nested(MAX_DEPTH + 1), false).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert_eq!(e.to_string(), "NBT nested too deep");
+    }
+
+    #[test]
+    fn refuses_unknown_tags() {
+        assert_eq!(
+            read(&[13, 0], true).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+}
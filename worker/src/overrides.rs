@@ -0,0 +1,129 @@
+use std::{env, net::IpAddr, sync::OnceLock};
+
+use clap::ValueEnum;
+
+use crate::{proxy_protocol::ProxyVersion, scanner::parse_cidr};
+
+/// Probe settings forced for one address or range instead of the defaults.
+#[derive(Default)]
+pub struct ProbeOptions {
+    /// Protocol version of the status and login handshakes
+    pub protocol: Option<i32>,
+    /// Server address sent in the handshakes instead of the IP
+    pub hostname: Option<String>,
+    /// PROXY header sent on every connection
+    pub proxy: Option<ProxyVersion>,
+    /// Skip the login probe, for hosts that ban or hang on joins
+    pub no_login: bool,
+}
+
+enum Target {
+    V4((u32, u32)),
+    Exact(IpAddr),
+}
+
+impl Target {
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self, ip) {
+            (Target::V4((start, end)), IpAddr::V4(v4)) => (*start..=*end).contains(&u32::from(v4)),
+            (Target::Exact(exact), ip) => *exact == ip,
+            _ => false,
+        }
+    }
+}
+
+struct Entry {
+    target: Target,
+    options: ProbeOptions,
+    text: String,
+}
+
+static DEFAULT: ProbeOptions = ProbeOptions {
+    protocol: None,
+    hostname: None,
+    proxy: None,
+    no_login: false,
+};
+
+/// Entries of `PROBE_OVERRIDES`, separated by `;`: an address or IPv4 CIDR
+/// range, `=`, and comma-separated options `protocol:<n>`, `hostname:<name>`,
+/// `proxy:v1|v2` and `no_login`, e.g.
+/// `203.0.113.7=protocol:47;198.51.100.0/24=proxy:v2,no_login`.
+fn entries() -> &'static [Entry] {
+    static ENTRIES: OnceLock<Vec<Entry>> = OnceLock::new();
+
+    ENTRIES.get_or_init(|| {
+        let value = env::var("PROBE_OVERRIDES").unwrap_or_default();
+        value
+            .split(';')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                parse_entry(entry.trim())
+                    .unwrap_or_else(|e| panic!("Invalid PROBE_OVERRIDES entry {:?}: {}", entry, e))
+            })
+            .collect()
+    })
+}
+
+fn parse_entry(entry: &str) -> Result<Entry, String> {
+    let (target, options) = entry
+        .split_once('=')
+        .ok_or_else(|| "expected <address or range>=<options>".to_string())?;
+    let target = match target.trim().parse::<IpAddr>() {
+        Ok(IpAddr::V4(v4)) => Target::V4((u32::from(v4), u32::from(v4))),
+        Ok(ip) => Target::Exact(ip),
+        Err(_) => Target::V4(parse_cidr(target)?),
+    };
+
+    let mut parsed = ProbeOptions::default();
+    for option in options.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+        match option.split_once(':') {
+            Some(("protocol", n)) => {
+                parsed.protocol = Some(n.trim().parse().map_err(|_| "bad protocol version")?)
+            }
+            Some(("hostname", name)) => parsed.hostname = Some(name.trim().to_string()),
+            Some(("proxy", version)) => {
+                parsed.proxy = Some(ProxyVersion::from_str(version.trim(), true)?)
+            }
+            None if option == "no_login" => parsed.no_login = true,
+            _ => return Err(format!("unknown option {}", option)),
+        }
+    }
+
+    Ok(Entry {
+        target,
+        options: parsed,
+        text: entry.to_string(),
+    })
+}
+
+pub fn describe() -> Vec<&'static str> {
+    entries().iter().map(|entry| entry.text.as_str()).collect()
+}
+
+/// Options of the first entry covering `ip`, the defaults when none does.
+pub fn lookup(ip: IpAddr) -> &'static ProbeOptions {
+    // Dual-stack sockets report IPv4 peers as mapped addresses
+    let ip = match ip {
+        IpAddr::V6(v6) => v6
+            .to_ipv4_mapped()
+            .map(IpAddr::V4)
+            .unwrap_or(IpAddr::V6(v6)),
+        v4 => v4,
+    };
+
+    entries()
+        .iter()
+        .find(|entry| entry.target.contains(ip))
+        .map(|entry| &entry.options)
+        .unwrap_or(&DEFAULT)
+}
+
+/// `lookup` for the host of a probe, which has no overrides when it is a
+/// name rather than an address.
+pub fn for_host(host: &str) -> &'static ProbeOptions {
+    match host.parse() {
+        Ok(ip) => lookup(ip),
+        Err(_) => &DEFAULT,
+    }
+}
@@ -1,10 +1,24 @@
 use std::io;
 
+use mine_search::RAKNET_MAGIC;
 use minecraft_protocol::{
     packet_builder::PacketBuilder, packet_reader::PacketReader, types::var_int::VarInt,
     UncompressedPacket,
 };
 
+use crate::{
+    conn_wrapper::{decode_varint, encode_varint},
+    identity::Identity,
+    protocol,
+};
+
+/// Longest string vanilla accepts, in characters. Longer status responses are
+/// still read, their frame is bounded, but mark the server as anomalous.
+pub const MAX_STRING_LENGTH: usize = 32767;
+/// Sample players kept from a status response; vanilla sends at most 12, the
+/// rest is dropped and marks the server as anomalous.
+pub const MAX_SAMPLE_PLAYERS: usize = 100;
+
 pub trait PacketActions {
     fn serialize(&self) -> UncompressedPacket;
     fn deserialize(packet: UncompressedPacket) -> io::Result<Self>
@@ -71,12 +85,23 @@ impl PacketActions for StatusResponse {
     }
 
     fn deserialize(packet: UncompressedPacket) -> io::Result<Self> {
-        let mut reader = PacketReader::new(&packet);
+        Ok(StatusResponse {
+            response: read_string(&packet.payload)?,
+        })
+    }
+}
 
-        let response = reader.read()?;
+/// Reads a string, refusing a length beyond the bytes actually received
+/// instead of allocating it.
+fn read_string(data: &[u8]) -> io::Result<String> {
+    let (length, read) = decode_varint(data)?;
+    let bytes = usize::try_from(length)
+        .ok()
+        .and_then(|length| data.get(read..read.checked_add(length)?))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bad string length"))?;
 
-        Ok(StatusResponse { response })
-    }
+    String::from_utf8(bytes.to_vec())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "string is not UTF-8"))
 }
 
 #[derive(Debug)]
@@ -143,3 +168,109 @@ impl PacketActions for SetCompression {
         Ok(SetCompression { threshold })
     }
 }
+
+/// Serverbound login packet switching to the configuration state (1.20.2+).
+pub const LOGIN_ACKNOWLEDGED_ID: i32 = 0x03;
+
+/// Serverbound "Client Information" of the configuration state, sent right
+/// after the login like a vanilla client with default options would.
+pub fn client_information(identity: &Identity, protocol: i32) -> Vec<u8> {
+    let mut data = vec![];
+    encode_varint(identity.locale.len() as u32, &mut data);
+    data.extend(identity.locale.as_bytes());
+    data.push(identity.view_distance as u8);
+    // Chat shown, with colours
+    data.extend([0x00, 0x01]);
+    // Every skin part, right hand
+    data.extend([0x7f, 0x01]);
+    // No text filtering, listed in the server's player sample
+    data.extend([0x00, 0x01]);
+    // Particles, added in 1.21.2
+    if protocol >= 768 {
+        data.push(0x00);
+    }
+    data
+}
+
+/// Packet ids of the configuration state introduced in 1.20.2.
+#[derive(Debug, Clone, Copy)]
+pub struct ConfigurationIds {
+    pub disconnect: i32,
+    pub finish: i32,
+    pub keep_alive: i32,
+    pub ping: i32,
+    pub registry_data: i32,
+    pub known_packs: Option<i32>,
+    pub client_information: i32,
+    pub finish_ack: i32,
+    pub keep_alive_reply: i32,
+    pub pong: i32,
+    pub known_packs_reply: Option<i32>,
+}
+
+/// Configuration ids of `protocol`, assuming versions newer than the known ones
+/// keep the latest layout.
+pub fn configuration_ids(protocol: i32) -> Option<ConfigurationIds> {
+    let protocol = protocol.min(protocol::LATEST);
+
+    Some(ConfigurationIds {
+        disconnect: protocol::configuration_disconnect_id(protocol)?,
+        finish: protocol::configuration_finish_id(protocol)?,
+        keep_alive: protocol::configuration_keep_alive_id(protocol)?,
+        ping: protocol::configuration_ping_id(protocol)?,
+        registry_data: protocol::configuration_registry_data_id(protocol)?,
+        known_packs: protocol::configuration_known_packs_id(protocol),
+        client_information: protocol::configuration_settings_id(protocol)?,
+        finish_ack: protocol::configuration_finish_ack_id(protocol)?,
+        keep_alive_reply: protocol::configuration_keep_alive_reply_id(protocol)?,
+        pong: protocol::configuration_pong_id(protocol)?,
+        known_packs_reply: protocol::configuration_known_packs_reply_id(protocol),
+    })
+}
+
+/// Clientbound play "Update Time" and chunk data packet ids. Versions from
+/// 1.20.2 on only reach play after the configuration phase.
+pub use crate::protocol::{chunk_data_id, time_update_id};
+
+/// RakNet unconnected ping, answered by Bedrock servers with their status.
+/// Plain UDP datagrams, so not a `PacketActions` packet.
+#[derive(Debug)]
+pub struct UnconnectedPing {
+    pub time: i64,
+    pub client_guid: i64,
+}
+
+impl UnconnectedPing {
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![0x01];
+        data.extend(self.time.to_be_bytes());
+        data.extend(RAKNET_MAGIC);
+        data.extend(self.client_guid.to_be_bytes());
+        data
+    }
+}
+
+#[derive(Debug)]
+pub struct UnconnectedPong {
+    /// Echo of the ping's time
+    pub time: i64,
+    /// `;`-separated server info, e.g. `MCPE;motd;protocol;version;online;max;...`
+    pub payload: String,
+}
+
+impl UnconnectedPong {
+    pub fn deserialize(data: &[u8]) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not an unconnected pong");
+        if data.len() < 35 || data[0] != 0x1C || data[17..33] != RAKNET_MAGIC {
+            return Err(invalid());
+        }
+
+        let len = u16::from_be_bytes([data[33], data[34]]) as usize;
+        let payload = data.get(35..35 + len).ok_or_else(invalid)?;
+
+        Ok(Self {
+            time: i64::from_be_bytes(data[1..9].try_into().unwrap()),
+            payload: String::from_utf8_lossy(payload).to_string(),
+        })
+    }
+}
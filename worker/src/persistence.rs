@@ -0,0 +1,185 @@
+use std::{env, net::IpAddr};
+
+use mine_search::{
+    description_to_str,
+    filter::{FieldValue, Filter, ParseError, Record},
+};
+
+use crate::{
+    database::Edition,
+    server_actions::{with_connection::ExtraData, without_connection::Status},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PersistAction {
+    Store,
+    Log,
+    Drop,
+}
+
+impl PersistAction {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "store" => Some(PersistAction::Store),
+            "log" => Some(PersistAction::Log),
+            "drop" => Some(PersistAction::Drop),
+            _ => None,
+        }
+    }
+}
+
+/// Ordered `action: filter` rules, the first matching rule decides what happens
+/// to a discovery. Servers matching no rule are stored.
+pub struct PersistRules {
+    rules: Vec<(PersistAction, Filter)>,
+}
+
+impl PersistRules {
+    pub fn from_env() -> Self {
+        let mut rules = vec![];
+
+        let min_players: i64 = env::var("MIN_PLAYERS")
+            .unwrap_or("0".to_string())
+            .parse()
+            .unwrap();
+        if min_players > 0 {
+            rules.push((
+                PersistAction::Drop,
+                Filter::parse(&format!("online < {}", min_players)).unwrap(),
+            ));
+        }
+
+        if let Ok(value) = env::var("PERSIST_RULES") {
+            rules.extend(
+                Self::parse(&value)
+                    .unwrap_or_else(|e| panic!("PERSIST_RULES: {}", e))
+                    .rules,
+            );
+        }
+
+        Self { rules }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, ParseError> {
+        let mut rules = vec![];
+
+        for rule in value.split(';').filter(|r| !r.trim().is_empty()) {
+            let (action, filter) = rule
+                .split_once(':')
+                .and_then(|(a, f)| Some((PersistAction::parse(a)?, f)))
+                .ok_or_else(|| ParseError::new(format!("expected 'action: filter' in {}", rule)))?;
+
+            let filter = Filter::parse(filter)?;
+            if let Some(unknown) = filter
+                .fields()
+                .into_iter()
+                .find(|field| !DISCOVERY_FIELDS.contains(field))
+            {
+                return Err(ParseError::new(format!(
+                    "unknown field {} in {}, expected one of {}",
+                    unknown,
+                    rule.trim(),
+                    DISCOVERY_FIELDS.join(", ")
+                )));
+            }
+            rules.push((action, filter));
+        }
+
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn describe(&self) -> Vec<String> {
+        self.rules
+            .iter()
+            .map(|(action, filter)| format!("{:?}: {}", action, filter))
+            .collect()
+    }
+
+    pub fn decide(&self, record: &DiscoveryRecord) -> PersistAction {
+        self.rules
+            .iter()
+            .find(|(_, filter)| filter.matches(record))
+            .map(|(action, _)| *action)
+            .unwrap_or(PersistAction::Store)
+    }
+
+    /// Whether a decision taken before the login probe is final.
+    pub fn decidable_without_extra_data(&self) -> bool {
+        self.rules.iter().all(|(_, filter)| {
+            filter
+                .fields()
+                .iter()
+                .all(|field| !matches!(*field, "license" | "white_list"))
+        })
+    }
+}
+
+pub const DISCOVERY_FIELDS: &[&str] = &[
+    "ip",
+    "port",
+    "edition",
+    "online",
+    "max",
+    "version",
+    "protocol",
+    "motd",
+    "license",
+    "white_list",
+    "country",
+];
+
+pub struct DiscoveryRecord<'a> {
+    pub ip: &'a IpAddr,
+    pub port: u16,
+    pub edition: Edition,
+    pub status: &'a Status,
+    pub extra_data: Option<&'a ExtraData>,
+    /// ISO code the GeoIP database places the address in
+    pub country: Option<&'a str>,
+}
+
+impl Record for DiscoveryRecord<'_> {
+    fn field(&self, name: &str) -> Option<FieldValue> {
+        Some(match name {
+            "ip" => self.ip.to_string().into(),
+            "port" => (self.port as i64).into(),
+            "edition" => self.edition.as_str().into(),
+            "online" => self.status.players.online.into(),
+            "max" => self.status.players.max.into(),
+            "version" => self.status.version.name.as_str().into(),
+            "protocol" => self.status.version.protocol.into(),
+            "motd" => description_to_str(self.status.description.clone())
+                .unwrap_or_default()
+                .into(),
+            "license" => self.extra_data.map(|e| e.license).into(),
+            "white_list" => self.extra_data.and_then(|e| e.white_list).into(),
+            "country" => self.country.into(),
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_take_discovery_fields() {
+        let rules = PersistRules::parse("drop: country == CN; log: version ~ \"1.8*\"").unwrap();
+        assert_eq!(rules.describe().len(), 2);
+    }
+
+    #[test]
+    fn rules_refuse_unknown_fields() {
+        let e = PersistRules::parse("drop: protocol < 47; store: onlnie > 5")
+            .err()
+            .unwrap();
+        assert!(e.to_string().contains("unknown field onlnie"), "{}", e);
+        // Stored server fields are not known before the server is stored
+        assert!(PersistRules::parse("drop: asn == 4134").is_err());
+    }
+}
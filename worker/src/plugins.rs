@@ -0,0 +1,204 @@
+use std::{env, path::PathBuf};
+
+use mine_search::filter::{FieldValue, Record};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+
+use crate::persistence::{DiscoveryRecord, DISCOVERY_FIELDS};
+
+/// Limits a hook runs within, so a runaway script costs one discovery its
+/// hook rather than stalling the worker running it.
+const MAX_OPERATIONS: u64 = 1_000_000;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_STRING_SIZE: usize = 64 * 1024;
+const MAX_ARRAY_SIZE: usize = 10_000;
+const MAX_MAP_SIZE: usize = 10_000;
+
+#[derive(Debug, Default)]
+pub struct PluginOutcome {
+    pub tags: Vec<String>,
+    pub store: Option<bool>,
+    pub notify: Option<String>,
+}
+
+/// Rhai script hook. The script defines `fn on_discovery(server)` which receives
+/// the discovery fields as a map and may return a map with `tags` (array of
+/// strings), `store` (bool) and `notify` (string).
+pub struct Plugins {
+    engine: Engine,
+    ast: Option<AST>,
+}
+
+impl Plugins {
+    pub fn from_env() -> Self {
+        let engine = engine();
+
+        let ast = env::var("PLUGIN_SCRIPT").ok().map(|path| {
+            engine
+                .compile_file(PathBuf::from(&path))
+                .unwrap_or_else(|e| panic!("Error loading {}: {}", path, e))
+        });
+
+        Self { engine, ast }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.ast.is_some()
+    }
+
+    pub fn on_discovery(&self, record: &DiscoveryRecord) -> PluginOutcome {
+        let Some(ast) = &self.ast else {
+            return PluginOutcome::default();
+        };
+
+        let mut server = Map::new();
+        for field in DISCOVERY_FIELDS {
+            let value = match record.field(field).unwrap_or(FieldValue::Null) {
+                FieldValue::Int(v) => Dynamic::from(v),
+                FieldValue::Str(v) => Dynamic::from(v),
+                FieldValue::Bool(v) => Dynamic::from(v),
+                FieldValue::Null => Dynamic::UNIT,
+            };
+            server.insert((*field).into(), value);
+        }
+
+        let result: Dynamic =
+            match self
+                .engine
+                .call_fn(&mut Scope::new(), ast, "on_discovery", (server,))
+            {
+                Ok(t) => t,
+                Err(e) if hit_limit(&e) => {
+                    eprintln!("[!] Plugin hook skipped for {}: {}", record.ip, e);
+                    return PluginOutcome::default();
+                }
+                Err(e) => {
+                    println!("[-] Plugin error: {}", e);
+                    return PluginOutcome::default();
+                }
+            };
+
+        let Some(map) = result.try_cast::<Map>() else {
+            return PluginOutcome::default();
+        };
+
+        PluginOutcome {
+            tags: map
+                .get("tags")
+                .and_then(|v| v.clone().try_cast::<Array>())
+                .map(|tags| {
+                    tags.into_iter()
+                        .filter_map(|t| t.into_string().ok())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            store: map.get("store").and_then(|v| v.as_bool().ok()),
+            notify: map.get("notify").and_then(|v| v.clone().into_string().ok()),
+        }
+    }
+}
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_ARRAY_SIZE)
+        .set_max_map_size(MAX_MAP_SIZE);
+    engine
+}
+
+/// Whether the script was stopped by one of the engine limits, in the hook
+/// or a function it called.
+fn hit_limit(e: &EvalAltResult) -> bool {
+    match e {
+        EvalAltResult::ErrorInFunctionCall(_, _, inner, _) => hit_limit(inner),
+        e => matches!(
+            e,
+            EvalAltResult::ErrorTooManyOperations(_)
+                | EvalAltResult::ErrorStackOverflow(_)
+                | EvalAltResult::ErrorDataTooLarge(..)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{database::Edition, server_actions::without_connection::Status};
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4));
+
+    fn run(script: &str) -> PluginOutcome {
+        let engine = engine();
+        let plugins = Plugins {
+            ast: Some(engine.compile(script).unwrap()),
+            engine,
+        };
+        let status: Status = serde_json::from_value(json!({
+            "players": {"online": 5, "max": 20},
+            "version": {"name": "1.21", "protocol": 767},
+        }))
+        .unwrap();
+        plugins.on_discovery(&DiscoveryRecord {
+            ip: &IP,
+            port: 25565,
+            edition: Edition::Java,
+            status: &status,
+            extra_data: None,
+            country: None,
+        })
+    }
+
+    #[test]
+    fn hooks_see_the_discovery() {
+        let outcome = run(r#"
+            fn on_discovery(server) {
+                #{ tags: [server.version, `online ${server.online}`], store: false }
+            }
+        "#);
+        assert_eq!(outcome.tags, ["1.21", "online 5"]);
+        assert_eq!(outcome.store, Some(false));
+    }
+
+    #[test]
+    fn hooks_past_a_limit_are_skipped() {
+        let endless = run("fn on_discovery(server) { loop {} }");
+        assert!(endless.tags.is_empty() && endless.store.is_none());
+
+        let deep = run(r#"
+            fn down(n) { down(n + 1) }
+            fn on_discovery(server) { down(0); #{ store: false } }
+        "#);
+        assert_eq!(deep.store, None);
+
+        let large = run(r#"
+            fn on_discovery(server) {
+                let s = "x";
+                loop { s += s; }
+            }
+        "#);
+        assert!(large.tags.is_empty());
+    }
+
+    #[test]
+    fn limits_are_reported_as_such() {
+        let engine = engine();
+        let ast = engine
+            .compile("fn down(n) { down(n + 1) } fn on_discovery(server) { down(0) }")
+            .unwrap();
+        let e = engine
+            .call_fn::<Dynamic>(&mut Scope::new(), &ast, "on_discovery", (Map::new(),))
+            .unwrap_err();
+        assert!(hit_limit(&e), "{}", e);
+
+        let e = engine
+            .call_fn::<Dynamic>(&mut Scope::new(), &ast, "missing", ())
+            .unwrap_err();
+        assert!(!hit_limit(&e));
+    }
+}
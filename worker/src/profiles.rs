@@ -0,0 +1,222 @@
+use std::{env, fs};
+
+/// Profile keys and the setting each one stands for. A profile only fills in
+/// settings that are neither passed as flags nor set in the environment.
+const KEYS: &[(&str, &str)] = &[
+    // What is probed
+    ("cidrs", "SCAN_CIDRS"),
+    ("targets", "SCAN_TARGETS"),
+    ("sweep", "SCAN_SWEEP"),
+    ("seed", "SCAN_SEED"),
+    ("sample", "SCAN_SAMPLE"),
+    ("exclude", "SCAN_EXCLUDE"),
+    ("ports", "SCAN_PORTS"),
+    ("bedrock_ports", "BEDROCK_PORTS"),
+    ("quotas", "SCAN_QUOTAS"),
+    // Pace and timeouts
+    ("threads", "THREADS"),
+    ("io_timeout_ms", "IO_TIMEOUT_MS"),
+    ("retry_attempts", "RETRY_MAX_ATTEMPTS"),
+    ("status_cache_ttl", "STATUS_CACHE_TTL"),
+    ("liveness_interval", "LIVENESS_INTERVAL"),
+    ("update_interval", "UPDATE_INTERVAL"),
+    ("login_concurrency", "LOGIN_CONCURRENCY"),
+    ("login_rate", "LOGIN_RATE"),
+    // Probe depth
+    ("fast", "FAST_MODE"),
+    ("honeypot_safe", "HONEYPOT_SAFE"),
+    ("query_probes", "QUERY_PROBES"),
+    ("vhost_scan", "VHOST_SCAN"),
+    ("vhost_wordlist", "VHOST_WORDLIST"),
+    ("trace_path", "TRACE_PATH"),
+    ("icmp_precheck", "ICMP_PRECHECK"),
+    ("icmp_timeout_ms", "ICMP_TIMEOUT_MS"),
+    ("forge_handshake", "FORGE_HANDSHAKE"),
+    ("fingerprint_services", "FINGERPRINT_SERVICES"),
+    ("legacy_handshakes", "LEGACY_HANDSHAKES"),
+    ("proxy_protocol", "PROXY_PROTOCOL"),
+    ("probe_daily_cap", "PROBE_DAILY_CAP"),
+    ("probe_usernames", "PROBE_USERNAMES"),
+    ("probe_locales", "PROBE_LOCALES"),
+    ("probe_view_distance", "PROBE_VIEW_DISTANCE"),
+    ("probe_overrides", "PROBE_OVERRIDES"),
+    // Where results go
+    ("reporter", "REPORTER"),
+    ("discovery_template", "DISCOVERY_TEMPLATE"),
+    ("persist_rules", "PERSIST_RULES"),
+    ("min_players", "MIN_PLAYERS"),
+    ("plugin_script", "PLUGIN_SCRIPT"),
+    ("webhook_urls", "WEBHOOK_URLS"),
+    ("webhook_events", "WEBHOOK_EVENTS"),
+    ("webhook_routes", "WEBHOOK_ROUTES"),
+    ("serve", "SERVE_ADDR"),
+];
+
+/// Settings of `--honeypot-safe`, for networks where every contact may be
+/// logged: no login, query or pre-1.7 handshakes, no retries or liveness
+/// pings, at most one probe a day per server and a daily updater pass.
+const HONEYPOT_SAFE: &[(&str, &str)] = &[
+    ("FAST_MODE", "true"),
+    ("LICENSE_RECHECK_INTERVAL", "0"),
+    ("FORGE_HANDSHAKE", "false"),
+    ("LEGACY_HANDSHAKES", "false"),
+    ("PROXY_PROTOCOL_DETECT", "false"),
+    ("QUERY_PROBES", "false"),
+    ("RETRY_MAX_ATTEMPTS", "0"),
+    ("LIVENESS_INTERVAL", "0"),
+    ("PROBE_DAILY_CAP", "1"),
+    ("UPDATE_INTERVAL", "86400"),
+    ("ON_DEMAND_RECHECK_SECS", "86400"),
+];
+
+/// Settings of `[profile.<name>]` in a TOML config file, as pairs of
+/// environment variable and value. Values are strings, numbers, booleans or
+/// arrays of them, which become comma-separated lists.
+fn read(path: &str, name: &str) -> Result<Vec<(&'static str, String)>, String> {
+    section(path, &format!("profile.{}", name))?
+        .into_iter()
+        .map(|(number, key, value)| {
+            let var = KEYS
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, var)| *var)
+                .ok_or_else(|| format!("{}:{}: unknown profile setting {:?}", path, number, key))?;
+            Ok((var, value))
+        })
+        .collect()
+}
+
+/// The `key = value` lines of section `[header]` of a TOML config file, with
+/// their line number, values parsed like those of profiles.
+pub fn section(path: &str, header: &str) -> Result<Vec<(usize, String, String)>, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+
+    let mut found = false;
+    let mut in_section = false;
+    let mut settings = vec![];
+    for (number, line) in content.lines().enumerate() {
+        let at = |message: String| format!("{}:{}: {}", path, number + 1, message);
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| at("unterminated section header".to_string()))?;
+            in_section = name.trim() == header;
+            found |= in_section;
+            continue;
+        }
+        if !in_section {
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| at(format!("expected `key = value`, got {:?}", line)))?;
+        let value = parse_value(value.trim()).map_err(at)?;
+        settings.push((number + 1, key.trim().to_string(), value));
+    }
+
+    if !found {
+        return Err(format!("{} has no [{}] section", path, header));
+    }
+    Ok(settings)
+}
+
+/// The line up to a `#` outside of quotes.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(value: &str) -> Result<String, String> {
+    if let Some(items) = value.strip_prefix('[') {
+        let items = items
+            .strip_suffix(']')
+            .ok_or_else(|| "arrays must be written on one line".to_string())?;
+        let items = split_items(items)
+            .into_iter()
+            .map(|item| parse_value(item.trim()))
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok(items.join(","));
+    }
+
+    for quote in ['"', '\''] {
+        if let Some(inner) = value.strip_prefix(quote) {
+            return inner
+                .strip_suffix(quote)
+                .map(|s| s.replace("\\\"", "\""))
+                .ok_or_else(|| format!("unterminated string {}", value));
+        }
+    }
+
+    let valid = value == "true"
+        || value == "false"
+        || value.replace('_', "").parse::<i64>().is_ok()
+        || value.parse::<f64>().is_ok();
+    if valid {
+        Ok(value.replace('_', ""))
+    } else {
+        Err(format!(
+            "{} is not a string, number, boolean or array",
+            value
+        ))
+    }
+}
+
+/// Splits array items on commas outside of quotes.
+fn split_items(items: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in items.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, ',') => {
+                parts.push(&items[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&items[start..]);
+    parts.retain(|part| !part.trim().is_empty());
+    parts
+}
+
+/// Applies a named profile from the config file to the environment, under
+/// the flags and variables already given.
+pub fn apply(path: &str, name: &str) {
+    let settings = read(path, name).unwrap_or_else(|e| panic!("Profile {}: {}", name, e));
+
+    for (var, value) in settings {
+        if env::var_os(var).is_none() {
+            env::set_var(var, value);
+        }
+    }
+    println!("[+] Using scan profile {} from {}", name, path);
+}
+
+/// Applies the honeypot-safe preset to the environment, over the variables
+/// and profile settings already given.
+pub fn honeypot_safe() {
+    for (var, value) in HONEYPOT_SAFE {
+        if env::var(var).is_ok_and(|v| v != *value) {
+            eprintln!("[!] --honeypot-safe overrides {}", var);
+        }
+        env::set_var(var, value);
+    }
+    eprintln!("[+] Honeypot-safe mode: one status ping per server and day, no login probes");
+}
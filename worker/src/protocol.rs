@@ -0,0 +1,5 @@
+//! Protocol numbers and the packet ids the scanner needs per game version,
+//! generated by `build.rs` from the minecraft-data extract in `protocol/`.
+//! Supporting a new version is a data update there, not an edit here.
+
+include!(concat!(env!("OUT_DIR"), "/protocol.rs"));
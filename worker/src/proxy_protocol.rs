@@ -0,0 +1,69 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::OnceLock,
+};
+
+use clap::ValueEnum;
+
+const V2_SIGNATURE: [u8; 12] = [
+    0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProxyVersion {
+    V1,
+    V2,
+}
+
+static ALWAYS: OnceLock<ProxyVersion> = OnceLock::new();
+
+/// Sends a PROXY header on every new connection from now on.
+pub fn enable(version: ProxyVersion) {
+    let _ = ALWAYS.set(version);
+}
+
+pub fn always() -> Option<ProxyVersion> {
+    ALWAYS.get().copied()
+}
+
+/// Header announcing `source` as the client of a connection to `destination`.
+pub fn header(version: ProxyVersion, source: SocketAddr, destination: SocketAddr) -> Vec<u8> {
+    let same_family = source.is_ipv4() == destination.is_ipv4();
+
+    match version {
+        ProxyVersion::V1 if !same_family => b"PROXY UNKNOWN\r\n".to_vec(),
+        ProxyVersion::V1 => format!(
+            "PROXY {} {} {} {} {}\r\n",
+            if source.is_ipv4() { "TCP4" } else { "TCP6" },
+            source.ip(),
+            destination.ip(),
+            source.port(),
+            destination.port()
+        )
+        .into_bytes(),
+        ProxyVersion::V2 => {
+            let mut header = V2_SIGNATURE.to_vec();
+            if !same_family {
+                // LOCAL command, no address block
+                header.extend([0x20, 0x00, 0x00, 0x00]);
+                return header;
+            }
+
+            let mut addresses = vec![];
+            for ip in [source.ip(), destination.ip()] {
+                match ip {
+                    IpAddr::V4(v4) => addresses.extend(v4.octets()),
+                    IpAddr::V6(v6) => addresses.extend(v6.octets()),
+                }
+            }
+            addresses.extend(source.port().to_be_bytes());
+            addresses.extend(destination.port().to_be_bytes());
+
+            header.push(0x21);
+            header.push(if source.is_ipv4() { 0x11 } else { 0x21 });
+            header.extend((addresses.len() as u16).to_be_bytes());
+            header.extend(addresses);
+            header
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    collections::HashMap,
+    env,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::geoip::GeoIp;
+
+const WINDOW: Duration = Duration::from_secs(3600);
+
+struct Window {
+    started: Instant,
+    used: HashMap<String, u32>,
+}
+
+/// Hourly probe limits per country or ASN from `SCAN_QUOTAS`, e.g.
+/// `CN=1000,RU=500,AS4134=200`. Addresses over a limit are skipped, not
+/// delayed, so a run stays within the limits whatever the scan mode.
+pub struct Quotas {
+    limits: HashMap<String, u32>,
+    geoip: Arc<GeoIp>,
+    window: Mutex<Window>,
+}
+
+impl Quotas {
+    pub fn from_env(geoip: Arc<GeoIp>) -> Option<Self> {
+        let value = env::var("SCAN_QUOTAS").ok()?;
+        let limits: HashMap<String, u32> = value
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                let (key, limit) = entry
+                    .split_once('=')
+                    .and_then(|(key, limit)| Some((key, limit.trim().parse().ok()?)))
+                    .unwrap_or_else(|| panic!("Invalid SCAN_QUOTAS entry {:?}", entry));
+                (key.trim().to_uppercase(), limit)
+            })
+            .collect();
+        if limits.is_empty() {
+            return None;
+        }
+
+        let needs_asn = limits.keys().any(|key| key.starts_with("AS"));
+        if !geoip.is_enabled() || (needs_asn && !geoip.has_asn()) {
+            panic!("SCAN_QUOTAS needs GEOIP_DATABASE, and GEOIP_ASN_DATABASE for ASN limits");
+        }
+
+        Some(Self {
+            limits,
+            geoip,
+            window: Mutex::new(Window {
+                started: Instant::now(),
+                used: HashMap::new(),
+            }),
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        let mut limits: Vec<String> = self
+            .limits
+            .iter()
+            .map(|(key, limit)| format!("{} {}/h", key, limit))
+            .collect();
+        limits.sort();
+        limits.join(", ")
+    }
+
+    /// Counts a probe of `ip` against its limits, or refuses it when one of
+    /// them is used up for this hour.
+    pub fn admit(&self, ip: IpAddr) -> bool {
+        let location = self.geoip.locate(ip);
+        let keys: Vec<String> = [location.country, location.asn.map(|n| format!("AS{}", n))]
+            .into_iter()
+            .flatten()
+            .filter(|key| self.limits.contains_key(key))
+            .collect();
+        if keys.is_empty() {
+            return true;
+        }
+
+        let mut window = self.window.lock().unwrap();
+        if window.started.elapsed() >= WINDOW {
+            window.started = Instant::now();
+            window.used.clear();
+        }
+
+        if keys
+            .iter()
+            .any(|key| window.used.get(key).copied().unwrap_or(0) >= self.limits[key])
+        {
+            return false;
+        }
+        for key in keys {
+            *window.used.entry(key).or_default() += 1;
+        }
+        true
+    }
+}
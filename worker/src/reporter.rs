@@ -0,0 +1,606 @@
+use std::{env, net::IpAddr, sync::Arc, time::Duration};
+
+use chrono::Local;
+use colored::Colorize;
+use mine_search::{description_to_str, model};
+use serde_json::{json, Value};
+
+use crate::{
+    database::Edition,
+    favicon::Graphics,
+    geoip::flag,
+    server_actions::{with_connection::ExtraData, without_connection::Status},
+    stats::{RunSummary, WorkerSnapshot},
+    traffic::{format_bytes, Traffic},
+};
+
+pub struct Discovery<'a> {
+    pub ip: &'a IpAddr,
+    pub port: u16,
+    pub edition: Edition,
+    pub status: &'a Status,
+    pub extra_data: Option<&'a ExtraData>,
+    pub stored: bool,
+    /// Time the status probe took
+    pub latency: Option<Duration>,
+    /// ISO country code of the address, when known
+    pub country: Option<&'a str>,
+}
+
+impl Discovery<'_> {
+    /// The discovery as one flat JSON object, as the `json` reporter prints
+    /// it.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "time": Local::now().to_rfc3339(),
+            "ip": self.ip.to_string(),
+            "port": self.port,
+            "edition": self.edition.as_str(),
+            "country": self.country,
+            "version_name": self.status.version.name,
+            "protocol": self.status.version.protocol,
+            "online": self.status.players.online,
+            "max": self.status.players.max,
+            "license": self.license(),
+            "white_list": self.extra_data.and_then(|e| e.white_list),
+            "registry_namespaces": self.extra_data.map(|e| &e.registry_namespaces),
+            "appeal_contact": self.extra_data.and_then(|e| e.appeal_contact.as_deref()),
+            "motd": self.motd(),
+            "stored": self.stored,
+        })
+    }
+
+    /// The public type of the discovery, what the sinks write.
+    pub fn to_model(&self) -> model::Discovery {
+        model::Discovery {
+            time: Local::now().fixed_offset(),
+            ip: *self.ip,
+            port: self.port,
+            edition: match self.edition {
+                Edition::Java => model::Edition::Java,
+                Edition::Bedrock => model::Edition::Bedrock,
+            },
+            country: self.country.map(str::to_string),
+            status: self.status.to_model(),
+            extra_data: self.extra_data.map(ExtraData::to_model),
+            latency_ms: self.latency.map(|latency| latency.as_millis() as u64),
+            stored: self.stored,
+        }
+    }
+
+    fn license(&self) -> Option<bool> {
+        self.extra_data.map(|e| e.license)
+    }
+
+    /// Address as stored, with the port when it is not the edition's default.
+    fn address(&self) -> String {
+        self.edition.key(self.ip, self.port)
+    }
+
+    fn motd(&self) -> String {
+        description_to_str(self.status.description.clone()).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Text(String),
+    Field(Field),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Addr,
+    Port,
+    Edition,
+    Version,
+    Protocol,
+    Players,
+    Online,
+    Max,
+    Motd,
+    License,
+    Country,
+    Latency,
+}
+
+/// Discovery line format from `DISCOVERY_TEMPLATE`, e.g.
+/// `{addr}:{port} {version} {players} {motd}`. `{{` and `}}` are literal braces.
+#[derive(Debug, Clone)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut segments = vec![];
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                    let field = match name.as_str() {
+                        "addr" => Field::Addr,
+                        "port" => Field::Port,
+                        "edition" => Field::Edition,
+                        "version" => Field::Version,
+                        "protocol" => Field::Protocol,
+                        "players" => Field::Players,
+                        "online" => Field::Online,
+                        "max" => Field::Max,
+                        "motd" => Field::Motd,
+                        "license" => Field::License,
+                        "country" => Field::Country,
+                        "latency" => Field::Latency,
+                        other => return Err(format!("unknown placeholder {{{}}}", other)),
+                    };
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Field(field));
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+
+        Ok(Self { segments })
+    }
+
+    fn from_env() -> Option<Self> {
+        let template = env::var("DISCOVERY_TEMPLATE").ok()?;
+        Some(Self::parse(&template).unwrap_or_else(|e| panic!("Invalid DISCOVERY_TEMPLATE: {}", e)))
+    }
+
+    pub fn render(&self, discovery: &Discovery) -> String {
+        let status = discovery.status;
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.clone(),
+                Segment::Field(field) => match field {
+                    Field::Addr => discovery.ip.to_string(),
+                    Field::Port => discovery.port.to_string(),
+                    Field::Edition => discovery.edition.as_str().to_string(),
+                    Field::Version => status.version.name.clone(),
+                    Field::Protocol => status.version.protocol.to_string(),
+                    Field::Players => format!("{}/{}", status.players.online, status.players.max),
+                    Field::Online => status.players.online.to_string(),
+                    Field::Max => status.players.max.to_string(),
+                    Field::Motd => discovery.motd(),
+                    Field::License => match discovery.license() {
+                        Some(true) => "yes",
+                        Some(false) => "no",
+                        None => "?",
+                    }
+                    .to_string(),
+                    Field::Country => discovery.country.unwrap_or("??").to_string(),
+                    Field::Latency => discovery
+                        .latency
+                        .map(|l| format!("{}ms", l.as_millis()))
+                        .unwrap_or("?".to_string()),
+                },
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Pass {
+    Update,
+    LicenseRecheck,
+    Rescan,
+    Domains,
+    Import,
+    Job,
+    Backfill,
+    Pipeline,
+}
+
+impl Pass {
+    fn label(&self) -> &'static str {
+        match self {
+            Pass::Update => "Updating",
+            Pass::LicenseRecheck => "Rechecking license",
+            Pass::Rescan => "Rescanning",
+            Pass::Domains => "Checking domains",
+            Pass::Import => "Importing saved servers",
+            Pass::Job => "Running scan job",
+            Pass::Backfill => "Backfilling enrichment",
+            Pass::Pipeline => "Probing pipeline targets",
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Pass::Update => "update",
+            Pass::LicenseRecheck => "license_recheck",
+            Pass::Rescan => "rescan",
+            Pass::Domains => "domains",
+            Pass::Import => "import",
+            Pass::Job => "job",
+            Pass::Backfill => "backfill",
+            Pass::Pipeline => "pipeline",
+        }
+    }
+}
+
+pub trait Reporter: Send + Sync {
+    fn discovery(&self, discovery: &Discovery);
+    fn pass_started(&self, pass: Pass);
+    fn pass_finished(&self, pass: Pass);
+    fn traffic(&self, run: &Traffic, workers: &[Arc<Traffic>]);
+    fn summary(&self, summary: &RunSummary);
+    /// Workers that have not finished an attempt for longer than the threshold
+    fn stuck_workers(&self, workers: &[WorkerSnapshot]);
+    /// Stored servers, and their ASNs, that seem to block the scanner
+    fn blocked(&self, servers: i64, asns: i64);
+}
+
+fn summary_lines(summary: &RunSummary) -> Vec<String> {
+    let mut lines = vec![
+        format!("Duration: {}s", summary.duration_secs),
+        format!(
+            "Attempted: {} | reachable: {} ({:.4}%)",
+            summary.attempted,
+            summary.reachable,
+            summary.hit_rate * 100.0
+        ),
+        format!(
+            "New servers: {} | already known: {}",
+            summary.new_servers, summary.known_servers
+        ),
+        format!(
+            "Traffic: sent {} received {}",
+            format_bytes(summary.bytes_sent),
+            format_bytes(summary.bytes_received)
+        ),
+    ];
+
+    if !summary.top_versions.is_empty() {
+        lines.push(format!(
+            "Top versions: {}",
+            summary
+                .top_versions
+                .iter()
+                .map(|v| format!("{} ({})", v.version, v.count))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    if let Some(sample) = &summary.sample {
+        lines.push(format!(
+            "Sample: {} of {} addresses ({}%), {} servers found, estimated {} in the range (95%: {}-{})",
+            sample.sampled,
+            sample.addresses,
+            sample.fraction * 100.0,
+            sample.servers_found,
+            sample.estimated_servers,
+            sample.estimated_low,
+            sample.estimated_high
+        ));
+        if !sample.estimated_versions.is_empty() {
+            lines.push(format!(
+                "Estimated versions: {}",
+                sample
+                    .estimated_versions
+                    .iter()
+                    .map(|v| format!("{} (~{})", v.version, v.count))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+    }
+
+    if !summary.errors.is_empty() {
+        let mut errors: Vec<_> = summary.errors.iter().collect();
+        errors.sort_by(|a, b| b.1.cmp(a.1));
+        lines.push(format!(
+            "Errors: {}",
+            errors
+                .iter()
+                .map(|(kind, count)| format!("{} ({})", kind, count))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    lines
+}
+
+fn stuck_line(worker: &WorkerSnapshot) -> String {
+    format!(
+        "Worker {} idle for {}s | attempts: {} | hits: {} | consecutive errors: {}",
+        worker.id, worker.idle_secs, worker.attempts, worker.hits, worker.consecutive_errors
+    )
+}
+
+fn worker_average(workers: &[Arc<Traffic>]) -> (u64, u64) {
+    let count = workers.len().max(1) as u64;
+    (
+        workers.iter().map(|w| w.sent()).sum::<u64>() / count,
+        workers.iter().map(|w| w.received()).sum::<u64>() / count,
+    )
+}
+
+pub fn from_env() -> Box<dyn Reporter> {
+    match env::var("REPORTER").as_deref() {
+        Ok("plain") => Box::new(PlainReporter {
+            template: Template::from_env(),
+        }),
+        Ok("json") => Box::new(JsonReporter),
+        Ok("silent") => Box::new(SilentReporter),
+        Ok("pretty") | Err(_) => Box::new(PrettyReporter {
+            template: Template::from_env(),
+            graphics: Graphics::from_env(),
+        }),
+        Ok(other) => panic!("Unknown REPORTER: {}", other),
+    }
+}
+
+fn timestamp() -> String {
+    Local::now().format("%H:%M:%S").to_string()
+}
+
+pub struct PrettyReporter {
+    pub template: Option<Template>,
+    /// Draws the favicon before the line in terminals with inline images
+    pub graphics: Option<Graphics>,
+}
+
+impl Reporter for PrettyReporter {
+    fn discovery(&self, discovery: &Discovery) {
+        if let Some(image) = self
+            .graphics
+            .zip(discovery.status.favicon.as_deref())
+            .and_then(|(graphics, favicon)| graphics.render(favicon))
+        {
+            print!("{} ", image);
+        }
+
+        if let Some(template) = &self.template {
+            println!("[{}] {}", timestamp(), template.render(discovery));
+            return;
+        }
+
+        let country = discovery
+            .country
+            .map(|code| format!(" {} {}", flag(code).unwrap_or_default(), code))
+            .unwrap_or_default();
+        println!(
+            "[{}] {} {}{} | 🛠  {} | 👥 {}/{} | {} | 🚀 {}",
+            timestamp(),
+            match discovery.edition {
+                Edition::Java => "🌐",
+                Edition::Bedrock => "📱",
+            },
+            discovery.address().blue(),
+            country,
+            discovery.status.version.name.yellow(),
+            discovery.status.players.online.to_string().green(),
+            discovery.status.players.max,
+            match discovery.license() {
+                Some(true) => "yes".red(),
+                Some(false) => "no".green(),
+                None => "?".normal(),
+            },
+            discovery.motd()
+        );
+    }
+
+    fn pass_started(&self, pass: Pass) {
+        println!("{}...", pass.label());
+    }
+
+    fn pass_finished(&self, pass: Pass) {
+        println!("{}: {}", pass.label(), "DONE".red());
+    }
+
+    fn traffic(&self, run: &Traffic, workers: &[Arc<Traffic>]) {
+        let (sent, received) = worker_average(workers);
+        println!(
+            "📶 Traffic: ↑ {} ↓ {} | per worker ↑ {} ↓ {}",
+            format_bytes(run.sent()).cyan(),
+            format_bytes(run.received()).cyan(),
+            format_bytes(sent),
+            format_bytes(received)
+        );
+    }
+
+    fn summary(&self, summary: &RunSummary) {
+        println!("{}", "Run summary".bold());
+        for line in summary_lines(summary) {
+            println!("  {}", line);
+        }
+    }
+
+    fn stuck_workers(&self, workers: &[WorkerSnapshot]) {
+        for worker in workers {
+            println!("⚠️  {}", stuck_line(worker).yellow());
+        }
+    }
+
+    fn blocked(&self, servers: i64, asns: i64) {
+        println!(
+            "⚠️  {}",
+            format!("We appear blocked by {} servers / {} ASNs", servers, asns).yellow()
+        );
+    }
+}
+
+pub struct PlainReporter {
+    pub template: Option<Template>,
+}
+
+impl Reporter for PlainReporter {
+    fn discovery(&self, discovery: &Discovery) {
+        if let Some(template) = &self.template {
+            println!("[{}] {}", timestamp(), template.render(discovery));
+            return;
+        }
+
+        println!(
+            "[{}] {} | {} | {} | {} | {}/{} | license: {} | {}",
+            timestamp(),
+            discovery.address(),
+            discovery.edition.as_str(),
+            discovery.country.unwrap_or("??"),
+            discovery.status.version.name,
+            discovery.status.players.online,
+            discovery.status.players.max,
+            match discovery.license() {
+                Some(true) => "yes",
+                Some(false) => "no",
+                None => "?",
+            },
+            discovery.motd()
+        );
+    }
+
+    fn pass_started(&self, pass: Pass) {
+        println!("[{}] {}...", timestamp(), pass.label());
+    }
+
+    fn pass_finished(&self, pass: Pass) {
+        println!("[{}] {}: DONE", timestamp(), pass.label());
+    }
+
+    fn traffic(&self, run: &Traffic, workers: &[Arc<Traffic>]) {
+        let (sent, received) = worker_average(workers);
+        println!(
+            "[{}] Traffic: sent {} received {} | per worker sent {} received {}",
+            timestamp(),
+            format_bytes(run.sent()),
+            format_bytes(run.received()),
+            format_bytes(sent),
+            format_bytes(received)
+        );
+    }
+
+    fn summary(&self, summary: &RunSummary) {
+        println!("[{}] Run summary", timestamp());
+        for line in summary_lines(summary) {
+            println!("[{}] {}", timestamp(), line);
+        }
+    }
+
+    fn stuck_workers(&self, workers: &[WorkerSnapshot]) {
+        for worker in workers {
+            println!("[{}] {}", timestamp(), stuck_line(worker));
+        }
+    }
+
+    fn blocked(&self, servers: i64, asns: i64) {
+        println!(
+            "[{}] We appear blocked by {} servers / {} ASNs",
+            timestamp(),
+            servers,
+            asns
+        );
+    }
+}
+
+pub struct JsonReporter;
+
+impl Reporter for JsonReporter {
+    fn discovery(&self, discovery: &Discovery) {
+        let mut event = discovery.to_json();
+        event["event"] = json!("discovery");
+        println!("{}", event);
+    }
+
+    fn pass_started(&self, pass: Pass) {
+        println!(
+            "{}",
+            json!({ "event": "pass_started", "time": Local::now().to_rfc3339(), "pass": pass.name() })
+        );
+    }
+
+    fn pass_finished(&self, pass: Pass) {
+        println!(
+            "{}",
+            json!({ "event": "pass_finished", "time": Local::now().to_rfc3339(), "pass": pass.name() })
+        );
+    }
+
+    fn traffic(&self, run: &Traffic, workers: &[Arc<Traffic>]) {
+        println!(
+            "{}",
+            json!({
+                "event": "traffic",
+                "time": Local::now().to_rfc3339(),
+                "bytes_sent": run.sent(),
+                "bytes_received": run.received(),
+                "workers": workers
+                    .iter()
+                    .map(|w| json!({ "bytes_sent": w.sent(), "bytes_received": w.received() }))
+                    .collect::<Vec<_>>(),
+            })
+        );
+    }
+
+    fn summary(&self, summary: &RunSummary) {
+        println!(
+            "{}",
+            json!({
+                "event": "summary",
+                "time": Local::now().to_rfc3339(),
+                "summary": summary,
+            })
+        );
+    }
+
+    fn stuck_workers(&self, workers: &[WorkerSnapshot]) {
+        println!(
+            "{}",
+            json!({
+                "event": "stuck_workers",
+                "time": Local::now().to_rfc3339(),
+                "workers": workers,
+            })
+        );
+    }
+
+    fn blocked(&self, servers: i64, asns: i64) {
+        println!(
+            "{}",
+            json!({
+                "event": "blocked",
+                "time": Local::now().to_rfc3339(),
+                "servers": servers,
+                "asns": asns,
+            })
+        );
+    }
+}
+
+pub struct SilentReporter;
+
+impl Reporter for SilentReporter {
+    fn discovery(&self, _discovery: &Discovery) {}
+
+    fn pass_started(&self, _pass: Pass) {}
+
+    fn pass_finished(&self, _pass: Pass) {}
+
+    fn traffic(&self, _run: &Traffic, _workers: &[Arc<Traffic>]) {}
+
+    fn summary(&self, _summary: &RunSummary) {}
+
+    fn stuck_workers(&self, _workers: &[WorkerSnapshot]) {}
+
+    fn blocked(&self, _servers: i64, _asns: i64) {}
+}
@@ -0,0 +1,136 @@
+use std::{io::ErrorKind, net::IpAddr, sync::Arc, time::Duration};
+
+use chrono::{Local, NaiveDateTime, TimeDelta, Timelike};
+use diesel::{dsl::insert_into, ExpressionMethods, QueryDsl, RunQueryDsl, SelectableHelper};
+use mine_search::connect;
+use tokio::{sync::Semaphore, time::timeout};
+
+use crate::{
+    conn_wrapper::Connection,
+    database::{DiscoverySource, RetryInsert, RetryModel},
+    etiquette, handle_valid_ip, schema, Context,
+};
+
+/// Delay before the first retry; it doubles with every failed attempt.
+const BASE_DELAY_SECS: i64 = 60;
+/// Entries picked up per round.
+const BATCH: i64 = 100;
+
+/// Errors worth probing again later because the host did answer once.
+pub fn is_transient(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::TimedOut
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::BrokenPipe
+            | ErrorKind::UnexpectedEof
+    )
+}
+
+fn now() -> NaiveDateTime {
+    Local::now().naive_local().with_nanosecond(0).unwrap()
+}
+
+/// Queues a hit whose probe failed half-way. Already queued targets keep their
+/// attempt count.
+pub async fn enqueue(ip: &IpAddr, port: u16, source: DiscoverySource, error: &str, ctx: &Context) {
+    if ctx.retry_attempts == 0 {
+        return;
+    }
+
+    insert_into(schema::retry_queue::dsl::retry_queue)
+        .values(RetryInsert {
+            ip: &ip.to_string(),
+            port: port as i32,
+            discovery_source: source.as_str(),
+            last_error: error,
+            next_attempt_at: now() + TimeDelta::seconds(BASE_DELAY_SECS),
+        })
+        .on_conflict_do_nothing()
+        .execute(&mut ctx.pool().get().await.conn)
+        .unwrap();
+}
+
+/// Works through due entries every 30 seconds until the process exits.
+pub async fn run(ctx: Arc<Context>) {
+    let semaphore = Arc::new(Semaphore::new(10));
+
+    loop {
+        tokio::time::sleep(Duration::from_secs(30)).await;
+
+        let due: Vec<RetryModel> = schema::retry_queue::dsl::retry_queue
+            .filter(schema::retry_queue::dsl::next_attempt_at.le(now()))
+            .order(schema::retry_queue::dsl::next_attempt_at)
+            .limit(BATCH)
+            .select(RetryModel::as_select())
+            .load(&mut ctx.pool().get().await.conn)
+            .unwrap();
+
+        let mut handles = vec![];
+        for entry in due {
+            let permit = semaphore.clone().acquire_owned().await.unwrap();
+            let th_ctx = ctx.clone();
+
+            handles.push(tokio::spawn(async move {
+                let _permit = permit;
+                attempt(entry, &th_ctx).await;
+            }));
+        }
+
+        for handle in handles {
+            let _ = handle.await;
+        }
+    }
+}
+
+async fn attempt(entry: RetryModel, ctx: &Arc<Context>) {
+    let (Ok(ip), Some(source)) = (
+        entry.ip.parse::<IpAddr>(),
+        DiscoverySource::parse(&entry.discovery_source),
+    ) else {
+        return remove(entry.id, ctx).await;
+    };
+    let port = entry.port as u16;
+    if !etiquette::allowed(&ip.to_string()) {
+        return remove(entry.id, ctx).await;
+    }
+
+    let error = match connect(&ip, port).await.map(Connection::from_stream) {
+        Some(Ok(conn)) => match timeout(
+            Duration::from_secs(10),
+            handle_valid_ip(conn, &ip, port, source, ctx),
+        )
+        .await
+        {
+            Ok(Ok(())) => return remove(entry.id, ctx).await,
+            Ok(Err(e)) if is_transient(e.kind()) => format!("{:?}", e.kind()),
+            Ok(Err(_)) => return remove(entry.id, ctx).await,
+            Err(_) => "Timeout".to_string(),
+        },
+        _ => "Unreachable".to_string(),
+    };
+
+    let attempts = entry.attempts + 1;
+    if attempts >= ctx.retry_attempts as i32 {
+        return remove(entry.id, ctx).await;
+    }
+
+    let delay = BASE_DELAY_SECS << attempts.min(16);
+    diesel::update(schema::retry_queue::dsl::retry_queue)
+        .filter(schema::retry_queue::dsl::id.eq(entry.id))
+        .set((
+            schema::retry_queue::dsl::attempts.eq(attempts),
+            schema::retry_queue::dsl::last_error.eq(error),
+            schema::retry_queue::dsl::next_attempt_at.eq(now() + TimeDelta::seconds(delay)),
+        ))
+        .execute(&mut ctx.pool().get().await.conn)
+        .unwrap();
+}
+
+async fn remove(id: i32, ctx: &Context) {
+    diesel::delete(schema::retry_queue::dsl::retry_queue)
+        .filter(schema::retry_queue::dsl::id.eq(id))
+        .execute(&mut ctx.pool().get().await.conn)
+        .unwrap();
+}
@@ -0,0 +1,631 @@
+use std::{
+    collections::HashSet,
+    env, fs,
+    net::{IpAddr, Ipv4Addr},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use chrono::{Local, Timelike};
+use diesel::{dsl::insert_into, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use mine_search::Probe;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    database::{DiscoverySource, Pool},
+    filtered::FilteredSpace,
+    quotas::Quotas,
+    schema,
+    stats::SampleCounts,
+    Context,
+};
+
+/// Key of the sweep row in `scan_checkpoints`.
+const SWEEP_CHECKPOINT: &str = "sweep";
+/// Size of the IPv4 address space walked by a sweep.
+const SPACE: u64 = 1 << 32;
+
+/// Reserved, private and otherwise unroutable IPv4 ranges (RFC 6890 and
+/// friends), skipped by every mode.
+const BOGONS: &[&str] = &[
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.0.0.0/24",
+    "192.0.2.0/24",
+    "192.88.99.0/24",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "198.51.100.0/24",
+    "203.0.113.0/24",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+];
+
+/// Inclusive range of addresses as integers.
+type Range = (u32, u32);
+
+/// Parses `a.b.c.d/len` or a single address.
+pub fn parse_cidr(value: &str) -> Result<Range, String> {
+    let (ip, len) = match value.trim().split_once('/') {
+        Some((ip, len)) => (ip, len.parse::<u32>().map_err(|e| e.to_string())?),
+        None => (value.trim(), 32),
+    };
+    let ip: Ipv4Addr = ip
+        .parse()
+        .map_err(|_| format!("{} is not an IPv4 address or CIDR range", value))?;
+    if len > 32 {
+        return Err(format!("{} has a prefix longer than 32 bits", value));
+    }
+
+    let mask = if len == 0 { 0 } else { u32::MAX << (32 - len) };
+    let start = u32::from(ip) & mask;
+    Ok((start, start | !mask))
+}
+
+/// Ranges from a file with one address or CIDR range per line; `#` starts a
+/// comment.
+fn read_ranges(path: &str) -> Vec<Range> {
+    let content =
+        fs::read_to_string(path).unwrap_or_else(|e| panic!("Could not read {}: {}", path, e));
+
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| parse_cidr(line).unwrap_or_else(|e| panic!("{}: {}", path, e)))
+        .collect()
+}
+
+/// Ranges of `OWN_NETWORKS`, the operator's own infrastructure.
+fn own_networks() -> Vec<Range> {
+    env::var("OWN_NETWORKS")
+        .unwrap_or_default()
+        .split(',')
+        .filter(|cidr| !cidr.trim().is_empty())
+        .map(|cidr| parse_cidr(cidr).unwrap_or_else(|e| panic!("OWN_NETWORKS: {}", e)))
+        .collect()
+}
+
+fn format_range((start, end): Range) -> String {
+    let len = 32
+        - (end - start)
+            .checked_add(1)
+            .map_or(32, |size| size.trailing_zeros());
+    format!("{}/{}", Ipv4Addr::from(start), len)
+}
+
+/// Sorted, merged ranges of addresses that must never be probed.
+pub struct Exclusions {
+    ranges: Vec<Range>,
+    own: Vec<Range>,
+}
+
+impl Exclusions {
+    pub fn new(blocklist: Option<&str>) -> Self {
+        let own = own_networks();
+        let mut ranges: Vec<Range> = BOGONS.iter().map(|r| parse_cidr(r).unwrap()).collect();
+        if let Some(path) = blocklist {
+            ranges.extend(read_ranges(path));
+        }
+        ranges.extend(&own);
+        ranges.sort();
+
+        let mut merged: Vec<Range> = vec![];
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+
+        Self {
+            ranges: merged,
+            own,
+        }
+    }
+
+    pub fn contains(&self, ip: u32) -> bool {
+        let i = self.ranges.partition_point(|(start, _)| *start <= ip);
+        i > 0 && ip <= self.ranges[i - 1].1
+    }
+
+    /// The own network overlapping `range`, as `a.b.c.d/len`.
+    pub fn own_network(&self, (start, end): Range) -> Option<String> {
+        self.own
+            .iter()
+            .find(|(own_start, own_end)| start <= *own_end && *own_start <= end)
+            .map(|range| format_range(*range))
+    }
+
+    /// The own network `ip` is in, for addresses taken from imported lists.
+    pub fn own_address(&self, ip: &IpAddr) -> Option<String> {
+        match ip {
+            IpAddr::V4(v4) => self.own_network((u32::from(*v4), u32::from(*v4))),
+            IpAddr::V6(v6) => v6
+                .to_ipv4_mapped()
+                .and_then(|v4| self.own_network((u32::from(v4), u32::from(v4)))),
+        }
+    }
+
+    pub fn own_count(&self) -> usize {
+        self.own.len()
+    }
+}
+
+/// Warns about the target ranges reaching into own networks; those
+/// addresses are skipped like any other exclusion.
+fn flag_own_networks(ranges: &[Range], exclusions: &Exclusions) {
+    for range in ranges {
+        if let Some(own) = exclusions.own_network(*range) {
+            eprintln!(
+                "[!] Target {} overlaps own network {}, its addresses there are not probed",
+                format_range(*range),
+                own
+            );
+        }
+    }
+}
+
+/// Walks a list of ranges in order, shared by all workers.
+pub struct Targets {
+    ranges: Vec<Range>,
+    total: u64,
+    next: AtomicU64,
+}
+
+impl Targets {
+    fn new(mut ranges: Vec<Range>, offset: u64) -> Self {
+        ranges.sort();
+        ranges.dedup();
+        let total = ranges.iter().map(|(s, e)| (*e - *s) as u64 + 1).sum();
+
+        Self {
+            ranges,
+            total,
+            next: AtomicU64::new(offset),
+        }
+    }
+
+    fn position(&self) -> u64 {
+        self.next.load(Ordering::Relaxed).min(self.total)
+    }
+
+    fn next(&self) -> Option<u32> {
+        let mut index = self.next.fetch_add(1, Ordering::Relaxed);
+        if index >= self.total {
+            return None;
+        }
+
+        for (start, end) in &self.ranges {
+            let len = (*end - *start) as u64 + 1;
+            if index < len {
+                return Some(start + index as u32);
+            }
+            index -= len;
+        }
+        None
+    }
+}
+
+/// How random and sweep scans walk the address space, and how many addresses
+/// of that order to skip. The same seed yields the same order on any machine.
+pub struct Order {
+    pub sweep: bool,
+    pub seed: u32,
+    pub offset: u64,
+    /// Share of the addresses of a finite scan to probe
+    pub sample: Option<f64>,
+}
+
+/// A uniform sample of the addresses of a finite scan, chosen by a hash of
+/// the address and seed so a continued run keeps to the same sample.
+struct Sample {
+    fraction: f64,
+    /// Hashes below this are probed, out of 2^32
+    threshold: u64,
+    seed: u64,
+    considered: AtomicU64,
+    probed: AtomicU64,
+}
+
+impl Sample {
+    fn new(fraction: f64, seed: u32) -> Self {
+        Self {
+            fraction,
+            threshold: (fraction * SPACE as f64) as u64,
+            seed: seed as u64,
+            considered: AtomicU64::new(0),
+            probed: AtomicU64::new(0),
+        }
+    }
+
+    fn admit(&self, ip: u32) -> bool {
+        self.considered.fetch_add(1, Ordering::Relaxed);
+        // splitmix64 finalizer
+        let mut hash = (ip as u64 ^ self.seed.rotate_left(32)).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        hash ^= hash >> 31;
+
+        let admitted = hash % SPACE < self.threshold;
+        if admitted {
+            self.probed.fetch_add(1, Ordering::Relaxed);
+        }
+        admitted
+    }
+}
+
+/// Parses the `--sample` share, e.g. `0.001`.
+pub fn parse_sample(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(fraction) if fraction > 0.0 && fraction <= 1.0 => Ok(fraction),
+        _ => Err(format!("{} is not a share between 0 and 1", value)),
+    }
+}
+
+/// Visits every IPv4 address exactly once in a scattered order, so no network
+/// sees a burst of consecutive probes. `index * multiplier + increment` modulo
+/// 2^32 is a permutation of the address space for any odd multiplier.
+pub struct Sweep {
+    multiplier: u64,
+    increment: u64,
+    position: AtomicU64,
+    /// Seed of the permutation, unknown for checkpoints saved without it
+    seed: Option<u32>,
+}
+
+impl Sweep {
+    /// The permutation of `seed`, starting `position` addresses in.
+    fn from_seed(seed: u32, position: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed as u64);
+        Self {
+            multiplier: rng.gen_range(0..SPACE) | 1,
+            increment: rng.gen_range(0..SPACE),
+            position: AtomicU64::new(position),
+            seed: Some(seed),
+        }
+    }
+
+    fn address(&self, index: u64) -> u32 {
+        (index
+            .wrapping_mul(self.multiplier)
+            .wrapping_add(self.increment)
+            % SPACE) as u32
+    }
+
+    fn next(&self) -> Option<u32> {
+        let index = self.position.fetch_add(1, Ordering::Relaxed);
+        (index < SPACE).then(|| self.address(index))
+    }
+
+    /// Like `next`, starting over once the space is exhausted.
+    fn next_wrapping(&self) -> u32 {
+        self.address(self.position.fetch_add(1, Ordering::Relaxed) % SPACE)
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position.load(Ordering::Relaxed).min(SPACE)
+    }
+
+    /// Continues the stored sweep, or starts one in the order of `order`.
+    /// The last `in_flight` addresses before the checkpoint may not have been
+    /// probed, so they are handed out again.
+    async fn resume(ctx: &Context, order: &Order, in_flight: u64) -> Self {
+        let Some(db) = &ctx.db else {
+            println!("[+] Starting a new sweep of the IPv4 space, without a checkpoint");
+            return Self::from_seed(order.seed, order.offset);
+        };
+        let mut conn = db.get().await;
+        let stored: Option<(i64, i64, i64, Option<i64>)> =
+            schema::scan_checkpoints::dsl::scan_checkpoints
+                .find(SWEEP_CHECKPOINT)
+                .select((
+                    schema::scan_checkpoints::dsl::multiplier,
+                    schema::scan_checkpoints::dsl::increment,
+                    schema::scan_checkpoints::dsl::position,
+                    schema::scan_checkpoints::dsl::seed,
+                ))
+                .first(&mut conn.conn)
+                .optional()
+                .unwrap();
+
+        match stored {
+            Some((multiplier, increment, position, seed)) => {
+                let position = (position as u64).saturating_sub(in_flight);
+                println!("[+] Resuming sweep at {} of {} addresses", position, SPACE);
+                Self {
+                    multiplier: multiplier as u64,
+                    increment: increment as u64,
+                    position: AtomicU64::new(position),
+                    seed: seed.map(|seed| seed as u32),
+                }
+            }
+            None => {
+                println!("[+] Starting a new sweep of the IPv4 space");
+                Self::from_seed(order.seed, order.offset)
+            }
+        }
+    }
+
+    pub async fn save(&self, db: &Pool) {
+        let values = (
+            schema::scan_checkpoints::dsl::name.eq(SWEEP_CHECKPOINT),
+            schema::scan_checkpoints::dsl::multiplier.eq(self.multiplier as i64),
+            schema::scan_checkpoints::dsl::increment.eq(self.increment as i64),
+            schema::scan_checkpoints::dsl::position.eq(self.position() as i64),
+            schema::scan_checkpoints::dsl::seed.eq(self.seed.map(i64::from)),
+            schema::scan_checkpoints::dsl::updated_at
+                .eq(Local::now().naive_local().with_nanosecond(0).unwrap()),
+        );
+
+        insert_into(schema::scan_checkpoints::dsl::scan_checkpoints)
+            .values(values)
+            .on_conflict(schema::scan_checkpoints::dsl::name)
+            .do_update()
+            .set(values)
+            .execute(&mut db.get().await.conn)
+            .unwrap();
+    }
+}
+
+/// Parses port lists like `25565,25570-25577`.
+pub fn parse_ports(values: &[String]) -> Result<Vec<u16>, String> {
+    let mut ports = vec![];
+    for value in values.iter().map(|v| v.trim()).filter(|v| !v.is_empty()) {
+        let (low, high) = value.split_once('-').unwrap_or((value, value));
+        let parse = |port: &str| {
+            port.trim()
+                .parse::<u16>()
+                .ok()
+                .filter(|port| *port > 0)
+                .ok_or_else(|| format!("{} is not a port or port range", value))
+        };
+        let (low, high) = (parse(low)?, parse(high)?);
+        if low > high {
+            return Err(format!("{} is an empty port range", value));
+        }
+        ports.extend(low..=high);
+    }
+
+    let mut seen = HashSet::new();
+    ports.retain(|port| seen.insert(*port));
+    Ok(ports)
+}
+
+/// Ports probed on every address: TCP for Java Edition, UDP for Bedrock.
+pub struct Ports {
+    pub java: Vec<u16>,
+    pub bedrock: Vec<u16>,
+}
+
+impl Ports {
+    pub fn parse(java: &[String], bedrock: &[String]) -> Self {
+        let parse = |values| parse_ports(values).unwrap_or_else(|e| panic!("{}", e));
+        Self {
+            java: parse(java),
+            bedrock: parse(bedrock),
+        }
+    }
+
+    /// What to probe each port with, TCP and RakNet at once on ports of both
+    /// editions.
+    pub fn probes(&self) -> Vec<(u16, Probe)> {
+        let java = self.java.iter().map(|port| {
+            let probe = if self.bedrock.contains(port) {
+                Probe::Both
+            } else {
+                Probe::Tcp
+            };
+            (*port, probe)
+        });
+        let bedrock = self
+            .bedrock
+            .iter()
+            .filter(|port| !self.java.contains(port))
+            .map(|port| (*port, Probe::Raknet));
+        java.chain(bedrock).collect()
+    }
+
+    fn describe(&self) -> String {
+        let list = |ports: &[u16]| {
+            ports
+                .iter()
+                .map(|port| port.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match (self.java.is_empty(), self.bedrock.is_empty()) {
+            (_, true) => format!("Java {}", list(&self.java)),
+            (true, false) => format!("Bedrock {}", list(&self.bedrock)),
+            (false, false) => format!("Java {}; Bedrock {}", list(&self.java), list(&self.bedrock)),
+        }
+    }
+}
+
+pub enum Mode {
+    /// A sweep that starts over instead of ending
+    Random(Sweep),
+    Targets(Targets),
+    Sweep(Sweep),
+}
+
+/// Hands out the addresses scan workers probe.
+pub struct Scanner {
+    mode: Mode,
+    exclusions: Exclusions,
+    quotas: Option<Quotas>,
+    filtered: Arc<FilteredSpace>,
+    ports: Ports,
+    sample: Option<Sample>,
+    /// Addresses handed out that may still be probed
+    in_flight: u64,
+    seed: Option<u32>,
+}
+
+impl Scanner {
+    /// Targets from `--cidr` and `--targets`, a sweep with `--sweep`, random
+    /// addresses otherwise.
+    pub async fn new(
+        cidrs: &[String],
+        targets: Option<&str>,
+        order: Order,
+        exclude: Option<&str>,
+        ports: Ports,
+        ctx: &Context,
+        in_flight: u64,
+    ) -> Self {
+        let mut ranges: Vec<Range> = cidrs
+            .iter()
+            .map(|cidr| parse_cidr(cidr).unwrap_or_else(|e| panic!("{}", e)))
+            .collect();
+        if let Some(path) = targets {
+            ranges.extend(read_ranges(path));
+        }
+
+        let exclusions = Exclusions::new(exclude);
+        if exclusions.own_count() > 0 {
+            println!("[+] Excluding {} own networks", exclusions.own_count());
+        }
+        flag_own_networks(&ranges, &exclusions);
+
+        let mode = if order.sweep {
+            Mode::Sweep(Sweep::resume(ctx, &order, in_flight).await)
+        } else if !ranges.is_empty() {
+            let targets = Targets::new(ranges, order.offset);
+            println!("[+] Scanning {} target addresses", targets.total);
+            Mode::Targets(targets)
+        } else {
+            Mode::Random(Sweep::from_seed(order.seed, order.offset))
+        };
+        // A resumed sweep keeps the order of its checkpoint
+        let seed = match &mode {
+            Mode::Sweep(sweep) => sweep.seed,
+            _ => Some(order.seed),
+        };
+
+        // `scan` refuses a sample of random addresses
+        let sample = order
+            .sample
+            .filter(|_| !matches!(mode, Mode::Random(_)))
+            .map(|fraction| {
+                println!(
+                    "[+] Probing a {}% sample of the addresses",
+                    fraction * 100.0
+                );
+                Sample::new(fraction, seed.unwrap_or(order.seed))
+            });
+
+        if ports.java != [25565] || !ports.bedrock.is_empty() {
+            println!("[+] Probing ports: {}", ports.describe());
+        }
+
+        let quotas = Quotas::from_env(ctx.geoip.clone());
+        if let Some(quotas) = &quotas {
+            println!("[+] Scan quotas: {}", quotas.describe());
+        }
+
+        Self {
+            mode,
+            exclusions,
+            quotas,
+            filtered: ctx.filtered.clone(),
+            ports,
+            sample,
+            in_flight,
+            seed,
+        }
+    }
+
+    /// Seed of the address order, `None` for a sweep resumed from a
+    /// checkpoint that didn't record it.
+    pub fn seed(&self) -> Option<u32> {
+        self.seed
+    }
+
+    /// Next address to probe, `None` once a finite mode ran out.
+    pub fn next(&self) -> Option<Ipv4Addr> {
+        loop {
+            let ip = match &self.mode {
+                Mode::Random(order) => order.next_wrapping(),
+                Mode::Targets(targets) => targets.next()?,
+                Mode::Sweep(sweep) => sweep.next()?,
+            };
+            if self.exclusions.contains(ip)
+                || self.sample.as_ref().is_some_and(|sample| !sample.admit(ip))
+                || self.filtered.skip(ip)
+            {
+                continue;
+            }
+
+            let ip = Ipv4Addr::from(ip);
+            if self.quotas.as_ref().is_none_or(|q| q.admit(ip.into())) {
+                return Some(ip);
+            }
+        }
+    }
+
+    /// How much of the range a sampled scan got through, for extrapolating.
+    pub fn sample(&self) -> Option<SampleCounts> {
+        self.sample.as_ref().map(|sample| SampleCounts {
+            fraction: sample.fraction,
+            addresses: sample.considered.load(Ordering::Relaxed),
+            sampled: sample.probed.load(Ordering::Relaxed),
+        })
+    }
+
+    pub fn ports(&self) -> &Ports {
+        &self.ports
+    }
+
+    pub fn is_finite(&self) -> bool {
+        !matches!(self.mode, Mode::Random(_))
+    }
+
+    pub fn source(&self) -> DiscoverySource {
+        match self.mode {
+            Mode::Random(_) => DiscoverySource::RandomScan,
+            _ => DiscoverySource::RangeScan,
+        }
+    }
+
+    /// Position in the address order before which every address was probed,
+    /// the `--offset` that continues this run.
+    pub fn position(&self) -> u64 {
+        let position = match &self.mode {
+            Mode::Random(order) => order.position.load(Ordering::Relaxed),
+            Mode::Targets(targets) => targets.position(),
+            Mode::Sweep(sweep) => sweep.position(),
+        };
+        position.saturating_sub(self.in_flight)
+    }
+
+    /// Stores the position in the run record, and the sweep checkpoint.
+    /// Without a database the position is only printed when the scan ends.
+    pub async fn save(&self, ctx: &Context) {
+        let (Some(db), Some(run_id)) = (&ctx.db, ctx.run_id) else {
+            return;
+        };
+        if let Mode::Sweep(sweep) = &self.mode {
+            sweep.save(db).await;
+        }
+        diesel::update(schema::runs::dsl::runs.find(run_id))
+            .set(schema::runs::dsl::position.eq(self.position() as i64))
+            .execute(&mut db.get().await.conn)
+            .unwrap();
+    }
+}
+
+/// Stores the scan position every 30 seconds.
+pub async fn checkpoint(ctx: Arc<Context>, scanner: Arc<Scanner>) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        scanner.save(&ctx).await;
+    }
+}
@@ -0,0 +1,311 @@
+use std::{
+    env, io,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    routing::get,
+    Json, Router,
+};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl, SelectableHelper};
+use mine_search::fields::Selection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    database::{Edition, PingModel, PlayerModel, ServerModel, SERVER_FIELDS},
+    jobs::{self, JobModel, JobSpec},
+    schema,
+    storage::{Mod, ServerFilter, Sighting},
+    Context,
+};
+
+/// Rows a single list request may return.
+const MAX_LIMIT: i64 = 500;
+
+type ApiResult<T> = Result<Json<T>, StatusCode>;
+
+#[derive(Deserialize)]
+pub struct ServerQuery {
+    /// Version name, `*` matches any characters
+    pub version: Option<String>,
+    pub min_online: Option<i32>,
+    pub license: Option<bool>,
+    pub white_list: Option<bool>,
+    pub edition: Option<String>,
+    pub software: Option<String>,
+    /// ISO country code
+    pub country: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    /// Comma-separated fields to return instead of whole rows
+    pub fields: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct FieldsQuery {
+    pub fields: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AddressQuery {
+    pub edition: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ServerDetail {
+    #[serde(flatten)]
+    pub server: ServerModel,
+    pub favicon: Option<String>,
+    pub players: Vec<PlayerModel>,
+    pub mods: Vec<Mod>,
+}
+
+#[derive(Deserialize)]
+pub struct PingsQuery {
+    pub edition: Option<String>,
+    pub limit: Option<i64>,
+}
+
+fn db_error(_: diesel::result::Error) -> StatusCode {
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+fn storage_error(_: io::Error) -> StatusCode {
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+fn selection(fields: Option<&str>) -> Result<Option<Selection>, StatusCode> {
+    fields
+        .map(|list| Selection::parse(list, SERVER_FIELDS))
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)
+}
+
+/// Servers as whole rows, or only the selected fields of each.
+fn listing(servers: &[ServerModel], fields: Option<&Selection>) -> Json<Vec<Value>> {
+    Json(
+        servers
+            .iter()
+            .map(|server| match fields {
+                Some(fields) => fields.to_json(server),
+                None => serde_json::to_value(server).unwrap(),
+            })
+            .collect(),
+    )
+}
+
+/// `servers.ip` for an address as typed, `1.2.3.4` or `1.2.3.4:25566`.
+pub fn address_key(addr: &str, edition: Edition) -> Option<String> {
+    if let Ok(addr) = addr.parse::<SocketAddr>() {
+        return Some(edition.key(&addr.ip(), addr.port()));
+    }
+    let ip: IpAddr = addr.parse().ok()?;
+    Some(edition.key(&ip, edition.default_port()))
+}
+
+/// Stored servers matching the query parameters, most recently seen first.
+pub async fn servers(
+    State(ctx): State<Arc<Context>>,
+    Query(query): Query<ServerQuery>,
+) -> ApiResult<Vec<Value>> {
+    let fields = selection(query.fields.as_deref())?;
+    let edition = query
+        .edition
+        .as_deref()
+        .map(|edition| Edition::parse(edition).ok_or(StatusCode::BAD_REQUEST))
+        .transpose()?;
+    let filter = ServerFilter {
+        version: query.version,
+        min_online: query.min_online,
+        license: query.license,
+        white_list: query.white_list,
+        edition,
+        software: query.software,
+        country: query.country.map(|country| country.to_uppercase()),
+        limit: query.limit.unwrap_or(50).clamp(1, MAX_LIMIT),
+        offset: query.offset.unwrap_or(0).max(0),
+    };
+
+    let servers = ctx.storage.servers(&filter).await.map_err(storage_error)?;
+    Ok(listing(&servers, fields.as_ref()))
+}
+
+/// The stored server at `addr`, `404` when there is none.
+async fn find_server(
+    ctx: &Context,
+    addr: &str,
+    edition: Option<&str>,
+) -> Result<ServerModel, StatusCode> {
+    let edition = match edition {
+        Some(edition) => Edition::parse(edition).ok_or(StatusCode::BAD_REQUEST)?,
+        None => Edition::Java,
+    };
+    let key = address_key(addr, edition).ok_or(StatusCode::NOT_FOUND)?;
+    ctx.storage
+        .server(&key, edition)
+        .await
+        .map_err(storage_error)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// One server by address, with its favicon, players and mods.
+pub async fn server(
+    State(ctx): State<Arc<Context>>,
+    Path(addr): Path<String>,
+    Query(query): Query<AddressQuery>,
+) -> ApiResult<ServerDetail> {
+    let mut server = find_server(&ctx, &addr, query.edition.as_deref()).await?;
+    let players = ctx
+        .storage
+        .players(server.id)
+        .await
+        .map_err(storage_error)?;
+    let mods = ctx.storage.mods(server.id).await.map_err(storage_error)?;
+
+    Ok(Json(ServerDetail {
+        favicon: server.favicon.take(),
+        server,
+        players,
+        mods,
+    }))
+}
+
+/// Latest status snapshots of one server by address, newest first.
+pub async fn pings(
+    State(ctx): State<Arc<Context>>,
+    Path(addr): Path<String>,
+    Query(query): Query<PingsQuery>,
+) -> ApiResult<Vec<PingModel>> {
+    let server = find_server(&ctx, &addr, query.edition.as_deref()).await?;
+    let limit = query.limit.unwrap_or(100).clamp(1, MAX_LIMIT);
+    let pings = ctx
+        .storage
+        .snapshots(server.id, limit)
+        .await
+        .map_err(storage_error)?;
+
+    Ok(Json(pings))
+}
+
+/// Servers a player name was seen on.
+pub async fn player(
+    State(ctx): State<Arc<Context>>,
+    Path(name): Path<String>,
+) -> ApiResult<Vec<Sighting>> {
+    let sightings = ctx
+        .storage
+        .sightings(&name, MAX_LIMIT)
+        .await
+        .map_err(storage_error)?;
+
+    Ok(Json(sightings))
+}
+
+/// Queues a scan job. With `SERVE_TOKEN` set, only requests carrying it as a
+/// bearer token may submit; without, the route is only mounted on loopback
+/// addresses.
+pub async fn submit_job(
+    State(ctx): State<Arc<Context>>,
+    headers: HeaderMap,
+    Json(spec): Json<JobSpec>,
+) -> Result<(StatusCode, Json<JobModel>), (StatusCode, String)> {
+    if let Ok(token) = env::var("SERVE_TOKEN") {
+        let bearer = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        if bearer != Some(token.as_str()) {
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                "missing or wrong token".to_string(),
+            ));
+        }
+    }
+
+    let total = spec.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let job = jobs::submit(&mut ctx.pool().get().await.conn, &spec, total)
+        .map_err(|e| (db_error(e), "could not queue the job".to_string()))?;
+
+    Ok((StatusCode::CREATED, Json(job)))
+}
+
+/// The latest scan jobs, newest first.
+pub async fn list_jobs(State(ctx): State<Arc<Context>>) -> ApiResult<Vec<JobModel>> {
+    let jobs = schema::scan_jobs::dsl::scan_jobs
+        .order(schema::scan_jobs::dsl::id.desc())
+        .limit(50)
+        .select(JobModel::as_select())
+        .load(&mut ctx.pool().get().await.conn)
+        .map_err(db_error)?;
+
+    Ok(Json(jobs))
+}
+
+/// Status and progress of one scan job.
+pub async fn job(State(ctx): State<Arc<Context>>, Path(id): Path<i32>) -> ApiResult<JobModel> {
+    let job = schema::scan_jobs::dsl::scan_jobs
+        .find(id)
+        .select(JobModel::as_select())
+        .first(&mut ctx.pool().get().await.conn)
+        .optional()
+        .map_err(db_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(job))
+}
+
+/// Servers a scan job found, new or already stored.
+pub async fn job_servers(
+    State(ctx): State<Arc<Context>>,
+    Path(id): Path<i32>,
+    Query(query): Query<FieldsQuery>,
+) -> ApiResult<Vec<Value>> {
+    let fields = selection(query.fields.as_deref())?;
+    let servers = schema::scan_job_servers::dsl::scan_job_servers
+        .inner_join(schema::servers::dsl::servers)
+        .filter(schema::scan_job_servers::dsl::job_id.eq(id))
+        .order(schema::servers::dsl::id)
+        .select(ServerModel::as_select())
+        .load::<ServerModel>(&mut ctx.pool().get().await.conn)
+        .map_err(db_error)?;
+
+    Ok(listing(&servers, fields.as_ref()))
+}
+
+/// Serves the query API on `addr` next to the scanner, sharing its database
+/// pool so results are live, and accepts scan jobs.
+pub async fn run(ctx: Arc<Context>, addr: SocketAddr) {
+    // Anyone reaching the API could make the scanner probe what they like
+    let jobs = if env::var_os("SERVE_TOKEN").is_some() || addr.ip().is_loopback() {
+        get(list_jobs).post(submit_job)
+    } else {
+        eprintln!(
+            "[!] SERVE_TOKEN is not set, not accepting scan jobs on non-loopback address {}",
+            addr
+        );
+        get(list_jobs)
+    };
+    let mut app = Router::new()
+        .route("/servers", get(servers))
+        .route("/servers/{addr}", get(server))
+        .route("/servers/{addr}/pings", get(pings))
+        .route("/players/{name}", get(player));
+    // Jobs are queued in the database
+    if ctx.db.is_some() {
+        app = app
+            .route("/jobs", jobs)
+            .route("/jobs/{id}", get(job))
+            .route("/jobs/{id}/servers", get(job_servers));
+    }
+    let app = app.with_state(ctx);
+
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .unwrap_or_else(|e| panic!("Could not listen on {}: {}", addr, e));
+    println!("[+] Query API listening on http://{}", addr);
+    axum::serve(listener, app).await.unwrap();
+}
@@ -0,0 +1,138 @@
+use std::{
+    io::{self, Error, ErrorKind},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use mine_search::source_port;
+use rand::Rng;
+use serde_json::Value;
+use tokio::{net::lookup_host, time::timeout};
+
+use crate::{
+    packets::{UnconnectedPing, UnconnectedPong},
+    server_actions::{
+        query::QueryData,
+        without_connection::{Players, Status, Version},
+    },
+    traffic::{record_received, record_sent},
+};
+
+/// Default port of Bedrock Edition servers.
+pub const BEDROCK_PORT: u16 = 19132;
+
+#[derive(Debug)]
+pub struct BedrockStatus {
+    pub motd: String,
+    /// Second MOTD line, usually the level name
+    pub sub_motd: Option<String>,
+    pub protocol: i64,
+    pub version: String,
+    pub online: i64,
+    pub max: i64,
+    pub gamemode: Option<String>,
+}
+
+impl BedrockStatus {
+    pub fn parse(payload: &str) -> io::Result<Self> {
+        let fields: Vec<&str> = payload.split(';').collect();
+        // Other RakNet games answer pings too; `MCEE` is Education Edition
+        if fields.len() < 6 || !matches!(fields[0], "MCPE" | "MCEE") {
+            return Err(Error::new(ErrorKind::InvalidData, "not a Bedrock server"));
+        }
+        let number = |i: usize| fields[i].trim().parse().unwrap_or_default();
+        let optional = |i: usize| {
+            fields
+                .get(i)
+                .map(|f| f.trim())
+                .filter(|f| !f.is_empty())
+                .map(str::to_string)
+        };
+
+        Ok(Self {
+            motd: fields[1].to_string(),
+            sub_motd: optional(7),
+            protocol: number(2),
+            version: fields[3].to_string(),
+            online: number(4),
+            max: number(5),
+            gamemode: optional(8),
+        })
+    }
+
+    /// The answer in the shape of a Java status, so stores, rules and
+    /// reporters handle both editions. The MOTD lines become the description.
+    pub fn to_status(&self) -> Status {
+        let description = match &self.sub_motd {
+            Some(sub_motd) => format!("{}\n{}", self.motd, sub_motd),
+            None => self.motd.clone(),
+        };
+
+        Status {
+            players: Players {
+                online: self.online,
+                max: self.max,
+                sample: None,
+            },
+            version: Version {
+                name: self.version.clone(),
+                protocol: self.protocol,
+            },
+            description: Value::String(description),
+            favicon: None,
+            forge_data: None,
+            modinfo: None,
+            raw: None,
+            anomalous: false,
+            era: None,
+        }
+    }
+
+    /// Level name and gamemode, stored like the map and game type of a
+    /// query.
+    pub fn to_query(&self) -> QueryData {
+        QueryData {
+            map: self.sub_motd.clone(),
+            game_type: self.gamemode.clone(),
+            game_id: None,
+            host_port: None,
+            plugins: None,
+        }
+    }
+}
+
+/// RakNet unconnected ping, the status request of Bedrock Edition.
+pub async fn get_bedrock_status(ip: &str, port: u16) -> io::Result<BedrockStatus> {
+    let remote = lookup_host(format!("{}:{}", ip, port))
+        .await?
+        .next()
+        .ok_or(ErrorKind::InvalidInput)?;
+    let socket = source_port::bind_udp(&remote).await?;
+    socket.connect(remote).await?;
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64;
+    let ping = UnconnectedPing {
+        time,
+        client_guid: rand::thread_rng().gen(),
+    };
+    record_sent(socket.send(&ping.serialize()).await?);
+
+    let mut buf = vec![0; 2048];
+    let len = timeout(Duration::from_secs(1), socket.recv(&mut buf))
+        .await
+        .map_err(|_| Error::from(ErrorKind::TimedOut))??;
+    record_received(len);
+
+    let pong = UnconnectedPong::deserialize(&buf[..len])?;
+    if pong.time != time {
+        return Err(Error::new(ErrorKind::InvalidData, "pong for another ping"));
+    }
+    BedrockStatus::parse(&pong.payload)
+}
+
+/// The status in a pong `check_server` received.
+pub fn status_of_pong(packet: &[u8]) -> io::Result<BedrockStatus> {
+    BedrockStatus::parse(&UnconnectedPong::deserialize(packet)?.payload)
+}
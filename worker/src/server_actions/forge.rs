@@ -0,0 +1,107 @@
+use std::{
+    io::{self, Error, ErrorKind},
+    time::{Duration, Instant},
+};
+
+use minecraft_protocol::types::var_int::VarInt;
+use tokio::time::timeout;
+
+use crate::{
+    conn_wrapper::{decode_varint, read_frame, Connection, ConnectionWrapper},
+    identity,
+    packets::{Handshake, LoginStart},
+    proxy_protocol::ProxyVersion,
+    server_actions::with_connection::read_string,
+};
+
+const HANDSHAKE_TIME: Duration = Duration::from_secs(5);
+
+const LOGIN_PLUGIN_REQUEST_ID: i32 = 0x04;
+const LOGIN_WRAPPER: &str = "fml:loginwrapper";
+const MOD_LIST_ID: i32 = 1;
+
+/// Mod ids from the FML handshake of a Forge 1.13+ server. A client that
+/// marks its handshake address with `\0FML<version>\0` gets the server's mod
+/// list as the first login plugin request, so nothing needs to be answered.
+/// Online-mode servers ask for encryption first and yield nothing.
+pub async fn get_forge_mods(
+    ip: &str,
+    port: u16,
+    protocol: i32,
+    fml_version: i64,
+    proxy: Option<ProxyVersion>,
+) -> io::Result<Vec<String>> {
+    let mut conn = Connection::connect_with(&format!("{}:{}", ip, port), proxy).await?;
+
+    conn.write_packet(Handshake {
+        protocol: VarInt(protocol),
+        server_address: format!("{}\0FML{}\0", ip, fml_version),
+        server_port: port,
+        next_state: VarInt(2),
+    })
+    .await?;
+
+    let identity = identity::next();
+    LoginStart {
+        name: identity.name,
+        uuid: identity.uuid,
+    }
+    .get_by_protocol(protocol)
+    .write(&mut conn)
+    .await?;
+
+    let mut threshold = None;
+    let started = Instant::now();
+    while let Some(remaining) = HANDSHAKE_TIME.checked_sub(started.elapsed()) {
+        let (id, payload) = timeout(remaining, read_frame(&mut conn, threshold))
+            .await
+            .map_err(|_| Error::from(ErrorKind::TimedOut))??;
+
+        match id {
+            0x00 => return Err(Error::new(ErrorKind::ConnectionAborted, "kicked")),
+            0x01 => return Err(Error::new(ErrorKind::PermissionDenied, "online mode")),
+            // Login success without a handshake, not a Forge server after all
+            0x02 => return Ok(vec![]),
+            0x03 => threshold = Some(decode_varint(&payload)?.0),
+            LOGIN_PLUGIN_REQUEST_ID => {
+                if let Some(mods) = parse_mod_list(&payload)? {
+                    return Ok(mods);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Err(Error::from(ErrorKind::TimedOut))
+}
+
+/// `message id, channel, data`, where the login wrapper's data is the inner
+/// channel and a length-prefixed FML packet.
+fn parse_mod_list(payload: &[u8]) -> io::Result<Option<Vec<String>>> {
+    let (_, mut pos) = decode_varint(payload)?;
+    let (channel, read) = read_string(&payload[pos..])?;
+    pos += read;
+    if channel != LOGIN_WRAPPER {
+        return Ok(None);
+    }
+
+    let (_, read) = read_string(&payload[pos..])?;
+    pos += read;
+    let (_, read) = decode_varint(&payload[pos..])?;
+    pos += read;
+    let (packet_id, read) = decode_varint(&payload[pos..])?;
+    pos += read;
+    if packet_id != MOD_LIST_ID {
+        return Ok(None);
+    }
+
+    let (count, read) = decode_varint(&payload[pos..])?;
+    pos += read;
+    let mut mods = Vec::with_capacity(count.clamp(0, 1024) as usize);
+    for _ in 0..count {
+        let (id, read) = read_string(&payload[pos..])?;
+        pos += read;
+        mods.push(id);
+    }
+    Ok(Some(mods))
+}
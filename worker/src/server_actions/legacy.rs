@@ -0,0 +1,256 @@
+use std::{
+    io::{self, ErrorKind},
+    time::Duration,
+};
+
+use serde_json::Value;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::timeout,
+};
+
+use crate::{
+    conn_wrapper::Connection,
+    proxy_protocol::ProxyVersion,
+    server_actions::without_connection::{Players, Status, Version},
+};
+
+/// Kick packet, what pre-1.7 servers answer pings and unexpected packets with.
+const KICK: u8 = 0xFF;
+const PING: u8 = 0xFE;
+const HANDSHAKE: u8 = 0x02;
+/// Protocol version sent in the 1.6 ping, that of 1.6.4.
+const PING_PROTOCOL: u8 = 78;
+/// Longest string accepted in a reply, in characters.
+const MAX_LENGTH: usize = 1024;
+/// Name sent in the login handshakes, servers only answer with a hash to it.
+const USERNAME: &str = "Player";
+/// Each step of the ladder gets this long before the next one is tried.
+const STEP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Protocol era of a server too old for the 1.7 status handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Era {
+    /// 1.4 to 1.6, answering the 0xFE 0x01 ping with their version
+    Release,
+    /// Beta 1.8 to 1.3, answering the bare 0xFE ping
+    LateBeta,
+    /// Beta 1.5 to 1.7, no ping but a login handshake with UTF-16 strings
+    Beta,
+    /// Alpha to Beta 1.4, a login handshake with UTF-8 strings
+    Alpha,
+}
+
+impl Era {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Era::Release => "release",
+            Era::LateBeta => "late_beta",
+            Era::Beta => "beta",
+            Era::Alpha => "alpha",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "release" => Some(Era::Release),
+            "late_beta" => Some(Era::LateBeta),
+            "beta" => Some(Era::Beta),
+            "alpha" => Some(Era::Alpha),
+            _ => None,
+        }
+    }
+
+    /// Version name of servers whose reply does not tell theirs.
+    fn version_name(&self) -> &'static str {
+        match self {
+            Era::Release => "1.4-1.6",
+            Era::LateBeta => "Beta 1.8-1.3",
+            Era::Beta => "Beta 1.5-1.7",
+            Era::Alpha => "Alpha-Beta 1.4",
+        }
+    }
+}
+
+/// Whether `banner`, the reply to a modern handshake, is the kick packet of a
+/// pre-1.7 server.
+pub fn speaks_legacy(banner: &[u8]) -> bool {
+    banner.len() >= 3 && banner[0] == KICK
+}
+
+/// Tries the handshakes of older and older eras, each on a new connection,
+/// and describes the server with the first one it answers.
+pub async fn get_status(ip: &str, port: u16, proxy: Option<ProxyVersion>) -> io::Result<Status> {
+    if let Ok(Ok(status)) = timeout(STEP_TIMEOUT, ping(ip, port, proxy)).await {
+        return Ok(status);
+    }
+    for era in [Era::Beta, Era::Alpha] {
+        if let Ok(Ok(())) = timeout(STEP_TIMEOUT, login_handshake(ip, port, proxy, era)).await {
+            return Ok(status_of(
+                era,
+                Players::default(),
+                era.version_name(),
+                0,
+                "",
+            ));
+        }
+    }
+    Err(io::Error::new(
+        ErrorKind::InvalidData,
+        "no pre-1.7 handshake was answered",
+    ))
+}
+
+/// The 1.6 ping. 1.4 and 1.5 ignore what follows 0xFE 0x01 and answer alike,
+/// Beta 1.8 to 1.3 stop at 0xFE and answer with an older format.
+async fn ping(ip: &str, port: u16, proxy: Option<ProxyVersion>) -> io::Result<Status> {
+    let mut conn = Connection::connect_with(&format!("{}:{}", ip, port), proxy).await?;
+
+    let mut packet = vec![PING, 0x01, 0xFA];
+    write_utf16("MC|PingHost", &mut packet);
+    packet.extend_from_slice(&(7 + 2 * ip.encode_utf16().count() as u16).to_be_bytes());
+    packet.push(PING_PROTOCOL);
+    write_utf16(ip, &mut packet);
+    packet.extend_from_slice(&(port as i32).to_be_bytes());
+    conn.write_all(&packet).await?;
+
+    if conn.read_u8().await? != KICK {
+        return Err(ErrorKind::InvalidData.into());
+    }
+    parse_ping(&read_utf16(&mut conn).await?)
+}
+
+fn parse_ping(reply: &str) -> io::Result<Status> {
+    let number = |field: &str| field.trim().parse::<i64>().unwrap_or_default();
+
+    if let Some(fields) = reply.strip_prefix("§1\0") {
+        // Protocol, version, MOTD, online and max
+        let fields: Vec<&str> = fields.split('\0').collect();
+        let [protocol, version, motd, online, max] = fields[..] else {
+            return Err(ErrorKind::InvalidData.into());
+        };
+        let players = Players {
+            online: number(online),
+            max: number(max),
+            sample: None,
+        };
+        return Ok(status_of(
+            Era::Release,
+            players,
+            version,
+            number(protocol),
+            motd,
+        ));
+    }
+
+    // MOTD, online and max, the MOTD may contain § itself
+    let mut fields = reply.rsplitn(3, '§');
+    let (Some(max), Some(online), Some(motd)) = (fields.next(), fields.next(), fields.next())
+    else {
+        // A kick of an older server that took 0xFE for a bad packet
+        return Err(ErrorKind::InvalidData.into());
+    };
+    let (Ok(online), Ok(max)) = (online.parse(), max.parse()) else {
+        return Err(ErrorKind::InvalidData.into());
+    };
+    let players = Players {
+        online,
+        max,
+        sample: None,
+    };
+    Ok(status_of(
+        Era::LateBeta,
+        players,
+        Era::LateBeta.version_name(),
+        0,
+        motd,
+    ))
+}
+
+/// Sends the login handshake of `era`, succeeding when the server answers
+/// with a hash or a kick in the same string encoding.
+async fn login_handshake(
+    ip: &str,
+    port: u16,
+    proxy: Option<ProxyVersion>,
+    era: Era,
+) -> io::Result<()> {
+    let mut conn = Connection::connect_with(&format!("{}:{}", ip, port), proxy).await?;
+
+    let mut packet = vec![HANDSHAKE];
+    match era {
+        Era::Alpha => write_utf8(USERNAME, &mut packet),
+        _ => write_utf16(USERNAME, &mut packet),
+    }
+    conn.write_all(&packet).await?;
+
+    let id = conn.read_u8().await?;
+    if id != HANDSHAKE && id != KICK {
+        return Err(ErrorKind::InvalidData.into());
+    }
+    let reply = match era {
+        Era::Alpha => read_utf8(&mut conn).await?,
+        _ => read_utf16(&mut conn).await?,
+    };
+    // Read in the wrong encoding, a reply turns into other characters
+    match reply.is_ascii() && !reply.chars().any(|c| c.is_ascii_control()) {
+        true => Ok(()),
+        false => Err(ErrorKind::InvalidData.into()),
+    }
+}
+
+fn status_of(era: Era, players: Players, version: &str, protocol: i64, motd: &str) -> Status {
+    Status {
+        players,
+        version: Version {
+            name: version.to_string(),
+            protocol,
+        },
+        description: Value::String(motd.to_string()),
+        favicon: None,
+        forge_data: None,
+        modinfo: None,
+        raw: None,
+        anomalous: false,
+        era: Some(era),
+    }
+}
+
+/// Length in UTF-16 units, then the units big-endian.
+fn write_utf16(value: &str, out: &mut Vec<u8>) {
+    let units: Vec<u16> = value.encode_utf16().collect();
+    out.extend_from_slice(&(units.len() as u16).to_be_bytes());
+    for unit in units {
+        out.extend_from_slice(&unit.to_be_bytes());
+    }
+}
+
+/// Length in bytes, then the bytes, like Java's `writeUTF` for ASCII.
+fn write_utf8(value: &str, out: &mut Vec<u8>) {
+    out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    out.extend_from_slice(value.as_bytes());
+}
+
+async fn read_utf16(conn: &mut Connection) -> io::Result<String> {
+    let length = conn.read_u16().await? as usize;
+    if length > MAX_LENGTH {
+        return Err(ErrorKind::InvalidData.into());
+    }
+    let mut bytes = vec![0; length * 2];
+    conn.read_exact(&mut bytes).await?;
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+        .collect();
+    String::from_utf16(&units).map_err(|_| ErrorKind::InvalidData.into())
+}
+
+async fn read_utf8(conn: &mut Connection) -> io::Result<String> {
+    let length = conn.read_u16().await? as usize;
+    if length > MAX_LENGTH {
+        return Err(ErrorKind::InvalidData.into());
+    }
+    let mut bytes = vec![0; length];
+    conn.read_exact(&mut bytes).await?;
+    String::from_utf8(bytes).map_err(|_| ErrorKind::InvalidData.into())
+}
@@ -1,2 +1,7 @@
+pub mod bedrock;
+pub mod forge;
+pub mod legacy;
+pub mod path;
+pub mod query;
 pub mod with_connection;
 pub mod without_connection;
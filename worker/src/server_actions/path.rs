@@ -0,0 +1,61 @@
+use std::{
+    io::{self, Error, ErrorKind},
+    net::IpAddr,
+};
+
+use tokio::process::Command;
+
+#[derive(Debug)]
+pub struct PathData {
+    pub hop_count: i32,
+    pub last_hop_network: Option<String>,
+}
+
+/// Runs the system `traceroute` (UDP probes, no privileges needed) and keeps
+/// the number of hops to the server and the network of the router before it.
+pub async fn trace_path(ip: &IpAddr) -> io::Result<PathData> {
+    let output = Command::new("traceroute")
+        .args(["-n", "-q", "1", "-w", "1", "-m", "30"])
+        .arg(ip.to_string())
+        .output()
+        .await?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    let mut last_hop = None;
+    for line in stdout.lines().skip(1) {
+        let mut parts = line.split_whitespace();
+        let (Some(hop), Some(addr)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        let Ok(hop) = hop.parse::<i32>() else {
+            continue;
+        };
+        let Ok(addr) = addr.parse::<IpAddr>() else {
+            continue;
+        };
+
+        if addr == *ip {
+            return Ok(PathData {
+                hop_count: hop,
+                last_hop_network: last_hop.map(network_of),
+            });
+        }
+        last_hop = Some(addr);
+    }
+
+    Err(Error::new(ErrorKind::NotFound, "target not reached"))
+}
+
+fn network_of(addr: IpAddr) -> String {
+    match addr {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            format!("{}.{}.{}.0/24", a, b, c)
+        }
+        IpAddr::V6(v6) => {
+            let s = v6.segments();
+            format!("{:x}:{:x}:{:x}::/48", s[0], s[1], s[2])
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    collections::HashMap,
+    io::{self, Error, ErrorKind},
+    time::Duration,
+};
+
+use mine_search::source_port;
+use tokio::{
+    net::{lookup_host, UdpSocket},
+    time::timeout,
+};
+
+use crate::traffic::{record_received, record_sent};
+
+const MAGIC: [u8; 2] = [0xFE, 0xFD];
+const SESSION_ID: i32 = 0x01020304 & 0x0F0F0F0F;
+
+#[derive(Debug, Clone)]
+pub struct QueryData {
+    pub map: Option<String>,
+    pub game_type: Option<String>,
+    pub game_id: Option<String>,
+    pub host_port: Option<i32>,
+    /// `Server software: Plugin 1.0; Other 2.1`, empty on vanilla
+    pub plugins: Option<String>,
+}
+
+/// Full stat request over the GameSpy4 Query protocol (`enable-query=true`).
+pub async fn get_query(ip: &str, port: u16) -> io::Result<QueryData> {
+    let remote = lookup_host(format!("{}:{}", ip, port))
+        .await?
+        .next()
+        .ok_or(ErrorKind::InvalidInput)?;
+    let socket = source_port::bind_udp(&remote).await?;
+    socket.connect(remote).await?;
+
+    let mut request = MAGIC.to_vec();
+    request.push(0x09);
+    request.extend(SESSION_ID.to_be_bytes());
+    let response = exchange(&socket, &request).await?;
+
+    let token: i32 = read_string(&response[5..])
+        .0
+        .trim()
+        .parse()
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "bad challenge token"))?;
+
+    let mut request = MAGIC.to_vec();
+    request.push(0x00);
+    request.extend(SESSION_ID.to_be_bytes());
+    request.extend(token.to_be_bytes());
+    request.extend([0, 0, 0, 0]);
+    let response = exchange(&socket, &request).await?;
+
+    parse_full_stat(&response)
+}
+
+async fn exchange(socket: &UdpSocket, request: &[u8]) -> io::Result<Vec<u8>> {
+    record_sent(socket.send(request).await?);
+
+    let mut buf = vec![0; 4096];
+    let len = timeout(Duration::from_secs(1), socket.recv(&mut buf))
+        .await
+        .map_err(|_| Error::from(ErrorKind::TimedOut))??;
+    record_received(len);
+    buf.truncate(len);
+
+    if buf.len() < 5 || buf[0] != request[2] {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "unexpected query response",
+        ));
+    }
+    Ok(buf)
+}
+
+fn read_string(data: &[u8]) -> (String, usize) {
+    let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    (
+        String::from_utf8_lossy(&data[..end]).to_string(),
+        (end + 1).min(data.len()),
+    )
+}
+
+fn parse_full_stat(response: &[u8]) -> io::Result<QueryData> {
+    // type + session id + "splitnum\0\x80\0"
+    let mut pos = 5 + 11;
+    if response.len() < pos {
+        return Err(Error::new(ErrorKind::InvalidData, "short query response"));
+    }
+
+    let mut values = HashMap::new();
+    loop {
+        let (key, read) = read_string(&response[pos..]);
+        pos += read;
+        if key.is_empty() || pos >= response.len() {
+            break;
+        }
+        let (value, read) = read_string(&response[pos..]);
+        pos += read;
+        values.insert(key, value);
+    }
+
+    Ok(QueryData {
+        map: values.remove("map"),
+        game_type: values.remove("gametype"),
+        game_id: values.remove("game_id"),
+        host_port: values.get("hostport").and_then(|p| p.parse().ok()),
+        plugins: values.remove("plugins").filter(|p| !p.trim().is_empty()),
+    })
+}
@@ -1,45 +1,94 @@
-use std::io::{self, Error, ErrorKind};
+use std::{
+    io::{self, Error, ErrorKind},
+    time::{Duration, Instant},
+};
 
+use mine_search::{
+    classify_kick, contact_url, description_to_str, mentions_queue, model, strip_formatting, Kick,
+};
 use minecraft_protocol::{packet_reader::PacketReader, types::var_int::VarInt, Packet};
-use tokio::net::TcpStream;
+use tokio::time::timeout;
 
 use crate::{
-    conn_wrapper::ConnectionWrapper,
-    packets::{Handshake, LoginStart, PacketActions, SetCompression},
+    conn_wrapper::{
+        decode_varint, read_frame, read_packet, read_uncompressed, write_frame, Connection,
+        ConnectionWrapper,
+    },
+    identity::{self, Identity},
+    nbt::{self, Tag},
+    overrides,
+    packets::{
+        chunk_data_id, client_information, configuration_ids, time_update_id, Handshake,
+        LoginStart, PacketActions, SetCompression, LOGIN_ACKNOWLEDGED_ID,
+    },
+    proxy_protocol::ProxyVersion,
 };
 
+const TICK_SAMPLE_TIME: Duration = Duration::from_secs(4);
+const CONFIGURATION_TIME: Duration = Duration::from_secs(5);
+
 #[derive(Debug)]
 pub struct ExtraData {
     pub license: bool,
     pub white_list: Option<bool>,
+    pub tps: Option<f32>,
+    pub queue_hint: bool,
+    /// Non-vanilla namespaces in the registries sent during configuration
+    pub registry_namespaces: Vec<String>,
+    /// Discord invite or website named in a whitelist or ban kick
+    pub appeal_contact: Option<String>,
+    /// Kicked as banned, most likely our address or probe name
+    pub banned: bool,
+}
+
+impl ExtraData {
+    pub fn to_model(&self) -> model::ExtraData {
+        model::ExtraData {
+            license: self.license,
+            white_list: self.white_list,
+            tps: self.tps,
+            queue_hint: self.queue_hint,
+            registry_namespaces: self.registry_namespaces.clone(),
+            appeal_contact: self.appeal_contact.clone(),
+            banned: self.banned,
+        }
+    }
 }
 
-pub async fn get_extra_data(ip: String, port: u16, protocol: i32) -> io::Result<ExtraData> {
-    let mut conn = TcpStream::connect(&format!("{}:{}", ip, port)).await?;
+pub async fn get_extra_data(
+    ip: String,
+    port: u16,
+    protocol: i32,
+    proxy: Option<ProxyVersion>,
+) -> io::Result<ExtraData> {
+    let mut conn = Connection::connect_with(&format!("{}:{}", ip, port), proxy).await?;
+    let options = overrides::for_host(&ip);
+    let protocol = options.protocol.unwrap_or(protocol);
 
     conn.write_packet(Handshake {
         protocol: VarInt(protocol),
-        server_address: ip,
+        server_address: options.hostname.clone().unwrap_or(ip),
         server_port: port,
         next_state: VarInt(2),
     })
     .await?;
 
+    let identity = identity::next();
     LoginStart {
-        name: "LookupPlayer".to_string(),
-        uuid: 0x1f6969963dace4643bfa0c99a4db549,
+        name: identity.name.clone(),
+        uuid: identity.uuid,
     }
     .get_by_protocol(protocol)
     .write(&mut conn)
     .await
     .unwrap();
 
-    // let mut threshold = None;
-    let packet = Packet::read_uncompressed(&mut conn).await?;
+    let mut threshold = None;
+    let packet = read_uncompressed(&mut conn).await?;
 
     let packet = if packet.packet_id.0 == 0x03 {
-        let threshold = Some(SetCompression::deserialize(packet)?.threshold.0);
-        Packet::read(&mut conn, threshold).await?
+        threshold = Some(SetCompression::deserialize(packet)?.threshold.0);
+        read_packet(&mut conn, threshold).await?
     } else {
         Packet::UnCompressed(packet)
     };
@@ -50,25 +99,255 @@ pub async fn get_extra_data(ip: String, port: u16, protocol: i32) -> io::Result<
         } else {
             "error".to_string()
         };
-        if reason == "{\"text\":\"You are not whitelisted on this server!\"}" {
-            return Ok(ExtraData {
-                license: false,
-                white_list: Some(true),
-            });
-        }
+        let text = kick_text(&reason);
 
-        return Err(Error::new(ErrorKind::InvalidData, reason));
+        // Only offline-mode servers get this far without an encryption request
+        return match classify_kick(&text) {
+            Some(kick) => Ok(ExtraData {
+                license: false,
+                white_list: (kick == Kick::WhiteList).then_some(true),
+                tps: None,
+                queue_hint: false,
+                registry_namespaces: vec![],
+                appeal_contact: contact_url(&text),
+                banned: kick == Kick::Ban,
+            }),
+            None => Err(Error::new(ErrorKind::InvalidData, reason)),
+        };
     }
 
     if packet.packet_id().await?.0 != 0x02 {
         return Ok(ExtraData {
             license: true,
             white_list: None,
+            tps: None,
+            queue_hint: false,
+            registry_namespaces: vec![],
+            appeal_contact: None,
+            banned: false,
         });
     }
 
+    let (mut kick, mut appeal_contact) = (None, None);
+    let (observation, registry_namespaces) =
+        match configure(&mut conn, threshold, protocol, &identity).await {
+            Ok(namespaces) => (
+                observe_play(&mut conn, threshold, protocol).await,
+                namespaces,
+            ),
+            Err(e) => {
+                // Whitelist plugins often kick during configuration instead
+                if e.kind() == ErrorKind::ConnectionAborted {
+                    let text = kick_text(&e.to_string());
+                    kick = classify_kick(&text);
+                    if kick.is_some() {
+                        appeal_contact = contact_url(&text);
+                    }
+                }
+                (PlayObservation::default(), vec![])
+            }
+        };
+
     Ok(ExtraData {
         license: false,
-        white_list: Some(false),
+        white_list: Some(kick == Some(Kick::WhiteList)),
+        tps: observation.tps,
+        queue_hint: observation.queue_hint,
+        registry_namespaces,
+        appeal_contact,
+        banned: kick == Some(Kick::Ban),
     })
 }
+
+/// Plain text of a disconnect reason with formatting codes removed.
+fn kick_text(reason: &str) -> String {
+    let text = serde_json::from_str(reason)
+        .ok()
+        .and_then(|value| description_to_str(value).ok())
+        .unwrap_or_else(|| reason.to_string());
+    strip_formatting(&text)
+}
+
+/// Acknowledges the login and answers the configuration state of 1.20.2+ until
+/// the server switches to play. Known packs are echoed back so the server can
+/// skip sending registries we already "have".
+async fn configure(
+    conn: &mut Connection,
+    threshold: Option<i32>,
+    protocol: i32,
+    identity: &Identity,
+) -> io::Result<Vec<String>> {
+    let Some(ids) = configuration_ids(protocol) else {
+        return Ok(vec![]);
+    };
+    let mut namespaces = vec![];
+
+    write_frame(conn, threshold, LOGIN_ACKNOWLEDGED_ID, &[]).await?;
+    write_frame(
+        conn,
+        threshold,
+        ids.client_information,
+        &client_information(identity, protocol),
+    )
+    .await?;
+
+    let started = Instant::now();
+    while let Some(remaining) = CONFIGURATION_TIME.checked_sub(started.elapsed()) {
+        let (id, payload) = timeout(remaining, read_frame(conn, threshold))
+            .await
+            .map_err(|_| Error::from(ErrorKind::TimedOut))??;
+
+        if id == ids.finish {
+            write_frame(conn, threshold, ids.finish_ack, &[]).await?;
+            namespaces.sort();
+            namespaces.dedup();
+            return Ok(namespaces);
+        } else if id == ids.registry_data {
+            if let Ok(found) = registry_namespaces(&payload, protocol) {
+                namespaces.extend(found);
+            }
+        } else if id == ids.keep_alive {
+            write_frame(conn, threshold, ids.keep_alive_reply, &payload).await?;
+        } else if id == ids.ping {
+            write_frame(conn, threshold, ids.pong, &payload).await?;
+        } else if Some(id) == ids.known_packs {
+            if let Some(reply) = ids.known_packs_reply {
+                write_frame(conn, threshold, reply, &payload).await?;
+            }
+        } else if id == ids.disconnect {
+            return Err(Error::new(
+                ErrorKind::ConnectionAborted,
+                text_component(&payload, protocol).unwrap_or_default(),
+            ));
+        }
+    }
+
+    Err(Error::from(ErrorKind::TimedOut))
+}
+
+pub fn read_string(data: &[u8]) -> io::Result<(String, usize)> {
+    let (len, read) = decode_varint(data)?;
+    let end = read + len.max(0) as usize;
+    if end > data.len() {
+        return Err(Error::from(ErrorKind::UnexpectedEof));
+    }
+    Ok((String::from_utf8_lossy(&data[read..end]).to_string(), end))
+}
+
+fn namespace_of(id: &str) -> Option<String> {
+    id.split_once(':')
+        .map(|(namespace, _)| namespace)
+        .filter(|namespace| *namespace != "minecraft")
+        .map(|namespace| namespace.to_string())
+}
+
+/// Text components are JSON strings before 1.20.3 and network NBT after.
+fn text_component(payload: &[u8], protocol: i32) -> io::Result<String> {
+    if protocol >= 765 {
+        Ok(nbt::read(payload, true)?.0.to_json().to_string())
+    } else {
+        Ok(read_string(payload)?.0)
+    }
+}
+
+/// Registry Data is one NBT compound of all registries up to 1.20.4 and one
+/// packet per registry with optional NBT per entry from 1.20.5.
+fn registry_namespaces(payload: &[u8], protocol: i32) -> io::Result<Vec<String>> {
+    let mut namespaces = vec![];
+
+    if protocol < 766 {
+        let (root, _) = nbt::read(payload, true)?;
+        let Tag::Compound(registries) = &root else {
+            return Ok(namespaces);
+        };
+
+        for (registry, value) in registries {
+            namespaces.extend(namespace_of(registry));
+            if let Some(Tag::List(entries)) = value.get("value") {
+                for entry in entries {
+                    if let Some(Tag::String(name)) = entry.get("name") {
+                        namespaces.extend(namespace_of(name));
+                    }
+                }
+            }
+        }
+        return Ok(namespaces);
+    }
+
+    let (registry, mut pos) = read_string(payload)?;
+    namespaces.extend(namespace_of(&registry));
+
+    let (count, read) = decode_varint(&payload[pos..])?;
+    pos += read;
+    for _ in 0..count {
+        let (entry, read) = read_string(&payload[pos..])?;
+        pos += read;
+        namespaces.extend(namespace_of(&entry));
+
+        let has_data = *payload.get(pos).ok_or(ErrorKind::UnexpectedEof)? != 0;
+        pos += 1;
+        if has_data {
+            pos += nbt::read(&payload[pos..], true)?.1;
+        }
+    }
+
+    Ok(namespaces)
+}
+
+#[derive(Debug, Default)]
+struct PlayObservation {
+    tps: Option<f32>,
+    queue_hint: bool,
+}
+
+/// Skims play-state packets for a few seconds. Vanilla servers send the world
+/// age every 20 ticks, so the age advanced per wall-clock second approximates
+/// the tick rate. Queue servers typically keep the player in a world without
+/// chunks and talk about the queue in chat or the action bar.
+async fn observe_play(
+    conn: &mut Connection,
+    threshold: Option<i32>,
+    protocol: i32,
+) -> PlayObservation {
+    let (Some(time_update), Some(chunk_data)) = (time_update_id(protocol), chunk_data_id(protocol))
+    else {
+        return PlayObservation::default();
+    };
+
+    let started = Instant::now();
+    let mut samples: Vec<(Instant, i64)> = vec![];
+    let mut chunks = 0;
+    let mut queue_message = false;
+
+    while let Some(remaining) = TICK_SAMPLE_TIME.checked_sub(started.elapsed()) {
+        let (id, payload) = match timeout(remaining, read_frame(conn, threshold)).await {
+            Ok(Ok(frame)) => frame,
+            _ => break,
+        };
+
+        if id == time_update && payload.len() >= 8 {
+            let world_age = i64::from_be_bytes(payload[..8].try_into().unwrap());
+            samples.push((Instant::now(), world_age));
+        } else if id == chunk_data {
+            chunks += 1;
+        } else if !queue_message {
+            queue_message = mentions_queue(&String::from_utf8_lossy(&payload));
+        }
+    }
+
+    let tps = match (samples.first(), samples.last()) {
+        (Some(first), Some(last)) if last.0.duration_since(first.0).as_secs_f32() >= 1.0 => {
+            let elapsed = last.0.duration_since(first.0).as_secs_f32();
+            Some(((last.1 - first.1) as f32 / elapsed).clamp(0.0, 20.0))
+        }
+        _ => None,
+    };
+
+    // Only call the world void when we clearly were in play state
+    let void_world = !samples.is_empty() && chunks == 0;
+
+    PlayObservation {
+        tps,
+        queue_hint: queue_message || void_world,
+    }
+}
@@ -1,57 +1,221 @@
 use std::io::{self, ErrorKind};
 
+use mine_search::model;
 use minecraft_protocol::types::var_int::VarInt;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
-use tokio::net::TcpStream;
+use tokio::io::AsyncWriteExt;
 
 use crate::{
-    conn_wrapper::ConnectionWrapper,
-    packets::{Handshake, StatusRequest, StatusResponse},
+    conn_wrapper::{Connection, ConnectionWrapper},
+    overrides,
+    packets::{Handshake, StatusRequest, StatusResponse, MAX_SAMPLE_PLAYERS, MAX_STRING_LENGTH},
+    proxy_protocol::ProxyVersion,
+    server_actions::legacy::Era,
 };
 
-#[derive(Deserialize, Debug)]
+impl Status {
+    /// The public type of the status, for sinks and library consumers.
+    pub fn to_model(&self) -> model::ServerStatus {
+        model::ServerStatus {
+            players: model::Players {
+                online: self.players.online,
+                max: self.players.max,
+                sample: self.players.sample.as_ref().map(|sample| {
+                    sample
+                        .iter()
+                        .map(|player| model::Player {
+                            id: player.id.clone(),
+                            name: player.name.clone(),
+                        })
+                        .collect()
+                }),
+            },
+            version: model::Version {
+                name: self.version.name.clone(),
+                protocol: self.version.protocol,
+            },
+            description: self.description.clone(),
+            favicon: self.favicon.clone(),
+            forge_data: self.forge_data.clone(),
+            modinfo: self.modinfo.clone(),
+            era: self.era.map(|era| era.as_str().to_string()),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Status {
+    #[serde(default)]
     pub players: Players,
+    #[serde(default)]
     pub version: Version,
+    #[serde(default)]
     pub description: Value,
+    #[serde(default, deserialize_with = "lenient_favicon")]
+    pub favicon: Option<String>,
+    /// Mods and channels of Forge 1.13+ servers
+    #[serde(default, rename = "forgeData")]
+    pub forge_data: Option<Value>,
+    /// Mod list of Forge 1.7 to 1.12 servers
+    #[serde(default)]
+    pub modinfo: Option<Value>,
+    /// Response body, kept when it only parsed leniently
+    #[serde(skip)]
+    pub raw: Option<String>,
+    /// Longer than vanilla allows, or listing more sample players than kept
+    #[serde(skip)]
+    pub anomalous: bool,
+    /// Set for servers that only answered a pre-1.7 handshake
+    #[serde(skip)]
+    pub era: Option<Era>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct Players {
+    #[serde(default, deserialize_with = "lenient_i64")]
     pub online: i64,
+    #[serde(default, deserialize_with = "lenient_i64")]
     pub max: i64,
+    #[serde(default, deserialize_with = "lenient_sample")]
     pub sample: Option<Vec<Player>>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Player {
     pub id: String,
     pub name: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct Version {
+    #[serde(default, deserialize_with = "lenient_string")]
     pub name: String,
+    #[serde(default, deserialize_with = "lenient_i64")]
     pub protocol: i64,
 }
 
-pub async fn get_status(ip: &str, port: u16) -> io::Result<Status> {
-    let mut conn = TcpStream::connect(&format!("{}:{}", ip, port)).await?;
+/// Numbers that some servers send as strings or floats.
+fn lenient_i64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Number(n) => n
+            .as_i64()
+            .or_else(|| n.as_f64().map(|f| f as i64))
+            .unwrap_or_default(),
+        Value::String(s) => s.trim().parse().unwrap_or_default(),
+        _ => 0,
+    })
+}
+
+fn lenient_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::String(s) => s,
+        Value::Null => String::new(),
+        other => other.to_string(),
+    })
+}
+
+fn lenient_favicon<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::String(s) => Some(s),
+        _ => None,
+    })
+}
+
+/// Drops sample entries that are not `{id, name}` objects instead of failing.
+fn lenient_sample<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<Player>>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Array(players) => Some(
+            players
+                .into_iter()
+                .filter_map(|p| serde_json::from_value(p).ok())
+                .take(MAX_SAMPLE_PLAYERS)
+                .collect(),
+        ),
+        _ => None,
+    })
+}
+
+/// Parses a status body, falling back to the first JSON value when there is
+/// trailing data. The body is kept whenever it strays from the documented shape.
+pub fn parse_status(body: &str) -> io::Result<Status> {
+    let (value, exact) = match serde_json::from_str::<Value>(body) {
+        Ok(value) => {
+            let exact = documented_shape(&value);
+            (value, exact)
+        }
+        Err(_) => (
+            serde_json::Deserializer::from_str(body)
+                .into_iter::<Value>()
+                .next()
+                .and_then(|v| v.ok())
+                .ok_or(ErrorKind::InvalidData)?,
+            false,
+        ),
+    };
+    if !value.is_object() {
+        return Err(ErrorKind::InvalidData.into());
+    }
 
-    conn.write_packet(Handshake {
-        protocol: VarInt(765),
-        server_address: ip.to_string(),
+    let anomalous = body.chars().count() > MAX_STRING_LENGTH
+        || value["players"]["sample"]
+            .as_array()
+            .is_some_and(|sample| sample.len() > MAX_SAMPLE_PLAYERS);
+
+    let mut status: Status = serde_json::from_value(value).map_err(|_| ErrorKind::InvalidData)?;
+    if !exact {
+        status.raw = Some(body.to_string());
+    }
+    status.anomalous = anomalous;
+    Ok(status)
+}
+
+fn documented_shape(value: &Value) -> bool {
+    value["players"]["online"].is_i64()
+        && value["players"]["max"].is_i64()
+        && value["version"]["name"].is_string()
+        && value["version"]["protocol"].is_i64()
+        && !value["description"].is_null()
+}
+
+/// Protocol version sent in the status handshake.
+pub const STATUS_PROTOCOL: i32 = 765;
+
+pub async fn get_status(ip: &str, port: u16, proxy: Option<ProxyVersion>) -> io::Result<Status> {
+    let mut conn = Connection::connect_with(&format!("{}:{}", ip, port), proxy).await?;
+    get_status_on(&mut conn, ip, port).await
+}
+
+/// Connects and sends only the status handshake before closing, far cheaper for
+/// the server than answering a status request. Succeeds when it was accepted.
+pub async fn check_alive(ip: &str, port: u16, proxy: Option<ProxyVersion>) -> io::Result<()> {
+    let mut conn = Connection::connect_with(&format!("{}:{}", ip, port), proxy).await?;
+    conn.write_packet(status_handshake(ip, port)).await?;
+
+    // A clean close, the abortive one could drop the handshake unsent
+    conn.shutdown().await
+}
+
+/// Status handshake for `ip`, with the protocol version and server address
+/// of its `PROBE_OVERRIDES` entry.
+fn status_handshake(ip: &str, port: u16) -> Handshake {
+    let options = overrides::for_host(ip);
+    Handshake {
+        protocol: VarInt(options.protocol.unwrap_or(STATUS_PROTOCOL)),
+        server_address: options.hostname.clone().unwrap_or(ip.to_string()),
         server_port: port,
         next_state: VarInt(1),
-    })
-    .await?;
+    }
+}
+
+pub async fn get_status_on(conn: &mut Connection, ip: &str, port: u16) -> io::Result<Status> {
+    conn.write_packet(status_handshake(ip, port)).await?;
 
     conn.write_packet(StatusRequest {}).await?;
 
     let response: StatusResponse = conn.read_packet().await?;
 
-    let value: Status =
-        serde_json::from_str(&response.response).map_err(|_| ErrorKind::InvalidData)?;
-    Ok(value)
+    parse_status(&response.response)
 }
@@ -0,0 +1,249 @@
+use std::{env, fs::OpenOptions, mem, time::Duration};
+
+use serde_json::{json, Value};
+use tokio::{
+    fs::File,
+    io::AsyncWriteExt,
+    sync::{mpsc, oneshot},
+    time::{interval, MissedTickBehavior},
+};
+
+use crate::reporter::Discovery;
+
+/// Rows a sink may hold before senders wait for it to catch up.
+const QUEUE_SIZE: usize = 10_000;
+
+/// How a sink groups rows: up to `size` per write, none waiting longer than
+/// `interval`. Set per sink with `SINK_BATCH_SIZE` and `SINK_FLUSH_MS`.
+#[derive(Debug, Clone, Copy)]
+pub struct Batching {
+    pub size: usize,
+    pub interval: Duration,
+}
+
+impl Batching {
+    /// Settings of sink `name`, the defaults where not configured.
+    pub fn of(name: &str, size: usize, interval_ms: u64) -> Self {
+        let size = setting("SINK_BATCH_SIZE", name)
+            .map(|n| n.parse().expect("SINK_BATCH_SIZE size"))
+            .unwrap_or(size);
+        let interval_ms = setting("SINK_FLUSH_MS", name)
+            .map(|n| n.parse().expect("SINK_FLUSH_MS milliseconds"))
+            .unwrap_or(interval_ms);
+        Self {
+            size: size.max(1),
+            interval: Duration::from_millis(interval_ms.max(1)),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "batches of {}, every {}ms",
+            self.size,
+            self.interval.as_millis()
+        )
+    }
+}
+
+/// `name=value` of a `SINK_*` list.
+fn setting(var: &str, name: &str) -> Option<String> {
+    env::var(var)
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|entry| entry.split_once('='))
+        .find(|(sink, _)| sink.trim() == name)
+        .map(|(_, value)| value.trim().to_string())
+}
+
+enum Message {
+    Row(Value),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Destination of discoveries besides the database.
+enum Sink {
+    /// JSON lines appended to a file
+    Ndjson { path: String, file: File },
+    /// Records produced through a Kafka REST proxy
+    Kafka {
+        url: String,
+        client: reqwest::Client,
+    },
+}
+
+impl Sink {
+    fn name(&self) -> &'static str {
+        match self {
+            Sink::Ndjson { .. } => "ndjson",
+            Sink::Kafka { .. } => "kafka",
+        }
+    }
+
+    async fn write(&mut self, rows: Vec<Value>) {
+        match self {
+            Sink::Ndjson { path, file } => {
+                let mut bytes = vec![];
+                for row in rows {
+                    bytes.extend_from_slice(row.to_string().as_bytes());
+                    bytes.push(b'\n');
+                }
+                if let Err(e) = file.write_all(&bytes).await {
+                    eprintln!("[!] Could not write to {}: {}", path, e);
+                }
+            }
+            Sink::Kafka { url, client } => {
+                let records: Vec<Value> = rows
+                    .into_iter()
+                    .map(|row| {
+                        let key = format!("{}:{}", row["ip"].as_str().unwrap_or(""), row["port"]);
+                        json!({ "key": key, "value": row })
+                    })
+                    .collect();
+                let count = records.len();
+                let response = client
+                    .post(url.as_str())
+                    .header("Content-Type", "application/vnd.kafka.json.v2+json")
+                    .json(&json!({ "records": records }))
+                    .send()
+                    .await
+                    .and_then(|r| r.error_for_status());
+                if let Err(e) = response {
+                    eprintln!("[!] Kafka proxy dropped {} records: {}", count, e);
+                }
+            }
+        }
+    }
+
+    /// Makes what was written durable, on explicit flushes.
+    async fn sync(&mut self) {
+        if let Sink::Ndjson { path, file } = self {
+            if let Err(e) = file.sync_data().await {
+                eprintln!("[!] Could not sync {}: {}", path, e);
+            }
+        }
+    }
+}
+
+/// Discovery sinks besides the database, each writing in batches of its own
+/// size and interval from a task of its own, so a slow destination holds up
+/// neither the others nor scanning until its queue fills.
+pub struct Sinks {
+    sinks: Vec<(String, mpsc::Sender<Message>)>,
+}
+
+impl Sinks {
+    /// Opens the sinks configured with `SINK_NDJSON` and `SINK_KAFKA`.
+    pub fn from_env() -> Self {
+        let mut sinks = vec![];
+        if let Ok(path) = env::var("SINK_NDJSON") {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map(File::from_std)
+                .unwrap_or_else(|e| panic!("Error opening {}: {}", path, e));
+            sinks.push((
+                Sink::Ndjson { path, file },
+                Batching::of("ndjson", 100, 1000),
+            ));
+        }
+        if let Ok(url) = env::var("SINK_KAFKA") {
+            let client = reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()
+                .unwrap();
+            sinks.push((
+                Sink::Kafka { url, client },
+                Batching::of("kafka", 500, 1000),
+            ));
+        }
+
+        Self {
+            sinks: sinks
+                .into_iter()
+                .map(|(sink, batching)| {
+                    let description = format!("{} ({})", sink.name(), batching.describe());
+                    (description, start(sink, batching))
+                })
+                .collect(),
+        }
+    }
+
+    pub fn describe(&self) -> Option<String> {
+        if self.sinks.is_empty() {
+            return None;
+        }
+        let names: Vec<&str> = self.sinks.iter().map(|(name, _)| name.as_str()).collect();
+        Some(names.join(", "))
+    }
+
+    /// Queues a discovery for every sink.
+    pub async fn discovery(&self, discovery: &Discovery<'_>) {
+        if self.sinks.is_empty() {
+            return;
+        }
+        let row = serde_json::to_value(discovery.to_model()).unwrap();
+        for (_, sink) in &self.sinks {
+            let _ = sink.send(Message::Row(row.clone())).await;
+        }
+    }
+
+    /// Waits until every queued discovery is written and synced.
+    pub async fn flush(&self) {
+        let mut waiting = vec![];
+        for (_, sink) in &self.sinks {
+            let (done, rx) = oneshot::channel();
+            let _ = sink.send(Message::Flush(done)).await;
+            waiting.push(rx);
+        }
+        for rx in waiting {
+            let _ = rx.await;
+        }
+    }
+}
+
+fn start(mut sink: Sink, batching: Batching) -> mpsc::Sender<Message> {
+    let (tx, mut rx) = mpsc::channel::<Message>(QUEUE_SIZE);
+
+    tokio::spawn(async move {
+        let mut batch: Vec<Value> = vec![];
+        let mut ticker = interval(batching.interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            let mut done = None;
+            let closed = tokio::select! {
+                message = rx.recv() => match message {
+                    Some(Message::Row(row)) => {
+                        batch.push(row);
+                        if batch.len() < batching.size {
+                            continue;
+                        }
+                        false
+                    }
+                    Some(Message::Flush(tx)) => {
+                        done = Some(tx);
+                        false
+                    }
+                    None => true,
+                },
+                _ = ticker.tick() => false,
+            };
+
+            if !batch.is_empty() {
+                sink.write(mem::take(&mut batch)).await;
+            }
+            if done.is_some() || closed {
+                sink.sync().await;
+            }
+            if let Some(done) = done {
+                let _ = done.send(());
+            }
+            if closed {
+                break;
+            }
+        }
+    });
+
+    tx
+}
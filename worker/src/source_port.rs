@@ -0,0 +1,103 @@
+use std::{
+    env,
+    io::{self, ErrorKind},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    ops::RangeInclusive,
+    sync::OnceLock,
+};
+
+use rand::Rng;
+use tokio::net::{lookup_host, TcpSocket, TcpStream, ToSocketAddrs, UdpSocket};
+
+/// Ports tried before giving up when the picked ones are taken.
+const ATTEMPTS: usize = 8;
+
+/// Local port range probes bind to, from `SOURCE_PORT_RANGE` (`low-high`).
+/// Without it the kernel picks from its ephemeral range.
+pub fn range() -> Option<&'static RangeInclusive<u16>> {
+    static RANGE: OnceLock<Option<RangeInclusive<u16>>> = OnceLock::new();
+
+    RANGE
+        .get_or_init(|| {
+            let value = env::var("SOURCE_PORT_RANGE").ok()?;
+            let (low, high) = value
+                .split_once('-')
+                .and_then(|(low, high)| Some((low.trim().parse().ok()?, high.trim().parse().ok()?)))
+                .filter(|(low, high): &(u16, u16)| *low > 0 && low <= high)
+                .unwrap_or_else(|| panic!("SOURCE_PORT_RANGE must look like 40000-60000"));
+            Some(low..=high)
+        })
+        .as_ref()
+}
+
+fn local_addr(remote: &SocketAddr, port: u16) -> SocketAddr {
+    match remote {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, port).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, port).into(),
+    }
+}
+
+/// Another socket holds the port, or the same four-tuple is still in use.
+fn port_taken(e: &io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::AddrInUse | ErrorKind::AddrNotAvailable)
+}
+
+/// Connects from a random port of `range()`, or any port without one.
+pub async fn connect_tcp(remote: SocketAddr) -> io::Result<TcpStream> {
+    let Some(range) = range() else {
+        return TcpStream::connect(remote).await;
+    };
+
+    let mut last_error = io::Error::from(ErrorKind::AddrInUse);
+    for _ in 0..ATTEMPTS {
+        let port = rand::thread_rng().gen_range(range.clone());
+        let socket = match remote {
+            SocketAddr::V4(_) => TcpSocket::new_v4()?,
+            SocketAddr::V6(_) => TcpSocket::new_v6()?,
+        };
+        // Ports of recently reset probes may still be in TIME_WAIT
+        socket.set_reuseaddr(true)?;
+
+        let result = match socket.bind(local_addr(&remote, port)) {
+            Ok(()) => socket.connect(remote).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Err(e) if port_taken(&e) => last_error = e,
+            result => return result,
+        }
+    }
+
+    Err(last_error)
+}
+
+/// `connect_tcp` for addresses that may need resolving, trying each in turn.
+pub async fn connect_tcp_to<A: ToSocketAddrs>(addr: A) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(ErrorKind::InvalidInput, "no addresses to connect to");
+    for remote in lookup_host(addr).await? {
+        match connect_tcp(remote).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(last_error)
+}
+
+/// UDP socket for talking to `remote`, bound like `connect_tcp`.
+pub async fn bind_udp(remote: &SocketAddr) -> io::Result<UdpSocket> {
+    let Some(range) = range() else {
+        return UdpSocket::bind(local_addr(remote, 0)).await;
+    };
+
+    let mut last_error = io::Error::from(ErrorKind::AddrInUse);
+    for _ in 0..ATTEMPTS {
+        let port = rand::thread_rng().gen_range(range.clone());
+        match UdpSocket::bind(local_addr(remote, port)).await {
+            Err(e) if port_taken(&e) => last_error = e,
+            result => return result,
+        }
+    }
+
+    Err(last_error)
+}
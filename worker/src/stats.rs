@@ -0,0 +1,317 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::traffic;
+
+/// Counters for the end-of-run summary.
+pub struct RunStats {
+    started: Instant,
+    attempted: AtomicU64,
+    reachable: AtomicU64,
+    new_servers: AtomicU64,
+    known_servers: AtomicU64,
+    /// Distinct servers that answered, each counted once in `versions`
+    servers: Mutex<HashMap<(IpAddr, u16), Tally>>,
+    versions: Mutex<HashMap<String, u64>>,
+    errors: Mutex<HashMap<String, u64>>,
+    sample: Mutex<Option<SampleCounts>>,
+}
+
+/// What the census keeps of an answering server.
+#[derive(Default)]
+struct Tally {
+    country: Option<String>,
+    license: Option<bool>,
+}
+
+/// Distinct servers that answered during the run, by version, country and
+/// license, the basis of `mine_search census --run`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CensusCounts {
+    pub servers: u64,
+    pub versions: HashMap<String, u64>,
+    pub countries: HashMap<String, u64>,
+    pub premium: u64,
+    pub cracked: u64,
+    pub license_unknown: u64,
+}
+
+/// Addresses a `--sample` scan went through and how many of them it probed.
+#[derive(Debug, Clone, Copy)]
+pub struct SampleCounts {
+    pub fraction: f64,
+    pub addresses: u64,
+    pub sampled: u64,
+}
+
+/// Servers of a sampled range extrapolated from the ones found, with a 95%
+/// interval treating the count found as Poisson.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SampleEstimate {
+    pub fraction: f64,
+    pub addresses: u64,
+    pub sampled: u64,
+    pub servers_found: u64,
+    pub estimated_servers: u64,
+    pub estimated_low: u64,
+    pub estimated_high: u64,
+    pub estimated_versions: Vec<VersionCount>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionCount {
+    pub version: String,
+    pub count: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunSummary {
+    pub duration_secs: u64,
+    pub attempted: u64,
+    pub reachable: u64,
+    pub hit_rate: f64,
+    pub new_servers: u64,
+    pub known_servers: u64,
+    pub top_versions: Vec<VersionCount>,
+    pub errors: HashMap<String, u64>,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub sample: Option<SampleEstimate>,
+    pub census: CensusCounts,
+}
+
+impl RunStats {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            attempted: AtomicU64::new(0),
+            reachable: AtomicU64::new(0),
+            new_servers: AtomicU64::new(0),
+            known_servers: AtomicU64::new(0),
+            servers: Mutex::new(HashMap::new()),
+            versions: Mutex::new(HashMap::new()),
+            errors: Mutex::new(HashMap::new()),
+            sample: Mutex::new(None),
+        }
+    }
+
+    pub fn attempted(&self) {
+        self.attempted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn reachable(&self) {
+        self.reachable.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stored(&self, new: bool) {
+        if new {
+            self.new_servers.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.known_servers.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts the version of a server that answered, once per server even
+    /// when it is probed again during the run.
+    pub fn responded(&self, ip: IpAddr, port: u16, version: &str, country: Option<&str>) {
+        let mut servers = self.servers.lock().unwrap();
+        if servers.contains_key(&(ip, port)) {
+            return;
+        }
+        servers.insert(
+            (ip, port),
+            Tally {
+                country: country.map(str::to_string),
+                license: None,
+            },
+        );
+        drop(servers);
+        *self
+            .versions
+            .lock()
+            .unwrap()
+            .entry(version.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn error(&self, kind: &str) {
+        *self
+            .errors
+            .lock()
+            .unwrap()
+            .entry(kind.to_string())
+            .or_default() += 1;
+    }
+
+    /// Records the license check of the login probe of a server that answered.
+    pub fn licensed(&self, ip: IpAddr, port: u16, license: bool) {
+        if let Some(tally) = self.servers.lock().unwrap().get_mut(&(ip, port)) {
+            tally.license = Some(license);
+        }
+    }
+
+    pub fn sampled(&self, counts: SampleCounts) {
+        *self.sample.lock().unwrap() = Some(counts);
+    }
+
+    pub fn summary(&self) -> RunSummary {
+        let attempted = self.attempted.load(Ordering::Relaxed);
+        let reachable = self.reachable.load(Ordering::Relaxed);
+
+        let mut top_versions: Vec<VersionCount> = self
+            .versions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(version, count)| VersionCount {
+                version: version.clone(),
+                count: *count,
+            })
+            .collect();
+        top_versions.sort_by(|a, b| b.count.cmp(&a.count).then(a.version.cmp(&b.version)));
+        let servers_found = self.servers.lock().unwrap().len() as u64;
+        top_versions.truncate(10);
+        let sample = (*self.sample.lock().unwrap())
+            .map(|counts| estimate(counts, servers_found, &top_versions));
+
+        RunSummary {
+            duration_secs: self.started.elapsed().as_secs(),
+            attempted,
+            reachable,
+            hit_rate: if attempted == 0 {
+                0.0
+            } else {
+                reachable as f64 / attempted as f64
+            },
+            new_servers: self.new_servers.load(Ordering::Relaxed),
+            known_servers: self.known_servers.load(Ordering::Relaxed),
+            top_versions,
+            errors: self.errors.lock().unwrap().clone(),
+            bytes_sent: traffic::RUN.sent(),
+            bytes_received: traffic::RUN.received(),
+            sample,
+            census: self.census(),
+        }
+    }
+
+    fn census(&self) -> CensusCounts {
+        let mut census = CensusCounts {
+            versions: self.versions.lock().unwrap().clone(),
+            ..Default::default()
+        };
+        for tally in self.servers.lock().unwrap().values() {
+            census.servers += 1;
+            if let Some(country) = &tally.country {
+                *census.countries.entry(country.clone()).or_default() += 1;
+            }
+            match tally.license {
+                Some(true) => census.premium += 1,
+                Some(false) => census.cracked += 1,
+                None => census.license_unknown += 1,
+            }
+        }
+        census
+    }
+}
+
+fn estimate(counts: SampleCounts, found: u64, versions: &[VersionCount]) -> SampleEstimate {
+    let scale = counts.addresses as f64 / counts.sampled.max(1) as f64;
+    let margin = 1.96 * (found as f64).sqrt();
+
+    SampleEstimate {
+        fraction: counts.fraction,
+        addresses: counts.addresses,
+        sampled: counts.sampled,
+        servers_found: found,
+        estimated_servers: (found as f64 * scale).round() as u64,
+        // At least the servers actually found
+        estimated_low: ((found as f64 - margin) * scale).round().max(found as f64) as u64,
+        estimated_high: ((found as f64 + margin) * scale).round() as u64,
+        estimated_versions: versions
+            .iter()
+            .map(|v| VersionCount {
+                version: v.version.clone(),
+                count: (v.count as f64 * scale).round() as u64,
+            })
+            .collect(),
+    }
+}
+
+/// Counters of one scan worker, shared with the stuck-worker watchdog.
+pub struct WorkerStats {
+    id: usize,
+    started: Instant,
+    attempts: AtomicU64,
+    hits: AtomicU64,
+    consecutive_errors: AtomicU64,
+    /// Milliseconds since `started` at the end of the last attempt
+    last_activity: AtomicU64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorkerSnapshot {
+    pub id: usize,
+    pub attempts: u64,
+    pub hits: u64,
+    pub consecutive_errors: u64,
+    pub idle_secs: u64,
+}
+
+impl WorkerStats {
+    pub fn new(id: usize) -> Self {
+        Self {
+            id,
+            started: Instant::now(),
+            attempts: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            consecutive_errors: AtomicU64::new(0),
+            last_activity: AtomicU64::new(0),
+        }
+    }
+
+    pub fn attempted(&self) {
+        self.attempts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records how a probe of a reachable address ended.
+    pub fn finished(&self, hit: bool) {
+        if hit {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            self.consecutive_errors.store(0, Ordering::Relaxed);
+        } else {
+            self.consecutive_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.active();
+    }
+
+    /// Marks the worker as alive, also when the address did not answer.
+    pub fn active(&self) {
+        self.last_activity
+            .store(self.started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn idle(&self) -> Duration {
+        self.started.elapsed().saturating_sub(Duration::from_millis(
+            self.last_activity.load(Ordering::Relaxed),
+        ))
+    }
+
+    pub fn snapshot(&self) -> WorkerSnapshot {
+        WorkerSnapshot {
+            id: self.id,
+            attempts: self.attempts.load(Ordering::Relaxed),
+            hits: self.hits.load(Ordering::Relaxed),
+            consecutive_errors: self.consecutive_errors.load(Ordering::Relaxed),
+            idle_secs: self.idle().as_secs(),
+        }
+    }
+}
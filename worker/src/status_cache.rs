@@ -0,0 +1,78 @@
+use std::{
+    collections::HashMap,
+    env,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::server_actions::without_connection::{Status, STATUS_PROTOCOL};
+
+/// Entries kept before expired ones are swept out.
+const SWEEP_AT: usize = 4096;
+
+/// Address, port and the protocol sent in the handshake; servers behind
+/// protocol translators answer differently per client version.
+type Key = (String, u16, i32);
+
+struct Entry {
+    at: Instant,
+    status: Status,
+    latency: Duration,
+}
+
+/// Recent status answers, so the scan workers, the updater and rescans don't
+/// ping the same server again within seconds of each other.
+pub struct StatusCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<Key, Entry>>,
+}
+
+impl StatusCache {
+    pub fn from_env() -> Self {
+        let ttl = env::var("STATUS_CACHE_TTL")
+            .unwrap_or("10".to_string())
+            .parse()
+            .unwrap();
+
+        Self::new(Duration::from_secs(ttl))
+    }
+
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The cached status and the latency it was measured with, if still fresh.
+    pub fn get(&self, ip: &str, port: u16) -> Option<(Status, Duration)> {
+        if self.ttl.is_zero() {
+            return None;
+        }
+
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(&(ip.to_string(), port, STATUS_PROTOCOL))
+            .filter(|entry| entry.at.elapsed() < self.ttl)
+            .map(|entry| (entry.status.clone(), entry.latency))
+    }
+
+    pub fn insert(&self, ip: &str, port: u16, status: &Status, latency: Duration) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= SWEEP_AT {
+            entries.retain(|_, entry| entry.at.elapsed() < self.ttl);
+        }
+        entries.insert(
+            (ip.to_string(), port, STATUS_PROTOCOL),
+            Entry {
+                at: Instant::now(),
+                status: status.clone(),
+                latency,
+            },
+        );
+    }
+}
@@ -0,0 +1,533 @@
+use std::{cmp::Reverse, collections::HashMap, io, sync::Mutex};
+
+use async_trait::async_trait;
+use chrono::{Local, Timelike};
+use mine_search::StatusClass;
+
+use crate::{
+    database::{
+        Edition, PingInsert, PingModel, PlayerInsert, PlayerModel, ServerInsert, ServerModel,
+    },
+    server_actions::path::PathData,
+    storage::{Access, LifecycleUpdate, Mod, ServerFilter, Sighting, StatusUpdate, Storage},
+};
+
+#[derive(Default)]
+struct Tables {
+    /// Server `id` is its index plus one
+    servers: Vec<ServerModel>,
+    /// Index into `servers` by `ip` and edition
+    addresses: HashMap<(String, String), usize>,
+    /// Players by name and server, like the unique index of `players`
+    players: HashMap<(String, i32), PlayerModel>,
+    pings: Vec<PingModel>,
+    mods: HashMap<i32, Vec<Mod>>,
+    next_player: i32,
+}
+
+/// Everything kept in memory for the length of the process, for runs and
+/// experiments that need no database for their results.
+#[derive(Default)]
+pub struct MemoryStorage {
+    tables: Mutex<Tables>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies `update` to the server `server_id`, if it is stored.
+    fn update(&self, server_id: i32, update: impl FnOnce(&mut ServerModel)) {
+        let mut tables = self.tables.lock().unwrap();
+        let index = (server_id as usize).wrapping_sub(1);
+        if let Some(server) = tables.servers.get_mut(index) {
+            update(server);
+        }
+    }
+}
+
+/// Whether `text` matches `pattern`, where `*` stands for any characters.
+fn glob(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+fn matches(server: &ServerModel, filter: &ServerFilter) -> bool {
+    filter
+        .version
+        .as_ref()
+        .is_none_or(|version| glob(version, &server.version_name))
+        && filter
+            .min_online
+            .is_none_or(|min_online| server.online >= min_online)
+        && filter
+            .license
+            .is_none_or(|license| server.license == Some(license))
+        && filter
+            .white_list
+            .is_none_or(|white_list| server.white_list == Some(white_list))
+        && filter
+            .edition
+            .is_none_or(|edition| server.edition == edition.as_str())
+        && filter
+            .software
+            .as_ref()
+            .is_none_or(|software| server.software.as_ref() == Some(software))
+        && filter
+            .country
+            .as_ref()
+            .is_none_or(|country| server.country.as_ref() == Some(country))
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn upsert_server(&self, server: ServerInsert) -> io::Result<Option<ServerModel>> {
+        let mut tables = self.tables.lock().unwrap();
+        let address = (server.ip.clone(), server.edition.clone());
+        if tables.addresses.contains_key(&address) {
+            return Ok(None);
+        }
+
+        let stored = ServerModel {
+            id: tables.servers.len() as i32 + 1,
+            ip: server.ip,
+            online: server.online,
+            max: server.max,
+            version_name: server.version_name,
+            protocol: server.protocol,
+            license: server.license,
+            white_list: server.white_list,
+            last_seen: Local::now().naive_local().with_nanosecond(0).unwrap(),
+            description: server.description,
+            discovery_source: server.discovery_source,
+            run_id: server.run_id,
+            peak_online: server.peak_online,
+            query_map: server.query_map,
+            query_game_type: server.query_game_type,
+            queue_server: server.queue_server,
+            proxy_protocol: false,
+            favicon: server.favicon,
+            canonical_id: None,
+            edition: server.edition,
+            software: server.software,
+            motd_plain: server.motd_plain,
+            country: None,
+            asn: None,
+            anomalous_response: server.anomalous_response,
+            status_class: server.status_class,
+            on_demand_host: server.on_demand_host,
+            lifecycle: server.lifecycle,
+            lifecycle_changed_at: None,
+            lifecycle_checked_at: None,
+            modpack: None,
+            rejections: 0,
+            blocked_at: None,
+            block_kind: None,
+            era: server.era,
+        };
+        let index = tables.servers.len();
+        tables.servers.push(stored.clone());
+        tables.addresses.insert(address, index);
+        Ok(Some(stored))
+    }
+
+    async fn record_mods(&self, server_id: i32, mods: Vec<Mod>) -> Vec<Mod> {
+        let mut tables = self.tables.lock().unwrap();
+        let known = tables.mods.entry(server_id).or_default();
+        for m in mods {
+            match known.iter_mut().find(|k| k.mod_id == m.mod_id) {
+                Some(k) => *k = m,
+                None => known.push(m),
+            }
+        }
+        known.sort_by(|a, b| a.mod_id.cmp(&b.mod_id));
+        known.clone()
+    }
+
+    async fn record_modpack(&self, server_id: i32, modpack: Option<(String, Option<String>)>) {
+        self.update(server_id, |server| {
+            server.modpack = modpack.map(|(name, _)| name)
+        });
+    }
+
+    async fn record_proxy_protocol(&self, server_id: i32) {
+        self.update(server_id, |server| server.proxy_protocol = true);
+    }
+
+    async fn record_status(&self, server_id: i32, update: StatusUpdate) {
+        self.update(server_id, |server| {
+            server.online = update.online;
+            server.max = update.max;
+            server.version_name = update.version_name;
+            server.protocol = update.protocol;
+            server.description = update.description;
+            server.motd_plain = update.motd_plain;
+            server.software = update.software;
+            server.anomalous_response = update.anomalous;
+            server.era = update.era;
+            server.status_class = update.status_class.map(|c| c.as_str().to_string());
+            server.favicon = update.favicon;
+            server.last_seen = update.seen_at;
+            server.peak_online = server.peak_online.max(update.online);
+            if let Some(query) = update.query {
+                server.query_map = query.map;
+                server.query_game_type = query.game_type;
+            }
+        });
+    }
+
+    async fn record_status_class(&self, server_id: i32, class: StatusClass) {
+        self.update(server_id, |server| {
+            server.status_class = Some(class.as_str().to_string())
+        });
+    }
+
+    async fn record_access(&self, server_id: i32, access: Access) {
+        self.update(server_id, |server| {
+            server.license = Some(access.license);
+            server.white_list = access.white_list;
+            server.queue_server = access.queue_server;
+        });
+    }
+
+    async fn record_lifecycle(&self, server_id: i32, lifecycle: LifecycleUpdate) {
+        self.update(server_id, |server| {
+            server.on_demand_host = Some(lifecycle.on_demand_host);
+            server.lifecycle = Some(lifecycle.lifecycle.as_str().to_string());
+            server.lifecycle_changed_at = lifecycle.changed_at;
+            server.lifecycle_checked_at = Some(lifecycle.checked_at);
+        });
+    }
+
+    /// Path data is no part of the server model, so nothing is kept.
+    async fn record_path(&self, _server_id: i32, _path: PathData) {}
+
+    async fn record_players(&self, players: Vec<PlayerInsert>) {
+        let mut tables = self.tables.lock().unwrap();
+        for player in players {
+            let key = (player.name.clone(), player.server_id);
+            if let Some(known) = tables.players.get_mut(&key) {
+                known.last_seen = player.last_seen;
+                continue;
+            }
+            tables.next_player += 1;
+            let id = tables.next_player;
+            tables.players.insert(
+                key,
+                PlayerModel {
+                    id,
+                    uuid: player.uuid,
+                    name: player.name,
+                    last_seen: player.last_seen,
+                    server_id: Some(player.server_id),
+                },
+            );
+        }
+    }
+
+    async fn record_snapshot(&self, snapshot: PingInsert) {
+        let mut tables = self.tables.lock().unwrap();
+        let id = tables.pings.len() as i32 + 1;
+        tables.pings.push(PingModel {
+            id,
+            server_id: snapshot.server_id,
+            online: snapshot.online,
+            max: snapshot.max,
+            version_name: snapshot.version_name,
+            protocol: snapshot.protocol,
+            latency_ms: snapshot.latency_ms,
+            checked_at: snapshot.checked_at,
+            samples: 1,
+        });
+    }
+
+    async fn flush(&self) {}
+
+    async fn server(&self, key: &str, edition: Edition) -> io::Result<Option<ServerModel>> {
+        let tables = self.tables.lock().unwrap();
+        let address = (key.to_string(), edition.as_str().to_string());
+        Ok(tables
+            .addresses
+            .get(&address)
+            .map(|index| tables.servers[*index].clone()))
+    }
+
+    async fn servers(&self, filter: &ServerFilter) -> io::Result<Vec<ServerModel>> {
+        let tables = self.tables.lock().unwrap();
+        let mut servers: Vec<&ServerModel> = tables
+            .servers
+            .iter()
+            .filter(|server| matches(server, filter))
+            .collect();
+        servers.sort_by_key(|row| Reverse(row.last_seen));
+
+        Ok(servers
+            .into_iter()
+            .skip(filter.offset.max(0) as usize)
+            .take(filter.limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn players(&self, server_id: i32) -> io::Result<Vec<PlayerModel>> {
+        let tables = self.tables.lock().unwrap();
+        let mut players: Vec<PlayerModel> = tables
+            .players
+            .values()
+            .filter(|player| player.server_id == Some(server_id))
+            .cloned()
+            .collect();
+        players.sort_by_key(|row| Reverse(row.last_seen));
+        Ok(players)
+    }
+
+    async fn mods(&self, server_id: i32) -> io::Result<Vec<Mod>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.mods.get(&server_id).cloned().unwrap_or_default())
+    }
+
+    async fn sightings(&self, player: &str, limit: i64) -> io::Result<Vec<Sighting>> {
+        let tables = self.tables.lock().unwrap();
+        let mut sightings: Vec<Sighting> = tables
+            .players
+            .values()
+            .filter(|p| p.name == player || p.uuid == player)
+            .filter_map(|p| {
+                let server = tables.servers.get(p.server_id? as usize - 1)?;
+                Some(Sighting {
+                    server_id: server.id,
+                    ip: server.ip.clone(),
+                    edition: server.edition.clone(),
+                    last_seen: p.last_seen,
+                })
+            })
+            .collect();
+        sightings.sort_by_key(|row| Reverse(row.last_seen));
+        sightings.truncate(limit.max(0) as usize);
+        Ok(sightings)
+    }
+
+    async fn snapshots(&self, server_id: i32, limit: i64) -> io::Result<Vec<PingModel>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .pings
+            .iter()
+            .rev()
+            .filter(|ping| ping.server_id == server_id)
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDateTime};
+    use serde_json::json;
+
+    use super::*;
+
+    fn insert(ip: &str, edition: Edition, version: &str, online: i32) -> ServerInsert {
+        ServerInsert {
+            ip: ip.to_string(),
+            online,
+            max: 20,
+            version_name: version.to_string(),
+            protocol: 767,
+            license: Some(true),
+            white_list: Some(false),
+            description: json!({"payload": "A Minecraft Server"}),
+            discovery_source: "scan".to_string(),
+            run_id: None,
+            peak_online: online,
+            tags: vec![],
+            query_map: None,
+            query_game_type: None,
+            query_game_id: None,
+            query_host_port: None,
+            tps: None,
+            queue_server: false,
+            raw_status: None,
+            favicon: None,
+            appeal_contact: None,
+            edition: edition.as_str().to_string(),
+            motd: None,
+            motd_plain: None,
+            software: None,
+            anomalous_response: false,
+            status_class: None,
+            on_demand_host: None,
+            lifecycle: None,
+            era: None,
+        }
+    }
+
+    fn at(minutes: i64) -> NaiveDateTime {
+        NaiveDateTime::default() + Duration::minutes(minutes)
+    }
+
+    fn filter(limit: i64, offset: i64) -> ServerFilter {
+        ServerFilter {
+            limit,
+            offset,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn upsert_stores_an_address_once_per_edition() {
+        let storage = MemoryStorage::new();
+        let java = storage
+            .upsert_server(insert("1.2.3.4", Edition::Java, "1.21", 3))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(java.id, 1);
+
+        let again = storage
+            .upsert_server(insert("1.2.3.4", Edition::Java, "1.20.4", 5))
+            .await
+            .unwrap();
+        assert!(again.is_none());
+
+        let bedrock = storage
+            .upsert_server(insert("1.2.3.4", Edition::Bedrock, "1.21.0", 1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(bedrock.id, 2);
+
+        let stored = storage.server("1.2.3.4", Edition::Java).await.unwrap();
+        assert_eq!(stored.unwrap().version_name, "1.21");
+    }
+
+    #[tokio::test]
+    async fn servers_are_filtered_and_paged() {
+        let storage = MemoryStorage::new();
+        for (i, version) in ["1.20.4", "1.21", "1.21.1", "Paper 1.21.1"]
+            .iter()
+            .enumerate()
+        {
+            let ip = format!("1.2.3.{}", i + 1);
+            storage
+                .upsert_server(insert(&ip, Edition::Java, version, i as i32 * 10))
+                .await
+                .unwrap();
+        }
+
+        let glob = ServerFilter {
+            version: Some("1.21*".to_string()),
+            ..filter(10, 0)
+        };
+        let versions: Vec<String> = storage
+            .servers(&glob)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|s| s.version_name)
+            .collect();
+        assert_eq!(versions.len(), 2);
+        assert!(versions.iter().all(|v| v.starts_with("1.21")));
+
+        let inner = ServerFilter {
+            version: Some("*1.21*".to_string()),
+            min_online: Some(20),
+            ..filter(10, 0)
+        };
+        let found = storage.servers(&inner).await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert!(found.iter().all(|s| s.online >= 20));
+
+        let first = storage.servers(&filter(3, 0)).await.unwrap();
+        let rest = storage.servers(&filter(3, 3)).await.unwrap();
+        assert_eq!((first.len(), rest.len()), (3, 1));
+        let mut ids: Vec<i32> = first.iter().chain(&rest).map(|s| s.id).collect();
+        ids.sort();
+        assert_eq!(ids, [1, 2, 3, 4]);
+
+        assert!(storage.servers(&filter(10, 4)).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn sightings_are_newest_first() {
+        let storage = MemoryStorage::new();
+        for ip in ["1.2.3.4", "1.2.3.5", "1.2.3.6"] {
+            storage
+                .upsert_server(insert(ip, Edition::Java, "1.21", 1))
+                .await
+                .unwrap();
+        }
+        let seen = |server_id, minutes| PlayerInsert {
+            uuid: "069a79f4-44e9-4726-a5be-fca90e38aaf5".to_string(),
+            name: "Notch".to_string(),
+            server_id,
+            last_seen: at(minutes),
+        };
+        storage
+            .record_players(vec![seen(1, 1), seen(2, 3), seen(3, 2)])
+            .await;
+        // Seen again, refreshing the sighting instead of adding one
+        storage.record_players(vec![seen(1, 4)]).await;
+
+        let sightings = storage.sightings("Notch", 10).await.unwrap();
+        let ips: Vec<&str> = sightings.iter().map(|s| s.ip.as_str()).collect();
+        assert_eq!(ips, ["1.2.3.4", "1.2.3.5", "1.2.3.6"]);
+
+        let by_uuid = storage
+            .sightings("069a79f4-44e9-4726-a5be-fca90e38aaf5", 2)
+            .await
+            .unwrap();
+        assert_eq!(by_uuid.len(), 2);
+        assert_eq!(by_uuid[0].last_seen, at(4));
+
+        assert!(storage.sightings("jeb_", 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn snapshots_are_latest_first_up_to_the_limit() {
+        let storage = MemoryStorage::new();
+        let ping = |server_id, online| PingInsert {
+            server_id,
+            online,
+            max: 20,
+            version_name: "1.21".to_string(),
+            protocol: 767,
+            latency_ms: Some(30),
+            checked_at: at(online as i64),
+        };
+        for (server_id, online) in [(1, 1), (1, 2), (2, 9), (1, 3)] {
+            storage.record_snapshot(ping(server_id, online)).await;
+        }
+
+        let online: Vec<i32> = storage
+            .snapshots(1, 2)
+            .await
+            .unwrap()
+            .iter()
+            .map(|p| p.online)
+            .collect();
+        assert_eq!(online, [3, 2]);
+        assert_eq!(storage.snapshots(1, 10).await.unwrap().len(), 3);
+        assert_eq!(storage.snapshots(2, 10).await.unwrap().len(), 1);
+        assert!(storage.snapshots(3, 10).await.unwrap().is_empty());
+    }
+}
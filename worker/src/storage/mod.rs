@@ -0,0 +1,158 @@
+use std::io;
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use mine_search::{Lifecycle, StatusClass};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    database::{
+        Edition, PingInsert, PingModel, PlayerInsert, PlayerModel, ServerInsert, ServerModel,
+    },
+    server_actions::{path::PathData, query::QueryData},
+};
+
+pub mod memory;
+pub mod postgres;
+
+/// Stored servers to list, most recently seen first.
+#[derive(Debug, Clone, Default)]
+pub struct ServerFilter {
+    /// Version name, `*` matches any characters
+    pub version: Option<String>,
+    pub min_online: Option<i32>,
+    pub license: Option<bool>,
+    pub white_list: Option<bool>,
+    pub edition: Option<Edition>,
+    pub software: Option<String>,
+    /// ISO country code, in upper case
+    pub country: Option<String>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+/// A mod or plugin recorded for a server.
+#[derive(Debug, Clone, Serialize)]
+pub struct Mod {
+    pub mod_id: String,
+    pub version: Option<String>,
+    pub source: String,
+}
+
+/// A stored server a player was seen on.
+#[derive(Debug, Clone, Serialize)]
+pub struct Sighting {
+    pub server_id: i32,
+    pub ip: String,
+    pub edition: String,
+    pub last_seen: NaiveDateTime,
+}
+
+/// What an update pass read from a stored server.
+#[derive(Debug, Clone)]
+pub struct StatusUpdate {
+    pub online: i32,
+    pub max: i32,
+    pub version_name: String,
+    pub protocol: i32,
+    /// `{"payload": ...}`, like `ServerModel::description`
+    pub description: Value,
+    pub motd: Option<String>,
+    pub motd_plain: Option<String>,
+    pub software: Option<String>,
+    pub raw_status: Option<String>,
+    pub anomalous: bool,
+    pub era: Option<String>,
+    pub status_class: Option<StatusClass>,
+    pub favicon: Option<String>,
+    /// Kept as stored when the server wasn't queried
+    pub query: Option<QueryData>,
+    pub seen_at: NaiveDateTime,
+}
+
+/// What a login probe learned of a stored server.
+#[derive(Debug, Clone)]
+pub struct Access {
+    pub license: bool,
+    pub white_list: Option<bool>,
+    pub tps: Option<f32>,
+    pub queue_server: bool,
+    pub appeal_contact: Option<String>,
+}
+
+/// State of a server on an on-demand host.
+#[derive(Debug, Clone)]
+pub struct LifecycleUpdate {
+    pub on_demand_host: String,
+    pub lifecycle: Lifecycle,
+    /// When it entered `lifecycle`
+    pub changed_at: Option<NaiveDateTime>,
+    pub checked_at: NaiveDateTime,
+}
+
+/// Where discovered servers, the players seen on them and their status
+/// snapshots are kept. Discoveries are stored and the query API's server and
+/// player routes read through it, so they work the same on any backend.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Stores a newly found server, `None` when one is stored at its address
+    /// already; the updater keeps known servers fresh.
+    async fn upsert_server(&self, server: ServerInsert) -> io::Result<Option<ServerModel>>;
+
+    /// Records the mods of a server, updating the versions of known ones,
+    /// and returns every mod stored for it.
+    async fn record_mods(&self, server_id: i32, mods: Vec<Mod>) -> Vec<Mod>;
+
+    /// Records the modpack a server's mods identify, `None` for none.
+    async fn record_modpack(&self, server_id: i32, modpack: Option<(String, Option<String>)>);
+
+    /// Marks a server as expecting a PROXY protocol header.
+    async fn record_proxy_protocol(&self, server_id: i32);
+
+    /// Records a fresh status of a stored server, raising its peak.
+    async fn record_status(&self, server_id: i32, update: StatusUpdate);
+
+    /// Classifies a server its host answered for while it is down, keeping
+    /// its last real status.
+    async fn record_status_class(&self, server_id: i32, class: StatusClass);
+
+    /// Records the outcome of a login probe, with a history entry when the
+    /// license or whitelist changed.
+    async fn record_access(&self, server_id: i32, access: Access);
+
+    /// Records the lifecycle of a server on an on-demand host.
+    async fn record_lifecycle(&self, server_id: i32, lifecycle: LifecycleUpdate);
+
+    /// Records the network path to a server.
+    async fn record_path(&self, server_id: i32, path: PathData);
+
+    /// Records players seen on a server, refreshing when known ones were.
+    async fn record_players(&self, players: Vec<PlayerInsert>);
+
+    /// Records a status snapshot.
+    async fn record_snapshot(&self, snapshot: PingInsert);
+
+    /// Waits until recorded players and snapshots are stored.
+    async fn flush(&self);
+
+    /// The server stored as `key` (`servers.ip`) for `edition`.
+    async fn server(&self, key: &str, edition: Edition) -> io::Result<Option<ServerModel>>;
+
+    async fn servers(&self, filter: &ServerFilter) -> io::Result<Vec<ServerModel>>;
+
+    /// Players seen on a server, most recently seen first.
+    async fn players(&self, server_id: i32) -> io::Result<Vec<PlayerModel>>;
+
+    /// Mods of a server by id.
+    async fn mods(&self, server_id: i32) -> io::Result<Vec<Mod>>;
+
+    /// Servers a player name or UUID was seen on, most recently first, up to
+    /// `limit`.
+    async fn sightings(&self, player: &str, limit: i64) -> io::Result<Vec<Sighting>>;
+
+    /// The latest `limit` status snapshots of a server, newest first.
+    async fn snapshots(&self, server_id: i32, limit: i64) -> io::Result<Vec<PingModel>>;
+}
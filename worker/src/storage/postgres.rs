@@ -0,0 +1,417 @@
+use std::{io, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::{Local, Timelike};
+use diesel::{
+    dsl::insert_into, upsert::excluded, BoolExpressionMethods, ExpressionMethods,
+    OptionalExtension, QueryDsl, QueryResult, RunQueryDsl, SelectableHelper, TextExpressionMethods,
+};
+use mine_search::StatusClass;
+
+use crate::{
+    database::{
+        Edition, ModUpsert, PingInsert, PingModel, PlayerInsert, PlayerModel, Pool, QueryUpdate,
+        ServerInsert, ServerModel, ServerUpdate, WhiteListHistoryInsert,
+    },
+    schema,
+    server_actions::path::PathData,
+    storage::{Access, LifecycleUpdate, Mod, ServerFilter, Sighting, StatusUpdate, Storage},
+    writer::Writers,
+};
+
+fn db_error(e: diesel::result::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+/// Reports a write that failed; scanning goes on without it.
+fn report<T>(what: &str, server_id: i32, result: QueryResult<T>) -> Option<T> {
+    result
+        .inspect_err(|e| {
+            eprintln!(
+                "[!] Could not store {} of server {}: {}",
+                what, server_id, e
+            )
+        })
+        .ok()
+}
+
+/// The Postgres database: inserts on the writer shard of each address,
+/// players and snapshots in batches, queries on the read replica if one is
+/// configured.
+pub struct PgStorage {
+    writers: Arc<Writers>,
+    reads: Arc<Pool>,
+}
+
+impl PgStorage {
+    pub fn new(writers: Arc<Writers>, reads: Arc<Pool>) -> Self {
+        Self { writers, reads }
+    }
+}
+
+#[async_trait]
+impl Storage for PgStorage {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn upsert_server(&self, server: ServerInsert) -> io::Result<Option<ServerModel>> {
+        let key = server.ip.clone();
+        self.writers
+            .run(&key, move |conn| {
+                insert_into(schema::servers::dsl::servers)
+                    .values(&server)
+                    .on_conflict((schema::servers::dsl::ip, schema::servers::dsl::edition))
+                    .do_nothing()
+                    .returning(ServerModel::as_returning())
+                    .get_result(conn)
+                    .optional()
+            })
+            .await
+            .map_err(db_error)
+    }
+
+    async fn record_mods(&self, server_id: i32, mods: Vec<Mod>) -> Vec<Mod> {
+        let now = Local::now().naive_local().with_nanosecond(0).unwrap();
+        let stored = self
+            .writers
+            .run(&server_id.to_string(), move |conn| {
+                let rows: Vec<ModUpsert> = mods
+                    .iter()
+                    .map(|m| ModUpsert {
+                        server_id,
+                        mod_id: &m.mod_id,
+                        version: m.version.as_deref(),
+                        source: &m.source,
+                        last_seen: now,
+                    })
+                    .collect();
+                insert_into(schema::mods::dsl::mods)
+                    .values(&rows)
+                    .on_conflict((schema::mods::dsl::server_id, schema::mods::dsl::mod_id))
+                    .do_update()
+                    .set((
+                        schema::mods::dsl::version.eq(excluded(schema::mods::dsl::version)),
+                        schema::mods::dsl::source.eq(excluded(schema::mods::dsl::source)),
+                        schema::mods::dsl::last_seen.eq(excluded(schema::mods::dsl::last_seen)),
+                    ))
+                    .execute(conn)?;
+
+                schema::mods::dsl::mods
+                    .filter(schema::mods::dsl::server_id.eq(server_id))
+                    .order(schema::mods::dsl::mod_id)
+                    .select((
+                        schema::mods::dsl::mod_id,
+                        schema::mods::dsl::version,
+                        schema::mods::dsl::source,
+                    ))
+                    .load::<(String, Option<String>, String)>(conn)
+            })
+            .await;
+
+        report("the mods", server_id, stored)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(mod_id, version, source)| Mod {
+                mod_id,
+                version,
+                source,
+            })
+            .collect()
+    }
+
+    async fn record_modpack(&self, server_id: i32, modpack: Option<(String, Option<String>)>) {
+        let (modpack, modpack_version) = modpack.unzip();
+        let modpack_version = modpack_version.flatten();
+        let result = self
+            .writers
+            .run(&server_id.to_string(), move |conn| {
+                diesel::update(schema::servers::dsl::servers.find(server_id))
+                    .set((
+                        schema::servers::dsl::modpack.eq(&modpack),
+                        schema::servers::dsl::modpack_version.eq(&modpack_version),
+                    ))
+                    .execute(conn)
+            })
+            .await;
+        report("the modpack", server_id, result);
+    }
+
+    async fn record_proxy_protocol(&self, server_id: i32) {
+        let result = self
+            .writers
+            .run(&server_id.to_string(), move |conn| {
+                diesel::update(schema::servers::dsl::servers.find(server_id))
+                    .set(schema::servers::dsl::proxy_protocol.eq(true))
+                    .execute(conn)
+            })
+            .await;
+        report("the PROXY protocol flag", server_id, result);
+    }
+
+    async fn record_status(&self, server_id: i32, update: StatusUpdate) {
+        let result = self
+            .writers
+            .run(&server_id.to_string(), move |conn| {
+                let row = schema::servers::dsl::servers.find(server_id);
+                diesel::update(row)
+                    .set((
+                        ServerUpdate {
+                            online: update.online,
+                            max: update.max,
+                            version_name: &update.version_name,
+                            protocol: update.protocol,
+                            description: &update.description,
+                            motd: update.motd.as_deref(),
+                            motd_plain: update.motd_plain.as_deref(),
+                            software: update.software.as_deref(),
+                        },
+                        schema::servers::dsl::last_seen.eq(update.seen_at),
+                        schema::servers::dsl::raw_status.eq(update.raw_status.as_deref()),
+                        schema::servers::dsl::anomalous_response.eq(update.anomalous),
+                        schema::servers::dsl::era.eq(update.era.as_deref()),
+                        schema::servers::dsl::status_class
+                            .eq(update.status_class.map(|c| c.as_str())),
+                        schema::servers::dsl::favicon.eq(update.favicon.as_deref()),
+                    ))
+                    .execute(conn)?;
+
+                if let Some(query) = &update.query {
+                    diesel::update(row)
+                        .set(QueryUpdate {
+                            query_map: query.map.as_deref(),
+                            query_game_type: query.game_type.as_deref(),
+                            query_game_id: query.game_id.as_deref(),
+                            query_host_port: query.host_port,
+                        })
+                        .execute(conn)?;
+                }
+
+                diesel::update(row)
+                    .filter(schema::servers::dsl::peak_online.lt(update.online))
+                    .set((
+                        schema::servers::dsl::peak_online.eq(update.online),
+                        schema::servers::dsl::peak_online_at.eq(update.seen_at),
+                    ))
+                    .execute(conn)
+            })
+            .await;
+        report("the status", server_id, result);
+    }
+
+    async fn record_status_class(&self, server_id: i32, class: StatusClass) {
+        let result = self
+            .writers
+            .run(&server_id.to_string(), move |conn| {
+                diesel::update(schema::servers::dsl::servers.find(server_id))
+                    .set(schema::servers::dsl::status_class.eq(class.as_str()))
+                    .execute(conn)
+            })
+            .await;
+        report("the status class", server_id, result);
+    }
+
+    async fn record_access(&self, server_id: i32, access: Access) {
+        let result = self
+            .writers
+            .run(&server_id.to_string(), move |conn| {
+                let row = schema::servers::dsl::servers.find(server_id);
+                let (license, white_list): (Option<bool>, Option<bool>) = row
+                    .select((
+                        schema::servers::dsl::license,
+                        schema::servers::dsl::white_list,
+                    ))
+                    .first(conn)?;
+
+                diesel::update(row)
+                    .set((
+                        schema::servers::dsl::tps.eq(access.tps),
+                        schema::servers::dsl::queue_server.eq(access.queue_server),
+                        schema::servers::dsl::appeal_contact.eq(&access.appeal_contact),
+                        schema::servers::dsl::license.eq(access.license),
+                        schema::servers::dsl::white_list.eq(access.white_list),
+                    ))
+                    .execute(conn)?;
+
+                if license == Some(access.license) && white_list == access.white_list {
+                    return Ok(0);
+                }
+                insert_into(schema::white_list_history::dsl::white_list_history)
+                    .values(WhiteListHistoryInsert {
+                        server_id,
+                        old_license: license,
+                        new_license: access.license,
+                        old_white_list: white_list,
+                        new_white_list: access.white_list,
+                    })
+                    .execute(conn)
+            })
+            .await;
+        report("the login probe", server_id, result);
+    }
+
+    async fn record_lifecycle(&self, server_id: i32, lifecycle: LifecycleUpdate) {
+        let result = self
+            .writers
+            .run(&server_id.to_string(), move |conn| {
+                diesel::update(schema::servers::dsl::servers.find(server_id))
+                    .set((
+                        schema::servers::dsl::on_demand_host.eq(&lifecycle.on_demand_host),
+                        schema::servers::dsl::lifecycle.eq(lifecycle.lifecycle.as_str()),
+                        schema::servers::dsl::lifecycle_changed_at.eq(lifecycle.changed_at),
+                        schema::servers::dsl::lifecycle_checked_at.eq(lifecycle.checked_at),
+                    ))
+                    .execute(conn)
+            })
+            .await;
+        report("the lifecycle", server_id, result);
+    }
+
+    async fn record_path(&self, server_id: i32, path: PathData) {
+        let result = self
+            .writers
+            .run(&server_id.to_string(), move |conn| {
+                diesel::update(schema::servers::dsl::servers.find(server_id))
+                    .set((
+                        schema::servers::dsl::hop_count.eq(path.hop_count),
+                        schema::servers::dsl::last_hop_network.eq(&path.last_hop_network),
+                    ))
+                    .execute(conn)
+            })
+            .await;
+        report("the network path", server_id, result);
+    }
+
+    async fn record_players(&self, players: Vec<PlayerInsert>) {
+        self.writers.players(players).await;
+    }
+
+    async fn record_snapshot(&self, snapshot: PingInsert) {
+        self.writers.ping(snapshot).await;
+    }
+
+    async fn flush(&self) {
+        self.writers.flush().await;
+    }
+
+    async fn server(&self, key: &str, edition: Edition) -> io::Result<Option<ServerModel>> {
+        schema::servers::dsl::servers
+            .filter(schema::servers::dsl::ip.eq(key))
+            .filter(schema::servers::dsl::edition.eq(edition.as_str()))
+            .select(ServerModel::as_select())
+            .first(&mut self.reads.get().await.conn)
+            .optional()
+            .map_err(db_error)
+    }
+
+    async fn servers(&self, filter: &ServerFilter) -> io::Result<Vec<ServerModel>> {
+        let mut select = schema::servers::dsl::servers
+            .select(ServerModel::as_select())
+            .into_boxed();
+
+        if let Some(version) = &filter.version {
+            let pattern = version
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+                .replace('*', "%");
+            select = select.filter(schema::servers::dsl::version_name.like(pattern));
+        }
+        if let Some(min_online) = filter.min_online {
+            select = select.filter(schema::servers::dsl::online.ge(min_online));
+        }
+        if let Some(license) = filter.license {
+            select = select.filter(schema::servers::dsl::license.eq(license));
+        }
+        if let Some(white_list) = filter.white_list {
+            select = select.filter(schema::servers::dsl::white_list.eq(white_list));
+        }
+        if let Some(edition) = filter.edition {
+            select = select.filter(schema::servers::dsl::edition.eq(edition.as_str()));
+        }
+        if let Some(software) = &filter.software {
+            select = select.filter(schema::servers::dsl::software.eq(software));
+        }
+        if let Some(country) = &filter.country {
+            select = select.filter(schema::servers::dsl::country.eq(country));
+        }
+
+        select
+            .order(schema::servers::dsl::last_seen.desc())
+            .limit(filter.limit)
+            .offset(filter.offset)
+            .load(&mut self.reads.get().await.conn)
+            .map_err(db_error)
+    }
+
+    async fn players(&self, server_id: i32) -> io::Result<Vec<PlayerModel>> {
+        schema::players::dsl::players
+            .filter(schema::players::dsl::server_id.eq(server_id))
+            .order(schema::players::dsl::last_seen.desc())
+            .select(PlayerModel::as_select())
+            .load(&mut self.reads.get().await.conn)
+            .map_err(db_error)
+    }
+
+    async fn mods(&self, server_id: i32) -> io::Result<Vec<Mod>> {
+        let rows: Vec<(String, Option<String>, String)> = schema::mods::dsl::mods
+            .filter(schema::mods::dsl::server_id.eq(server_id))
+            .order(schema::mods::dsl::mod_id)
+            .select((
+                schema::mods::dsl::mod_id,
+                schema::mods::dsl::version,
+                schema::mods::dsl::source,
+            ))
+            .load(&mut self.reads.get().await.conn)
+            .map_err(db_error)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(mod_id, version, source)| Mod {
+                mod_id,
+                version,
+                source,
+            })
+            .collect())
+    }
+
+    async fn sightings(&self, player: &str, limit: i64) -> io::Result<Vec<Sighting>> {
+        let rows: Vec<(i32, String, String, chrono::NaiveDateTime)> = schema::players::dsl::players
+            .inner_join(schema::servers::dsl::servers)
+            .filter(
+                schema::players::dsl::name
+                    .eq(player)
+                    .or(schema::players::dsl::uuid.eq(player)),
+            )
+            .order(schema::players::dsl::last_seen.desc())
+            .limit(limit)
+            .select((
+                schema::servers::dsl::id,
+                schema::servers::dsl::ip,
+                schema::servers::dsl::edition,
+                schema::players::dsl::last_seen,
+            ))
+            .load(&mut self.reads.get().await.conn)
+            .map_err(db_error)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(server_id, ip, edition, last_seen)| Sighting {
+                server_id,
+                ip,
+                edition,
+                last_seen,
+            })
+            .collect())
+    }
+
+    async fn snapshots(&self, server_id: i32, limit: i64) -> io::Result<Vec<PingModel>> {
+        schema::pings::dsl::pings
+            .filter(schema::pings::dsl::server_id.eq(server_id))
+            .order(schema::pings::dsl::checked_at.desc())
+            .limit(limit)
+            .select(PingModel::as_select())
+            .load(&mut self.reads.get().await.conn)
+            .map_err(db_error)
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+#[derive(Debug, Default)]
+pub struct Traffic {
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+impl Traffic {
+    pub const fn new() -> Self {
+        Self {
+            sent: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+        }
+    }
+
+    pub fn sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    pub fn received(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+}
+
+/// Totals for the whole run.
+pub static RUN: Traffic = Traffic::new();
+
+tokio::task_local! {
+    /// Counters of the scan worker the current task belongs to.
+    pub static WORKER: Arc<Traffic>;
+}
+
+pub fn record_sent(bytes: usize) {
+    RUN.sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    let _ = WORKER.try_with(|t| t.sent.fetch_add(bytes as u64, Ordering::Relaxed));
+}
+
+pub fn record_received(bytes: usize) {
+    RUN.received.fetch_add(bytes as u64, Ordering::Relaxed);
+    let _ = WORKER.try_with(|t| t.received.fetch_add(bytes as u64, Ordering::Relaxed));
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,107 @@
+use std::{fs, net::IpAddr, sync::Arc, time::Duration};
+
+use diesel::{dsl::insert_into, ExpressionMethods, RunQueryDsl};
+use serde_json::{json, Value};
+use tokio::time::timeout;
+
+use crate::{
+    conn_wrapper::Connection, database::VirtualHostInsert, lookups::Lookups, schema,
+    server_actions::without_connection::get_status_on, Context,
+};
+
+const DEFAULT_PREFIXES: &[&str] = &["mc", "play", "join", "server", "minecraft"];
+
+pub struct VhostScanner {
+    prefixes: Vec<String>,
+    hosts: Vec<String>,
+}
+
+impl VhostScanner {
+    /// Wordlist entries containing a dot are tried as full hostnames, the rest
+    /// are prefixes for the domain derived from reverse DNS.
+    pub fn load(wordlist: Option<&str>) -> Self {
+        let words: Vec<String> = match wordlist {
+            Some(path) => fs::read_to_string(path)
+                .unwrap_or_else(|e| panic!("Error reading {}: {}", path, e))
+                .lines()
+                .map(|l| l.trim().to_string())
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .collect(),
+            None => DEFAULT_PREFIXES.iter().map(|p| p.to_string()).collect(),
+        };
+
+        let (hosts, prefixes) = words.into_iter().partition(|w| w.contains('.'));
+        Self { prefixes, hosts }
+    }
+
+    async fn candidates(&self, ip: IpAddr, lookups: &Lookups) -> Vec<String> {
+        let mut candidates = self.hosts.clone();
+
+        if let Some(hostname) = lookups.reverse_dns(ip).await {
+            let labels: Vec<&str> = hostname.split('.').collect();
+
+            if labels.len() >= 2 {
+                let domain = labels[labels.len() - 2..].join(".");
+                candidates.extend(self.prefixes.iter().map(|p| format!("{}.{}", p, domain)));
+                candidates.push(domain);
+            }
+            candidates.push(hostname);
+        }
+
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+}
+
+/// Retries the status handshake with candidate hostnames and records the ones
+/// that answer, noting whether they serve something other than the bare IP.
+pub async fn scan(
+    ctx: Arc<Context>,
+    server_id: i32,
+    ip: IpAddr,
+    port: u16,
+    baseline_description: Value,
+    baseline_version: String,
+) {
+    let Some(scanner) = &ctx.vhosts else {
+        return;
+    };
+
+    for hostname in scanner.candidates(ip, &ctx.lookups).await {
+        let status = timeout(Duration::from_secs(3), async {
+            let mut conn = Connection::connect((ip, port)).await?;
+            get_status_on(&mut conn, &hostname, port).await
+        })
+        .await;
+
+        let Ok(Ok(status)) = status else {
+            continue;
+        };
+
+        let description = json!({ "payload": status.description });
+        let differs =
+            description != baseline_description || status.version.name != baseline_version;
+
+        insert_into(schema::virtual_hosts::dsl::virtual_hosts)
+            .values(VirtualHostInsert {
+                server_id,
+                hostname: &hostname,
+                version_name: &status.version.name,
+                description: &description,
+                differs_from_ip: differs,
+            })
+            .on_conflict((
+                schema::virtual_hosts::dsl::server_id,
+                schema::virtual_hosts::dsl::hostname,
+            ))
+            .do_update()
+            .set((
+                schema::virtual_hosts::dsl::version_name.eq(&status.version.name),
+                schema::virtual_hosts::dsl::description.eq(&description),
+                schema::virtual_hosts::dsl::differs_from_ip.eq(differs),
+            ))
+            .execute(&mut ctx.pool().get().await.conn)
+            .unwrap();
+    }
+}
@@ -0,0 +1,378 @@
+use std::{collections::HashSet, env, time::Duration};
+
+use mine_search::filter::{FieldValue, Filter, Record};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+/// Deliveries waiting to be sent before new events are dropped.
+const QUEUE_SIZE: usize = 1024;
+
+const EVENTS: &[&str] = &[
+    "discovered",
+    "version_changed",
+    "motd_changed",
+    "white_list_toggled",
+    "license_toggled",
+    "went_offline",
+    "plugin_notification",
+    "alert",
+];
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Discovered {
+        ip: String,
+        version_name: String,
+        protocol: i32,
+        license: Option<bool>,
+        white_list: Option<bool>,
+        country: Option<String>,
+        rdns: Option<String>,
+    },
+    VersionChanged {
+        ip: String,
+        old_version_name: String,
+        new_version_name: String,
+        old_protocol: i32,
+        new_protocol: i32,
+    },
+    MotdChanged {
+        ip: String,
+        old_value: Value,
+        new_value: Value,
+    },
+    WhiteListToggled {
+        ip: String,
+        white_list: Option<bool>,
+    },
+    LicenseToggled {
+        ip: String,
+        license: bool,
+    },
+    WentOffline {
+        ip: String,
+    },
+    PluginNotification {
+        ip: String,
+        message: String,
+    },
+    Alert {
+        ip: String,
+        rule: String,
+        online: i64,
+        max: i64,
+        latency_ms: u64,
+    },
+    /// Sent to the `notify` URLs of a pipeline only, not through the routes
+    PipelineMatched {
+        pipeline: String,
+        ip: String,
+        version_name: String,
+        online: i32,
+        max: i32,
+    },
+}
+
+/// Scheme and host of a webhook URL, leaving out the credentials and the
+/// path, which holds the token of Discord and Telegram webhooks.
+fn redact(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(url) => match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}://{}:{}/…", url.scheme(), host, port),
+            (Some(host), None) => format!("{}://{}/…", url.scheme(), host),
+            (None, _) => format!("{}:…", url.scheme()),
+        },
+        Err(_) => "(invalid URL)".to_string(),
+    }
+}
+
+impl Event {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::Discovered { .. } => "discovered",
+            Event::VersionChanged { .. } => "version_changed",
+            Event::MotdChanged { .. } => "motd_changed",
+            Event::WhiteListToggled { .. } => "white_list_toggled",
+            Event::LicenseToggled { .. } => "license_toggled",
+            Event::WentOffline { .. } => "went_offline",
+            Event::PluginNotification { .. } => "plugin_notification",
+            Event::Alert { .. } => "alert",
+            Event::PipelineMatched { .. } => "pipeline_matched",
+        }
+    }
+
+    /// One line for chat sinks, which show text rather than JSON.
+    fn summary(&self) -> String {
+        match self {
+            Event::Discovered {
+                ip,
+                version_name,
+                country,
+                ..
+            } => format!(
+                "Discovered {} ({}, {})",
+                ip,
+                version_name,
+                country.as_deref().unwrap_or("??")
+            ),
+            Event::VersionChanged {
+                ip,
+                old_version_name,
+                new_version_name,
+                ..
+            } => format!(
+                "{} changed version: {} -> {}",
+                ip, old_version_name, new_version_name
+            ),
+            Event::MotdChanged { ip, .. } => format!("{} changed its MOTD", ip),
+            Event::WhiteListToggled { ip, white_list } => {
+                format!("{} white list is now {:?}", ip, white_list)
+            }
+            Event::LicenseToggled { ip, license } => {
+                format!("{} license check is now {}", ip, license)
+            }
+            Event::WentOffline { ip } => format!("{} went offline", ip),
+            Event::PluginNotification { ip, message } => format!("{}: {}", ip, message),
+            Event::Alert {
+                ip,
+                rule,
+                online,
+                max,
+                ..
+            } => format!("Alert {} on {} ({}/{} online)", rule, ip, online, max),
+            Event::PipelineMatched {
+                pipeline,
+                ip,
+                version_name,
+                online,
+                max,
+            } => format!(
+                "Pipeline {} matched {} ({}, {}/{} online)",
+                pipeline, ip, version_name, online, max
+            ),
+        }
+    }
+}
+
+/// Event fields for route filters, with `event` holding the event name.
+impl Record for Event {
+    fn field(&self, name: &str) -> Option<FieldValue> {
+        if name == "event" {
+            return Some(self.kind().into());
+        }
+        match serde_json::to_value(self).ok()?.get(name)? {
+            Value::Bool(b) => Some((*b).into()),
+            Value::Number(n) => n.as_i64().map(Into::into),
+            Value::String(s) => Some(s.as_str().into()),
+            _ => None,
+        }
+    }
+}
+
+/// Events, optionally narrowed by a filter, sent to a set of URLs.
+struct Route {
+    /// `None` for every event
+    events: Option<HashSet<String>>,
+    filter: Option<Filter>,
+    urls: Vec<String>,
+}
+
+impl Route {
+    fn matches(&self, event: &Event) -> bool {
+        self.events
+            .as_ref()
+            .is_none_or(|events| events.contains(event.kind()))
+            && self
+                .filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(event))
+    }
+}
+
+pub struct Webhooks {
+    queue: Option<mpsc::Sender<(Event, Vec<String>)>>,
+    routes: Vec<Route>,
+}
+
+impl Webhooks {
+    /// `WEBHOOK_URLS` receive the `WEBHOOK_EVENTS`, `WEBHOOK_ROUTES` add
+    /// sinks for narrower selections.
+    pub fn from_env() -> Self {
+        let urls = env::var("WEBHOOK_URLS")
+            .map(|v| split_list(&v))
+            .unwrap_or_default();
+        let events = env::var("WEBHOOK_EVENTS")
+            .ok()
+            .map(|v| split_list(&v).into_iter().collect());
+
+        let mut routes = vec![];
+        if !urls.is_empty() {
+            routes.push(Route {
+                events,
+                filter: None,
+                urls,
+            });
+        }
+        if let Ok(value) = env::var("WEBHOOK_ROUTES") {
+            routes.extend(parse_routes(&value).unwrap_or_else(|e| panic!("WEBHOOK_ROUTES: {}", e)));
+        }
+
+        let queue = if routes.is_empty() {
+            None
+        } else {
+            let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+            tokio::spawn(deliver(receiver));
+            Some(sender)
+        };
+
+        Self { queue, routes }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.queue.is_some()
+    }
+
+    pub fn describe(&self) -> Vec<String> {
+        self.routes
+            .iter()
+            .map(|route| {
+                let events = match &route.events {
+                    Some(events) => {
+                        let mut events: Vec<&str> = events.iter().map(String::as_str).collect();
+                        events.sort();
+                        events.join(",")
+                    }
+                    None => "*".to_string(),
+                };
+                let urls: Vec<String> = route.urls.iter().map(|url| redact(url)).collect();
+                match &route.filter {
+                    Some(filter) => format!("{}: {} -> {}", events, filter, urls.join(", ")),
+                    None => format!("{} -> {}", events, urls.join(", ")),
+                }
+            })
+            .collect()
+    }
+
+    pub fn fire(&self, event: Event) {
+        let Some(queue) = &self.queue else {
+            return;
+        };
+
+        let mut urls: Vec<String> = vec![];
+        for route in self.routes.iter().filter(|route| route.matches(&event)) {
+            for url in &route.urls {
+                if !urls.contains(url) {
+                    urls.push(url.clone());
+                }
+            }
+        }
+        if urls.is_empty() {
+            return;
+        }
+
+        if queue.try_send((event, urls)).is_err() {
+            eprintln!("[!] Webhook queue full, event dropped");
+        }
+    }
+}
+
+/// `;`-separated `events: filter -> urls` routes. Events and URLs are
+/// comma-separated, `*` stands for every event and the filter may be left
+/// out.
+fn parse_routes(value: &str) -> Result<Vec<Route>, String> {
+    let mut routes = vec![];
+
+    for route in value.split(';').filter(|r| !r.trim().is_empty()) {
+        let expected = || format!("expected 'events: filter -> urls' in {}", route);
+        let (selection, urls) = route.rsplit_once("->").ok_or_else(expected)?;
+        let (events, filter) = selection.split_once(':').unwrap_or((selection, ""));
+
+        let events = split_list(events);
+        let urls = split_list(urls);
+        if events.is_empty() || urls.is_empty() {
+            return Err(expected());
+        }
+        if let Some(unknown) = events
+            .iter()
+            .find(|event| *event != "*" && !EVENTS.contains(&event.as_str()))
+        {
+            return Err(format!(
+                "unknown event {}, expected one of {}",
+                unknown,
+                EVENTS.join(", ")
+            ));
+        }
+
+        routes.push(Route {
+            events: (!events.iter().any(|event| event == "*"))
+                .then(|| events.into_iter().collect()),
+            filter: match filter.trim() {
+                "" => None,
+                filter => Some(Filter::parse(filter).map_err(|e| e.to_string())?),
+            },
+            urls,
+        });
+    }
+
+    Ok(routes)
+}
+
+/// The body for `url`: Discord and Telegram get a chat message, anything else
+/// the event as JSON.
+fn payload(url: &str, event: &Event) -> Value {
+    let host = url
+        .split("://")
+        .nth(1)
+        .and_then(|rest| rest.split(['/', '?']).next())
+        .unwrap_or_default();
+    match host {
+        "discord.com" | "discordapp.com" => json!({ "content": event.summary() }),
+        // The chat goes in the URL, e.g. `.../bot<token>/sendMessage?chat_id=<chat>`
+        "api.telegram.org" => json!({ "text": event.summary() }),
+        _ => serde_json::to_value(event).unwrap(),
+    }
+}
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap()
+}
+
+async fn post(client: &reqwest::Client, url: &str, event: &Event) {
+    if let Err(e) = client.post(url).json(&payload(url, event)).send().await {
+        eprintln!("[!] Webhook to {} failed: {}", redact(url), e.without_url());
+    }
+}
+
+async fn deliver(mut receiver: mpsc::Receiver<(Event, Vec<String>)>) {
+    let client = client();
+
+    while let Some((event, urls)) = receiver.recv().await {
+        for url in &urls {
+            post(&client, url, &event).await;
+        }
+    }
+}
+
+/// Posts `events` to `urls` and waits for the deliveries, for commands that
+/// exit once done instead of leaving them to the queue.
+pub async fn send(urls: &[String], events: &[Event]) {
+    let client = client();
+    for event in events {
+        for url in urls {
+            post(&client, url, event).await;
+        }
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
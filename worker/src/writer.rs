@@ -0,0 +1,325 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet},
+    env,
+    hash::{Hash, Hasher},
+    mem, thread,
+};
+
+use diesel::{
+    dsl::insert_into,
+    result::{DatabaseErrorKind, Error},
+    upsert::excluded,
+    ExpressionMethods, PgConnection, QueryResult, RunQueryDsl,
+};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task,
+    time::{interval, MissedTickBehavior},
+};
+
+use crate::{
+    database::{DatabaseWrapper, LivenessInsert, PingInsert, PlayerInsert},
+    schema,
+    sinks::Batching,
+};
+
+/// Jobs a shard may hold before senders wait for it to catch up.
+const QUEUE_SIZE: usize = 256;
+/// Most rows written by one batched insert; keeps the widest rows, pings of
+/// seven columns, below the 65535 bind parameters Postgres allows per
+/// statement.
+const MAX_BATCH_SIZE: usize = 9000;
+/// Tries of a batch or a job, each on a fresh connection after the first,
+/// before its rows are dropped or its error returned.
+const WRITE_ATTEMPTS: u32 = 3;
+
+/// Work for a shard, given the connection of its thread, if it has one.
+type Job = Box<dyn FnOnce(&mut Option<DatabaseWrapper>) + Send>;
+
+enum Batched<T> {
+    Row(T),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Database writer threads, each owning its own connection, opened with the
+/// first job and again after it was lost. Writes for the same server address
+/// always land on the same shard, so they stay ordered while different
+/// servers are written in parallel. High-volume rows (pings,
+/// liveness samples and players) are sent without waiting and written in
+/// batches.
+pub struct Writers {
+    shards: Vec<mpsc::Sender<Job>>,
+    pings: mpsc::Sender<Batched<PingInsert>>,
+    liveness: mpsc::Sender<Batched<LivenessInsert>>,
+    players: mpsc::Sender<Batched<PlayerInsert>>,
+    batching: Batching,
+}
+
+impl Writers {
+    pub fn from_env() -> Self {
+        let count = env::var("DB_WRITERS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(1)
+            .max(1);
+
+        Self::start(count)
+    }
+
+    pub fn start(count: usize) -> Self {
+        let shards = (0..count)
+            .map(|shard| {
+                let (tx, mut rx) = mpsc::channel::<Job>(QUEUE_SIZE);
+
+                thread::Builder::new()
+                    .name(format!("db-writer-{}", shard))
+                    .spawn(move || {
+                        let mut db = None;
+                        while let Some(job) = rx.blocking_recv() {
+                            job(&mut db);
+                        }
+                    })
+                    .unwrap();
+
+                tx
+            })
+            .collect();
+
+        let mut batching = Batching::of("db", 1000, 1000);
+        batching.size = batching.size.min(MAX_BATCH_SIZE);
+        Self {
+            shards,
+            pings: batcher("pings", write_pings, batching),
+            liveness: batcher("liveness samples", write_liveness, batching),
+            players: batcher("players", write_players, batching),
+            batching,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn batching(&self) -> Batching {
+        self.batching
+    }
+
+    /// Runs `job` on the shard owning `key` and waits for its result. A job
+    /// that lost the connection runs again on a new one.
+    pub async fn run<T, F>(&self, key: &str, job: F) -> QueryResult<T>
+    where
+        T: Send + 'static,
+        F: FnMut(&mut PgConnection) -> QueryResult<T> + Send + 'static,
+    {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let shard = &self.shards[hasher.finish() as usize % self.shards.len()];
+
+        let (tx, rx) = oneshot::channel();
+        shard
+            .send(Box::new(move |db| {
+                let _ = tx.send(attempt(db, job));
+            }))
+            .await
+            .expect("database writer stopped");
+
+        rx.await.expect("database writer dropped a job")
+    }
+
+    /// Queues a status snapshot for the `pings` table.
+    pub async fn ping(&self, ping: PingInsert) {
+        let _ = self.pings.send(Batched::Row(ping)).await;
+    }
+
+    /// Queues a liveness sample for the `liveness` table.
+    pub async fn liveness(&self, sample: LivenessInsert) {
+        let _ = self.liveness.send(Batched::Row(sample)).await;
+    }
+
+    /// Queues players seen on a server, refreshing `last_seen` of known ones.
+    pub async fn players(&self, players: Vec<PlayerInsert>) {
+        for player in players {
+            let _ = self.players.send(Batched::Row(player)).await;
+        }
+    }
+
+    /// Waits until every queued ping, liveness sample and player is written.
+    pub async fn flush(&self) {
+        for rx in [
+            flush(&self.pings).await,
+            flush(&self.liveness).await,
+            flush(&self.players).await,
+        ] {
+            let _ = rx.await;
+        }
+    }
+}
+
+/// Runs `job` on the connection in `db`, reconnecting when there is none
+/// or it was lost, up to `WRITE_ATTEMPTS` times.
+fn attempt<T>(
+    db: &mut Option<DatabaseWrapper>,
+    mut job: impl FnMut(&mut PgConnection) -> QueryResult<T>,
+) -> QueryResult<T> {
+    let mut tries = 1;
+    loop {
+        let result = match db {
+            Some(db) => job(&mut db.conn),
+            None => match DatabaseWrapper::try_establish() {
+                Ok(connected) => job(&mut db.insert(connected).conn),
+                Err(e) => Err(Error::DatabaseError(
+                    DatabaseErrorKind::UnableToSendCommand,
+                    Box::new(e.to_string()),
+                )),
+            },
+        };
+        match result {
+            Err(e) if connection_lost(&e) => {
+                *db = None;
+                if tries == WRITE_ATTEMPTS {
+                    return Err(e);
+                }
+                eprintln!("[!] Database writer lost its connection, retrying: {}", e);
+                tries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Whether `e` came from the connection rather than the statement.
+fn connection_lost(e: &Error) -> bool {
+    matches!(
+        e,
+        Error::DatabaseError(
+            DatabaseErrorKind::ClosedConnection | DatabaseErrorKind::UnableToSendCommand,
+            _
+        ) | Error::BrokenTransactionManager
+    )
+}
+
+async fn flush<T>(tx: &mpsc::Sender<Batched<T>>) -> oneshot::Receiver<()> {
+    let (done, rx) = oneshot::channel();
+    let _ = tx.send(Batched::Flush(done)).await;
+    rx
+}
+
+/// Collects rows and hands them to `write` in batches of up to
+/// `batching.size`, at least every `batching.interval`. A failed batch is
+/// retried on a new connection and dropped after `WRITE_ATTEMPTS`, so one
+/// bad row or a lost connection doesn't stop the writer.
+fn batcher<T: Send + 'static>(
+    name: &'static str,
+    write: fn(&mut PgConnection, &mut Vec<T>) -> QueryResult<usize>,
+    batching: Batching,
+) -> mpsc::Sender<Batched<T>> {
+    let (tx, mut rx) = mpsc::channel::<Batched<T>>(batching.size);
+
+    tokio::spawn(async move {
+        let mut db: Option<DatabaseWrapper> = None;
+        let mut batch: Vec<T> = vec![];
+        let mut ticker = interval(batching.interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            let mut done = None;
+            let closed = tokio::select! {
+                message = rx.recv() => match message {
+                    Some(Batched::Row(row)) => {
+                        batch.push(row);
+                        if batch.len() < batching.size {
+                            continue;
+                        }
+                        false
+                    }
+                    Some(Batched::Flush(tx)) => {
+                        done = Some(tx);
+                        false
+                    }
+                    None => true,
+                },
+                _ = ticker.tick() => false,
+            };
+
+            if !batch.is_empty() {
+                let rows = mem::take(&mut batch);
+                let conn = db.take();
+                db = task::spawn_blocking(move || write_batch(name, write, conn, rows))
+                    .await
+                    .unwrap_or_else(|e| {
+                        eprintln!("[!] Writing {} failed, batch dropped: {}", name, e);
+                        None
+                    });
+            }
+            if let Some(done) = done {
+                let _ = done.send(());
+            }
+            if closed {
+                break;
+            }
+        }
+    });
+
+    tx
+}
+
+/// Writes `rows` on `conn`, or a new connection, returning the connection
+/// to keep using.
+fn write_batch<T>(
+    name: &str,
+    write: fn(&mut PgConnection, &mut Vec<T>) -> QueryResult<usize>,
+    mut conn: Option<DatabaseWrapper>,
+    mut rows: Vec<T>,
+) -> Option<DatabaseWrapper> {
+    for attempt in 1..=WRITE_ATTEMPTS {
+        let db = conn.get_or_insert_with(DatabaseWrapper::establish);
+        match write(&mut db.conn, &mut rows) {
+            Ok(_) => return conn,
+            Err(e) if attempt < WRITE_ATTEMPTS => {
+                eprintln!(
+                    "[!] Could not write {} {}, retrying: {}",
+                    rows.len(),
+                    name,
+                    e
+                );
+                conn = None;
+            }
+            Err(e) => eprintln!(
+                "[!] Could not write {} {}, dropped: {}",
+                rows.len(),
+                name,
+                e
+            ),
+        }
+    }
+    None
+}
+
+fn write_pings(conn: &mut PgConnection, pings: &mut Vec<PingInsert>) -> QueryResult<usize> {
+    insert_into(schema::pings::dsl::pings)
+        .values(&*pings)
+        .execute(conn)
+}
+
+fn write_liveness(
+    conn: &mut PgConnection,
+    samples: &mut Vec<LivenessInsert>,
+) -> QueryResult<usize> {
+    insert_into(schema::liveness::dsl::liveness)
+        .values(&*samples)
+        .execute(conn)
+}
+
+fn write_players(conn: &mut PgConnection, players: &mut Vec<PlayerInsert>) -> QueryResult<usize> {
+    // One statement may not update the same row twice
+    let mut seen = HashSet::new();
+    players.reverse();
+    players.retain(|p| seen.insert((p.name.clone(), p.server_id)));
+
+    insert_into(schema::players::dsl::players)
+        .values(&*players)
+        .on_conflict((schema::players::dsl::name, schema::players::dsl::server_id))
+        .do_update()
+        .set(schema::players::dsl::last_seen.eq(excluded(schema::players::dsl::last_seen)))
+        .execute(conn)
+}